@@ -0,0 +1,753 @@
+use crate::conflict::Side;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::{HashMap, VecDeque};
+
+/// How many keys the echo overlay remembers.
+const ECHO_KEYS: usize = 6;
+
+/// Something the editor can be asked to do. Keys are resolved to actions
+/// first, so everything that cares about what a key means (the key echo,
+/// read-only checks) sees the same answer as the dispatcher.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Action {
+    Quit,
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    WordLeft,
+    WordRight,
+    SubwordLeft,
+    SubwordRight,
+    LineStart,
+    LineEnd,
+    PageUp,
+    PageDown,
+    SelectUp,
+    SelectDown,
+    SelectLeft,
+    SelectRight,
+    SelectWordLeft,
+    SelectWordRight,
+    SelectSubwordLeft,
+    SelectSubwordRight,
+    SelectLineStart,
+    SelectLineEnd,
+    Cut,
+    Copy,
+    Paste,
+    CyclePaste,
+    Collapse,
+    Save,
+    Open,
+    NextBuffer,
+    PreviousBuffer,
+    CloseBuffer,
+    FindFile,
+    PickBuffer,
+    Find,
+    FindWord,
+    /// Moves to (or with `till` just short of) the next occurrence on the
+    /// line of a character typed afterwards.
+    FindChar {
+        forward: bool,
+        till: bool,
+    },
+    CommandLine,
+    Undo,
+    Redo,
+    DeleteBackward,
+    DeleteForward,
+    DeleteSubwordBackward,
+    DeleteSubwordForward,
+    InsertNewline,
+    InsertTab,
+    InsertChar(char),
+    ToggleMetrics,
+    /// Replaces the merge conflict around the cursor with the side kept.
+    ResolveConflict(Side),
+    NextConflict,
+    PreviousConflict,
+    /// Makes a file read with mixed line endings save with one kind.
+    NormalizeEndings,
+    StripBom,
+}
+
+/// Every action a key can be bound to in the config.
+pub const BINDABLE: &[Action] = &[
+    Action::Quit,
+    Action::MoveUp,
+    Action::MoveDown,
+    Action::MoveLeft,
+    Action::MoveRight,
+    Action::WordLeft,
+    Action::WordRight,
+    Action::SubwordLeft,
+    Action::SubwordRight,
+    Action::LineStart,
+    Action::LineEnd,
+    Action::PageUp,
+    Action::PageDown,
+    Action::SelectUp,
+    Action::SelectDown,
+    Action::SelectLeft,
+    Action::SelectRight,
+    Action::SelectWordLeft,
+    Action::SelectWordRight,
+    Action::SelectSubwordLeft,
+    Action::SelectSubwordRight,
+    Action::SelectLineStart,
+    Action::SelectLineEnd,
+    Action::Cut,
+    Action::Copy,
+    Action::Paste,
+    Action::CyclePaste,
+    Action::Collapse,
+    Action::Save,
+    Action::Open,
+    Action::NextBuffer,
+    Action::PreviousBuffer,
+    Action::CloseBuffer,
+    Action::FindFile,
+    Action::PickBuffer,
+    Action::Find,
+    Action::FindWord,
+    Action::FindChar {
+        forward: true,
+        till: false,
+    },
+    Action::FindChar {
+        forward: true,
+        till: true,
+    },
+    Action::FindChar {
+        forward: false,
+        till: false,
+    },
+    Action::FindChar {
+        forward: false,
+        till: true,
+    },
+    Action::CommandLine,
+    Action::Undo,
+    Action::Redo,
+    Action::DeleteBackward,
+    Action::DeleteForward,
+    Action::DeleteSubwordBackward,
+    Action::DeleteSubwordForward,
+    Action::InsertNewline,
+    Action::InsertTab,
+    Action::ToggleMetrics,
+    Action::ResolveConflict(Side::Ours),
+    Action::ResolveConflict(Side::Theirs),
+    Action::ResolveConflict(Side::Both),
+    Action::ResolveConflict(Side::Base),
+    Action::NextConflict,
+    Action::PreviousConflict,
+    Action::NormalizeEndings,
+    Action::StripBom,
+];
+
+impl Action {
+    /// The action called `name`, as `name` gives it.
+    pub fn from_name(name: &str) -> Option<Self> {
+        BINDABLE.iter().copied().find(|action| action.name() == name)
+    }
+
+    /// The default key bindings.
+    pub fn from_key(key: KeyEvent) -> Option<Self> {
+        let action = match (key.code, key.modifiers) {
+            (KeyCode::Char(ch), KeyModifiers::CONTROL) => match ch {
+                'q' => Action::Quit,
+                's' => Action::Save,
+                'o' => Action::Open,
+                'w' => Action::CloseBuffer,
+                'p' => Action::FindFile,
+                'b' => Action::PickBuffer,
+                'f' => Action::Find,
+                'e' => Action::CommandLine,
+                'z' => Action::Undo,
+                'y' => Action::Redo,
+                'x' => Action::Cut,
+                'c' => Action::Copy,
+                'v' => Action::Paste,
+                _ => return None,
+            },
+            (KeyCode::Char(ch), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                Action::InsertChar(ch)
+            }
+            (KeyCode::PageDown, KeyModifiers::CONTROL) => Action::NextBuffer,
+            (KeyCode::PageUp, KeyModifiers::CONTROL) => Action::PreviousBuffer,
+            (KeyCode::Left, KeyModifiers::CONTROL) => Action::WordLeft,
+            (KeyCode::Right, KeyModifiers::CONTROL) => Action::WordRight,
+            (code, KeyModifiers::SHIFT) => match code {
+                KeyCode::Up => Action::SelectUp,
+                KeyCode::Down => Action::SelectDown,
+                KeyCode::Left => Action::SelectLeft,
+                KeyCode::Right => Action::SelectRight,
+                KeyCode::Home => Action::SelectLineStart,
+                KeyCode::End => Action::SelectLineEnd,
+                _ => return None,
+            },
+            (KeyCode::Left, modifiers) if modifiers == KeyModifiers::CONTROL | KeyModifiers::SHIFT => {
+                Action::SelectWordLeft
+            }
+            (KeyCode::Right, modifiers) if modifiers == KeyModifiers::CONTROL | KeyModifiers::SHIFT => {
+                Action::SelectWordRight
+            }
+            // Alt-Shift-f may arrive with or without the Shift modifier.
+            (KeyCode::Char(ch @ ('f' | 't' | 'F' | 'T')), modifiers)
+                if modifiers == KeyModifiers::ALT
+                    || modifiers == KeyModifiers::ALT | KeyModifiers::SHIFT =>
+            {
+                Action::FindChar {
+                    forward: ch.is_lowercase(),
+                    till: ch.eq_ignore_ascii_case(&'t'),
+                }
+            }
+            (KeyCode::Char('v'), KeyModifiers::ALT) => Action::CyclePaste,
+            (code, KeyModifiers::ALT) => match code {
+                KeyCode::Left => Action::SubwordLeft,
+                KeyCode::Right => Action::SubwordRight,
+                KeyCode::Backspace => Action::DeleteSubwordBackward,
+                KeyCode::Delete => Action::DeleteSubwordForward,
+                KeyCode::Char('1') => Action::ResolveConflict(Side::Ours),
+                KeyCode::Char('2') => Action::ResolveConflict(Side::Theirs),
+                KeyCode::Char('3') => Action::ResolveConflict(Side::Both),
+                KeyCode::Char('4') => Action::ResolveConflict(Side::Base),
+                KeyCode::Char('n') => Action::NextConflict,
+                KeyCode::Char('p') => Action::PreviousConflict,
+                KeyCode::Char('l') => Action::NormalizeEndings,
+                KeyCode::Char('b') => Action::StripBom,
+                _ => return None,
+            },
+            (KeyCode::Left, modifiers) if modifiers == KeyModifiers::ALT | KeyModifiers::SHIFT => {
+                Action::SelectSubwordLeft
+            }
+            (KeyCode::Right, modifiers) if modifiers == KeyModifiers::ALT | KeyModifiers::SHIFT => {
+                Action::SelectSubwordRight
+            }
+            (code, KeyModifiers::NONE) => match code {
+                KeyCode::Up => Action::MoveUp,
+                KeyCode::Down => Action::MoveDown,
+                KeyCode::Left => Action::MoveLeft,
+                KeyCode::Right => Action::MoveRight,
+                KeyCode::Home => Action::LineStart,
+                KeyCode::End => Action::LineEnd,
+                KeyCode::PageUp => Action::PageUp,
+                KeyCode::PageDown => Action::PageDown,
+                KeyCode::Backspace => Action::DeleteBackward,
+                KeyCode::Delete => Action::DeleteForward,
+                KeyCode::Enter => Action::InsertNewline,
+                KeyCode::Tab => Action::InsertTab,
+                KeyCode::Esc => Action::Collapse,
+                KeyCode::F(3) => Action::FindWord,
+                KeyCode::F(12) => Action::ToggleMetrics,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(action)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::MoveUp => "move-up",
+            Action::MoveDown => "move-down",
+            Action::MoveLeft => "move-left",
+            Action::MoveRight => "move-right",
+            Action::WordLeft => "word-left",
+            Action::WordRight => "word-right",
+            Action::SubwordLeft => "subword-left",
+            Action::SubwordRight => "subword-right",
+            Action::LineStart => "line-start",
+            Action::LineEnd => "line-end",
+            Action::PageUp => "page-up",
+            Action::PageDown => "page-down",
+            Action::SelectUp => "select-up",
+            Action::SelectDown => "select-down",
+            Action::SelectLeft => "select-left",
+            Action::SelectRight => "select-right",
+            Action::SelectWordLeft => "select-word-left",
+            Action::SelectWordRight => "select-word-right",
+            Action::SelectSubwordLeft => "select-subword-left",
+            Action::SelectSubwordRight => "select-subword-right",
+            Action::SelectLineStart => "select-line-start",
+            Action::SelectLineEnd => "select-line-end",
+            Action::Cut => "cut",
+            Action::Copy => "copy",
+            Action::Paste => "paste",
+            Action::CyclePaste => "cycle-paste",
+            Action::Collapse => "collapse",
+            Action::Save => "save",
+            Action::Open => "open",
+            Action::NextBuffer => "next-buffer",
+            Action::PreviousBuffer => "previous-buffer",
+            Action::CloseBuffer => "close-buffer",
+            Action::FindFile => "find-file",
+            Action::PickBuffer => "pick-buffer",
+            Action::Find => "find",
+            Action::FindWord => "find-word",
+            Action::FindChar {
+                forward: true,
+                till: false,
+            } => "find-char",
+            Action::FindChar {
+                forward: true,
+                till: true,
+            } => "till-char",
+            Action::FindChar {
+                forward: false,
+                till: false,
+            } => "find-char-backward",
+            Action::FindChar {
+                forward: false,
+                till: true,
+            } => "till-char-backward",
+            Action::CommandLine => "command-line",
+            Action::Undo => "undo",
+            Action::Redo => "redo",
+            Action::DeleteBackward => "delete-backward",
+            Action::DeleteForward => "delete-forward",
+            Action::DeleteSubwordBackward => "delete-subword-backward",
+            Action::DeleteSubwordForward => "delete-subword-forward",
+            Action::InsertNewline => "insert-newline",
+            Action::InsertTab => "insert-tab",
+            Action::InsertChar(_) => "insert-char",
+            Action::ToggleMetrics => "toggle-metrics",
+            Action::ResolveConflict(Side::Ours) => "keep-ours",
+            Action::ResolveConflict(Side::Theirs) => "keep-theirs",
+            Action::ResolveConflict(Side::Both) => "keep-both",
+            Action::ResolveConflict(Side::Base) => "keep-base",
+            Action::NextConflict => "next-conflict",
+            Action::PreviousConflict => "previous-conflict",
+            Action::NormalizeEndings => "normalize-endings",
+            Action::StripBom => "strip-bom",
+        }
+    }
+
+    /// What the action does, in a line, for `:help`.
+    pub fn about(&self) -> &'static str {
+        match self {
+            Action::Quit => "Quit, asking about unsaved changes",
+            Action::MoveUp => "Move the cursor up a line",
+            Action::MoveDown => "Move the cursor down a line",
+            Action::MoveLeft => "Move the cursor left",
+            Action::MoveRight => "Move the cursor right",
+            Action::WordLeft => "Move to the start of the word",
+            Action::WordRight => "Move past the end of the word",
+            Action::SubwordLeft => "Move to the start of the subword (camelCase, snake_case)",
+            Action::SubwordRight => "Move past the end of the subword",
+            Action::LineStart => "Move to the start of the line",
+            Action::LineEnd => "Move to the end of the line",
+            Action::PageUp => "Move up a screen",
+            Action::PageDown => "Move down a screen",
+            Action::SelectUp => "Extend the selection up a line",
+            Action::SelectDown => "Extend the selection down a line",
+            Action::SelectLeft => "Extend the selection left",
+            Action::SelectRight => "Extend the selection right",
+            Action::SelectWordLeft => "Extend the selection to the start of the word",
+            Action::SelectWordRight => "Extend the selection past the end of the word",
+            Action::SelectSubwordLeft => "Extend the selection to the start of the subword",
+            Action::SelectSubwordRight => "Extend the selection past the end of the subword",
+            Action::SelectLineStart => "Extend the selection to the start of the line",
+            Action::SelectLineEnd => "Extend the selection to the end of the line",
+            Action::Cut => "Cut the selection",
+            Action::Copy => "Copy the selection",
+            Action::Paste => "Paste the clipboard",
+            Action::CyclePaste => "Switch the last paste between re-indented and as copied",
+            Action::Collapse => "Clear the selection",
+            Action::Save => "Save the buffer",
+            Action::Open => "Open a file, or a new one under a name that doesn't exist",
+            Action::NextBuffer => "Switch to the next buffer",
+            Action::PreviousBuffer => "Switch to the previous buffer",
+            Action::CloseBuffer => "Close the buffer",
+            Action::FindFile => "Pick a file of the project to open",
+            Action::PickBuffer => "Pick an open buffer",
+            Action::Find => "Search the buffer",
+            Action::FindWord => "Jump to the next occurrence of the word under the cursor",
+            Action::FindChar {
+                forward: true,
+                till: false,
+            } => "Move to the next occurrence on the line of the character typed next",
+            Action::FindChar {
+                forward: true,
+                till: true,
+            } => "Move just short of the next occurrence of the character typed next",
+            Action::FindChar {
+                forward: false,
+                till: false,
+            } => "Move to the previous occurrence of the character typed next",
+            Action::FindChar {
+                forward: false,
+                till: true,
+            } => "Move just past the previous occurrence of the character typed next",
+            Action::CommandLine => "Open the command line",
+            Action::Undo => "Undo the last change",
+            Action::Redo => "Redo the last undone change",
+            Action::DeleteBackward => "Delete the character before the cursor",
+            Action::DeleteForward => "Delete the character under the cursor",
+            Action::DeleteSubwordBackward => "Delete back to the start of the subword",
+            Action::DeleteSubwordForward => "Delete to the end of the subword",
+            Action::InsertNewline => "Break the line",
+            Action::InsertTab => "Insert a tab",
+            Action::InsertChar(_) => "Type a character",
+            Action::ToggleMetrics => "Show or hide the performance overlay",
+            Action::ResolveConflict(Side::Ours) => "Keep our side of the merge conflict",
+            Action::ResolveConflict(Side::Theirs) => "Keep their side of the merge conflict",
+            Action::ResolveConflict(Side::Both) => "Keep both sides of the merge conflict",
+            Action::ResolveConflict(Side::Base) => "Keep the base of the merge conflict",
+            Action::NextConflict => "Jump to the next merge conflict",
+            Action::PreviousConflict => "Jump to the previous merge conflict",
+            Action::NormalizeEndings => "Save a file with mixed line endings with one kind",
+            Action::StripBom => "Save a UTF-8 file without its byte order mark",
+        }
+    }
+
+    /// For actions that move the cursor, whether they extend the selection
+    /// (otherwise they clear it).
+    pub fn extends_selection(&self) -> Option<bool> {
+        match self {
+            Action::MoveUp
+            | Action::MoveDown
+            | Action::MoveLeft
+            | Action::MoveRight
+            | Action::WordLeft
+            | Action::WordRight
+            | Action::SubwordLeft
+            | Action::SubwordRight
+            | Action::LineStart
+            | Action::LineEnd
+            | Action::PageUp
+            | Action::PageDown
+            | Action::FindWord
+            | Action::NextConflict
+            | Action::PreviousConflict => Some(false),
+            Action::SelectUp
+            | Action::SelectDown
+            | Action::SelectLeft
+            | Action::SelectRight
+            | Action::SelectWordLeft
+            | Action::SelectWordRight
+            | Action::SelectSubwordLeft
+            | Action::SelectSubwordRight
+            | Action::SelectLineStart
+            | Action::SelectLineEnd => Some(true),
+            _ => None,
+        }
+    }
+
+    /// Whether the action is a motion or plain edit, which happens at
+    /// every cursor when there are several.
+    pub fn is_cursor_local(&self) -> bool {
+        matches!(
+            self,
+            Action::MoveUp
+                | Action::MoveDown
+                | Action::MoveLeft
+                | Action::MoveRight
+                | Action::WordLeft
+                | Action::WordRight
+                | Action::SubwordLeft
+                | Action::SubwordRight
+                | Action::LineStart
+                | Action::LineEnd
+                | Action::SelectUp
+                | Action::SelectDown
+                | Action::SelectLeft
+                | Action::SelectRight
+                | Action::SelectWordLeft
+                | Action::SelectWordRight
+                | Action::SelectSubwordLeft
+                | Action::SelectSubwordRight
+                | Action::SelectLineStart
+                | Action::SelectLineEnd
+                | Action::Paste
+                | Action::DeleteBackward
+                | Action::DeleteForward
+                | Action::DeleteSubwordBackward
+                | Action::DeleteSubwordForward
+                | Action::InsertNewline
+                | Action::InsertTab
+                | Action::InsertChar(_)
+        )
+    }
+
+    /// Whether the action is typing, which `undo_group` may gather several
+    /// keys of into one undo step.
+    pub fn is_typing(&self) -> bool {
+        matches!(
+            self,
+            Action::DeleteBackward
+                | Action::DeleteForward
+                | Action::InsertNewline
+                | Action::InsertTab
+                | Action::InsertChar(_)
+        )
+    }
+
+    /// Whether the action changes the buffer, and so is refused in
+    /// read-only buffers.
+    pub fn is_edit(&self) -> bool {
+        matches!(
+            self,
+            Action::Undo
+                | Action::Redo
+                | Action::Cut
+                | Action::Paste
+                | Action::CyclePaste
+                | Action::DeleteBackward
+                | Action::DeleteForward
+                | Action::DeleteSubwordBackward
+                | Action::DeleteSubwordForward
+                | Action::InsertNewline
+                | Action::InsertTab
+                | Action::InsertChar(_)
+                | Action::ResolveConflict(_)
+                | Action::NormalizeEndings
+                | Action::StripBom
+        )
+    }
+}
+
+/// A key as it would be written in documentation, such as `Ctrl-S`.
+pub fn describe_key(key: &KeyEvent) -> String {
+    let mut name = String::new();
+    if key.modifiers.contains(KeyModifiers::CONTROL) {
+        name.push_str("Ctrl-");
+    }
+    if key.modifiers.contains(KeyModifiers::ALT) {
+        name.push_str("Alt-");
+    }
+    let shifted_char = matches!(key.code, KeyCode::Char(_));
+    if key.modifiers.contains(KeyModifiers::SHIFT) && !shifted_char {
+        name.push_str("Shift-");
+    }
+    match key.code {
+        KeyCode::Char(' ') => name.push_str("Space"),
+        KeyCode::Char(ch) if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            name.push(ch.to_ascii_uppercase())
+        }
+        KeyCode::Char(ch) => name.push(ch),
+        KeyCode::F(n) => name.push_str(&format!("F{}", n)),
+        KeyCode::Up => name.push_str("Up"),
+        KeyCode::Down => name.push_str("Down"),
+        KeyCode::Left => name.push_str("Left"),
+        KeyCode::Right => name.push_str("Right"),
+        KeyCode::Home => name.push_str("Home"),
+        KeyCode::End => name.push_str("End"),
+        KeyCode::PageUp => name.push_str("PageUp"),
+        KeyCode::PageDown => name.push_str("PageDown"),
+        KeyCode::Backspace => name.push_str("Backspace"),
+        KeyCode::Delete => name.push_str("Delete"),
+        KeyCode::Enter => name.push_str("Enter"),
+        KeyCode::Tab => name.push_str("Tab"),
+        KeyCode::BackTab => name.push_str("Shift-Tab"),
+        KeyCode::Esc => name.push_str("Esc"),
+        KeyCode::Insert => name.push_str("Insert"),
+        KeyCode::Null => name.push_str("Null"),
+    }
+    name
+}
+
+/// Reads a key written as `describe_key` writes it, such as `Ctrl-S`,
+/// `Alt-Left` or `F3`.
+pub fn parse_key(text: &str) -> Option<KeyEvent> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = text;
+    loop {
+        let (prefix, modifier) = match rest {
+            _ if rest.starts_with("Ctrl-") => ("Ctrl-", KeyModifiers::CONTROL),
+            _ if rest.starts_with("Alt-") => ("Alt-", KeyModifiers::ALT),
+            _ if rest.starts_with("Shift-") => ("Shift-", KeyModifiers::SHIFT),
+            _ => break,
+        };
+        modifiers |= modifier;
+        rest = &rest[prefix.len()..];
+    }
+    let code = match rest {
+        "Space" => KeyCode::Char(' '),
+        "Up" => KeyCode::Up,
+        "Down" => KeyCode::Down,
+        "Left" => KeyCode::Left,
+        "Right" => KeyCode::Right,
+        "Home" => KeyCode::Home,
+        "End" => KeyCode::End,
+        "PageUp" => KeyCode::PageUp,
+        "PageDown" => KeyCode::PageDown,
+        "Backspace" => KeyCode::Backspace,
+        "Delete" => KeyCode::Delete,
+        "Enter" => KeyCode::Enter,
+        "Tab" if modifiers.contains(KeyModifiers::SHIFT) => {
+            modifiers -= KeyModifiers::SHIFT;
+            KeyCode::BackTab
+        }
+        "Tab" => KeyCode::Tab,
+        "Esc" => KeyCode::Esc,
+        "Insert" => KeyCode::Insert,
+        _ => match rest.strip_prefix('F').and_then(|n| n.parse::<u8>().ok()) {
+            Some(n) => KeyCode::F(n),
+            None => {
+                let mut chars = rest.chars();
+                let ch = chars.next().filter(|_| chars.next().is_none())?;
+                // Terminals report Ctrl with the lowercase letter.
+                if modifiers.contains(KeyModifiers::CONTROL) {
+                    KeyCode::Char(ch.to_ascii_lowercase())
+                } else {
+                    KeyCode::Char(ch)
+                }
+            }
+        },
+    };
+    Some(normalize(KeyEvent::new(code, modifiers)))
+}
+
+/// A character key without the Shift modifier, which the character itself
+/// already shows and terminals don't report consistently.
+fn normalize(key: KeyEvent) -> KeyEvent {
+    match key.code {
+        KeyCode::Char(_) => KeyEvent::new(key.code, key.modifiers - KeyModifiers::SHIFT),
+        _ => key,
+    }
+}
+
+/// The key bindings in use: the defaults of `Action::from_key`, with the
+/// config's `[keys]` table (key = "action", or "none" to unbind) over them.
+pub struct Keymap {
+    overrides: HashMap<KeyEvent, Option<Action>>,
+}
+
+impl Keymap {
+    /// Builds the keymap for `[keys]` entries, noting those that name an
+    /// unknown key or action in `errors`.
+    pub fn new(bindings: &HashMap<String, String>, errors: &mut Vec<String>) -> Self {
+        let mut overrides = HashMap::new();
+        for (key, name) in bindings {
+            let Some(key_event) = parse_key(key) else {
+                errors.push(format!("Unknown key: {}", key));
+                continue;
+            };
+            let action = match name.as_str() {
+                "none" => None,
+                _ => match Action::from_name(name) {
+                    Some(action) => Some(action),
+                    None => {
+                        errors.push(format!("Unknown action for {}: {}", key, name));
+                        continue;
+                    }
+                },
+            };
+            overrides.insert(key_event, action);
+        }
+        Self { overrides }
+    }
+
+    pub fn action(&self, key: KeyEvent) -> Option<Action> {
+        match self.overrides.get(&normalize(key)) {
+            Some(&action) => action,
+            None => Action::from_key(key),
+        }
+    }
+
+    /// The keys bound to `action`, as `describe_key` writes them.
+    pub fn keys_for(&self, action: Action) -> Vec<String> {
+        let mut names: Vec<String> = candidate_keys()
+            .chain(self.overrides.keys().copied())
+            .filter(|&key| self.action(key) == Some(action))
+            .map(|key| describe_key(&key))
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+/// The keys the default bindings can use: letters, digits and named keys
+/// with every combination of modifiers they're bound with.
+fn candidate_keys() -> impl Iterator<Item = KeyEvent> {
+    let named = [
+        KeyCode::Up,
+        KeyCode::Down,
+        KeyCode::Left,
+        KeyCode::Right,
+        KeyCode::Home,
+        KeyCode::End,
+        KeyCode::PageUp,
+        KeyCode::PageDown,
+        KeyCode::Backspace,
+        KeyCode::Delete,
+        KeyCode::Enter,
+        KeyCode::Tab,
+        KeyCode::Esc,
+    ];
+    let codes: Vec<KeyCode> = ('a'..='z')
+        .chain('A'..='Z')
+        .chain('0'..='9')
+        .map(KeyCode::Char)
+        .chain(named)
+        .chain((1..=12).map(KeyCode::F))
+        .collect();
+    let modifiers = [
+        KeyModifiers::NONE,
+        KeyModifiers::SHIFT,
+        KeyModifiers::CONTROL,
+        KeyModifiers::ALT,
+        KeyModifiers::CONTROL | KeyModifiers::SHIFT,
+        KeyModifiers::ALT | KeyModifiers::SHIFT,
+    ];
+    codes.into_iter().flat_map(move |code| {
+        modifiers
+            .into_iter()
+            .map(move |modifiers| normalize(KeyEvent::new(code, modifiers)))
+    })
+}
+
+/// The keys shown by the echo overlay (`:keyecho`), newest last. A key
+/// pressed several times in a row is shown once with a count.
+#[derive(Default)]
+pub struct KeyEcho {
+    pub visible: bool,
+    keys: VecDeque<(String, usize)>,
+}
+
+impl KeyEcho {
+    /// Remembers `key`, along with the action it resolved to. Keys typed
+    /// into a prompt have no action.
+    pub fn record(&mut self, key: &KeyEvent, action: Option<Action>) {
+        if !self.visible {
+            return;
+        }
+        let mut entry = describe_key(key);
+        match action {
+            Some(Action::InsertChar(_)) | None => {}
+            Some(action) => {
+                entry.push_str("  ");
+                entry.push_str(action.name());
+            }
+        }
+        match self.keys.back_mut() {
+            Some((last, count)) if *last == entry => *count += 1,
+            _ => {
+                if self.keys.len() == ECHO_KEYS {
+                    self.keys.pop_front();
+                }
+                self.keys.push_back((entry, 1));
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.keys.clear();
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        self.keys
+            .iter()
+            .map(|(entry, count)| match count {
+                1 => entry.clone(),
+                _ => format!("{} x{}", entry, count),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,160 @@
+use crossterm::style::Color;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Something shown alongside a line of text: a sign in the gutter, text
+/// after the end of the line, text within it, a background tint, or any mix
+/// of them. None of it is part of the buffer.
+#[derive(Clone, Default)]
+pub struct Annotation {
+    pub sign: Option<char>,
+    pub text: Option<String>,
+    /// Text shown before the character at a byte offset of the line.
+    pub hint: Option<(usize, String)>,
+    pub tint: Option<Color>,
+}
+
+/// The annotations of a buffer, by line. Each comes from a named layer
+/// (bookmarks, script output, ...) so a source can replace its own
+/// without disturbing the others. Lines move with the text as rows are
+/// inserted and deleted.
+#[derive(Default)]
+pub struct Annotations {
+    lines: BTreeMap<usize, Vec<(&'static str, Annotation)>>,
+}
+
+impl Annotations {
+    /// Adds an annotation to `line`, replacing the one `layer` had there.
+    pub fn set(&mut self, layer: &'static str, line: usize, annotation: Annotation) {
+        let annotations = self.lines.entry(line).or_default();
+        annotations.retain(|(from, _)| *from != layer);
+        annotations.push((layer, annotation));
+    }
+
+    /// Removes `layer`'s annotation from `line`, reporting whether it had
+    /// one.
+    pub fn remove(&mut self, layer: &'static str, line: usize) -> bool {
+        let Some(annotations) = self.lines.get_mut(&line) else {
+            return false;
+        };
+        let before = annotations.len();
+        annotations.retain(|(from, _)| *from != layer);
+        let removed = annotations.len() < before;
+        if annotations.is_empty() {
+            self.lines.remove(&line);
+        }
+        removed
+    }
+
+    pub fn clear(&mut self, layer: &'static str) {
+        self.lines.retain(|_, annotations| {
+            annotations.retain(|(from, _)| *from != layer);
+            !annotations.is_empty()
+        });
+    }
+
+    /// The lines `layer` has annotated, in order.
+    pub fn lines(&self, layer: &'static str) -> Vec<usize> {
+        self.lines
+            .iter()
+            .filter(|(_, annotations)| annotations.iter().any(|(from, _)| *from == layer))
+            .map(|(&line, _)| line)
+            .collect()
+    }
+
+    /// Whether any line has a sign, so the gutter needs a sign column.
+    pub fn has_signs(&self) -> bool {
+        self.lines
+            .values()
+            .flatten()
+            .any(|(_, annotation)| annotation.sign.is_some())
+    }
+
+    fn on(&self, line: usize) -> impl Iterator<Item = &Annotation> {
+        self.lines
+            .get(&line)
+            .into_iter()
+            .flatten()
+            .map(|(_, annotation)| annotation)
+    }
+
+    /// The sign for `line`; the most recently added wins.
+    pub fn sign(&self, line: usize) -> Option<char> {
+        self.on(line).filter_map(|annotation| annotation.sign).last()
+    }
+
+    /// The tint for `line`; the most recently added wins.
+    pub fn tint(&self, line: usize) -> Option<Color> {
+        self.on(line).filter_map(|annotation| annotation.tint).last()
+    }
+
+    /// The end-of-line texts for `line`, joined.
+    pub fn text(&self, line: usize) -> Option<String> {
+        let texts: Vec<&str> = self
+            .on(line)
+            .filter_map(|annotation| annotation.text.as_deref())
+            .collect();
+        (!texts.is_empty()).then(|| texts.join("  "))
+    }
+
+    /// A line describing each annotation on `line`: its text, or else the
+    /// name of its layer.
+    pub fn describe(&self, line: usize) -> Vec<String> {
+        self.lines
+            .get(&line)
+            .into_iter()
+            .flatten()
+            .map(|(layer, annotation)| {
+                let text = annotation.text.as_deref();
+                let hint = annotation.hint.as_ref().map(|(_, hint)| hint.trim());
+                text.or(hint).unwrap_or(layer).to_string()
+            })
+            .collect()
+    }
+
+    /// The inline hints for `line`, by byte offset.
+    pub fn hints(&self, line: usize) -> Vec<(usize, &str)> {
+        let mut hints: Vec<(usize, &str)> = self
+            .on(line)
+            .filter_map(|annotation| annotation.hint.as_ref())
+            .map(|(at, text)| (*at, text.as_str()))
+            .collect();
+        hints.sort_by_key(|(at, _)| *at);
+        hints
+    }
+
+    /// Moves annotations at or below `at` down a line, for a row inserted
+    /// there.
+    pub fn insert_line(&mut self, at: usize) {
+        let moved = self.lines.split_off(&at);
+        self.lines
+            .extend(moved.into_iter().map(|(line, annotations)| (line + 1, annotations)));
+    }
+
+    /// Drops the annotations of deleted row `at` and moves those below it
+    /// up a line.
+    pub fn delete_line(&mut self, at: usize) {
+        let mut moved = self.lines.split_off(&at);
+        moved.remove(&at);
+        self.lines
+            .extend(moved.into_iter().map(|(line, annotations)| (line - 1, annotations)));
+    }
+}
+
+/// A line of checker output in the usual `file:line[:column]: message`
+/// form, as the file, the zero-based line and the message.
+pub fn parse_report(line: &str) -> Option<(PathBuf, usize, String)> {
+    let mut fields = line.splitn(3, ':');
+    let file = fields.next()?.trim();
+    let number = fields.next()?.trim().parse::<usize>().ok()?;
+    let rest = fields.next()?;
+    // Skip the column, if there is one.
+    let message = match rest.split_once(':') {
+        Some((column, message)) if column.trim().parse::<usize>().is_ok() => message,
+        _ => rest,
+    };
+    if file.is_empty() || number == 0 {
+        return None;
+    }
+    Some((file.into(), number - 1, message.trim().into()))
+}
@@ -0,0 +1,111 @@
+use std::collections::hash_map::RandomState;
+use std::fs::{self, File, OpenOptions};
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Path, PathBuf};
+use std::{env, io, process};
+
+/// Writes `path` through `write`, so that a crash part way leaves either the
+/// old file or the new one and never half of it. The new text goes to a
+/// temporary file beside it, which is synced to disk, given the old file's
+/// permissions and owner, and renamed over it. A symlink's target is what
+/// gets replaced.
+///
+/// Where no temporary file can be made (a directory we may not write to,
+/// though the file itself we may), the file is overwritten in place.
+pub fn write<T>(path: &Path, write: impl FnOnce(&mut File) -> io::Result<T>) -> io::Result<T> {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let temporary = temporary_path(&path);
+    let mut file = match OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&temporary)
+    {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => {
+            let mut file = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&path)?;
+            let result = write(&mut file)?;
+            file.sync_all()?;
+            return Ok(result);
+        }
+        Err(err) => return Err(err),
+    };
+    match replace(&mut file, &temporary, &path, write) {
+        Ok(result) => {
+            sync_directory(&path);
+            Ok(result)
+        }
+        Err(err) => {
+            drop(file);
+            let _ = fs::remove_file(&temporary);
+            Err(err)
+        }
+    }
+}
+
+/// Fills the temporary file and moves it over `path`.
+fn replace<T>(
+    file: &mut File,
+    temporary: &Path,
+    path: &Path,
+    write: impl FnOnce(&mut File) -> io::Result<T>,
+) -> io::Result<T> {
+    let result = write(file)?;
+    file.sync_all()?;
+    if let Ok(metadata) = fs::metadata(path) {
+        fs::set_permissions(temporary, metadata.permissions())?;
+        keep_owner(temporary, &metadata);
+    }
+    fs::rename(temporary, path)?;
+    Ok(result)
+}
+
+fn temporary_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.{}.tmp", name, process::id()))
+}
+
+/// Makes a new directory in the system's temporary directory that only we
+/// can use, under a name no one else can guess ahead of time. It is never
+/// one that already existed, so nothing planted there can be followed.
+pub fn private_dir(prefix: &str) -> io::Result<PathBuf> {
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    loop {
+        let salt = RandomState::new().build_hasher().finish();
+        let dir = env::temp_dir().join(format!("{}-{}-{:016x}", prefix, process::id(), salt));
+        match builder.create(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Gives `path` the owner and group in `metadata`, as far as we are
+/// allowed to: only root can give a file away, but anyone can keep one
+/// in a group they belong to.
+#[cfg(unix)]
+fn keep_owner(path: &Path, metadata: &fs::Metadata) {
+    use std::os::unix::fs::MetadataExt;
+    if std::os::unix::fs::chown(path, Some(metadata.uid()), Some(metadata.gid())).is_err() {
+        let _ = std::os::unix::fs::chown(path, None, Some(metadata.gid()));
+    }
+}
+
+#[cfg(not(unix))]
+fn keep_owner(_path: &Path, _metadata: &fs::Metadata) {}
+
+/// Makes the rename itself durable. Not every system lets a directory be
+/// opened for this, and the file is safely written either way.
+fn sync_directory(path: &Path) {
+    if let Some(directory) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        if let Ok(directory) = File::open(directory) {
+            let _ = directory.sync_all();
+        }
+    }
+}
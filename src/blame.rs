@@ -0,0 +1,137 @@
+use crate::convert;
+use crate::shell;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+/// A line for each line of the text, or why `git blame` failed.
+type Outcome = Result<Vec<String>, String>;
+
+/// Who last changed each line of a buffer and when, for `:blame`. `git
+/// blame` is run in the background on the text as it is, edits and all,
+/// and again whenever it has changed since.
+pub struct Blame {
+    /// Whether every line is shown, rather than just the cursor's.
+    pub all: bool,
+    lines: Vec<String>,
+    /// The buffer's change count `lines` are for.
+    blamed: Option<u64>,
+    /// The change count being blamed, and where the lines will come.
+    running: Option<(u64, Receiver<Outcome>)>,
+    /// The change count and cursor row last shown, to leave the
+    /// annotations be while neither moves.
+    pub shown: Option<(u64, usize)>,
+}
+
+impl Blame {
+    pub fn new(all: bool) -> Self {
+        Self {
+            all,
+            lines: Vec::new(),
+            blamed: None,
+            running: None,
+            shown: None,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.is_some()
+    }
+
+    /// Starts blaming `contents` as `file` unless it is being done or has
+    /// been done for change count `dirty`.
+    pub fn refresh(&mut self, file: &Path, dirty: u64, contents: impl FnOnce() -> String) {
+        if self.running.is_some() || self.blamed == Some(dirty) {
+            return;
+        }
+        let dir = file.parent().filter(|dir| !dir.as_os_str().is_empty());
+        let dir = dir.unwrap_or(Path::new(".")).to_string_lossy().into_owned();
+        let name = file.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let command = format!(
+            "cd {} && git blame --porcelain --contents - -- {}",
+            shell::quote(&dir),
+            shell::quote(&name)
+        );
+        let contents = contents();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let result = shell::filter(&command, &contents).map(|out| parse(&out));
+            // Why it failed says enough without the command around it.
+            let result = result.map_err(|err| match err.split_once(" failed: ") {
+                Some((_, why)) => format!("git blame: {}", why),
+                None => err,
+            });
+            let _ = sender.send(result);
+        });
+        self.running = Some((dirty, receiver));
+    }
+
+    /// Takes in the lines once `git blame` is done, giving whether they
+    /// came, or why not.
+    pub fn poll(&mut self) -> Result<bool, String> {
+        let Some((dirty, receiver)) = &self.running else {
+            return Ok(false);
+        };
+        let result = match receiver.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return Ok(false),
+            Err(TryRecvError::Disconnected) => Err("git blame stopped".into()),
+        };
+        self.blamed = Some(*dirty);
+        self.running = None;
+        self.lines = result?;
+        Ok(true)
+    }
+
+    /// Whether the lines are for the text at change count `dirty`.
+    pub fn is_current(&self, dirty: u64) -> bool {
+        self.blamed == Some(dirty)
+    }
+
+    pub fn line(&self, at: usize) -> Option<&str> {
+        self.lines.get(at).map(String::as_str)
+    }
+}
+
+/// A line for each line of the text in `git blame --porcelain` output:
+/// the author, the date and the commit.
+fn parse(porcelain: &str) -> Vec<String> {
+    #[derive(Default)]
+    struct Commit<'a> {
+        author: &'a str,
+        time: u64,
+        summary: &'a str,
+    }
+    let mut commits: HashMap<&str, Commit> = HashMap::new();
+    let mut lines = Vec::new();
+    let mut hash = "";
+    for line in porcelain.lines() {
+        if line.starts_with('\t') {
+            let commit = commits.get(hash);
+            lines.push(match commit {
+                _ if hash.bytes().all(|byte| byte == b'0') => "Not committed yet".into(),
+                Some(commit) => format!(
+                    "{}, {}, {}: {}",
+                    commit.author,
+                    &convert::utc(commit.time)[..10],
+                    &hash[..hash.len().min(7)],
+                    commit.summary
+                ),
+                None => hash[..hash.len().min(7)].into(),
+            });
+            continue;
+        }
+        let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+        match key {
+            "author" => commits.entry(hash).or_default().author = value,
+            "author-time" => commits.entry(hash).or_default().time = value.parse().unwrap_or(0),
+            "summary" => commits.entry(hash).or_default().summary = value,
+            _ if key.len() == 40 && key.bytes().all(|byte| byte.is_ascii_hexdigit()) => {
+                hash = key
+            }
+            _ => {}
+        }
+    }
+    lines
+}
@@ -0,0 +1,49 @@
+use clap::Parser;
+use std::path::PathBuf;
+
+/// A keyboard-oriented text editor for the terminal.
+#[derive(Parser)]
+#[command(name = "rustext", bin_name = "rustext", version)]
+pub struct Args {
+    /// Files to open, each as `file`, `file:line` or `file:line:column`;
+    /// `+line` or `+/regex` first opens the first file there, and `-`
+    /// reads stdin into an unnamed buffer.
+    #[arg(value_name = "FILE")]
+    pub files: Vec<String>,
+    /// Show two files side by side, lined up, with the lines that differ
+    /// marked.
+    #[arg(long, num_args = 2, value_names = ["OLD", "NEW"], conflicts_with = "files")]
+    pub diff: Option<Vec<PathBuf>>,
+    /// Open the files read-only.
+    #[arg(long)]
+    pub readonly: bool,
+    /// Write the first buffer to stdout on quitting, drawing the editor on
+    /// the terminal even when stdout is piped.
+    #[arg(long)]
+    pub stdout: bool,
+    /// Read settings from this file instead of
+    /// `~/.config/rustext/config.toml`.
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+    /// Append notifications and problems to this file as they happen.
+    #[arg(long, value_name = "FILE")]
+    pub log: Option<PathBuf>,
+    /// Write the actions keys come to, and where the cursor was, to this
+    /// file, leaving out the text.
+    #[arg(long, value_name = "FILE")]
+    pub record: Option<PathBuf>,
+    /// Perform the actions a `--record` trace holds on the files opened.
+    #[arg(long, value_name = "TRACE")]
+    pub replay: Option<PathBuf>,
+    /// With no files given, open the ones the last session in this
+    /// directory was quit with.
+    #[arg(long)]
+    pub resume: bool,
+    /// Neither read nor write what is kept between sessions.
+    #[arg(long)]
+    pub no_state: bool,
+    /// Report on the terminal, the config, the programs it runs and the
+    /// data directory, then exit.
+    #[arg(long)]
+    pub health: bool,
+}
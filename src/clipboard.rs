@@ -0,0 +1,93 @@
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+))]
+use arboard::{GetExtLinux, LinuxClipboardKind, SetExtLinux};
+
+/// Where cut and copied text goes. The system clipboard is used when
+/// there is one, so text moves to and from other applications; without a
+/// display (a console or an SSH session) text stays in an internal
+/// register instead.
+#[derive(Default)]
+pub struct Clipboard {
+    system: Option<arboard::Clipboard>,
+    connected: bool,
+    register: String,
+}
+
+impl Clipboard {
+    /// The system clipboard, connected on first use since connecting can
+    /// take a moment and most sessions never copy anything.
+    fn system(&mut self) -> Option<&mut arboard::Clipboard> {
+        if !self.connected {
+            self.connected = true;
+            self.system = arboard::Clipboard::new().ok();
+        }
+        self.system.as_mut()
+    }
+
+    pub fn set(&mut self, text: String) {
+        if let Some(system) = self.system() {
+            // Keep the register either way, in case the system clipboard
+            // can't be read back later.
+            let _ = system.set_text(text.clone());
+        }
+        self.register = text;
+    }
+
+    pub fn get(&mut self) -> String {
+        let text = match self.system().map(|system| system.get_text()) {
+            Some(Ok(text)) => text,
+            _ => self.register.clone(),
+        };
+        text.replace("\r\n", "\n")
+    }
+
+    /// Puts `text` in the primary selection, which X11 and Wayland
+    /// applications paste with the middle button.
+    #[cfg(all(
+        unix,
+        not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+    ))]
+    pub fn set_primary(&mut self, text: String) {
+        if let Some(system) = self.system() {
+            let _ = system.set().clipboard(LinuxClipboardKind::Primary).text(text);
+        }
+    }
+
+    #[cfg(all(
+        unix,
+        not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+    ))]
+    pub fn primary(&mut self) -> Option<String> {
+        let system = self.system()?;
+        let text = system.get().clipboard(LinuxClipboardKind::Primary).text().ok()?;
+        Some(text.replace("\r\n", "\n"))
+    }
+
+    /// Other systems have no primary selection.
+    #[cfg(not(all(
+        unix,
+        not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+    )))]
+    pub fn set_primary(&mut self, _text: String) {}
+
+    #[cfg(not(all(
+        unix,
+        not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+    )))]
+    pub fn primary(&mut self) -> Option<String> {
+        None
+    }
+
+    /// The text last cut or copied in the editor itself.
+    pub fn register(&self) -> &str {
+        &self.register
+    }
+
+    /// Puts back the register an earlier session left, without touching
+    /// the system clipboard.
+    pub fn restore(&mut self, text: String) {
+        self.register = text;
+    }
+}
@@ -0,0 +1,647 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::HashMap;
+
+/// A command typed at the `:` command line.
+pub enum Command {
+    Global {
+        pattern: String,
+        invert: bool,
+        action: LineAction,
+    },
+    New,
+    /// Opens a file, or switches to it if it is open already.
+    Edit {
+        file: String,
+    },
+    /// Switches to the open buffer with this name.
+    Buffer {
+        name: String,
+    },
+    BufferNext,
+    BufferPrevious,
+    BufferDelete {
+        force: bool,
+    },
+    Notes,
+    Persist,
+    Format,
+    EnclosingNode,
+    NextFunction,
+    PreviousFunction,
+    KeyEcho,
+    Files,
+    Buffers,
+    /// Lists every line in the project matching a regex.
+    Grep {
+        pattern: String,
+    },
+    /// Switches the spell checker to another dictionary, or with no
+    /// language reports the current one.
+    SpellLanguage {
+        language: Option<String>,
+    },
+    /// Downloads a dictionary, by default the current language's.
+    SpellInstall {
+        language: Option<String>,
+    },
+    /// Adds the word under the cursor to the project's word list.
+    SpellGood,
+    /// Jumps to the next misspelled word.
+    SpellNext,
+    /// Toggles typographic substitutions in the current buffer.
+    Prose,
+    /// Shows the cursor line's annotations in full.
+    Info,
+    /// Sets what the gutter numbers lines with, or with no argument moves
+    /// to the next style (off, absolute, relative).
+    Numbers {
+        style: Option<String>,
+    },
+    /// Shows the notifications of this session.
+    Notifications,
+    /// Toggles a bookmark on the cursor line.
+    Mark,
+    /// Lists the bookmarks of every open buffer.
+    Marks,
+    /// Lists the files edited in earlier sessions.
+    Recent,
+    /// Lists the themes, trying each on the text, to keep one in the
+    /// config.
+    Theme,
+    /// Lists the key binding presets likewise.
+    Keymap,
+    /// Lists every command, the user's included, to run one.
+    Commands,
+    /// Opens the help on a command, setting or action, or on all of them.
+    Help {
+        topic: Option<String>,
+    },
+    /// Runs a checker and annotates the lines it reports, or with no
+    /// command clears its annotations.
+    Annotate {
+        command: Option<String>,
+    },
+    /// Opens a picker defined in the config.
+    Pick {
+        name: String,
+    },
+    /// Puts a cursor on every match of a regex within the selection.
+    SelectMatches {
+        pattern: String,
+    },
+    /// Pipes the lines the selection spans through a shell command and
+    /// replaces them with what it prints.
+    Filter {
+        command: String,
+    },
+    /// Shows the number under the cursor in other bases, or as a date, to
+    /// replace it with one of them.
+    Convert,
+    /// Saves the file as UTF-8 from now on, whatever it was in.
+    Utf8,
+    /// Writes the block the selection spans as a table, to a file or the
+    /// clipboard.
+    Export {
+        format: String,
+        file: Option<String>,
+    },
+    /// Opens a view of another part of the buffer above the rows being
+    /// edited, from the given line or the top of the screen, or without a
+    /// line closes the open one.
+    Split {
+        line: Option<usize>,
+    },
+    /// Reloads the file whenever another program writes it, or stops.
+    Follow,
+    /// Shows who last changed the cursor line, or every line, or stops.
+    Blame {
+        all: bool,
+    },
+    /// Saves the file and runs it, showing what it prints below the text.
+    Run,
+    /// Stops the running job, if any, and closes its output pane.
+    Stop,
+    /// Filters the buffer through a pipeline typed at a prompt, previewing
+    /// the result as it is typed.
+    Pipe,
+    /// Runs a shell command, asked for if not given, on the terminal and
+    /// inserts what it prints at the cursor, or with `scratch` opens it in
+    /// a new scratch buffer.
+    Read {
+        command: Option<String>,
+        scratch: bool,
+    },
+    /// Writes the open files, where each was left and the settings changed
+    /// with `:set` to a file others can import.
+    ExportSession {
+        file: String,
+    },
+    /// Opens the files of an exported session and applies its settings.
+    ImportSession {
+        file: String,
+    },
+    /// Shows what has changed in the buffer since the editor last went
+    /// idle.
+    SinceBreak,
+    /// Shows what this session has done: keys, characters typed and
+    /// deleted, saves and time spent.
+    Stats,
+    /// Says how the buffer's last save went: when, and the bytes written
+    /// or the error.
+    LastSave,
+    /// Reads the file again in the given encoding.
+    Reopen {
+        encoding: String,
+    },
+    /// Changes a setting for this session, or without a value reports it
+    /// (or, without a name, all of them).
+    Set {
+        name: Option<String>,
+        value: Option<String>,
+    },
+}
+
+/// What the word being typed at the end of a command line names, so Tab
+/// can complete it.
+pub enum Completing {
+    Command,
+    File,
+    Buffer,
+    Picker,
+    Language,
+    Setting,
+    HelpTopic,
+    Value(&'static [&'static str]),
+}
+
+/// The built-in commands, with the arguments they take and what they do,
+/// for completion and `:help`. Long forms (`:edit`, `:buffer`) are given
+/// with the short ones.
+pub const COMMANDS: &[(&str, &str, &str)] = &[
+    ("annotate", "[command]", "Annotate the lines a checker reports, or clear them"),
+    ("b", "<name>", "Switch to an open buffer (also :buffer)"),
+    ("blame", "[all]", "Show who last changed the cursor line, or every line, or stop"),
+    ("bd", "", "Close the buffer, or with bd! even when modified (also :bdelete)"),
+    ("bn", "", "Switch to the next buffer (also :bnext)"),
+    ("bp", "", "Switch to the previous buffer (also :bprevious)"),
+    ("buffers", "", "Pick an open buffer (also :ls)"),
+    ("commands", "", "Pick a command to run, the user's own included"),
+    ("convert", "", "Show the number under the cursor in other bases or as a date"),
+    ("e", "<file>", "Open a file, or switch to it if it is open (also :edit)"),
+    ("enclosing", "", "Jump to the syntax node around the cursor"),
+    ("export", "<csv|tsv|markdown> [file]", "Write the selected columns as a table"),
+    ("files", "", "Pick a file of the project to open"),
+    ("filter", "<command>", "Replace the selected lines with a shell command's output for them"),
+    ("follow", "", "Reload the file whenever it is written elsewhere, or stop"),
+    ("format", "", "Run the formatter over the buffer"),
+    ("grep", "<regex>", "List every line in the project matching a regex"),
+    ("help", "[topic]", "Show help on a command, setting or action, or on everything"),
+    ("info", "", "Show the cursor line's annotations in full"),
+    ("keyecho", "", "Show or hide the keys as they are pressed"),
+    ("keymap", "", "Pick standard or modal keys, trying each, and keep it in the config"),
+    ("lastsave", "", "Say when the buffer was last saved, and whether it worked"),
+    ("mark", "", "Set or remove a bookmark on the cursor line"),
+    ("marks", "", "Pick a bookmark of any open buffer"),
+    ("messages", "", "Show this session's notifications (also :notifications)"),
+    ("new", "", "Open a scratch buffer"),
+    ("nextfn", "", "Jump to the next function"),
+    ("notes", "", "Open the project's notes file"),
+    ("notifications", "", "Show this session's notifications"),
+    ("numbers", "[off|absolute|relative]", "Set the line numbers, or cycle them"),
+    ("persist", "", "Append a scratch buffer to the project's notes"),
+    ("pick", "<name>", "Open a picker defined in the config"),
+    ("pipe", "", "Try out a shell pipeline on the buffer, then apply it"),
+    ("prevfn", "", "Jump to the previous function"),
+    ("prose", "", "Toggle typographic quotes and dashes"),
+    ("read", "[command]", "Insert what a shell command prints, or with read! open it apart"),
+    ("recent", "", "Pick a file edited in an earlier session"),
+    ("reopen", "<encoding>", "Read the file again as latin1, utf-16le or another encoding"),
+    ("run", "", "Save and run the file, by its #! line or its filetype's run"),
+    ("select", "<regex>", "Put a cursor on every match within the selection"),
+    ("session", "<export|import> <file>", "Write the open files for others, or open theirs"),
+    ("set", "[name[=value]]", "Change a setting, or show one or all of them"),
+    ("sincebreak", "", "Show what has changed since the last idle break"),
+    ("spellgood", "", "Add the word under the cursor to the project's word list"),
+    ("spellinstall", "[language]", "Download a dictionary"),
+    ("spelllang", "[language]", "Switch the spell checker's dictionary, or show it"),
+    ("spellnext", "", "Jump to the next misspelled word"),
+    ("split", "[line]", "View another part of the buffer above it, or close the view"),
+    ("stats", "", "Show keys, edits, saves and time active this session"),
+    ("stop", "", "Stop the job :run started and close its output"),
+    ("theme", "", "Pick a theme, trying each on the text, and keep it in the config"),
+    ("utf8", "", "Save the file as UTF-8 from now on"),
+];
+
+/// The settings `:set` knows, with the values each takes and what it
+/// does.
+pub const SETTINGS: &[(&str, &[&str], &str)] = &[
+    ("numbers", NUMBER_STYLES, "What the gutter numbers lines with"),
+    ("smartpaste", &["on", "off"], "Re-indent multi-line pastes to the cursor's line"),
+    ("checkpaste", &["on", "off"], "Say when a paste breaks a JSON, YAML or TOML file"),
+    ("checksave", CHECK_SAVE_MODES, "What saving a JSON, YAML or TOML file with errors does"),
+    ("fileencoding", ENCODINGS, "What the file is saved in (:reopen reads it as another)"),
+    ("fileformat", &["unix", "dos"], "Whether the file is saved with LF or CRLF line endings"),
+    ("autoindent", &["on", "off"], "Start new lines with the indentation of the last"),
+    ("undogroup", UNDO_GROUPS, "How much typing one undo step takes back"),
+    ("changemarks", &["head", "saved", "off"], "What the gutter marks changed lines against"),
+    ("modal", &["on", "off"], "Vi-style normal, insert and visual modes"),
+];
+
+/// The settings that belong to the current buffer rather than the
+/// session, which an exported session leaves out.
+pub const BUFFER_SETTINGS: &[&str] = &["fileencoding", "fileformat"];
+
+const NUMBER_STYLES: &[&str] = &["off", "absolute", "relative"];
+
+const CHECK_SAVE_MODES: &[&str] = &["warn", "confirm", "off"];
+
+const UNDO_GROUPS: &[&str] = &["key", "word", "pause", "command"];
+
+const ENCODINGS: &[&str] = &["utf-8", "latin1", "utf-16le", "utf-16be"];
+
+const TABLE_FORMATS: &[&str] = &["csv", "tsv", "markdown"];
+
+const SESSION_ACTIONS: &[&str] = &["export", "import"];
+
+/// What is being typed at the end of `input` and the byte offset its word
+/// starts at, or `None` for arguments that aren't completed.
+pub fn completing(input: &str) -> Option<(usize, Completing)> {
+    let Some(space) = input.find(' ') else {
+        return Some((0, Completing::Command));
+    };
+    let start = space + 1;
+    let completing = match &input[..space] {
+        "e" | "edit" => Completing::File,
+        "b" | "buffer" => Completing::Buffer,
+        "pick" => Completing::Picker,
+        "spelllang" => Completing::Language,
+        "help" => Completing::HelpTopic,
+        "numbers" => Completing::Value(NUMBER_STYLES),
+        "reopen" => Completing::Value(ENCODINGS),
+        "export" if !input[start..].contains(' ') => Completing::Value(TABLE_FORMATS),
+        "session" => match input[start..].split_once(' ') {
+            None => Completing::Value(SESSION_ACTIONS),
+            Some((action, _)) => return Some((start + action.len() + 1, Completing::File)),
+        },
+        "set" => match input[start..].split_once('=') {
+            None => Completing::Setting,
+            Some((name, _)) => {
+                let (_, values, _) = SETTINGS.iter().find(|(setting, ..)| *setting == name)?;
+                return Some((start + name.len() + 1, Completing::Value(values)));
+            }
+        },
+        _ => return None,
+    };
+    Some((start, completing))
+}
+
+/// What a global command does to each selected line.
+pub enum LineAction {
+    Delete,
+    Substitute {
+        from: String,
+        to: String,
+        all: bool,
+    },
+    Normal(Vec<KeyEvent>),
+}
+
+impl Command {
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let input = input.trim_start_matches(':').trim();
+        match input {
+            "new" => return Ok(Command::New),
+            "e" | "edit" => return Err("Usage: edit <file>".into()),
+            "b" | "buffer" => return Err("Usage: buffer <name>".into()),
+            "bn" | "bnext" => return Ok(Command::BufferNext),
+            "bp" | "bprevious" => return Ok(Command::BufferPrevious),
+            "bd" | "bdelete" => return Ok(Command::BufferDelete { force: false }),
+            "bd!" | "bdelete!" => return Ok(Command::BufferDelete { force: true }),
+            "notes" => return Ok(Command::Notes),
+            "persist" => return Ok(Command::Persist),
+            "format" => return Ok(Command::Format),
+            "enclosing" => return Ok(Command::EnclosingNode),
+            "nextfn" => return Ok(Command::NextFunction),
+            "prevfn" => return Ok(Command::PreviousFunction),
+            "keyecho" => return Ok(Command::KeyEcho),
+            "files" => return Ok(Command::Files),
+            "ls" | "buffers" => return Ok(Command::Buffers),
+            "select" => return Err("Usage: select <regex>".into()),
+            "filter" => return Err("Usage: filter <command>".into()),
+            "grep" => return Err("Usage: grep <regex>".into()),
+            "export" => return Err("Usage: export <csv|tsv|markdown> [file]".into()),
+            "pick" => return Err("Usage: pick <name>".into()),
+            "session" => return Err("Usage: session <export|import> <file>".into()),
+            "spelllang" => return Ok(Command::SpellLanguage { language: None }),
+            "spellinstall" => return Ok(Command::SpellInstall { language: None }),
+            "spellgood" => return Ok(Command::SpellGood),
+            "spellnext" => return Ok(Command::SpellNext),
+            "prose" => return Ok(Command::Prose),
+            "numbers" => return Ok(Command::Numbers { style: None }),
+            "info" => return Ok(Command::Info),
+            "notifications" | "messages" => return Ok(Command::Notifications),
+            "mark" => return Ok(Command::Mark),
+            "marks" => return Ok(Command::Marks),
+            "recent" => return Ok(Command::Recent),
+            "theme" => return Ok(Command::Theme),
+            "keymap" => return Ok(Command::Keymap),
+            "commands" => return Ok(Command::Commands),
+            "convert" => return Ok(Command::Convert),
+            "utf8" => return Ok(Command::Utf8),
+            "split" => return Ok(Command::Split { line: None }),
+            "follow" => return Ok(Command::Follow),
+            "blame" => return Ok(Command::Blame { all: false }),
+            "blame all" => return Ok(Command::Blame { all: true }),
+            "run" => return Ok(Command::Run),
+            "stop" => return Ok(Command::Stop),
+            "pipe" => return Ok(Command::Pipe),
+            "read" | "read!" => {
+                return Ok(Command::Read {
+                    command: None,
+                    scratch: input.ends_with('!'),
+                })
+            }
+            "sincebreak" => return Ok(Command::SinceBreak),
+            "stats" => return Ok(Command::Stats),
+            "lastsave" => return Ok(Command::LastSave),
+            "help" => return Ok(Command::Help { topic: None }),
+            "annotate" => return Ok(Command::Annotate { command: None }),
+            "set" => {
+                return Ok(Command::Set {
+                    name: None,
+                    value: None,
+                });
+            }
+            _ => {}
+        }
+        if let Some(file) = input
+            .strip_prefix("e ")
+            .or_else(|| input.strip_prefix("edit "))
+        {
+            return Ok(Command::Edit {
+                file: file.trim().into(),
+            });
+        }
+        if let Some(name) = input
+            .strip_prefix("b ")
+            .or_else(|| input.strip_prefix("buffer "))
+        {
+            return Ok(Command::Buffer {
+                name: name.trim().into(),
+            });
+        }
+        if let Some(setting) = input.strip_prefix("set ") {
+            let (name, value) = match setting.split_once('=') {
+                Some((name, value)) => (name, Some(value.trim().into())),
+                None => (setting, None),
+            };
+            return Ok(Command::Set {
+                name: Some(name.trim().into()),
+                value,
+            });
+        }
+        if let Some(rest) = input.strip_prefix("session ") {
+            let (action, file) = rest.trim().split_once(' ').unwrap_or((rest.trim(), ""));
+            let file = file.trim().to_string();
+            return match action {
+                _ if file.is_empty() => Err("Usage: session <export|import> <file>".into()),
+                "export" => Ok(Command::ExportSession { file }),
+                "import" => Ok(Command::ImportSession { file }),
+                _ => Err("Usage: session <export|import> <file>".into()),
+            };
+        }
+        if let Some(encoding) = input.strip_prefix("reopen ") {
+            return Ok(Command::Reopen {
+                encoding: encoding.trim().into(),
+            });
+        }
+        if let Some(pattern) = input.strip_prefix("select ") {
+            return Ok(Command::SelectMatches {
+                pattern: pattern.into(),
+            });
+        }
+        if let Some(command) = input.strip_prefix("filter ") {
+            return Ok(Command::Filter {
+                command: command.trim().into(),
+            });
+        }
+        if let Some(pattern) = input.strip_prefix("grep ") {
+            return Ok(Command::Grep {
+                pattern: pattern.into(),
+            });
+        }
+        if let Some(language) = input.strip_prefix("spelllang ") {
+            return Ok(Command::SpellLanguage {
+                language: Some(language.trim().into()),
+            });
+        }
+        if let Some(language) = input.strip_prefix("spellinstall ") {
+            return Ok(Command::SpellInstall {
+                language: Some(language.trim().into()),
+            });
+        }
+        if let Some(style) = input.strip_prefix("numbers ") {
+            return Ok(Command::Numbers {
+                style: Some(style.trim().into()),
+            });
+        }
+        if let Some(line) = input.strip_prefix("split ") {
+            return match line.trim().parse::<usize>() {
+                Ok(line) if line > 0 => Ok(Command::Split { line: Some(line) }),
+                _ => Err("Usage: split [line]".into()),
+            };
+        }
+        if let Some(rest) = input.strip_prefix("export ") {
+            let rest = rest.trim();
+            let (format, file) = match rest.split_once(' ') {
+                Some((format, file)) => (format, Some(file.trim().into())),
+                None => (rest, None),
+            };
+            return Ok(Command::Export {
+                format: format.into(),
+                file,
+            });
+        }
+        if let Some(command) = input.strip_prefix("read! ") {
+            return Ok(Command::Read {
+                command: Some(command.trim().into()),
+                scratch: true,
+            });
+        }
+        if let Some(command) = input.strip_prefix("read ") {
+            return Ok(Command::Read {
+                command: Some(command.trim().into()),
+                scratch: false,
+            });
+        }
+        if let Some(command) = input.strip_prefix("annotate ") {
+            return Ok(Command::Annotate {
+                command: Some(command.trim().into()),
+            });
+        }
+        if let Some(topic) = input.strip_prefix("help ") {
+            return Ok(Command::Help {
+                topic: Some(topic.trim().trim_start_matches(':').into()),
+            });
+        }
+        if let Some(name) = input.strip_prefix("pick ") {
+            return Ok(Command::Pick {
+                name: name.trim().into(),
+            });
+        }
+        let (invert, rest) = if let Some(rest) = input.strip_prefix("g!") {
+            (true, rest)
+        } else if let Some(rest) = input.strip_prefix('g') {
+            (false, rest)
+        } else if let Some(rest) = input.strip_prefix('v') {
+            (true, rest)
+        } else {
+            return Err(format!("Not an editor command: {}", input));
+        };
+        let mut fields = split_delimited(rest, 2)?;
+        if fields.len() != 2 {
+            return Err("Usage: g/pattern/command".into());
+        }
+        let action = fields.pop().unwrap();
+        let pattern = fields.pop().unwrap();
+        if pattern.is_empty() {
+            return Err("Empty pattern".into());
+        }
+        Ok(Command::Global {
+            pattern,
+            invert,
+            action: LineAction::parse(action.trim())?,
+        })
+    }
+}
+
+impl LineAction {
+    fn parse(input: &str) -> Result<Self, String> {
+        if input == "d" || input == "delete" {
+            return Ok(LineAction::Delete);
+        }
+        if let Some(rest) = input.strip_prefix("normal ") {
+            return parse_keys(rest).map(LineAction::Normal);
+        }
+        if let Some(rest) = input.strip_prefix('s') {
+            let fields = split_delimited(rest, 3)?;
+            let (from, to, flags) = match fields.as_slice() {
+                [from, to] => (from, to, ""),
+                [from, to, flags] => (from, to, flags.as_str()),
+                _ => return Err("Usage: s/from/to/[g]".into()),
+            };
+            if from.is_empty() {
+                return Err("Empty pattern".into());
+            }
+            if !flags.is_empty() && flags != "g" {
+                return Err(format!("Unknown flags: {}", flags));
+            }
+            return Ok(LineAction::Substitute {
+                from: from.clone(),
+                to: to.clone(),
+                all: flags == "g",
+            });
+        }
+        Err(format!("Unsupported command: '{}'", input))
+    }
+}
+
+/// `input` with its first word replaced by what it is an alias for, if it
+/// is one.
+pub fn expand_alias(input: &str, aliases: &HashMap<String, String>) -> String {
+    let input = input.trim_start_matches(':').trim();
+    let (name, rest) = input.split_once(' ').unwrap_or((input, ""));
+    match aliases.get(name) {
+        Some(command) if rest.is_empty() => command.clone(),
+        Some(command) => format!("{} {}", command, rest),
+        None => input.into(),
+    }
+}
+
+/// `text` from a user command with its arguments filled in: `{args}` for
+/// all of them, `{1}` to `{9}` for each word, and `{file}` for the current
+/// file's name.
+pub fn substitute(text: &str, args: &str, file: &str) -> String {
+    let words: Vec<&str> = args.split_whitespace().collect();
+    let mut text = text.replace("{args}", args).replace("{file}", file);
+    for number in 1..=9 {
+        let word = words.get(number - 1).copied().unwrap_or("");
+        text = text.replace(&format!("{{{}}}", number), word);
+    }
+    text
+}
+
+/// Whether the command line `line` hands the rest of it to the shell, so
+/// that what is filled into it has to be quoted.
+pub fn runs_shell(line: &str) -> bool {
+    let name = line.split_whitespace().next().unwrap_or_default();
+    matches!(name, "filter" | "read" | "read!" | "annotate")
+}
+
+/// Splits `/a/b/c` into at most `max` fields, honouring `\/` escapes. The
+/// delimiter is whatever character follows the command name; the last field
+/// is kept verbatim so it can be parsed as a command of its own.
+fn split_delimited(input: &str, max: usize) -> Result<Vec<String>, String> {
+    let mut chars = input.chars();
+    let delimiter = match chars.next() {
+        Some(ch) if !ch.is_alphanumeric() && !ch.is_whitespace() => ch,
+        _ => return Err("Missing delimiter".into()),
+    };
+    let mut fields = vec![String::new()];
+    let mut escaped = false;
+    for ch in chars {
+        let last = fields.len() == max;
+        let field = fields.last_mut().unwrap();
+        if last {
+            field.push(ch);
+        } else if escaped {
+            if ch != delimiter {
+                field.push('\\');
+            }
+            field.push(ch);
+            escaped = false;
+        } else if ch == '\\' {
+            escaped = true;
+        } else if ch == delimiter {
+            fields.push(String::new());
+        } else {
+            field.push(ch);
+        }
+    }
+    if escaped {
+        fields.last_mut().unwrap().push('\\');
+    }
+    Ok(fields)
+}
+
+/// Parses key notation such as `A<BS><End>;` into key events.
+pub fn parse_keys(input: &str) -> Result<Vec<KeyEvent>, String> {
+    let mut keys = Vec::new();
+    let mut rest = input;
+    while let Some(ch) = rest.chars().next() {
+        if ch == '<' {
+            if let Some(end) = rest.find('>') {
+                let code = match rest[1..end].to_ascii_lowercase().as_str() {
+                    "cr" | "enter" => KeyCode::Enter,
+                    "bs" => KeyCode::Backspace,
+                    "del" => KeyCode::Delete,
+                    "tab" => KeyCode::Tab,
+                    "home" => KeyCode::Home,
+                    "end" => KeyCode::End,
+                    "left" => KeyCode::Left,
+                    "right" => KeyCode::Right,
+                    "up" => KeyCode::Up,
+                    "down" => KeyCode::Down,
+                    "lt" => KeyCode::Char('<'),
+                    name => return Err(format!("Unknown key: <{}>", name)),
+                };
+                keys.push(KeyEvent::new(code, KeyModifiers::NONE));
+                rest = &rest[end + 1..];
+                continue;
+            }
+        }
+        keys.push(KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE));
+        rest = &rest[ch.len_utf8()..];
+    }
+    Ok(keys)
+}
@@ -0,0 +1,129 @@
+use crate::popup::{Anchor, Popup};
+use std::fs;
+use std::path::PathBuf;
+
+/// The most candidates the menu shows at once.
+const MENU_ROWS: usize = 8;
+
+/// The candidates being cycled through at a prompt: what replaces the
+/// input from byte `start` on, and which one is in now.
+pub struct Menu {
+    pub start: usize,
+    candidates: Vec<String>,
+    selected: Option<usize>,
+}
+
+impl Menu {
+    /// The menu, a window of candidates around the selected one shown
+    /// above the prompt from `column` on.
+    pub fn popup(&self, column: usize) -> Popup {
+        let first = self.selected.unwrap_or(0).saturating_sub(MENU_ROWS - 1);
+        let lines = self
+            .candidates
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(MENU_ROWS)
+            .map(|(index, candidate)| {
+                let marker = if Some(index) == self.selected { '>' } else { ' ' };
+                format!("{} {}", marker, candidate)
+            })
+            .collect();
+        let popup = Popup::new(Anchor::Bottom(column), lines);
+        match (self.selected, self.candidates.len()) {
+            (_, count) if count <= MENU_ROWS => popup,
+            (Some(selected), count) => popup.titled(&format!("{}/{}", selected + 1, count)),
+            (None, count) => popup.titled(&count.to_string()),
+        }
+    }
+}
+
+/// Tab (or Shift-Tab, going `forward` or not) at a prompt: the first press
+/// completes `input` as far as the candidates `complete` finds agree and
+/// opens a menu of them, and further presses put each in turn. Reports
+/// whether the prompt completes at all, so Tab is typed where it doesn't.
+pub fn tab(
+    menu: &mut Option<Menu>,
+    input: &mut String,
+    forward: bool,
+    complete: impl FnOnce(&str) -> Option<(usize, Vec<String>)>,
+) -> bool {
+    if let Some(menu) = menu {
+        let count = menu.candidates.len();
+        let selected = match (menu.selected, forward) {
+            (None, true) => 0,
+            (None, false) => count - 1,
+            (Some(selected), true) => (selected + 1) % count,
+            (Some(selected), false) => (selected + count - 1) % count,
+        };
+        menu.selected = Some(selected);
+        input.truncate(menu.start);
+        input.push_str(&menu.candidates[selected]);
+        return true;
+    }
+    let Some((start, candidates)) = complete(input) else {
+        return false;
+    };
+    if let Some(first) = candidates.first() {
+        let common = candidates.iter().fold(first.as_str(), |common, candidate| {
+            let len = common
+                .char_indices()
+                .zip(candidate.chars())
+                .take_while(|((_, a), b)| a == b)
+                .last()
+                .map_or(0, |((at, ch), _)| at + ch.len_utf8());
+            &common[..len]
+        });
+        input.truncate(start);
+        input.push_str(common);
+    }
+    if candidates.len() > 1 {
+        *menu = Some(Menu {
+            start,
+            candidates,
+            selected: None,
+        });
+    }
+    true
+}
+
+/// Those of `candidates` that start with `word`, sorted and without
+/// duplicates.
+pub fn matching(word: &str, candidates: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut matching: Vec<String> = candidates
+        .into_iter()
+        .filter(|candidate| candidate.starts_with(word))
+        .collect();
+    matching.sort();
+    matching.dedup();
+    matching
+}
+
+/// The paths `word` could be completed to: entries of the directory it
+/// names (the working directory if none, with `~/` for home) that start
+/// with its last part. Directories end in `/`, and hidden entries only
+/// come up once their `.` is typed.
+pub fn paths(word: &str) -> Vec<String> {
+    let (dir, name) = match word.rfind('/') {
+        Some(slash) => (&word[..=slash], &word[slash + 1..]),
+        None => ("", word),
+    };
+    let path = match dir.strip_prefix("~/") {
+        Some(rest) => crate::config::home_dir().join(rest),
+        None if dir.is_empty() => PathBuf::from("."),
+        None => PathBuf::from(dir),
+    };
+    let Ok(entries) = fs::read_dir(path) else {
+        return Vec::new();
+    };
+    let candidates = entries.filter_map(|entry| {
+        let entry = entry.ok()?;
+        let file_name = entry.file_name().into_string().ok()?;
+        if file_name.starts_with('.') && !name.starts_with('.') {
+            return None;
+        }
+        let slash = if entry.path().is_dir() { "/" } else { "" };
+        Some(format!("{}{}{}", dir, file_name, slash))
+    });
+    matching(word, candidates)
+}
@@ -0,0 +1,867 @@
+use crate::action::{Action, Keymap};
+use crate::atomic;
+use crate::filetype::FileType;
+use crate::palette::Depth;
+use crate::undo;
+use crate::theme::ColorNames;
+use crossterm::style::Color;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{env, fs, io};
+
+pub const PROJECT_CONFIG_FILE: &str = ".rustext.toml";
+const TRUST_FILE: &str = "trusted-projects";
+
+/// Settings as they appear in a config file. Every field is optional so a
+/// project file only has to mention what it wants to override.
+#[derive(Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    tab_stop: Option<usize>,
+    expand_tab: Option<bool>,
+    indent_width: Option<usize>,
+    formatter: Option<String>,
+    undo_max_entries: Option<usize>,
+    undo_max_bytes: Option<usize>,
+    undo_group: Option<UndoGroup>,
+    /// Milliseconds without typing that end an undo step with
+    /// `undo_group = "pause"`.
+    undo_pause: Option<u64>,
+    change_marks: Option<ChangeMarks>,
+    theme: Option<String>,
+    smart_paste: Option<bool>,
+    check_paste: Option<bool>,
+    check_save: Option<CheckSave>,
+    primary_selection: Option<bool>,
+    auto_indent: Option<bool>,
+    spell_language: Option<String>,
+    prose: Option<bool>,
+    line_numbers: Option<LineNumbers>,
+    modal: Option<bool>,
+    smooth_scroll: Option<bool>,
+    color_depth: Option<Depth>,
+    trailing_newline: Option<bool>,
+    resume: Option<bool>,
+    stats_on_exit: Option<bool>,
+    stats_log: Option<bool>,
+    terminal_title: Option<bool>,
+    /// Megabytes past which opening a file asks whether to load it all,
+    /// read it as needed or only look at its start and end; 0 never asks.
+    confirm_open_mb: Option<u64>,
+    /// Seconds without a keypress after which modified files are saved;
+    /// 0 turns autosaving off.
+    autosave: Option<u64>,
+    /// Seconds without a keypress after which the buffers are snapshotted
+    /// for `:sincebreak`; 0 turns it off.
+    idle_snapshot: Option<u64>,
+    backup: Option<bool>,
+    backup_dir: Option<String>,
+    #[serde(default)]
+    hooks: Hooks,
+    #[serde(default)]
+    keys: HashMap<String, String>,
+    #[serde(default)]
+    colors: ColorNames,
+    #[serde(default)]
+    status_bar: StatusBarFile,
+    #[serde(default)]
+    filetype: HashMap<String, FileTypeSettings>,
+    #[serde(default)]
+    picker: HashMap<String, PickerSource>,
+    #[serde(default)]
+    commands: HashMap<String, Vec<CommandStep>>,
+    #[serde(default)]
+    aliases: HashMap<String, String>,
+}
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Hooks {
+    /// Shell command run after every successful save; `{file}` is replaced
+    /// by the saved path.
+    pub on_save: Option<String>,
+}
+
+pub fn parse_color(name: &str) -> Option<Color> {
+    match name.strip_prefix('#') {
+        Some(hex) if hex.len() == 6 => {
+            let channel = |at: usize| u8::from_str_radix(hex.get(at..at + 2)?, 16).ok();
+            Some(Color::Rgb {
+                r: channel(0)?,
+                g: channel(2)?,
+                b: channel(4)?,
+            })
+        }
+        Some(_) => None,
+        None => Color::try_from(name).ok(),
+    }
+}
+
+/// The `[status_bar]` table: what the status bar shows besides the file
+/// name and position.
+#[derive(Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+struct StatusBarFile {
+    filetype: Option<bool>,
+    column: Option<bool>,
+    branch: Option<bool>,
+    last_save: Option<bool>,
+}
+
+pub struct StatusBar {
+    /// Show the name of the file's type.
+    pub filetype: bool,
+    /// Show the cursor's column next to its line.
+    pub column: bool,
+    /// Show the git branch, marked when the work tree has changes.
+    pub branch: bool,
+    /// Show whether the buffer's last save worked, and how long ago.
+    pub last_save: bool,
+}
+
+/// Overrides for one filetype, from a `[filetype.<name>]` table.
+#[derive(Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct FileTypeSettings {
+    /// Characters besides letters, digits and `_` that belong to words.
+    pub word_chars: Option<String>,
+    pub tab_stop: Option<usize>,
+    pub expand_tab: Option<bool>,
+    pub indent_width: Option<usize>,
+    /// Shell command `:run` runs files without a `#!` line with; `{file}`
+    /// is replaced by the file's path.
+    pub run: Option<String>,
+    /// Shell command run before saving, given the text about to be saved
+    /// on stdin and as `{file}`, a copy of it. If it fails, the lines it
+    /// reports as `file:line: message` are marked, as `check_save` says.
+    pub lint: Option<String>,
+}
+
+/// What the gutter shows left of each line.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LineNumbers {
+    Off,
+    Absolute,
+    /// The distance from the cursor's line, which shows its own number.
+    Relative,
+}
+
+impl LineNumbers {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "off" => Some(LineNumbers::Off),
+            "absolute" => Some(LineNumbers::Absolute),
+            "relative" => Some(LineNumbers::Relative),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LineNumbers::Off => "off",
+            LineNumbers::Absolute => "absolute",
+            LineNumbers::Relative => "relative",
+        }
+    }
+}
+
+/// How saving a JSON, YAML or TOML file with a syntax error, or a file its
+/// filetype's `lint` command fails on, is handled.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckSave {
+    Off,
+    /// Mark the error's line and say what it is, then save.
+    Warn,
+    /// As `Warn`, but ask before saving.
+    Confirm,
+}
+
+impl CheckSave {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "off" => Some(CheckSave::Off),
+            "warn" => Some(CheckSave::Warn),
+            "confirm" => Some(CheckSave::Confirm),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CheckSave::Off => "off",
+            CheckSave::Warn => "warn",
+            CheckSave::Confirm => "confirm",
+        }
+    }
+}
+
+/// How far a run of typing (characters, Enter, Tab, Backspace and Delete)
+/// goes into one undo step. Any other key ends the step.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum UndoGroup {
+    /// Every key is a step of its own.
+    Key,
+    /// A step ends where a new word starts.
+    Word,
+    /// A step ends when typing stops for `undo_pause`.
+    Pause,
+    /// A step lasts until some other key is pressed.
+    Command,
+}
+
+impl UndoGroup {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "key" => Some(UndoGroup::Key),
+            "word" => Some(UndoGroup::Word),
+            "pause" => Some(UndoGroup::Pause),
+            "command" => Some(UndoGroup::Command),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            UndoGroup::Key => "key",
+            UndoGroup::Word => "word",
+            UndoGroup::Pause => "pause",
+            UndoGroup::Command => "command",
+        }
+    }
+
+    /// Whether typing `next` joins the step typing `last` was in, `since`
+    /// after it.
+    pub fn joins(self, last: Action, since: Duration, pause: Duration, next: Action) -> bool {
+        let word = |action| {
+            matches!(action, Action::InsertChar(ch) if ch.is_alphanumeric() || ch == '_')
+        };
+        match self {
+            UndoGroup::Key => false,
+            UndoGroup::Word => word(last) || !word(next),
+            UndoGroup::Pause => since < pause,
+            UndoGroup::Command => true,
+        }
+    }
+}
+
+/// What the gutter marks lines as added, changed or removed against.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeMarks {
+    /// The file as committed at git's HEAD, or for files git doesn't
+    /// track, as last saved.
+    Head,
+    /// The file as last saved.
+    Saved,
+    Off,
+}
+
+impl ChangeMarks {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "head" => Some(ChangeMarks::Head),
+            "saved" => Some(ChangeMarks::Saved),
+            "off" => Some(ChangeMarks::Off),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ChangeMarks::Head => "head",
+            ChangeMarks::Saved => "saved",
+            ChangeMarks::Off => "off",
+        }
+    }
+}
+
+/// A picker fed by shell commands, from a `[picker.<name>]` table. In
+/// `preview` and `accept`, `{item}` is replaced by the highlighted item.
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PickerSource {
+    /// Command printing the items, one per line.
+    pub items: String,
+    /// Command whose output previews an item.
+    pub preview: Option<String>,
+    /// Command run on the chosen item. Without one, items are file names
+    /// (optionally followed by `:line`) and the chosen file is opened.
+    pub accept: Option<String>,
+}
+
+/// A step of a user command from the `[commands]` table: an action by
+/// name (as `[keys]` names them), a command line starting with `:`, or
+/// `{ insert = "text" }`. Commands and text can use the command's
+/// arguments, as `{args}` or one at a time as `{1}` to `{9}`, and the
+/// current file as `{file}`.
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum CommandStep {
+    Run(String),
+    Insert { insert: String },
+}
+
+impl CommandStep {
+    /// A `:` command line, which might run shell commands.
+    fn command_line(&self) -> Option<&str> {
+        match self {
+            CommandStep::Run(run) => run.strip_prefix(':'),
+            CommandStep::Insert { .. } => None,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            CommandStep::Run(run) => run.clone(),
+            CommandStep::Insert { insert } => format!("insert {:?}", insert),
+        }
+    }
+}
+
+impl FileTypeSettings {
+    fn merge(self, over: Self) -> Self {
+        Self {
+            word_chars: over.word_chars.or(self.word_chars),
+            tab_stop: over.tab_stop.or(self.tab_stop),
+            expand_tab: over.expand_tab.or(self.expand_tab),
+            indent_width: over.indent_width.or(self.indent_width),
+            run: over.run.or(self.run),
+            lint: over.lint.or(self.lint),
+        }
+    }
+}
+
+impl ConfigFile {
+    fn read(path: &Path) -> Result<Option<(Self, String)>, String> {
+        match fs::read_to_string(path) {
+            Ok(text) => toml::from_str(&text)
+                .map(|config| Some((config, text)))
+                .map_err(|err| format!("{}: {}", path.display(), err.message())),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(format!("{}: {}", path.display(), err)),
+        }
+    }
+
+    /// Settings that make the editor run shell commands.
+    fn is_sensitive(&self) -> bool {
+        self.formatter.is_some()
+            || self.hooks.on_save.is_some()
+            || !self.picker.is_empty()
+            || !self.aliases.is_empty()
+            || self.filetype.values().any(|settings| settings.run.is_some())
+            || self.filetype.values().any(|settings| settings.lint.is_some())
+            || self.commands.values().flatten().any(|step| step.command_line().is_some())
+    }
+
+    /// The shell commands the file would run, one per line, for review.
+    fn commands(&self) -> Vec<String> {
+        let mut commands = Vec::new();
+        commands.extend(self.formatter.iter().map(|command| format!("formatter: {}", command)));
+        commands.extend(self.hooks.on_save.iter().map(|command| format!("on_save: {}", command)));
+        let mut pickers: Vec<_> = self.picker.iter().collect();
+        pickers.sort_by_key(|(name, _)| name.as_str());
+        for (name, source) in pickers {
+            commands.push(format!("picker.{}: {}", name, source.items));
+            for command in source.preview.iter().chain(&source.accept) {
+                commands.push(format!("picker.{}: {}", name, command));
+            }
+        }
+        let mut filetypes: Vec<_> = self.filetype.iter().collect();
+        filetypes.sort_by_key(|(name, _)| name.as_str());
+        for (name, settings) in filetypes {
+            if let Some(command) = &settings.run {
+                commands.push(format!("filetype.{}.run: {}", name, command));
+            }
+            if let Some(command) = &settings.lint {
+                commands.push(format!("filetype.{}.lint: {}", name, command));
+            }
+        }
+        let mut user_commands: Vec<_> = self.commands.iter().collect();
+        user_commands.sort_by_key(|(name, _)| name.as_str());
+        for (name, steps) in user_commands {
+            for command in steps.iter().filter_map(CommandStep::command_line) {
+                commands.push(format!("commands.{}: :{}", name, command));
+            }
+        }
+        let mut aliases: Vec<_> = self.aliases.iter().collect();
+        aliases.sort();
+        for (name, command) in aliases {
+            commands.push(format!("aliases.{}: :{}", name, command));
+        }
+        commands
+    }
+
+    fn without_sensitive(self) -> Self {
+        Self {
+            formatter: None,
+            hooks: Hooks::default(),
+            picker: HashMap::new(),
+            commands: self
+                .commands
+                .into_iter()
+                .filter(|(_, steps)| steps.iter().all(|step| step.command_line().is_none()))
+                .collect(),
+            aliases: HashMap::new(),
+            filetype: self
+                .filetype
+                .into_iter()
+                .map(|(name, settings)| {
+                    let settings = FileTypeSettings {
+                        run: None,
+                        lint: None,
+                        ..settings
+                    };
+                    (name, settings)
+                })
+                .collect(),
+            ..self
+        }
+    }
+
+    fn merge(self, over: Self) -> Self {
+        Self {
+            tab_stop: over.tab_stop.or(self.tab_stop),
+            expand_tab: over.expand_tab.or(self.expand_tab),
+            indent_width: over.indent_width.or(self.indent_width),
+            formatter: over.formatter.or(self.formatter),
+            undo_max_entries: over.undo_max_entries.or(self.undo_max_entries),
+            undo_max_bytes: over.undo_max_bytes.or(self.undo_max_bytes),
+            undo_group: over.undo_group.or(self.undo_group),
+            undo_pause: over.undo_pause.or(self.undo_pause),
+            change_marks: over.change_marks.or(self.change_marks),
+            theme: over.theme.or(self.theme),
+            smart_paste: over.smart_paste.or(self.smart_paste),
+            check_paste: over.check_paste.or(self.check_paste),
+            check_save: over.check_save.or(self.check_save),
+            primary_selection: over.primary_selection.or(self.primary_selection),
+            auto_indent: over.auto_indent.or(self.auto_indent),
+            spell_language: over.spell_language.or(self.spell_language),
+            prose: over.prose.or(self.prose),
+            line_numbers: over.line_numbers.or(self.line_numbers),
+            modal: over.modal.or(self.modal),
+            smooth_scroll: over.smooth_scroll.or(self.smooth_scroll),
+            color_depth: over.color_depth.or(self.color_depth),
+            trailing_newline: over.trailing_newline.or(self.trailing_newline),
+            resume: over.resume.or(self.resume),
+            stats_on_exit: over.stats_on_exit.or(self.stats_on_exit),
+            stats_log: over.stats_log.or(self.stats_log),
+            terminal_title: over.terminal_title.or(self.terminal_title),
+            autosave: over.autosave.or(self.autosave),
+            idle_snapshot: over.idle_snapshot.or(self.idle_snapshot),
+            confirm_open_mb: over.confirm_open_mb.or(self.confirm_open_mb),
+            backup: over.backup.or(self.backup),
+            backup_dir: over.backup_dir.or(self.backup_dir),
+            keys: {
+                let mut keys = self.keys;
+                keys.extend(over.keys);
+                keys
+            },
+            colors: self.colors.merge(over.colors),
+            status_bar: StatusBarFile {
+                filetype: over.status_bar.filetype.or(self.status_bar.filetype),
+                column: over.status_bar.column.or(self.status_bar.column),
+                branch: over.status_bar.branch.or(self.status_bar.branch),
+                last_save: over.status_bar.last_save.or(self.status_bar.last_save),
+            },
+            hooks: Hooks {
+                on_save: over.hooks.on_save.or(self.hooks.on_save),
+            },
+            filetype: {
+                let mut filetype = self.filetype;
+                for (name, settings) in over.filetype {
+                    let merged = filetype.remove(&name).unwrap_or_default().merge(settings);
+                    filetype.insert(name, merged);
+                }
+                filetype
+            },
+            picker: {
+                let mut picker = self.picker;
+                picker.extend(over.picker);
+                picker
+            },
+            commands: {
+                let mut commands = self.commands;
+                commands.extend(over.commands);
+                commands
+            },
+            aliases: {
+                let mut aliases = self.aliases;
+                aliases.extend(over.aliases);
+                aliases
+            },
+        }
+    }
+
+    fn check(&self, errors: &mut Vec<String>) {
+        for name in self.filetype.keys() {
+            if !FileType::is_known(name) {
+                errors.push(format!("Unknown filetype: {}", name));
+            }
+        }
+        for name in self.colors.names() {
+            if parse_color(name).is_none() {
+                errors.push(format!("Unknown color: {}", name));
+            }
+        }
+        Keymap::new(&self.keys, errors);
+        for (name, steps) in &self.commands {
+            // Built-in commands are all lowercase, so these can't clash.
+            if !name.starts_with(|ch: char| ch.is_ascii_uppercase())
+                || !name.chars().all(|ch| ch.is_ascii_alphanumeric())
+            {
+                errors.push(format!("Command names start with a capital letter: {}", name));
+            }
+            for step in steps {
+                if let CommandStep::Run(run) = step {
+                    if !run.starts_with(':') && Action::from_name(run).is_none() {
+                        errors.push(format!("Unknown action in command {}: {}", name, run));
+                    }
+                }
+            }
+        }
+        for name in self.aliases.keys() {
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                errors.push(format!("Invalid alias: {:?}", name));
+            }
+        }
+    }
+}
+
+pub struct Config {
+    pub tab_stop: usize,
+    pub expand_tab: bool,
+    pub indent_width: usize,
+    pub formatter: Option<String>,
+    /// Undo steps kept per buffer before the oldest are merged.
+    pub undo_max_entries: usize,
+    /// Memory the undo history of a buffer may use before the oldest
+    /// steps are dropped.
+    pub undo_max_bytes: usize,
+    /// What typing is gathered into undo steps by.
+    pub undo_group: UndoGroup,
+    pub undo_pause: Duration,
+    /// What the gutter marks changed lines against.
+    pub change_marks: ChangeMarks,
+    /// Name of the theme: a theme file in the themes directory, or else a
+    /// syntax highlighting theme.
+    pub theme: Option<String>,
+    /// Whether multi-line pastes are re-indented to the cursor's line.
+    pub smart_paste: bool,
+    /// Whether pastes into JSON, YAML and TOML files are checked for
+    /// breaking the document.
+    pub check_paste: bool,
+    /// What saving a JSON, YAML or TOML file that doesn't parse does.
+    pub check_save: CheckSave,
+    /// Whether mouse selections go to the primary selection, for the
+    /// middle button to paste.
+    pub primary_selection: bool,
+    /// Whether Enter carries the line's indentation over to the new one.
+    pub auto_indent: bool,
+    /// Dictionary the spell checker starts with.
+    pub spell_language: String,
+    /// Whether text files start in prose mode, with curly quotes and
+    /// dashes typed for their straight counterparts.
+    pub prose: bool,
+    pub line_numbers: LineNumbers,
+    /// Whether editing starts in vi-style normal mode.
+    pub modal: bool,
+    /// Whether page and search jumps scroll in a few steps.
+    pub smooth_scroll: bool,
+    /// How many colours the terminal shows, when the config says.
+    pub color_depth: Option<Depth>,
+    /// Whether saved files end with a newline, when the config says;
+    /// otherwise each file keeps what it had.
+    pub trailing_newline: Option<bool>,
+    /// Whether starting without files opens those last left open in the
+    /// directory, as `--resume` does.
+    pub resume: bool,
+    /// Whether quitting prints a summary of the session's keys, edits,
+    /// saves and time active.
+    pub stats_on_exit: bool,
+    /// Whether each session's figures are appended to `stats.jsonl` in the
+    /// data directory.
+    pub stats_log: bool,
+    /// Whether the window title shows the current buffer's name and the
+    /// terminal is told its directory (OSC 7).
+    pub terminal_title: bool,
+    /// How long the editor sits idle before saving modified files.
+    pub autosave: Option<Duration>,
+    /// How long the editor sits idle before snapshotting the buffers, to
+    /// show what changed over the break.
+    pub idle_snapshot: Option<Duration>,
+    /// Size in bytes past which opening a file asks how to open it.
+    pub confirm_open: Option<u64>,
+    /// Whether saving first copies the file as it was to a backup.
+    pub backup: bool,
+    /// Where backups go, when not beside the file.
+    pub backup_dir: Option<PathBuf>,
+    pub hooks: Hooks,
+    pub keymap: Keymap,
+    /// Colours set over the theme's.
+    pub colors: ColorNames,
+    pub status_bar: StatusBar,
+    /// Per-filetype settings, keyed by filetype name.
+    pub filetypes: HashMap<String, FileTypeSettings>,
+    /// Pickers defined by the user, keyed by name.
+    pub pickers: HashMap<String, PickerSource>,
+    /// Commands defined by the user, keyed by name.
+    pub commands: HashMap<String, Vec<CommandStep>>,
+    /// Other names for commands: the first word of a command line is
+    /// replaced by what it is an alias for.
+    pub aliases: HashMap<String, String>,
+}
+
+impl From<ConfigFile> for Config {
+    fn from(file: ConfigFile) -> Self {
+        Self {
+            tab_stop: file.tab_stop.filter(|&n| n > 0).unwrap_or(crate::TAB_STOP),
+            expand_tab: file.expand_tab.unwrap_or(false),
+            indent_width: file.indent_width.filter(|&n| n > 0).unwrap_or(4),
+            formatter: file.formatter,
+            undo_max_entries: file.undo_max_entries.unwrap_or(undo::MAX_ENTRIES),
+            undo_max_bytes: file.undo_max_bytes.unwrap_or(undo::MAX_BYTES),
+            undo_group: file.undo_group.unwrap_or(UndoGroup::Key),
+            undo_pause: Duration::from_millis(file.undo_pause.unwrap_or(1000)),
+            change_marks: file.change_marks.unwrap_or(ChangeMarks::Head),
+            theme: file.theme,
+            smart_paste: file.smart_paste.unwrap_or(true),
+            check_paste: file.check_paste.unwrap_or(true),
+            check_save: file.check_save.unwrap_or(CheckSave::Warn),
+            primary_selection: file.primary_selection.unwrap_or(false),
+            auto_indent: file.auto_indent.unwrap_or(true),
+            spell_language: file.spell_language.unwrap_or_else(|| "en".into()),
+            prose: file.prose.unwrap_or(false),
+            line_numbers: file.line_numbers.unwrap_or(LineNumbers::Off),
+            modal: file.modal.unwrap_or(false),
+            smooth_scroll: file.smooth_scroll.unwrap_or(false),
+            color_depth: file.color_depth,
+            trailing_newline: file.trailing_newline,
+            resume: file.resume.unwrap_or(false),
+            stats_on_exit: file.stats_on_exit.unwrap_or(false),
+            stats_log: file.stats_log.unwrap_or(false),
+            terminal_title: file.terminal_title.unwrap_or(true),
+            autosave: file
+                .autosave
+                .filter(|&seconds| seconds > 0)
+                .map(Duration::from_secs),
+            idle_snapshot: file
+                .idle_snapshot
+                .filter(|&seconds| seconds > 0)
+                .map(Duration::from_secs),
+            confirm_open: Some(file.confirm_open_mb.unwrap_or(256))
+                .filter(|&megabytes| megabytes > 0)
+                .map(|megabytes| megabytes * 1024 * 1024),
+            backup: file.backup.unwrap_or(false),
+            backup_dir: file.backup_dir.map(|dir| match dir.strip_prefix("~/") {
+                Some(rest) => home_dir().join(rest),
+                None => PathBuf::from(dir),
+            }),
+            hooks: file.hooks,
+            // Mistakes were reported by `check` when the file was read.
+            keymap: Keymap::new(&file.keys, &mut Vec::new()),
+            colors: file.colors,
+            status_bar: StatusBar {
+                filetype: file.status_bar.filetype.unwrap_or(false),
+                column: file.status_bar.column.unwrap_or(false),
+                branch: file.status_bar.branch.unwrap_or(true),
+                last_save: file.status_bar.last_save.unwrap_or(false),
+            },
+            filetypes: file.filetype,
+            pickers: file.picker,
+            commands: file.commands,
+            aliases: file.aliases,
+        }
+    }
+}
+
+/// A project config whose shell-running settings were held back until the
+/// user agrees to trust it.
+pub struct PendingTrust {
+    pub path: PathBuf,
+    hash: String,
+    user: ConfigFile,
+    project: ConfigFile,
+}
+
+impl PendingTrust {
+    /// The shell commands trusting the project would allow.
+    pub fn commands(&self) -> Vec<String> {
+        self.project.commands()
+    }
+
+    /// Records the project as trusted and returns the full merged config.
+    pub fn accept(self) -> io::Result<Config> {
+        let store = data_dir().join(TRUST_FILE);
+        fs::create_dir_all(data_dir())?;
+        let mut trusted = fs::read_to_string(&store).unwrap_or_default();
+        trusted.push_str(&format!("{} {}\n", self.hash, self.path.display()));
+        fs::write(store, trusted)?;
+        Ok(self.user.merge(self.project).into())
+    }
+}
+
+pub struct LoadedConfig {
+    pub config: Config,
+    pub pending_trust: Option<PendingTrust>,
+    pub errors: Vec<String>,
+}
+
+impl Config {
+    /// Loads the user config, from `file` if given, and merges the
+    /// project's `.rustext.toml` over it. Formatter and hook settings from
+    /// an untrusted project file are left out and returned as
+    /// `pending_trust` instead.
+    pub fn load(file: Option<&Path>) -> LoadedConfig {
+        let mut errors = Vec::new();
+        let file = file.map_or_else(|| config_dir().join("config.toml"), Path::to_path_buf);
+        let user = match ConfigFile::read(&file) {
+            Ok(file) => file.map(|(config, _)| config).unwrap_or_default(),
+            Err(err) => {
+                errors.push(err);
+                ConfigFile::default()
+            }
+        };
+        user.check(&mut errors);
+        let path = crate::project_root().join(PROJECT_CONFIG_FILE);
+        let (project, text) = match ConfigFile::read(&path) {
+            Ok(Some(file)) => file,
+            result => {
+                if let Err(err) = result {
+                    errors.push(err);
+                }
+                return LoadedConfig {
+                    config: user.into(),
+                    pending_trust: None,
+                    errors,
+                };
+            }
+        };
+        project.check(&mut errors);
+        let hash = content_hash(&text);
+        if !project.is_sensitive() || is_trusted(&path, &hash) {
+            return LoadedConfig {
+                config: user.merge(project).into(),
+                pending_trust: None,
+                errors,
+            };
+        }
+        LoadedConfig {
+            config: user.clone().merge(project.clone().without_sensitive()).into(),
+            pending_trust: Some(PendingTrust {
+                path,
+                hash,
+                user,
+                project,
+            }),
+            errors,
+        }
+    }
+
+    /// Where saving `file` keeps its previous version: `file~` beside it,
+    /// or in the backup directory under its whole path with each `/` as
+    /// `%`, so files of the same name in different places don't collide.
+    pub fn backup_path(&self, file: &Path) -> PathBuf {
+        let file = absolute(file);
+        match &self.backup_dir {
+            Some(dir) => {
+                let name = file.to_string_lossy().replace(std::path::MAIN_SEPARATOR, "%");
+                dir.join(format!("{}~", name))
+            }
+            None => {
+                let name = file.file_name().unwrap_or_default().to_string_lossy();
+                file.with_file_name(format!("{}~", name))
+            }
+        }
+    }
+}
+
+/// The SHA-256 of `text` in hex, so a file can't be edited to look like
+/// one accepted earlier.
+fn content_hash(text: &str) -> String {
+    hex(&Sha256::digest(text.as_bytes()))
+}
+
+/// A project file is trusted if this exact content was accepted before;
+/// editing it means asking again.
+fn is_trusted(path: &Path, hash: &str) -> bool {
+    let entry = format!("{} {}", hash, path.display());
+    fs::read_to_string(data_dir().join(TRUST_FILE))
+        .map(|trusted| trusted.lines().any(|line| line == entry))
+        .unwrap_or(false)
+}
+
+pub fn home_dir() -> PathBuf {
+    env::var_os("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Sets the top-level `key` to `value`, written as TOML, in the user's
+/// config file (`file` if given), leaving the rest of the file as it is:
+/// an existing setting is replaced, keeping any comment after it, or else
+/// one is added after the other top-level settings. Returns the file
+/// written.
+pub fn write_setting(file: Option<&Path>, key: &str, value: &str) -> io::Result<PathBuf> {
+    let path = file.map_or_else(|| config_dir().join("config.toml"), Path::to_path_buf);
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err),
+    };
+    let invalid = |err: toml_edit::TomlError| io::Error::new(io::ErrorKind::InvalidData, err);
+    let mut document: toml_edit::DocumentMut = text.parse().map_err(invalid)?;
+    let mut value: toml_edit::Value = value.parse().map_err(invalid)?;
+    match document.get_mut(key).and_then(toml_edit::Item::as_value_mut) {
+        Some(old) => {
+            *value.decor_mut() = old.decor().clone();
+            *old = value;
+        }
+        None => document[key] = toml_edit::value(value),
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let text = document.to_string();
+    atomic::write(&path, |file| file.write_all(text.as_bytes()))?;
+    Ok(path)
+}
+
+pub fn config_dir() -> PathBuf {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home_dir().join(".config"))
+        .join("rustext")
+}
+
+pub fn data_dir() -> PathBuf {
+    env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home_dir().join(".local/share"))
+        .join("rustext")
+}
+
+pub fn absolute(file: &Path) -> PathBuf {
+    fs::canonicalize(file).unwrap_or_else(|_| {
+        env::current_dir()
+            .map(|dir| dir.join(file))
+            .unwrap_or_else(|_| file.to_path_buf())
+    })
+}
+
+/// A short, filesystem-safe name for per-file state kept in the data
+/// directory: the first half of the SHA-256 of its absolute path, which
+/// stays the same across builds and Rust versions.
+pub fn path_key(file: &Path) -> String {
+    hex(&Sha256::digest(absolute(file).as_os_str().as_encoded_bytes())[..16])
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
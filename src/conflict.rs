@@ -0,0 +1,80 @@
+use std::ops::Range;
+
+/// Which side of a merge conflict to keep.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Side {
+    Ours,
+    Theirs,
+    Both,
+    /// The common ancestor, which only diff3-style conflicts show.
+    Base,
+}
+
+/// A merge conflict as git leaves it in a file: a `<<<<<<<` row, our side,
+/// optionally a `|||||||` row and the common base, a `=======` row, their
+/// side and a `>>>>>>>` row. Ranges are of rows.
+pub struct Conflict {
+    pub start: usize,
+    pub ours: Range<usize>,
+    pub base: Option<Range<usize>>,
+    pub theirs: Range<usize>,
+    pub end: usize,
+}
+
+impl Conflict {
+    pub fn contains(&self, row: usize) -> bool {
+        (self.start..=self.end).contains(&row)
+    }
+
+    /// The rows left once resolved by keeping `side`, or `None` when it
+    /// has no base to keep.
+    pub fn kept(&self, side: Side) -> Option<Vec<usize>> {
+        Some(match side {
+            Side::Ours => self.ours.clone().collect(),
+            Side::Theirs => self.theirs.clone().collect(),
+            Side::Both => self.ours.clone().chain(self.theirs.clone()).collect(),
+            Side::Base => self.base.clone()?.collect(),
+        })
+    }
+}
+
+/// Whether `line` is a conflict marker: the marker, then nothing or a
+/// space and a label such as `HEAD`.
+fn is_marker(line: &str, marker: &str) -> bool {
+    line.strip_prefix(marker)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(' '))
+}
+
+/// The conflicts among `lines`, in order. Markers that don't make up a
+/// whole conflict are left alone.
+pub fn find(lines: impl Iterator<Item = impl AsRef<str>>) -> Vec<Conflict> {
+    let mut conflicts = Vec::new();
+    // The `<<<<<<<`, `|||||||` and `=======` rows of the conflict so far.
+    let mut open: Option<(usize, Option<usize>, Option<usize>)> = None;
+    for (at, line) in lines.enumerate() {
+        let line = line.as_ref();
+        if is_marker(line, "<<<<<<<") {
+            open = Some((at, None, None));
+            continue;
+        }
+        let Some((start, base, middle)) = &mut open else {
+            continue;
+        };
+        match *middle {
+            None if base.is_none() && is_marker(line, "|||||||") => *base = Some(at),
+            None if is_marker(line, "=======") => *middle = Some(at),
+            Some(middle) if is_marker(line, ">>>>>>>") => {
+                conflicts.push(Conflict {
+                    start: *start,
+                    ours: *start + 1..base.unwrap_or(middle),
+                    base: base.map(|base| base + 1..middle),
+                    theirs: middle + 1..at,
+                    end: at,
+                });
+                open = None;
+            }
+            _ => {}
+        }
+    }
+    conflicts
+}
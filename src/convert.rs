@@ -0,0 +1,89 @@
+use std::process::Command;
+
+/// Numbers between these (2000 to 2100, in seconds since the epoch) are
+/// offered as dates too; so are the same times in milliseconds.
+const EARLIEST_TIMESTAMP: u64 = 946_684_800;
+const LATEST_TIMESTAMP: u64 = 4_102_444_800;
+
+/// The other ways of writing `word` if it is a number, decimal or with a
+/// `0x`, `0o` or `0b` prefix: in each of those bases and, when it looks
+/// like a Unix timestamp, as a date in UTC and in local time.
+pub fn conversions(word: &str) -> Option<Vec<(&'static str, String)>> {
+    let value = parse(word)?;
+    let mut conversions = vec![
+        ("dec", value.to_string()),
+        ("hex", format!("{:#x}", value)),
+        ("oct", format!("{:#o}", value)),
+        ("bin", format!("{:#b}", value)),
+    ];
+    let timestamp = |seconds: u64| (EARLIEST_TIMESTAMP..LATEST_TIMESTAMP).contains(&seconds);
+    let seconds = if timestamp(value) {
+        Some(value)
+    } else if timestamp(value / 1000) {
+        Some(value / 1000)
+    } else {
+        None
+    };
+    if let Some(seconds) = seconds {
+        conversions.push(("utc", format!("{} UTC", utc(seconds))));
+        if let Some(local) = local(seconds) {
+            conversions.push(("local", local));
+        }
+    }
+    Some(conversions)
+}
+
+fn parse(word: &str) -> Option<u64> {
+    let word = word.replace('_', "");
+    let prefixed = |prefixes: [&str; 2]| prefixes.iter().find_map(|p| word.strip_prefix(p));
+    if let Some(digits) = prefixed(["0x", "0X"]) {
+        u64::from_str_radix(digits, 16).ok()
+    } else if let Some(digits) = prefixed(["0o", "0O"]) {
+        u64::from_str_radix(digits, 8).ok()
+    } else if let Some(digits) = prefixed(["0b", "0B"]) {
+        u64::from_str_radix(digits, 2).ok()
+    } else if word.bytes().all(|byte| byte.is_ascii_digit()) {
+        word.parse().ok()
+    } else {
+        None
+    }
+}
+
+/// `seconds` since the epoch as a UTC date and time.
+pub fn utc(seconds: u64) -> String {
+    let days = (seconds / 86_400) as i64;
+    let time = seconds % 86_400;
+    // Howard Hinnant's days-to-civil algorithm.
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        time / 3_600,
+        time % 3_600 / 60,
+        time % 60
+    )
+}
+
+/// `seconds` since the epoch in local time, as `date` shows it: GNU's
+/// takes the time with `-d`, BSD's with `-r`.
+fn local(seconds: u64) -> Option<String> {
+    let format = "+%Y-%m-%d %H:%M:%S %z";
+    [["-d".to_string(), format!("@{}", seconds)], ["-r".to_string(), seconds.to_string()]]
+        .into_iter()
+        .find_map(|args| {
+            let output = Command::new("date").args(args).arg(format).output().ok()?;
+            let text = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            (output.status.success() && !text.is_empty()).then_some(text)
+        })
+}
@@ -0,0 +1,103 @@
+use crate::popup::{Anchor, Popup};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Answer {
+    Yes,
+    No,
+    Cancel,
+}
+
+const ANSWERS: [(Answer, &str); 3] = [
+    (Answer::Yes, "Yes"),
+    (Answer::No, "No"),
+    (Answer::Cancel, "Cancel"),
+];
+
+/// A question answered by picking one of its buttons, shown in a popup.
+/// Keys pick a button directly (its first letter, or Esc for the one that
+/// changes nothing), or move between the buttons (arrows, Tab) and Enter
+/// picks the highlighted one. The button that changes nothing is
+/// highlighted to begin with, so a stray Enter is harmless too.
+pub struct Dialog {
+    title: String,
+    lines: Vec<String>,
+    labels: Vec<&'static str>,
+    /// The button Esc picks and that starts out highlighted.
+    harmless: usize,
+    selected: usize,
+}
+
+impl Dialog {
+    /// A question answered with Yes, No or Cancel.
+    pub fn new(title: &str, lines: Vec<String>) -> Self {
+        Self::choice(title, lines, ANSWERS.map(|(_, label)| label).to_vec(), ANSWERS.len() - 1)
+    }
+
+    /// A choice between `labels`, of which the one at `harmless` leaves
+    /// things as they are. Its answer is the index of the label picked.
+    pub fn choice(
+        title: &str,
+        lines: Vec<String>,
+        labels: Vec<&'static str>,
+        harmless: usize,
+    ) -> Self {
+        Self {
+            title: title.into(),
+            lines,
+            labels,
+            harmless,
+            selected: harmless,
+        }
+    }
+
+    pub fn popup(&self) -> Popup {
+        let buttons: Vec<String> = self
+            .labels
+            .iter()
+            .enumerate()
+            .map(|(i, label)| {
+                if i == self.selected {
+                    format!("[ {} ]", label)
+                } else {
+                    format!("  {}  ", label)
+                }
+            })
+            .collect();
+        let mut lines = self.lines.clone();
+        lines.push(String::new());
+        lines.push(buttons.join(" "));
+        Popup::new(Anchor::Center, lines).titled(&self.title)
+    }
+
+    /// Handles a key, giving the index of the button once one is picked.
+    pub fn key(&mut self, key: KeyEvent) -> Option<usize> {
+        if key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) {
+            return None;
+        }
+        let count = self.labels.len();
+        match key.code {
+            KeyCode::Char(ch) => {
+                let ch = ch.to_ascii_lowercase();
+                let initial = |label: &str| label.chars().next().map(|c| c.to_ascii_lowercase());
+                self.labels.iter().position(|label| initial(label) == Some(ch))
+            }
+            KeyCode::Esc => Some(self.harmless),
+            KeyCode::Enter => Some(self.selected),
+            KeyCode::Left | KeyCode::BackTab => {
+                self.selected = (self.selected + count - 1) % count;
+                None
+            }
+            KeyCode::Right | KeyCode::Tab => {
+                self.selected = (self.selected + 1) % count;
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The answer picked in a dialog made by `Dialog::new`.
+pub fn answer(index: usize) -> Answer {
+    ANSWERS[index].0
+}
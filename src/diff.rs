@@ -0,0 +1,192 @@
+use std::ops::Range;
+
+/// Diffs needing more edits than this are shown as one big change, since
+/// finding the shortest edit script costs memory in the square of it.
+const MAX_EDITS: usize = 2000;
+
+/// A run of changed lines: `old` lines of the first text were replaced by
+/// `new` lines of the second. Either range may be empty.
+pub struct Hunk {
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+}
+
+/// The changes turning `old` into `new`, line by line, in order.
+pub fn diff(old: &[&str], new: &[&str]) -> Vec<Hunk> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+    if old_middle.is_empty() && new_middle.is_empty() {
+        return Vec::new();
+    }
+    let hunks = shortest_edit(old_middle, new_middle).unwrap_or_else(|| {
+        vec![Hunk {
+            old: 0..old_middle.len(),
+            new: 0..new_middle.len(),
+        }]
+    });
+    hunks
+        .into_iter()
+        .map(|hunk| Hunk {
+            old: hunk.old.start + prefix..hunk.old.end + prefix,
+            new: hunk.new.start + prefix..hunk.new.end + prefix,
+        })
+        .collect()
+}
+
+/// A row of a side-by-side diff: the line of each text shown on it, or
+/// `None` across from a line only the other text has.
+pub struct Pair {
+    pub old: Option<usize>,
+    pub new: Option<usize>,
+    pub changed: bool,
+}
+
+/// `old` and `new` lined up to be shown side by side, the lines they share
+/// across from each other. Changed lines are paired in order, and those
+/// left over go across from blanks.
+pub fn side_by_side(old: &[&str], new: &[&str]) -> Vec<Pair> {
+    let mut pairs = Vec::new();
+    let same = |x, y| Pair {
+        old: Some(x),
+        new: Some(y),
+        changed: false,
+    };
+    let (mut x, mut y) = (0, 0);
+    for hunk in diff(old, new) {
+        pairs.extend((x..hunk.old.start).zip(y..).map(|(x, y)| same(x, y)));
+        for at in 0..hunk.old.len().max(hunk.new.len()) {
+            let line = |lines: &Range<usize>| (at < lines.len()).then(|| lines.start + at);
+            pairs.push(Pair {
+                old: line(&hunk.old),
+                new: line(&hunk.new),
+                changed: true,
+            });
+        }
+        (x, y) = (hunk.old.end, hunk.new.end);
+    }
+    pairs.extend((x..old.len()).zip(y..).map(|(x, y)| same(x, y)));
+    pairs
+}
+
+/// Where each run of changed rows in `pairs` starts.
+pub fn changes(pairs: &[Pair]) -> Vec<usize> {
+    (0..pairs.len())
+        .filter(|&at| pairs[at].changed && (at == 0 || !pairs[at - 1].changed))
+        .collect()
+}
+
+/// Myers' algorithm: the furthest each diagonal reaches with `d` edits is
+/// kept for every `d`, then walked back from the end to find the edits.
+fn shortest_edit(old: &[&str], new: &[&str]) -> Option<Vec<Hunk>> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    for d in 0..=MAX_EDITS as isize {
+        let mut reach = vec![0; 2 * d as usize + 1];
+        for k in (-d..=d).step_by(2) {
+            let previous = |k: isize| trace[(d - 1) as usize][(k + d - 1) as usize];
+            let mut x = if d == 0 {
+                0
+            } else if k == -d || (k != d && previous(k - 1) < previous(k + 1)) {
+                previous(k + 1)
+            } else {
+                previous(k - 1) + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            reach[(k + d) as usize] = x;
+            if x >= n && y >= m {
+                trace.push(reach);
+                return Some(backtrack(&trace, n, m));
+            }
+        }
+        trace.push(reach);
+    }
+    None
+}
+
+fn backtrack(trace: &[Vec<isize>], n: isize, m: isize) -> Vec<Hunk> {
+    let mut hunks: Vec<Hunk> = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for d in (1..trace.len() as isize).rev() {
+        let previous = &trace[(d - 1) as usize];
+        let k = x - y;
+        let at = |k: isize| previous[(k + d - 1) as usize];
+        let k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let (from_x, from_y) = (at(k), at(k) - k);
+        // Skip the unchanged lines, then take the one edit before them.
+        let diagonal = (x - from_x).min(y - from_y);
+        x -= diagonal;
+        y -= diagonal;
+        let (old, new) = ((from_x as usize)..(x as usize), (from_y as usize)..(y as usize));
+        match hunks.last_mut() {
+            Some(last) if last.old.start == old.end && last.new.start == new.end => {
+                last.old.start = old.start;
+                last.new.start = new.start;
+            }
+            _ => hunks.push(Hunk { old, new }),
+        }
+        (x, y) = (from_x, from_y);
+    }
+    hunks.reverse();
+    hunks
+}
+
+/// `old` and `new` as a unified diff, each change shown with `context`
+/// unchanged lines around it and changes that close merged.
+pub fn unified(old: &[&str], new: &[&str], context: usize) -> Vec<String> {
+    let hunks = diff(old, new);
+    let mut lines = Vec::new();
+    let mut at = 0;
+    while at < hunks.len() {
+        // Take every following hunk whose context would touch this one's.
+        let mut last = at;
+        while last + 1 < hunks.len()
+            && hunks[last + 1].old.start - hunks[last].old.end <= 2 * context
+        {
+            last += 1;
+        }
+        let old_start = hunks[at].old.start.saturating_sub(context);
+        let new_start = hunks[at].new.start - (hunks[at].old.start - old_start);
+        let old_end = (hunks[last].old.end + context).min(old.len());
+        let new_end = hunks[last].new.end + (old_end - hunks[last].old.end);
+        lines.push(format!(
+            "@@ -{} +{} @@",
+            header_range(old_start, old_end),
+            header_range(new_start, new_end)
+        ));
+        let mut line = old_start;
+        for hunk in &hunks[at..=last] {
+            lines.extend(old[line..hunk.old.start].iter().map(|text| format!(" {}", text)));
+            lines.extend(old[hunk.old.clone()].iter().map(|text| format!("-{}", text)));
+            lines.extend(new[hunk.new.clone()].iter().map(|text| format!("+{}", text)));
+            line = hunk.old.end;
+        }
+        lines.extend(old[line..old_end].iter().map(|text| format!(" {}", text)));
+        at = last + 1;
+    }
+    lines
+}
+
+/// A range of lines as a unified diff header gives it: first line
+/// (counting from 1) and length.
+fn header_range(start: usize, end: usize) -> String {
+    match end - start {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        len => format!("{},{}", start + 1, len),
+    }
+}
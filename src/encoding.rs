@@ -0,0 +1,134 @@
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// How much of a file the UTF-16 guess looks at.
+const SNIFF_BYTES: usize = 4096;
+
+/// What a file's bytes are in, which saving writes them back in.
+#[derive(Clone, Copy, PartialEq)]
+pub struct FileEncoding {
+    pub encoding: &'static Encoding,
+    /// Whether the file starts with a byte order mark.
+    pub bom: bool,
+}
+
+impl Default for FileEncoding {
+    fn default() -> Self {
+        Self {
+            encoding: UTF_8,
+            bom: false,
+        }
+    }
+}
+
+impl FileEncoding {
+    /// Works out what `bytes` are in. A byte order mark says; without one
+    /// they are UTF-8 if they are valid as that, UTF-16 if every other byte
+    /// is zero (as in mostly-ASCII UTF-16 text), and otherwise
+    /// Windows-1252, the superset of Latin-1 that browsers read it as.
+    pub fn detect(bytes: &[u8]) -> Self {
+        if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+            return Self {
+                encoding,
+                bom: true,
+            };
+        }
+        let encoding = if std::str::from_utf8(bytes).is_ok() {
+            UTF_8
+        } else {
+            utf16_guess(&bytes[..bytes.len().min(SNIFF_BYTES)]).unwrap_or(WINDOWS_1252)
+        };
+        Self {
+            encoding,
+            bom: false,
+        }
+    }
+
+    /// The encoding a label such as `latin1` or `utf-16le` names, as the
+    /// WHATWG Encoding Standard has them, without a byte order mark.
+    pub fn for_label(label: &str) -> Option<Self> {
+        Some(Self {
+            encoding: Encoding::for_label(label.as_bytes())?,
+            bom: false,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.encoding.name()
+    }
+
+    pub fn is_utf16(&self) -> bool {
+        self.encoding == UTF_16LE || self.encoding == UTF_16BE
+    }
+
+    /// What a saved file starts with.
+    pub fn bom_bytes(&self) -> &'static [u8] {
+        match (self.bom, self.encoding.name()) {
+            (false, _) => b"",
+            (true, "UTF-16LE") => b"\xFF\xFE",
+            (true, "UTF-16BE") => b"\xFE\xFF",
+            (true, _) => b"\xEF\xBB\xBF",
+        }
+    }
+
+    /// `bytes` as text, without a byte order mark. Bytes that aren't valid
+    /// in the encoding become U+FFFD.
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        let bytes = bytes.strip_prefix(self.bom_bytes()).unwrap_or(bytes);
+        let (text, _) = self.encoding.decode_without_bom_handling(bytes);
+        text.into_owned()
+    }
+
+    /// Like `decode`, but `None` if some of `bytes` aren't valid in the
+    /// encoding, rather than putting U+FFFD in their place.
+    pub fn decode_exact(&self, bytes: &[u8]) -> Option<String> {
+        let bytes = bytes.strip_prefix(self.bom_bytes()).unwrap_or(bytes);
+        let (text, had_errors) = self.encoding.decode_without_bom_handling(bytes);
+        (!had_errors).then(|| text.into_owned())
+    }
+
+    /// Whether the encoding has a character for everything in `text`.
+    pub fn fits(&self, text: &str) -> bool {
+        self.is_utf16() || !self.encoding.encode(text).2
+    }
+
+    /// `text` in this encoding; characters it has none for come out as
+    /// HTML character references, so check `fits` first.
+    pub fn encode<'a>(&self, text: &'a str) -> Cow<'a, [u8]> {
+        let units = |to_bytes: fn(u16) -> [u8; 2]| {
+            text.encode_utf16().flat_map(to_bytes).collect::<Vec<u8>>()
+        };
+        match self.encoding.name() {
+            "UTF-16LE" => Cow::Owned(units(u16::to_le_bytes)),
+            "UTF-16BE" => Cow::Owned(units(u16::to_be_bytes)),
+            _ => self.encoding.encode(text).0,
+        }
+    }
+}
+
+/// Reads a file, working out what it is in unless `encoding` says.
+pub fn read(path: &Path, encoding: Option<FileEncoding>) -> io::Result<(String, FileEncoding)> {
+    let bytes = fs::read(path)?;
+    let encoding = encoding.unwrap_or_else(|| FileEncoding::detect(&bytes));
+    Ok((encoding.decode(&bytes), encoding))
+}
+
+/// UTF-16 if the zero bytes of `bytes` are nearly all at odd offsets (a
+/// little-endian ASCII character is the byte then a zero) or nearly all at
+/// even ones.
+fn utf16_guess(bytes: &[u8]) -> Option<&'static Encoding> {
+    if bytes.len() < 2 || !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    let zeros = |parity: usize| bytes.iter().skip(parity).step_by(2).filter(|&&b| b == 0).count();
+    let (even, odd) = (zeros(0), zeros(1));
+    let units = bytes.len() / 2;
+    match (even, odd) {
+        (even, odd) if odd * 2 > units && even * 10 < units => Some(UTF_16LE),
+        (even, odd) if even * 2 > units && odd * 10 < units => Some(UTF_16BE),
+        _ => None,
+    }
+}
@@ -0,0 +1,268 @@
+use crate::config::{Config, FileTypeSettings};
+use std::path::Path;
+
+/// What the editor knows about a kind of file. Settings here are the
+/// defaults; a `[filetype.<name>]` table in the config overrides them.
+pub struct FileType {
+    pub name: &'static str,
+    extensions: &'static [&'static str],
+    /// Characters besides letters, digits and `_` that belong to words.
+    word_chars: &'static str,
+    /// Whether files of this type are prose rather than code.
+    prose: bool,
+}
+
+const FILETYPES: &[FileType] = &[
+    FileType {
+        name: "rust",
+        extensions: &["rs"],
+        word_chars: "",
+        prose: false,
+    },
+    FileType {
+        name: "c",
+        extensions: &["c", "h", "cc", "cpp", "hpp"],
+        word_chars: "",
+        prose: false,
+    },
+    FileType {
+        name: "python",
+        extensions: &["py"],
+        word_chars: "",
+        prose: false,
+    },
+    FileType {
+        name: "javascript",
+        extensions: &["js", "mjs", "ts", "jsx", "tsx"],
+        word_chars: "$",
+        prose: false,
+    },
+    FileType {
+        name: "css",
+        extensions: &["css", "scss", "less"],
+        word_chars: "-",
+        prose: false,
+    },
+    FileType {
+        name: "html",
+        extensions: &["html", "htm", "xml"],
+        word_chars: "-",
+        prose: false,
+    },
+    FileType {
+        name: "ruby",
+        extensions: &["rb"],
+        word_chars: ":?!",
+        prose: false,
+    },
+    FileType {
+        name: "lisp",
+        extensions: &["lisp", "el", "clj", "scm"],
+        word_chars: "-?!*<>=/+",
+        prose: false,
+    },
+    FileType {
+        name: "shell",
+        extensions: &["sh", "bash", "zsh"],
+        word_chars: "$",
+        prose: false,
+    },
+    FileType {
+        name: "markdown",
+        extensions: &["md", "markdown"],
+        word_chars: "",
+        prose: true,
+    },
+    FileType {
+        name: "toml",
+        extensions: &["toml"],
+        word_chars: "-",
+        prose: false,
+    },
+    FileType {
+        name: "json",
+        extensions: &["json"],
+        word_chars: "",
+        prose: false,
+    },
+    FileType {
+        name: "yaml",
+        extensions: &["yaml", "yml"],
+        word_chars: "-",
+        prose: false,
+    },
+    FileType {
+        name: "text",
+        extensions: &["txt", "text", "rst", "adoc"],
+        word_chars: "",
+        prose: true,
+    },
+];
+
+impl FileType {
+    pub fn detect(file: &Path) -> Option<&'static FileType> {
+        let extension = file.extension()?.to_str()?;
+        FILETYPES
+            .iter()
+            .find(|filetype| filetype.extensions.contains(&extension))
+    }
+
+    pub fn is_known(name: &str) -> bool {
+        FILETYPES.iter().any(|filetype| filetype.name == name)
+    }
+
+    /// Whether prose mode may be used for `file`: anything but code,
+    /// including files of no known type such as commit messages.
+    pub fn allows_prose(file: Option<&Path>) -> bool {
+        file.and_then(FileType::detect)
+            .is_none_or(|filetype| filetype.prose)
+    }
+}
+
+/// How a file is indented: how wide tabs are drawn, and whether Tab types
+/// spaces instead (up to the next multiple of `indent_width`).
+#[derive(Clone, Copy)]
+pub struct Indentation {
+    pub tab_stop: usize,
+    pub expand_tab: bool,
+    pub indent_width: usize,
+}
+
+impl Indentation {
+    /// The indentation for `file`, taking its filetype's settings in the
+    /// config over the config's own.
+    pub fn for_file(file: Option<&Path>, config: &Config) -> Self {
+        let settings = file
+            .and_then(FileType::detect)
+            .and_then(|filetype| config.filetypes.get(filetype.name));
+        let setting = |get: fn(&FileTypeSettings) -> Option<usize>, default| {
+            settings.and_then(get).filter(|&n| n > 0).unwrap_or(default)
+        };
+        Self {
+            tab_stop: setting(|settings| settings.tab_stop, config.tab_stop),
+            expand_tab: settings
+                .and_then(|settings| settings.expand_tab)
+                .unwrap_or(config.expand_tab),
+            indent_width: setting(|settings| settings.indent_width, config.indent_width),
+        }
+    }
+}
+
+/// Which characters make up a word, for word motions and searching for
+/// the word under the cursor.
+#[derive(Clone, Default)]
+pub struct WordChars {
+    extra: String,
+}
+
+impl WordChars {
+    /// The word characters for `file`, taking the config's filetype
+    /// settings over the built-in ones.
+    pub fn for_file(file: Option<&Path>, config: &Config) -> Self {
+        let filetype = match file.and_then(FileType::detect) {
+            Some(filetype) => filetype,
+            None => return Self::default(),
+        };
+        let extra = config
+            .filetypes
+            .get(filetype.name)
+            .and_then(|settings| settings.word_chars.clone())
+            .unwrap_or_else(|| filetype.word_chars.into());
+        Self { extra }
+    }
+
+    pub fn contains(&self, ch: char) -> bool {
+        ch.is_alphanumeric() || ch == '_' || self.extra.contains(ch)
+    }
+
+    /// The byte range of the word touching byte `at` of `line`, preferring
+    /// the one starting there.
+    pub fn word_at(&self, line: &str, at: usize) -> Option<(usize, usize)> {
+        let at = at.min(line.len());
+        let mut start = at;
+        for (index, ch) in line[..at].char_indices().rev() {
+            if !self.contains(ch) {
+                break;
+            }
+            start = index;
+        }
+        let end = line[at..]
+            .char_indices()
+            .find(|&(_, ch)| !self.contains(ch))
+            .map_or(line.len(), |(index, _)| at + index);
+        if start == end {
+            return None;
+        }
+        Some((start, end))
+    }
+
+    /// Whether `line[start..end]` is a whole word rather than part of one.
+    pub fn is_whole_word(&self, line: &str, start: usize, end: usize) -> bool {
+        let before = line[..start].chars().next_back();
+        let after = line[end..].chars().next();
+        !before.is_some_and(|ch| self.contains(ch)) && !after.is_some_and(|ch| self.contains(ch))
+    }
+
+    /// The start of the next word after byte `at`, or the end of the line.
+    pub fn next_word(&self, line: &str, at: usize) -> usize {
+        let at = at.min(line.len());
+        let mut chars = line[at..].char_indices().peekable();
+        let in_word = chars.peek().is_some_and(|&(_, ch)| self.contains(ch));
+        while chars.next_if(|&(_, ch)| self.contains(ch) == in_word).is_some() {}
+        while chars.next_if(|&(_, ch)| !self.contains(ch)).is_some() {}
+        chars.peek().map_or(line.len(), |&(index, _)| at + index)
+    }
+
+    /// The start of the word before byte `at`, or the start of the line.
+    pub fn previous_word(&self, line: &str, at: usize) -> usize {
+        let at = at.min(line.len());
+        let mut chars = line[..at].char_indices().rev().peekable();
+        while chars.next_if(|&(_, ch)| !self.contains(ch)).is_some() {}
+        let mut start = chars.peek().map_or(0, |&(index, _)| index);
+        while let Some((index, _)) = chars.next_if(|&(_, ch)| self.contains(ch)) {
+            start = index;
+        }
+        start
+    }
+}
+
+/// Byte offsets in `line` where sub-words start: at each run of letters
+/// and digits, at camelCase humps, and before the last capital of an
+/// acronym (`HTTPServer` splits as `HTTP` `Server`).
+fn subword_starts(line: &str) -> Vec<usize> {
+    let chars: Vec<(usize, char)> = line.char_indices().collect();
+    (0..chars.len())
+        .filter(|&i| {
+            let ch = chars[i].1;
+            if !ch.is_alphanumeric() {
+                return false;
+            }
+            match i.checked_sub(1).map(|previous| chars[previous].1) {
+                Some(previous) if previous.is_alphanumeric() => {
+                    let next = chars.get(i + 1).map(|&(_, next)| next);
+                    ch.is_uppercase()
+                        && (!previous.is_uppercase() || next.is_some_and(char::is_lowercase))
+                }
+                _ => true,
+            }
+        })
+        .map(|i| chars[i].0)
+        .collect()
+}
+
+/// The start of the next sub-word after byte `at`, or the end of the line.
+pub fn next_subword(line: &str, at: usize) -> usize {
+    subword_starts(line)
+        .into_iter()
+        .find(|&start| start > at)
+        .unwrap_or(line.len())
+}
+
+/// The start of the sub-word before byte `at`, or the start of the line.
+pub fn previous_subword(line: &str, at: usize) -> usize {
+    subword_starts(line)
+        .into_iter()
+        .rev()
+        .find(|&start| start < at)
+        .unwrap_or(0)
+}
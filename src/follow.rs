@@ -0,0 +1,49 @@
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+
+/// How long the editor waits for a key before looking for writes to
+/// followed files again.
+pub const POLL: Duration = Duration::from_millis(100);
+
+/// Watches a file for other processes writing it, for `:follow`. The
+/// directory is watched rather than the file, so a file that is replaced
+/// by renaming a new one over it (as most editors save) is still seen.
+pub struct Follow {
+    path: PathBuf,
+    events: Receiver<notify::Result<Event>>,
+    /// Kept for as long as the file is followed; dropping it stops the
+    /// watch.
+    _watcher: RecommendedWatcher,
+}
+
+impl Follow {
+    pub fn new(file: &Path) -> notify::Result<Self> {
+        let path = file.canonicalize()?;
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            let _ = sender.send(event);
+        })?;
+        let dir = path.parent().unwrap_or(Path::new("/"));
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        Ok(Self {
+            path,
+            events,
+            _watcher: watcher,
+        })
+    }
+
+    /// Whether the file has been written, created or renamed into place
+    /// since this was last asked.
+    pub fn changed(&self) -> bool {
+        let mut changed = false;
+        for event in self.events.try_iter().flatten() {
+            changed |= matches!(
+                event.kind,
+                EventKind::Create(_) | EventKind::Modify(_) | EventKind::Any
+            ) && event.paths.contains(&self.path);
+        }
+        changed
+    }
+}
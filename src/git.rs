@@ -0,0 +1,105 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the editor looks whether git, run in the background for the
+/// status bar or `:blame`, is done.
+pub const POLL: Duration = Duration::from_millis(100);
+/// How long the branch shown is trusted before git is asked again.
+const REFRESH: Duration = Duration::from_secs(5);
+
+/// `file` as committed at HEAD, or `None` where it isn't in a repository
+/// or git doesn't track it.
+pub fn head(file: &Path) -> Option<Vec<u8>> {
+    let dir = file.parent().filter(|dir| !dir.as_os_str().is_empty());
+    let name = file.file_name()?.to_str()?;
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir.unwrap_or(Path::new(".")))
+        .arg("show")
+        .arg(format!("HEAD:./{}", name))
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    output.status.success().then_some(output.stdout)
+}
+
+/// The branch of the repository around a directory, with a `*` when its
+/// work tree has changes, for the status bar. Git is asked in the
+/// background, and only again once `REFRESH` has passed or the directory
+/// changes, so keys never wait on it.
+#[derive(Default)]
+pub struct Repo {
+    dir: Option<PathBuf>,
+    asked: Option<Instant>,
+    label: Option<String>,
+    running: Option<Receiver<Option<String>>>,
+}
+
+impl Repo {
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.is_some()
+    }
+
+    /// Has the next `refresh` ask git again, as after a save.
+    pub fn expire(&mut self) {
+        self.asked = None;
+    }
+
+    /// Asks git about `dir` unless it was asked lately, and takes in the
+    /// answer once it comes.
+    pub fn refresh(&mut self, dir: &Path) {
+        if let Some(running) = &self.running {
+            if let Ok(label) = running.try_recv() {
+                self.running = None;
+                self.label = label;
+            }
+            return;
+        }
+        let moved = self.dir.as_deref() != Some(dir);
+        if !moved && self.asked.is_some_and(|asked| asked.elapsed() < REFRESH) {
+            return;
+        }
+        if moved {
+            self.label = None;
+        }
+        self.dir = Some(dir.into());
+        self.asked = Some(Instant::now());
+        let (sender, receiver) = mpsc::channel();
+        let dir = dir.to_path_buf();
+        thread::spawn(move || {
+            let _ = sender.send(status(&dir));
+        });
+        self.running = Some(receiver);
+    }
+}
+
+fn status(dir: &Path) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["status", "--porcelain", "--branch", "--untracked-files=no"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let text = String::from_utf8_lossy(&output.stdout);
+    let mut lines = text.lines();
+    // `## main...origin/main [ahead 1]`, `## No commits yet on main` or
+    // `## HEAD (no branch)`.
+    let branch = lines.next()?.strip_prefix("## ")?;
+    let branch = branch.strip_prefix("No commits yet on ").unwrap_or(branch);
+    let branch = branch.split("...").next()?.split(' ').next()?;
+    let dirty = lines.next().is_some();
+    Some(format!("{}{}", branch, if dirty { "*" } else { "" }))
+}
@@ -0,0 +1,139 @@
+use crate::config::{self, Config, PROJECT_CONFIG_FILE};
+use crate::highlight::Highlighter;
+use crate::palette::Depth;
+use crate::spell;
+use crate::theme::Theme;
+use crossterm::terminal;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Prints what `--health` finds out about the terminal, the config, the
+/// programs the config runs and the editor's own directories, returning
+/// whether everything looked fine.
+pub fn check(config_file: Option<&Path>) -> bool {
+    let mut report = Report::default();
+
+    report.section("Terminal");
+    let var = |name| env::var(name).unwrap_or_else(|_| "(not set)".into());
+    report.line("TERM", var("TERM"));
+    report.line("COLORTERM", var("COLORTERM"));
+    match terminal::size() {
+        Ok((columns, rows)) => report.line("size", format!("{}x{}", columns, rows)),
+        Err(err) => report.problem("size", format!("not a terminal? {}", err)),
+    }
+    let ssh = env::var_os("SSH_CONNECTION").is_some() || env::var_os("SSH_TTY").is_some();
+    report.line("over SSH", if ssh { "yes" } else { "no" }.into());
+    let clipboard = match arboard::Clipboard::new() {
+        Ok(_) => "system clipboard".into(),
+        Err(err) => format!("kept inside the editor ({})", err),
+    };
+    report.line("clipboard", clipboard);
+
+    report.section("Config");
+    let user_file =
+        config_file.map_or_else(|| config::config_dir().join("config.toml"), Into::into);
+    let project_file = crate::project_root().join(PROJECT_CONFIG_FILE);
+    for file in [&user_file, &project_file] {
+        let state = if file.is_file() { "read" } else { "not there" };
+        report.line(state, file.display().to_string());
+    }
+    let mut loaded = Config::load(config_file);
+    let config = &loaded.config;
+    let depth = match config.color_depth {
+        Some(depth) => format!("{} (color_depth)", depth.name()),
+        None => format!("{} (guessed from COLORTERM and TERM)", Depth::detect().name()),
+    };
+    report.line("colours", depth);
+    let theme = Theme::load(
+        config.theme.as_deref(),
+        &config.colors,
+        config.color_depth.unwrap_or_else(Depth::detect),
+        &mut loaded.errors,
+    );
+    Highlighter::load(&theme, &mut loaded.errors);
+    for error in &loaded.errors {
+        report.problem("error", error.clone());
+    }
+    if loaded.pending_trust.is_some() {
+        report.line("untrusted", format!("{} (asked about on start)", project_file.display()));
+    }
+    let language = &config.spell_language;
+    if spell::installed().contains(language) {
+        report.line("dictionary", language.clone());
+    } else {
+        report.line("dictionary", format!("{} not installed (:spellinstall)", language));
+    }
+
+    report.section("Programs");
+    let mut programs = vec![("git".to_string(), "git".to_string())];
+    programs.extend(config.formatter.iter().map(|command| ("formatter".into(), command.clone())));
+    let hook = config.hooks.on_save.iter();
+    programs.extend(hook.map(|command| ("on_save".into(), command.clone())));
+    let mut filetypes: Vec<_> = config.filetypes.iter().collect();
+    filetypes.sort_by_key(|(name, _)| name.as_str());
+    for (name, settings) in filetypes {
+        if let Some(run) = &settings.run {
+            programs.push((format!("{} run", name), run.clone()));
+        }
+        if let Some(lint) = &settings.lint {
+            programs.push((format!("{} lint", name), lint.clone()));
+        }
+    }
+    for (label, command) in programs {
+        let program = command.split_whitespace().next().unwrap_or_default();
+        match find_program(program) {
+            Some(path) => report.line(&label, path.display().to_string()),
+            None => report.problem(&label, format!("{} not found", program)),
+        }
+    }
+
+    report.section("Directories");
+    let data_dir = config::data_dir();
+    match writable(&data_dir) {
+        Ok(()) => report.line("writable", data_dir.display().to_string()),
+        Err(err) => report.problem("data", format!("{}: {}", data_dir.display(), err)),
+    }
+    report.problems == 0
+}
+
+/// The lines of the report, printed as they are added.
+#[derive(Default)]
+struct Report {
+    problems: usize,
+}
+
+impl Report {
+    fn section(&self, title: &str) {
+        println!("{}", title);
+    }
+
+    fn line(&self, label: &str, text: String) {
+        println!("  {:<14} {}", label, text);
+    }
+
+    fn problem(&mut self, label: &str, text: String) {
+        self.problems += 1;
+        println!("! {:<14} {}", label, text);
+    }
+}
+
+/// Where `program` would be run from, searching `PATH` unless it is a
+/// path already.
+fn find_program(program: &str) -> Option<PathBuf> {
+    if program.contains('/') {
+        return Path::new(program).is_file().then(|| program.into());
+    }
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|path| path.is_file())
+}
+
+/// Creates `dir` if need be and writes a file there, to make sure state,
+/// journals and swap files can be kept.
+fn writable(dir: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let probe = dir.join(".health");
+    fs::write(&probe, "")?;
+    fs::remove_file(probe)
+}
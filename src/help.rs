@@ -0,0 +1,89 @@
+use crate::action::BINDABLE;
+use crate::command::{COMMANDS, SETTINGS};
+use crate::config::{CommandStep, Config};
+
+/// What `:help` can be asked about: commands, settings and actions, by
+/// name.
+pub fn topics(config: &Config) -> Vec<String> {
+    COMMANDS
+        .iter()
+        .map(|(name, ..)| name.to_string())
+        .chain(config.commands.keys().cloned())
+        .chain(config.aliases.keys().cloned())
+        .chain(SETTINGS.iter().map(|(name, ..)| name.to_string()))
+        .chain(BINDABLE.iter().map(|action| action.name().to_string()))
+        .collect()
+}
+
+/// The help on `topic`, or on everything without one. It is put together
+/// from the tables the command line, `:set` and the keymap work from, so
+/// it says what the editor actually does.
+pub fn text(topic: Option<&str>, config: &Config) -> Result<String, String> {
+    let wanted = |name: &str| topic.is_none_or(|topic| topic == name);
+    let mut commands = Vec::new();
+    for (name, arguments, about) in COMMANDS.iter().filter(|(name, ..)| wanted(name)) {
+        commands.push(entry(format!(":{} {}", name, arguments).trim_end(), about));
+    }
+    let mut user_commands: Vec<_> = config
+        .commands
+        .iter()
+        .filter(|(name, _)| wanted(name))
+        .collect();
+    user_commands.sort_by_key(|(name, _)| name.as_str());
+    for (name, steps) in user_commands {
+        let steps: Vec<String> = steps.iter().map(CommandStep::describe).collect();
+        commands.push(entry(&format!(":{}", name), &steps.join("; ")));
+    }
+    let mut aliases: Vec<_> = config
+        .aliases
+        .iter()
+        .filter(|(name, _)| wanted(name))
+        .collect();
+    aliases.sort();
+    for (name, command) in aliases {
+        commands.push(entry(&format!(":{}", name), &format!("Alias for :{}", command)));
+    }
+    let settings: Vec<String> = SETTINGS
+        .iter()
+        .filter(|(name, ..)| wanted(name))
+        .map(|(name, values, about)| entry(&format!("{}={}", name, values.join("|")), about))
+        .collect();
+    let actions: Vec<String> = BINDABLE
+        .iter()
+        .filter(|action| wanted(action.name()))
+        .map(|action| {
+            let keys = config.keymap.keys_for(*action);
+            let heading = if keys.is_empty() {
+                action.name().to_string()
+            } else {
+                format!("{}  ({})", action.name(), keys.join(", "))
+            };
+            entry(&heading, action.about())
+        })
+        .collect();
+    let mut text = String::new();
+    for (title, entries) in [
+        ("Commands", commands),
+        ("Settings (:set name=value)", settings),
+        ("Actions and their keys", actions),
+    ] {
+        if entries.is_empty() {
+            continue;
+        }
+        if !text.is_empty() {
+            text.push('\n');
+        }
+        text.push_str(&format!("{}\n\n", title));
+        for entry in entries {
+            text.push_str(&entry);
+        }
+    }
+    match topic {
+        Some(topic) if text.is_empty() => Err(format!("No help for {}", topic)),
+        _ => Ok(text),
+    }
+}
+
+fn entry(heading: &str, about: &str) -> String {
+    format!("{}\n    {}\n", heading, about)
+}
@@ -0,0 +1,271 @@
+use crate::palette::Depth;
+use crate::theme::Theme as EditorTheme;
+use crate::{config, EditorRows};
+use crossterm::style::{Color, SetForegroundColor};
+use std::mem;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use syntect::highlighting::{
+    HighlightIterator, HighlightState, Highlighter as ThemeHighlighter, StyleModifier, Theme,
+    ThemeItem, ThemeSet,
+};
+use syntect::parsing::{ParseState, ScopeStack, SyntaxSet};
+
+pub const DEFAULT_THEME: &str = "base16-ocean.dark";
+
+/// A run of render bytes drawn in one colour.
+pub type Span = (Range<usize>, Color);
+
+/// The grammars and theme used for syntax highlighting. The bundled
+/// defaults are extended with `.sublime-syntax` files from
+/// `<config dir>/syntaxes` and `.tmTheme` files from `<config dir>/themes`,
+/// and the editor theme's scope colours are laid over the syntax theme.
+pub struct Highlighter {
+    syntaxes: SyntaxSet,
+    themes: ThemeSet,
+    theme: Theme,
+    depth: Depth,
+}
+
+impl Highlighter {
+    pub fn load(editor_theme: &EditorTheme, errors: &mut Vec<String>) -> Self {
+        let mut syntaxes = SyntaxSet::load_defaults_newlines();
+        let syntax_dir = config::config_dir().join("syntaxes");
+        // Rebuilding the bundled set is slow, so only do it for user grammars.
+        if syntax_dir.is_dir() {
+            let mut builder = syntaxes.into_builder();
+            if let Err(err) = builder.add_from_folder(&syntax_dir, true) {
+                errors.push(format!("{}: {}", syntax_dir.display(), err));
+            }
+            syntaxes = builder.build();
+        }
+        let mut themes = ThemeSet::load_defaults();
+        let theme_dir = config::config_dir().join("themes");
+        if theme_dir.is_dir() {
+            if let Err(err) = themes.add_from_folder(&theme_dir) {
+                errors.push(format!("{}: {}", theme_dir.display(), err));
+            }
+        }
+        let mut highlighter = Self {
+            syntaxes,
+            themes,
+            theme: Theme::default(),
+            depth: editor_theme.depth,
+        };
+        highlighter.set_theme(editor_theme, errors);
+        highlighter
+    }
+
+    /// Switches to the syntax theme `editor_theme` goes with, its scope
+    /// colours laid over it.
+    pub fn set_theme(&mut self, editor_theme: &EditorTheme, errors: &mut Vec<String>) {
+        let name = editor_theme.syntax.as_deref().unwrap_or(DEFAULT_THEME);
+        let mut theme = match self.themes.themes.get(name) {
+            Some(theme) => theme.clone(),
+            None => {
+                errors.push(format!("Unknown theme: {}", name));
+                self.themes.themes.get(DEFAULT_THEME).cloned().unwrap_or_default()
+            }
+        };
+        // syntect prefers the earlier of two equally good matches, so the
+        // overrides go first.
+        for (scope, (r, g, b)) in editor_theme.scopes.iter().rev() {
+            match scope.parse() {
+                Ok(selector) => theme.scopes.insert(
+                    0,
+                    ThemeItem {
+                        scope: selector,
+                        style: StyleModifier {
+                            foreground: Some(syntect::highlighting::Color {
+                                r: *r,
+                                g: *g,
+                                b: *b,
+                                a: 0xff,
+                            }),
+                            ..StyleModifier::default()
+                        },
+                    },
+                ),
+                Err(_) => errors.push(format!("Invalid scope: {}", scope)),
+            }
+        }
+        self.theme = theme;
+        self.depth = editor_theme.depth;
+    }
+
+    /// The names of the syntax themes, bundled and the user's own.
+    pub fn theme_names(&self) -> impl Iterator<Item = &str> {
+        self.themes.themes.keys().map(String::as_str)
+    }
+
+    /// The theme's colour for text in `scope`, such as `"keyword"` or
+    /// `"entity.name.function"`.
+    #[cfg(feature = "tree-sitter")]
+    pub fn scope_color(&self, scope: &str) -> Color {
+        let theme_highlighter = ThemeHighlighter::new(&self.theme);
+        let style = match syntect::parsing::Scope::new(scope) {
+            Ok(scope) => theme_highlighter.style_for_stack(&[scope]),
+            Err(_) => theme_highlighter.get_default(),
+        };
+        self.depth.fit(rgb(style.foreground))
+    }
+
+    /// The grammar for `file`, chosen by extension and then by first line.
+    fn syntax_for(&self, file: &Path, first_line: &str) -> Option<String> {
+        let by_extension = file
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(|extension| self.syntaxes.find_syntax_by_extension(extension));
+        by_extension
+            .or_else(|| self.syntaxes.find_syntax_by_first_line(first_line))
+            .filter(|syntax| syntax.name != "Plain Text")
+            .map(|syntax| syntax.name.clone())
+    }
+}
+
+/// Highlighting for the rows of one buffer, computed top to bottom as far
+/// as the screen has needed. `states[i]` is the parser state at the start
+/// of row `i`, so an edit only throws away the rows from its own onwards.
+#[derive(Default)]
+pub struct RowCache {
+    detected_for: Option<Option<PathBuf>>,
+    syntax: Option<String>,
+    states: Vec<(ParseState, HighlightState)>,
+    spans: Vec<Vec<Span>>,
+}
+
+impl RowCache {
+    pub fn invalidate(&mut self, from: usize) {
+        self.spans.truncate(from);
+        self.states.truncate(from + 1);
+    }
+
+    pub fn cached_rows(&self) -> usize {
+        self.spans.len()
+    }
+
+    /// Rough size of the cache, not counting what parser states point to.
+    pub fn memory(&self) -> usize {
+        self.states.len() * mem::size_of::<(ParseState, HighlightState)>()
+            + self
+                .spans
+                .iter()
+                .map(|spans| mem::size_of::<Vec<Span>>() + spans.len() * mem::size_of::<Span>())
+                .sum::<usize>()
+    }
+
+    pub fn row(&self, at: usize) -> Option<&[Span]> {
+        self.spans.get(at).map(Vec::as_slice)
+    }
+
+    /// Highlights the rows before `to` that aren't cached yet. The grammar
+    /// is picked again whenever the buffer's file name changes.
+    pub fn update(
+        &mut self,
+        highlighter: &Highlighter,
+        filename: Option<&Path>,
+        rows: &EditorRows,
+        tab_stop: usize,
+        to: usize,
+    ) {
+        if self.detected_for.as_ref().map(Option::as_deref) != Some(filename) {
+            let first_line = match rows.number_of_rows() {
+                0 => Default::default(),
+                _ => rows.get_row(0),
+            };
+            let first_line = first_line.as_ref();
+            *self = Self {
+                detected_for: Some(filename.map(Path::to_path_buf)),
+                syntax: filename.and_then(|file| highlighter.syntax_for(file, first_line)),
+                ..Self::default()
+            };
+        }
+        let syntax = match &self.syntax {
+            Some(name) => match highlighter.syntaxes.find_syntax_by_name(name) {
+                Some(syntax) => syntax,
+                None => return,
+            },
+            None => return,
+        };
+        let theme_highlighter = ThemeHighlighter::new(&highlighter.theme);
+        if self.states.is_empty() {
+            self.states.push((
+                ParseState::new(syntax),
+                HighlightState::new(&theme_highlighter, ScopeStack::new()),
+            ));
+        }
+        let to = to.min(rows.number_of_rows());
+        while self.spans.len() < to {
+            let (mut parse_state, mut highlight_state) = self.states.last().unwrap().clone();
+            let row = rows.get_row(self.spans.len());
+            let line = format!("{}\n", row);
+            let ops = parse_state
+                .parse_line(&line, &highlighter.syntaxes)
+                .unwrap_or_default();
+            let mut runs = Vec::new();
+            let mut at = 0;
+            for (style, text) in
+                HighlightIterator::new(&mut highlight_state, &ops, &line, &theme_highlighter)
+            {
+                runs.push((at..at + text.len(), highlighter.depth.fit(rgb(style.foreground))));
+                at += text.len();
+            }
+            let spans = render_spans(&row, &runs, tab_stop);
+            self.spans.push(spans);
+            self.states.push((parse_state, highlight_state));
+        }
+    }
+}
+
+fn rgb(color: syntect::highlighting::Color) -> Color {
+    Color::Rgb {
+        r: color.r,
+        g: color.g,
+        b: color.b,
+    }
+}
+
+/// Converts colour runs over a row's bytes into spans over its render,
+/// expanding tabs the way `Row::render` does.
+pub fn render_spans(content: &str, runs: &[Span], tab_stop: usize) -> Vec<Span> {
+    let mut spans: Vec<Span> = Vec::new();
+    let mut chars = content.char_indices().peekable();
+    let (mut column, mut offset) = (0, 0);
+    for (range, color) in runs {
+        let start = offset;
+        while let Some((_, ch)) = chars.next_if(|&(at, _)| at < range.end) {
+            let width = if ch == '\t' {
+                tab_stop - column % tab_stop
+            } else {
+                crate::width::char_width(ch)
+            };
+            column += width;
+            offset += if ch == '\t' { width } else { ch.len_utf8() };
+        }
+        match spans.last_mut() {
+            Some((range, last)) if last == color => range.end = offset,
+            _ if offset > start => spans.push((start..offset, *color)),
+            _ => {}
+        }
+    }
+    spans
+}
+
+/// `text[start..end]` with colour escapes for the spans that cover it.
+pub fn styled(text: &str, start: usize, end: usize, spans: &[Span]) -> String {
+    let mut styled = String::with_capacity(end - start);
+    let mut at = start;
+    for (range, color) in spans {
+        if range.end <= at || at >= end {
+            continue;
+        }
+        let span_end = range.end.min(end);
+        styled.push_str(&SetForegroundColor(*color).to_string());
+        styled.push_str(&text[at..span_end]);
+        at = span_end;
+    }
+    // Only the foreground: the line may have a background tint.
+    styled.push_str(&SetForegroundColor(Color::Reset).to_string());
+    styled.push_str(&text[at..end]);
+    styled
+}
@@ -0,0 +1,49 @@
+/// The spaces and tabs `line` starts with.
+pub fn leading_whitespace(line: &str) -> &str {
+    let end = line
+        .find(|c: char| c != ' ' && c != '\t')
+        .unwrap_or(line.len());
+    &line[..end]
+}
+
+/// Re-indents multi-line `text` for pasting on a line indented by
+/// `indent`: the indentation the lines have in common is replaced by
+/// `indent`, keeping their indentation relative to each other. The first
+/// line goes where the cursor is, so it only loses its indentation when it
+/// has some (that is, when it was copied from the start of a line).
+pub fn reindent(text: &str, indent: &str) -> String {
+    let lines: Vec<&str> = text.split('\n').collect();
+    if lines.len() < 2 {
+        return text.into();
+    }
+    let first_indented = !leading_whitespace(lines[0]).is_empty();
+    let common = lines
+        .iter()
+        .enumerate()
+        .filter(|&(i, line)| (i > 0 || first_indented) && !line.trim().is_empty())
+        .map(|(_, line)| leading_whitespace(line).chars().count())
+        .min()
+        .unwrap_or(0);
+    let strip = |line: &str| {
+        let skip = line
+            .char_indices()
+            .take_while(|&(_, c)| c == ' ' || c == '\t')
+            .take(common)
+            .last()
+            .map_or(0, |(at, c)| at + c.len_utf8());
+        String::from(&line[skip..])
+    };
+    let mut reindented = if first_indented {
+        strip(lines[0])
+    } else {
+        lines[0].into()
+    };
+    for line in &lines[1..] {
+        reindented.push('\n');
+        if !line.trim().is_empty() {
+            reindented.push_str(indent);
+            reindented.push_str(&strip(line));
+        }
+    }
+    reindented
+}
@@ -0,0 +1,103 @@
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+/// How often the output pane is brought up to date while the job runs.
+pub const POLL: Duration = Duration::from_millis(100);
+
+/// A command running in the background for `:run`, its stdout and stderr
+/// collected line by line, as they come, for the output pane.
+pub struct Job {
+    pub command: String,
+    pub lines: Vec<String>,
+    child: Child,
+    /// Lines read by the threads draining the pipes, which hang up once
+    /// both are closed.
+    receiver: Receiver<String>,
+    status: Option<ExitStatus>,
+}
+
+impl Job {
+    pub fn start(command: &str) -> Result<Self, String> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| format!("{}: {}", command, err))?;
+        let (sender, receiver) = mpsc::channel();
+        send_lines(child.stdout.take().unwrap(), sender.clone());
+        send_lines(child.stderr.take().unwrap(), sender);
+        Ok(Self {
+            command: command.into(),
+            lines: Vec::new(),
+            child,
+            receiver,
+            status: None,
+        })
+    }
+
+    /// Takes in the lines printed since the last call, and once both pipes
+    /// are closed, the exit status.
+    pub fn poll(&mut self) {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(line) => self.lines.push(line),
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => break,
+            }
+        }
+        if self.status.is_none() {
+            self.status = self.child.wait().ok();
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.status.is_none()
+    }
+
+    /// What the pane's title line says the job is doing.
+    pub fn state(&self) -> String {
+        match self.status.map(|status| status.code()) {
+            None => "running".into(),
+            Some(Some(0)) => "done".into(),
+            Some(Some(code)) => format!("exited with {}", code),
+            Some(None) => "killed".into(),
+        }
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        if self.is_running() {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+fn send_lines(pipe: impl Read + Send + 'static, sender: Sender<String>) {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(pipe);
+        let mut line = Vec::new();
+        while reader.read_until(b'\n', &mut line).is_ok_and(|read| read > 0) {
+            let text = String::from_utf8_lossy(&line);
+            // Colour codes and the like would upset the screen around the
+            // pane, so control characters are left out.
+            let text: String = text
+                .trim_end_matches(['\n', '\r'])
+                .replace('\t', "    ")
+                .chars()
+                .filter(|ch| !ch.is_control())
+                .collect();
+            if sender.send(text).is_err() {
+                return;
+            }
+            line.clear();
+        }
+    });
+}
@@ -0,0 +1,299 @@
+use crate::config;
+use crate::encoding::FileEncoding;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const HEADER: &str = "rustext-journal 1";
+const SYNC_INTERVAL: Duration = Duration::from_secs(2);
+const SYNC_EVERY: usize = 64;
+
+/// One primitive change to an `EditorRows`. Every buffer mutation is made
+/// of these, so replaying them in order reproduces the edit history.
+pub enum Edit {
+    InsertChar { y: usize, x: usize, ch: char },
+    DeleteChar { y: usize, x: usize },
+    InsertRow { y: usize, text: String },
+    DeleteRow { y: usize },
+    SetRow { y: usize, text: String },
+    JoinRows { y: usize },
+    Replace { text: String },
+    /// Sets how the file is written back: its line endings, whether it
+    /// starts with a byte order mark, and whether it was read with mixed
+    /// line endings.
+    SetFormat { crlf: bool, bom: bool, mixed: bool },
+    /// Sets the encoding the file is written back in.
+    SetEncoding { encoding: FileEncoding },
+}
+
+impl Edit {
+    fn encode(&self) -> String {
+        match self {
+            Edit::InsertChar { y, x, ch } => format!("i {} {} {}", y, x, escape(&ch.to_string())),
+            Edit::DeleteChar { y, x } => format!("d {} {}", y, x),
+            Edit::InsertRow { y, text } => format!("r {} {}", y, escape(text)),
+            Edit::DeleteRow { y } => format!("x {}", y),
+            Edit::SetRow { y, text } => format!("w {} {}", y, escape(text)),
+            Edit::JoinRows { y } => format!("j {}", y),
+            Edit::Replace { text } => format!("R {}", escape(text)),
+            Edit::SetFormat { crlf, bom, mixed } => {
+                format!("f {} {} {}", u8::from(*crlf), u8::from(*bom), u8::from(*mixed))
+            }
+            Edit::SetEncoding { encoding } => {
+                format!("e {} {}", encoding.name(), u8::from(encoding.bom))
+            }
+        }
+    }
+
+    /// Text is escaped so it never contains spaces, which keeps every
+    /// encoded edit a plain space-separated record.
+    fn decode(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.split(' ').collect();
+        let number = |at: usize| fields.get(at)?.parse::<usize>().ok();
+        let text = |at: usize| unescape(fields.get(at).copied().unwrap_or(""));
+        Some(match fields[0] {
+            "i" => Edit::InsertChar {
+                y: number(1)?,
+                x: number(2)?,
+                ch: text(3)?.chars().next()?,
+            },
+            "d" => Edit::DeleteChar {
+                y: number(1)?,
+                x: number(2)?,
+            },
+            "r" => Edit::InsertRow {
+                y: number(1)?,
+                text: text(2)?,
+            },
+            "x" => Edit::DeleteRow { y: number(1)? },
+            "w" => Edit::SetRow {
+                y: number(1)?,
+                text: text(2)?,
+            },
+            "j" => Edit::JoinRows { y: number(1)? },
+            "R" => Edit::Replace { text: text(1)? },
+            "f" => Edit::SetFormat {
+                crlf: number(1)? == 1,
+                bom: number(2)? == 1,
+                mixed: number(3)? == 1,
+            },
+            "e" => Edit::SetEncoding {
+                encoding: FileEncoding {
+                    bom: number(2)? == 1,
+                    ..FileEncoding::for_label(fields.get(1)?)?
+                },
+            },
+            _ => return None,
+        })
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            ' ' => escaped.push_str("\\s"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+fn unescape(text: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            unescaped.push(ch);
+            continue;
+        }
+        unescaped.push(match chars.next()? {
+            '\\' => '\\',
+            'n' => '\n',
+            'r' => '\r',
+            's' => ' ',
+            _ => return None,
+        });
+    }
+    Some(unescaped)
+}
+
+/// Identifies the saved file a journal applies on top of, so edits are
+/// never replayed onto a file that changed since.
+pub fn file_stamp(file: &Path) -> String {
+    match fs::metadata(file) {
+        Ok(metadata) => {
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .unwrap_or_default();
+            format!("{}.{:09} {}", modified.as_secs(), modified.subsec_nanos(), metadata.len())
+        }
+        Err(_) => "missing".into(),
+    }
+}
+
+fn journal_dir() -> PathBuf {
+    config::data_dir().join("journal")
+}
+
+/// Where the journal for `file` lives: one log per absolute file path.
+fn journal_path(file: &Path) -> PathBuf {
+    journal_dir().join(format!("{}.log", config::path_key(file)))
+}
+
+/// The file a journal's header says it is for.
+fn journal_file(text: &str) -> Option<PathBuf> {
+    let mut lines = text.lines();
+    if lines.next() != Some(HEADER) {
+        return None;
+    }
+    lines.next()?.strip_prefix("file ").map(PathBuf::from)
+}
+
+/// The edits in a journal, if it has any and `file` is still the version
+/// they were made to.
+fn replayable(text: &str, file: &Path) -> Option<Vec<Edit>> {
+    let mut lines = text.lines().skip(2);
+    if lines.next() != Some(format!("base {}", file_stamp(file)).as_str()) {
+        return None;
+    }
+    let edits: Vec<Edit> = lines.map(Edit::decode).collect::<Option<_>>()?;
+    Some(edits).filter(|edits| !edits.is_empty())
+}
+
+/// Edits a session that didn't exit cleanly left behind for a file.
+pub struct Stale {
+    pub file: PathBuf,
+    pub edits: usize,
+    pub modified: Option<SystemTime>,
+}
+
+/// Every journal left behind by a session that didn't exit cleanly, most
+/// recent first, except for files `in_use` says are being edited. Those
+/// that can no longer be replayed are discarded.
+pub fn stale(in_use: impl Fn(&Path) -> bool) -> Vec<Stale> {
+    let entries = match fs::read_dir(journal_dir()) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut stale = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(_) => continue,
+        };
+        let file = journal_file(&text);
+        if file.as_deref().is_some_and(&in_use) {
+            continue;
+        }
+        match file.and_then(|file| Some((replayable(&text, &file)?.len(), file))) {
+            Some((edits, file)) => stale.push(Stale {
+                file,
+                edits,
+                modified: entry.metadata().and_then(|metadata| metadata.modified()).ok(),
+            }),
+            None => {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+    stale.sort_by_key(|stale| std::cmp::Reverse(stale.modified));
+    stale
+}
+
+/// Throws away the edits left behind for `file`.
+pub fn discard_stale(file: &Path) {
+    let _ = fs::remove_file(journal_path(file));
+}
+
+/// An append-only log of the edits made to a buffer since it was last
+/// saved.
+pub struct Journal {
+    path: PathBuf,
+    file: File,
+    unsynced: usize,
+    last_sync: Instant,
+}
+
+impl Journal {
+    /// Reads the edits left behind by a session that didn't exit cleanly.
+    /// Journals written against a different version of the file are stale
+    /// and are discarded.
+    pub fn recover(file: &Path) -> Option<Vec<Edit>> {
+        let path = journal_path(file);
+        let text = fs::read_to_string(&path).ok()?;
+        let edits = journal_file(&text).and_then(|_| replayable(&text, file));
+        if edits.is_none() {
+            let _ = fs::remove_file(path);
+        }
+        edits
+    }
+
+    /// Starts a fresh journal for `file`, replacing any previous one.
+    pub fn start(file: &Path) -> io::Result<Self> {
+        fs::create_dir_all(journal_dir())?;
+        let path = journal_path(file);
+        let mut journal = Self {
+            file: File::create(&path)?,
+            path,
+            unsynced: 0,
+            last_sync: Instant::now(),
+        };
+        journal.write_header(file)?;
+        Ok(journal)
+    }
+
+    fn write_header(&mut self, file: &Path) -> io::Result<()> {
+        writeln!(
+            self.file,
+            "{}\nfile {}\nbase {}",
+            HEADER,
+            config::absolute(file).display(),
+            file_stamp(file)
+        )?;
+        self.file.sync_data()
+    }
+
+    pub fn record(&mut self, edit: &Edit) -> io::Result<()> {
+        writeln!(self.file, "{}", edit.encode())?;
+        self.unsynced += 1;
+        if self.unsynced >= SYNC_EVERY {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    pub fn sync(&mut self) -> io::Result<()> {
+        if self.unsynced > 0 {
+            self.file.sync_data()?;
+            self.unsynced = 0;
+        }
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+
+    pub fn sync_if_due(&mut self) -> io::Result<()> {
+        if self.unsynced > 0 && self.last_sync.elapsed() >= SYNC_INTERVAL {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Everything up to now is on disk: start over against the saved file.
+    pub fn reset(&mut self, file: &Path) -> io::Result<()> {
+        self.file = File::create(&self.path)?;
+        self.unsynced = 0;
+        self.write_header(file)
+    }
+
+    pub fn discard(self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,62 @@
+use std::borrow::Cow;
+
+/// How a file ends its lines. The buffer always holds bare newlines; the
+/// file's own endings are put back when it is saved.
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+}
+
+impl LineEnding {
+    /// What `text` mostly uses, LF for text without line breaks.
+    pub fn detect(text: &str) -> Self {
+        let breaks = text.matches('\n').count();
+        let crlf = text.matches("\r\n").count();
+        if crlf > 0 && crlf * 2 >= breaks {
+            LineEnding::Crlf
+        } else {
+            LineEnding::Lf
+        }
+    }
+
+    /// Whether `text` has both CRLF and bare LF line breaks, which saving
+    /// makes all one or the other.
+    pub fn is_mixed(text: &str) -> bool {
+        let crlf = text.matches("\r\n").count();
+        crlf > 0 && crlf < text.matches('\n').count()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::Crlf => "CRLF",
+        }
+    }
+
+    /// The ending a `fileformat` value names: `unix` or `dos`, as Vim has
+    /// them, or `lf` or `crlf`.
+    pub fn for_format(format: &str) -> Option<Self> {
+        match format {
+            "unix" | "lf" => Some(LineEnding::Lf),
+            "dos" | "crlf" => Some(LineEnding::Crlf),
+            _ => None,
+        }
+    }
+
+    pub fn format(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "unix",
+            LineEnding::Crlf => "dos",
+        }
+    }
+
+    /// `text`, with bare newlines, as the file should hold it.
+    pub fn apply(self, text: &str) -> Cow<'_, str> {
+        match self {
+            LineEnding::Lf => Cow::Borrowed(text),
+            LineEnding::Crlf => Cow::Owned(text.replace('\n', "\r\n")),
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use crate::encoding::FileEncoding;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::mem;
+use std::path::Path;
+
+/// Reads a file a chunk at a time, so a huge file can be shown as soon as
+/// its first screenful is in and the rest read while the editor is idle.
+/// Each chunk ends at a line break, making it whole rows.
+pub struct Loader {
+    file: File,
+    size: u64,
+    read: u64,
+    /// The start of a line that continues into the next chunk.
+    partial: Vec<u8>,
+    /// What the file is in, judged by its first chunk. A later chunk that
+    /// isn't valid in it stops the loading rather than being garbled.
+    encoding: Option<FileEncoding>,
+}
+
+impl Loader {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            file,
+            size,
+            read: 0,
+            partial: Vec::new(),
+            encoding: None,
+        })
+    }
+
+    /// How many bytes have been read, and of how many.
+    pub fn progress(&self) -> (u64, u64) {
+        (self.read, self.size)
+    }
+
+    pub fn encoding(&self) -> FileEncoding {
+        self.encoding.unwrap_or_default()
+    }
+
+    /// Reads up to `size` more bytes, giving the whole lines among them
+    /// (possibly none, in the middle of a very long line), or `None` once
+    /// the whole file has been given.
+    pub fn next_chunk(&mut self, size: usize) -> io::Result<Option<String>> {
+        let mut bytes = mem::take(&mut self.partial);
+        let start = bytes.len();
+        bytes.resize(start + size, 0);
+        let read = self.file.read(&mut bytes[start..])?;
+        bytes.truncate(start + read);
+        self.read += read as u64;
+        if read == 0 && bytes.is_empty() {
+            return Ok(None);
+        }
+        if read > 0 {
+            let end = bytes.iter().rposition(|&byte| byte == b'\n').map_or(0, |at| at + 1);
+            self.partial = bytes.split_off(end);
+        }
+        let encoding = *self.encoding.get_or_insert_with(|| FileEncoding::detect(&bytes));
+        // Its line breaks aren't single bytes, so chunks would split characters.
+        if encoding.is_utf16() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "UTF-16 files this large can't be opened",
+            ));
+        }
+        match encoding.decode_exact(&bytes) {
+            Some(text) => Ok(Some(text)),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("the file isn't all {} past its start", encoding.name()),
+            )),
+        }
+    }
+
+    /// Reads the whole lines in the last `size` bytes of the file, for a
+    /// look at the end without loading what comes before, giving them and
+    /// how many bytes are left out between what was read and them.
+    pub fn tail(&mut self, size: u64) -> io::Result<(u64, String)> {
+        let start = self.size.saturating_sub(size).max(self.read);
+        self.file.seek(SeekFrom::Start(start))?;
+        let mut bytes = Vec::new();
+        (&mut self.file).take(size).read_to_end(&mut bytes)?;
+        // The first line is likely cut off, unless the tail starts right
+        // where reading stopped.
+        let skip = if start > self.read {
+            bytes.iter().position(|&byte| byte == b'\n').map_or(bytes.len(), |at| at + 1)
+        } else {
+            0
+        };
+        let skipped = start - self.read + skip as u64;
+        let mut text = mem::take(&mut self.partial);
+        if skipped > 0 {
+            text.clear();
+        }
+        text.extend_from_slice(&bytes[skip..]);
+        Ok((skipped, self.encoding().decode(&text)))
+    }
+}
@@ -0,0 +1,79 @@
+use crate::config;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Another editor that appears to have the file open.
+pub enum Conflict {
+    Rustext(u32),
+    VimSwap(PathBuf),
+}
+
+impl Conflict {
+    pub fn describe(&self) -> String {
+        match self {
+            Conflict::Rustext(pid) => format!("already open in Rustext (pid {})", pid),
+            Conflict::VimSwap(swap) => format!("has a Vim swap file {}", swap.display()),
+        }
+    }
+}
+
+fn lock_path(file: &Path) -> PathBuf {
+    config::data_dir()
+        .join("locks")
+        .join(format!("{}.lock", config::path_key(file)))
+}
+
+pub fn is_running(pid: u32) -> bool {
+    let proc = Path::new("/proc");
+    // Without /proc there's no cheap liveness check, so assume the worst.
+    !proc.exists() || proc.join(pid.to_string()).exists()
+}
+
+/// The running process holding the lock on `file`, if any.
+fn holder(file: &Path) -> Option<u32> {
+    fs::read_to_string(lock_path(file))
+        .ok()
+        .and_then(|pid| pid.trim().parse::<u32>().ok())
+        .filter(|&pid| is_running(pid))
+}
+
+/// Whether some Rustext, this one included, is editing `file`.
+pub fn is_locked(file: &Path) -> bool {
+    holder(file).is_some()
+}
+
+/// Looks for a live Rustext lock or a Vim swap file for `file`.
+pub fn find_conflict(file: &Path) -> Option<Conflict> {
+    if let Some(pid) = holder(file).filter(|&pid| pid != process::id()) {
+        return Some(Conflict::Rustext(pid));
+    }
+    let name = file.file_name()?.to_str()?;
+    let dir = file.parent().unwrap_or_else(|| Path::new(""));
+    ["swp", "swo"]
+        .iter()
+        .map(|extension| dir.join(format!(".{}.{}", name, extension)))
+        .find(|swap| swap.exists())
+        .map(Conflict::VimSwap)
+}
+
+/// An advisory lock announcing that this process is editing a file. The
+/// lock is released when dropped.
+pub struct FileLock {
+    path: PathBuf,
+}
+
+impl FileLock {
+    pub fn acquire(file: &Path) -> Option<Self> {
+        let path = lock_path(file);
+        fs::create_dir_all(path.parent()?).ok()?;
+        fs::write(&path, process::id().to_string()).ok()?;
+        Some(Self { path })
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,26 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The file `--log` names, once opened.
+static LOG: Mutex<Option<File>> = Mutex::new(None);
+
+/// Starts appending to `path`.
+pub fn open(path: &Path) -> io::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    *LOG.lock().unwrap() = Some(file);
+    Ok(())
+}
+
+/// Appends `message` to the log, if there is one, after the time in
+/// seconds since the Unix epoch.
+pub fn write(message: &str) {
+    let mut log = LOG.lock().unwrap();
+    let Some(file) = log.as_mut() else {
+        return;
+    };
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let _ = writeln!(file, "[{}.{:03}] {}", time.as_secs(), time.subsec_millis(), message);
+}
@@ -1,53 +1,283 @@
+mod action;
+mod annotations;
+mod atomic;
+mod blame;
+mod cli;
+mod clipboard;
+mod command;
+mod complete;
+mod config;
+mod conflict;
+mod convert;
+mod dialog;
+mod diff;
+mod encoding;
+mod filetype;
+mod follow;
+mod git;
+mod health;
+mod help;
+mod highlight;
+mod indent;
+mod job;
+mod journal;
+mod line_ending;
+mod loader;
+mod lock;
+mod log;
+mod metrics;
+mod modal;
+mod notify;
+mod osc;
+mod palette;
+mod picker;
+mod popup;
+mod progress;
+mod search;
+mod shell;
+mod spell;
+mod state;
+mod stats;
+mod swap;
+#[cfg(feature = "tree-sitter")]
+mod syntax_tree;
+mod table;
+mod text;
+mod theme;
+mod trace;
+mod tty;
+mod typography;
+mod undo;
+mod validate;
+mod width;
+
+use action::{Action, KeyEcho};
+use annotations::{Annotation, Annotations};
+use blame::Blame;
+use cli::Args;
+use clipboard::Clipboard;
+use command::{Command, Completing, LineAction};
+use config::{
+    ChangeMarks, CheckSave, CommandStep, Config, LineNumbers, PendingTrust, UndoGroup,
+};
+use conflict::{Conflict, Side};
+use dialog::{Answer, Dialog};
+use diff::{Hunk, Pair};
+use encoding::FileEncoding;
+use filetype::{FileType, Indentation, WordChars};
+use follow::Follow;
+use highlight::{Highlighter, RowCache, Span};
+use job::Job;
+use journal::{Edit, Journal};
+use line_ending::LineEnding;
+use loader::Loader;
+use lock::FileLock;
+use metrics::{BufferStats, Metrics};
+use modal::{Effect, Modal};
+use notify::Notifications;
+use palette::Depth;
+use picker::{Picker, Target};
+use popup::{Anchor, Popup};
+use progress::Progress;
+use search::{Matcher, SavedPosition, SearchState};
+use spell::Spell;
+use state::{FileState, Session, SharedFile, SharedSession, State};
+use stats::Stats;
+use swap::Swap;
+use text::TextBuffer;
+use theme::Theme;
+use trace::{Recorded, Recorder};
+use tty::screen;
+#[cfg(feature = "tree-sitter")]
+use syntax_tree::SyntaxTree;
+use undo::{History, Step};
+use clap::Parser;
 use crossterm::event::*;
 use crossterm::terminal::ClearType;
 use crossterm::{cursor, event, execute, queue, style, terminal};
+use std::borrow::Cow;
 use std::cmp::Ordering;
-use std::io::{stdout, ErrorKind, Write};
-use std::path::PathBuf;
-use std::time::{Duration, Instant};
-use std::{cmp, env, fs, io};
+use std::collections::BTreeMap;
+use std::io::{IsTerminal, Read, Write};
+use std::ops::{Range, RangeInclusive};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{cmp, env, fs, io, iter, mem, process, thread};
+use unicode_segmentation::UnicodeSegmentation;
 
-const VERSION: &str = "0.0.1";
 const TAB_STOP: usize = 8;
-const QUIT_TIMES: u8 = 3;
+/// How far one step of the mouse wheel scrolls.
+const SCROLL_ROWS: usize = 3;
+/// Annotation layers: `:mark` bookmarks and `:annotate` checker output.
+const BOOKMARK_LAYER: &str = "bookmark";
+const SCRIPT_LAYER: &str = "script";
+/// How deep user commands may run other user commands, which stops one
+/// that runs itself.
+const MAX_NESTING: usize = 10;
+/// Annotation layer for the match count shown while searching.
+const SEARCH_LAYER: &str = "search";
+/// Annotation layer for the syntax error found when saving a JSON, YAML or
+/// TOML file.
+const SYNTAX_LAYER: &str = "syntax";
+/// Annotation layer for the lines the filetype's `lint` command reported
+/// when saving.
+const LINT_LAYER: &str = "lint";
+/// Annotation layer for who last changed lines, as `:blame` shows.
+const BLAME_LAYER: &str = "blame";
+const NOTES_FILE: &str = ".rustext-notes.md";
+/// Rows longer than this (minified bundles, single-line JSON dumps) are
+/// never rendered as a whole; see `Row::is_long`.
+const LONG_LINE_THRESHOLD: usize = 64 * 1024;
+/// Files bigger than this are read a chunk at a time while the editor is
+/// idle, starting with a small first chunk so they open at once, and are
+/// never highlighted.
+const LARGE_FILE_THRESHOLD: u64 = 16 * 1024 * 1024;
+const FIRST_CHUNK: usize = 256 * 1024;
+/// How much of the end of a file opened to see only its head and tail is
+/// read.
+const TAIL_CHUNK: u64 = 256 * 1024;
+/// A file read as needed is read further once the cursor is this many
+/// rows from the end of what has been read.
+const READ_AHEAD: usize = 10_000;
+const LOAD_CHUNK: usize = 4 * 1024 * 1024;
+/// How often the screen is redrawn while notifications are showing.
+const TOAST_POLL: Duration = Duration::from_millis(250);
+/// Smooth scrolling shows a jump in this many steps, this far apart, unless
+/// drawing the screen takes longer than `SLOW_FRAME`.
+const SCROLL_FRAMES: usize = 4;
+const SCROLL_FRAME_TIME: Duration = Duration::from_millis(12);
+const SLOW_FRAME: Duration = Duration::from_millis(15);
+/// How often an idle editor looks whether the current file changed on
+/// disk, standing in for noticing when the terminal gets focus back.
+const DISK_CHECK: Duration = Duration::from_secs(2);
+/// How long typing has to pause before `:pipe` runs the pipeline again.
+const PIPE_DELAY: Duration = Duration::from_millis(300);
+/// How long the mouse rests on an annotated line before its details show.
+const HOVER_DELAY: Duration = Duration::from_millis(600);
+/// Turns reports of the mouse moving with no button held on and off, which
+/// crossterm's mouse capture leaves out.
+const HOVER_TRACKING_ON: &str = "\x1b[?1003h";
+const HOVER_TRACKING_OFF: &str = "\x1b[?1003l";
+
+/// The nearest ancestor of the working directory containing a `.git`
+/// directory, falling back to the working directory itself.
+fn project_root() -> PathBuf {
+    let cwd = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    cwd.ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .unwrap_or(&cwd)
+        .to_path_buf()
+}
+
+/// What restoring the edits a crashed session left behind for `file`
+/// would change, as a unified diff.
+fn recovery_diff(file: &Path) -> Result<Vec<String>, String> {
+    let edits = Journal::recover(file).ok_or("Nothing left to recover")?;
+    let mut editor_rows = EditorRows::open(file.to_path_buf()).map_err(|err| err.to_string())?;
+    editor_rows.load_all().map_err(|err| err.to_string())?;
+    let saved = editor_rows.contents();
+    for edit in &edits {
+        if !editor_rows.apply(edit) {
+            break;
+        }
+    }
+    let recovered = editor_rows.contents();
+    let old: Vec<&str> = saved.lines().collect();
+    let new: Vec<&str> = recovered.lines().collect();
+    Ok(diff::unified(&old, &new, 3))
+}
+
+/// The annotation marking a bookmarked line.
+fn bookmark() -> Annotation {
+    Annotation {
+        sign: Some('>'),
+        ..Annotation::default()
+    }
+}
 
 struct CleanUp;
 impl Drop for CleanUp {
     fn drop(&mut self) {
+        let _ = execute!(screen(), style::Print(HOVER_TRACKING_OFF), DisableMouseCapture);
         terminal::disable_raw_mode().expect("Unable to disable raw mode");
         Output::clear_screen().expect("error");
     }
 }
 
+/// Hands the terminal back as it was before the editor started while `f`
+/// runs a program that may use it, then takes it over again, even if
+/// handing it back failed. Ctrl-C and Ctrl-\ meanwhile reach the program
+/// but not the editor.
+fn release_terminal<T>(f: impl FnOnce() -> T) -> crossterm::Result<T> {
+    let released = execute!(screen(), style::Print(HOVER_TRACKING_OFF), DisableMouseCapture)
+        .and_then(|()| terminal::disable_raw_mode())
+        .and_then(|()| Output::clear_screen());
+    let result = released.map(|()| tty::shielded(f));
+    let taken = terminal::enable_raw_mode()
+        .and_then(|()| execute!(screen(), EnableMouseCapture, style::Print(HOVER_TRACKING_ON)));
+    let result = result?;
+    taken?;
+    Ok(result)
+}
+
 #[macro_export]
 macro_rules! prompt {
-    ($output:expr,$($args:tt)*) => {{
+    (
+        $output:expr,
+        history = $history:expr,
+        complete = $complete:expr,
+        callback = $callback:expr,
+        $($args:tt)*
+    ) => {{
         let output:&mut Output = $output;
         let mut input = String::with_capacity(32);
+        let mut recall = state::Recall::new($history);
+        let mut menu: Option<complete::Menu> = None;
         loop {
             output.status_message.set_message(format!($($args)*, input));
             output.refresh_screen()?;
-            match Reader.read_key()? {
+            let key_event = Reader.read_key()?;
+            output.key_echo.record(&key_event, None);
+            let code = key_event.code;
+            if matches!(code, KeyCode::Tab | KeyCode::BackTab)
+                && complete::tab(&mut menu, &mut input, code == KeyCode::Tab, |input| {
+                    $complete(&*output, input)
+                })
+            {
+                // The menu sits under where the completed word starts.
+                output.popup = menu.as_ref().map(|menu| {
+                    menu.popup(width::str_width(&format!($($args)*, &input[..menu.start])))
+                });
+                continue;
+            }
+            if menu.take().is_some() {
+                output.popup = None;
+            }
+            match key_event {
                 KeyEvent {
                     code:KeyCode::Enter,
                     modifiers:KeyModifiers::NONE
-                } => {
-                    if !input.is_empty() {
-                        output.status_message.set_message(String::new());
-                        break;
-                    }
+                } if !input.is_empty() => {
+                    output.status_message.set_message(String::new());
+                    $callback(output, &input, key_event);
+                    break;
                 }
                 KeyEvent {
                     code: KeyCode::Esc, ..
                 } => {
                     output.status_message.set_message(String::new());
                     input.clear();
+                    $callback(output, &input, key_event);
                     break;
                 }
                 KeyEvent {
                     code: KeyCode::Backspace | KeyCode::Delete,
                     modifiers: KeyModifiers::NONE,
                 } => { input.pop(); }
+                KeyEvent {
+                    code: code @ KeyCode::Char('p' | 'n'),
+                    modifiers: KeyModifiers::CONTROL,
+                } => { recall.step(code == KeyCode::Char('p'), &mut input); }
                 KeyEvent {
                     code: code @ (KeyCode::Char(..) | KeyCode::Tab),
                     modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
@@ -58,9 +288,42 @@ macro_rules! prompt {
                     }),
                 _=> {}
             }
+            $callback(output, &input, key_event);
         }
         if input.is_empty() { None } else { Some (input) }
     }};
+    ($output:expr,history = $history:expr,callback = $callback:expr,$($args:tt)*) => {
+        prompt!(
+            $output,
+            history = $history,
+            complete = |_: &Output, _: &str| None,
+            callback = $callback,
+            $($args)*
+        )
+    };
+    ($output:expr,callback = $callback:expr,$($args:tt)*) => {
+        prompt!($output, history = Vec::new(), callback = $callback, $($args)*)
+    };
+    ($output:expr,history = $history:expr,complete = $complete:expr,$($args:tt)*) => {
+        prompt!(
+            $output,
+            history = $history,
+            complete = $complete,
+            callback = |_: &mut Output, _: &str, _: KeyEvent| {},
+            $($args)*
+        )
+    };
+    ($output:expr,history = $history:expr,$($args:tt)*) => {
+        prompt!(
+            $output,
+            history = $history,
+            callback = |_: &mut Output, _: &str, _: KeyEvent| {},
+            $($args)*
+        )
+    };
+    ($output:expr,$($args:tt)*) => {
+        prompt!($output, history = Vec::new(), $($args)*)
+    };
 }
 
 struct StatusMessage {
@@ -94,133 +357,848 @@ impl StatusMessage {
     }
 }
 
-#[derive(Default)]
-struct Row {
-    row_content: String,
-    render: String,
+/// The grapheme clusters (what reads as one character: a letter with
+/// combining accents, a flag, a ZWJ emoji sequence) either side of byte `at`
+/// of `row`, which the cursor steps over and Backspace and Delete remove
+/// whole.
+fn grapheme_before(row: &str, at: usize) -> Option<&str> {
+    row[..at].graphemes(true).next_back()
 }
 
-impl Row {
-    fn new(row_content: String, render: String) -> Self {
-        Self {
-            row_content,
-            render,
+fn grapheme_after(row: &str, at: usize) -> Option<&str> {
+    row[at..].graphemes(true).next()
+}
+
+/// A row of the buffer, borrowed from its text when it lies in one piece
+/// there.
+struct Row<'a> {
+    row_content: Cow<'a, str>,
+}
+
+impl Row<'_> {
+    /// Long rows skip tab expansion: `draw_rows` renders just the visible
+    /// chunk, one column per byte, so editing stays responsive however big
+    /// the line is.
+    fn is_long(&self) -> bool {
+        self.row_content.len() > LONG_LINE_THRESHOLD
+    }
+
+    /// The offset into `render` of content byte `at`.
+    fn render_offset(&self, at: usize, tab_stop: usize) -> usize {
+        if self.is_long() {
+            return at;
+        }
+        let mut column = 0;
+        let mut offset = 0;
+        for c in self.row_content[..at].chars() {
+            if c == '\t' {
+                let width = tab_stop - column % tab_stop;
+                column += width;
+                offset += width;
+            } else {
+                column += width::char_width(c);
+                offset += c.len_utf8();
+            }
+        }
+        offset
+    }
+
+    /// The content byte shown at render column `column`, or the end of
+    /// the row if it is shorter.
+    fn content_offset(&self, column: usize, tab_stop: usize) -> usize {
+        let content = &self.row_content;
+        if self.is_long() {
+            let mut at = cmp::min(column, content.len());
+            while !content.is_char_boundary(at) {
+                at -= 1;
+            }
+            return at;
+        }
+        let mut at = 0;
+        for (index, c) in content.char_indices() {
+            let width = if c == '\t' {
+                tab_stop - at % tab_stop
+            } else {
+                width::char_width(c)
+            };
+            if at + width > column {
+                return index;
+            }
+            at += width;
         }
+        content.len()
     }
 
-    fn insert_char(&mut self, at: usize, ch: char) {
-        self.row_content.insert(at, ch);
-        EditorRows::render_row(self)
+    /// The row as drawn, with tabs expanded to the next multiple of
+    /// `tab_stop` columns (wide characters counting twice). Long rows have
+    /// none.
+    fn render(&self, tab_stop: usize) -> String {
+        if self.is_long() {
+            return String::new();
+        }
+        let mut index = 0;
+        let capacity = self
+            .row_content
+            .chars()
+            .fold(0, |acc, next| acc + if next == '\t' { tab_stop } else { 1 });
+        let mut render = String::with_capacity(capacity);
+        self.row_content.chars().for_each(|c| {
+            if c == '\t' {
+                index += 1;
+                render.push(' ');
+                while index % tab_stop != 0 {
+                    render.push(' ');
+                    index += 1
+                }
+            } else {
+                index += width::char_width(c);
+                render.push(c);
+            }
+        });
+        render
     }
 
-    fn delete_char(&mut self, at: usize) {
-        self.row_content.remove(at);
-        EditorRows::render_row(self)
+    /// The visible part of a long row, from byte `start` for `len` columns.
+    fn render_chunk(&self, start: usize, len: usize) -> String {
+        let content = &self.row_content;
+        let mut start = cmp::min(start, content.len());
+        while !content.is_char_boundary(start) {
+            start += 1;
+        }
+        let mut end = cmp::min(start + len, content.len());
+        while !content.is_char_boundary(end) {
+            end -= 1;
+        }
+        content[start..end]
+            .chars()
+            .map(|c| if c.is_control() { ' ' } else { c })
+            .collect()
     }
 }
 
 
 
 struct EditorRows {
-    row_contents: Vec<Row>,
+    /// The text, kept in whatever suits its size.
+    text: Box<dyn TextBuffer>,
     filename: Option<PathBuf>,
+    tab_stop: usize,
+    /// The file's encoding and line endings, which saving writes back.
+    encoding: FileEncoding,
+    line_ending: LineEnding,
+    /// Whether the file ends with a newline, which saving keeps. New files
+    /// get one.
+    trailing_newline: bool,
+    /// Set when the file was read with both CRLF and LF line endings, until
+    /// it is saved with `line_ending` throughout.
+    mixed_endings: bool,
+    journal: Option<Journal>,
+    /// Set once the buffer has held a row over `LONG_LINE_THRESHOLD`.
+    long_line_mode: bool,
+    /// Set for files over `LARGE_FILE_THRESHOLD`.
+    large_file: bool,
+    /// Reads the rest of a large file; the buffer can't be edited until
+    /// it is done.
+    loader: Option<Loader>,
+    /// Set when the loader should only read on as the cursor nears the end
+    /// of what has been read, rather than whenever the editor is idle.
+    on_demand: bool,
+    history: History,
+    /// Number of changes since the file was loaded or last saved.
+    dirty: u64,
+    /// The text as last read or saved, to mark the lines changed since.
+    saved: Box<dyn TextBuffer>,
+    /// The file as committed at git's HEAD once read, `Some(None)` if git
+    /// doesn't have it.
+    head: Option<Option<Box<dyn TextBuffer>>>,
+    /// The lines changed since, as worked out when `dirty` was at the count
+    /// given.
+    changes: Option<(u64, Vec<Hunk>)>,
+    highlights: RowCache,
+    annotations: Annotations,
+    /// The modification time and size of the file as last read or
+    /// written, to notice it changing underneath us.
+    disk_stamp: Option<String>,
+    #[cfg(feature = "tree-sitter")]
+    syntax_tree: Option<SyntaxTree>,
+    /// The file name `syntax_tree` was chosen for.
+    #[cfg(feature = "tree-sitter")]
+    syntax_tree_for: Option<PathBuf>,
 }
 impl EditorRows {
-    fn new() -> Self {
-        match env::args().nth(1) {
-            None => Self {
-                row_contents: Vec::new(),
-                filename: None,
-            },
-            Some(file) => Self::from_file(file.into()),
+    fn empty() -> Self {
+        Self {
+            text: text::for_size(0),
+            filename: None,
+            tab_stop: TAB_STOP,
+            encoding: FileEncoding::default(),
+            line_ending: LineEnding::default(),
+            trailing_newline: true,
+            mixed_endings: false,
+            journal: None,
+            long_line_mode: false,
+            large_file: false,
+            loader: None,
+            on_demand: false,
+            history: History::new(),
+            dirty: 0,
+            saved: text::for_size(0),
+            head: None,
+            changes: None,
+            highlights: RowCache::default(),
+            annotations: Annotations::default(),
+            disk_stamp: None,
+            #[cfg(feature = "tree-sitter")]
+            syntax_tree: None,
+            #[cfg(feature = "tree-sitter")]
+            syntax_tree_for: None,
         }
     }
 
-    fn from_file(file: PathBuf) -> Self {
-        let file_contents = fs::read_to_string(&file).expect("Unable to read file");
-        Self {
+    fn from_file(file: PathBuf) -> io::Result<Self> {
+        let disk_stamp = Some(journal::file_stamp(&file));
+        let size = fs::metadata(&file)?.len();
+        if size > LARGE_FILE_THRESHOLD {
+            let mut editor_rows = Self {
+                text: text::for_size(size as usize),
+                loader: Some(Loader::open(&file)?),
+                large_file: true,
+                disk_stamp,
+                filename: Some(file),
+                ..Self::empty()
+            };
+            editor_rows.load_more(FIRST_CHUNK)?;
+            return Ok(editor_rows);
+        }
+        let (file_contents, encoding) = encoding::read(&file, None)?;
+        let mut editor_rows = Self {
+            encoding,
+            line_ending: LineEnding::detect(&file_contents),
+            trailing_newline: file_contents.ends_with('\n'),
+            mixed_endings: LineEnding::is_mixed(&file_contents),
+            disk_stamp,
             filename: Some(file),
-            row_contents: file_contents
-                .lines()
-                .map(|it| {
-                    let mut row = Row::new(it.into(), String::new());
-                    Self::render_row(&mut row);
-                    row
-                })
-                .collect(),
+            ..Self::empty()
+        };
+        editor_rows.replace_contents(&file_contents);
+        editor_rows.history.clear();
+        editor_rows.mark_saved();
+        Ok(editor_rows)
+    }
+
+    /// An unnamed buffer holding what was piped in for `-`. The text is
+    /// nowhere else, so it starts out unsaved.
+    fn from_stdin(bytes: &[u8]) -> Self {
+        let encoding = FileEncoding::detect(bytes);
+        let text = encoding.decode(bytes);
+        let mut editor_rows = Self {
+            encoding,
+            line_ending: LineEnding::detect(&text),
+            trailing_newline: text.is_empty() || text.ends_with('\n'),
+            mixed_endings: LineEnding::is_mixed(&text),
+            ..Self::empty()
+        };
+        editor_rows.replace_contents(&text);
+        editor_rows.history.clear();
+        editor_rows
+    }
+
+    /// Reads up to `bytes` more of a file that is still loading. If that
+    /// fails, loading stops there.
+    fn load_more(&mut self, bytes: usize) -> io::Result<()> {
+        let Some(loader) = &mut self.loader else {
+            return Ok(());
+        };
+        let chunk = loader.next_chunk(bytes);
+        let encoding = loader.encoding();
+        match chunk {
+            Ok(Some(chunk)) => {
+                // A large file's endings are judged by its first chunk.
+                if self.text.len_bytes() == 0 {
+                    self.encoding = encoding;
+                    self.line_ending = LineEnding::detect(&chunk);
+                    self.mixed_endings = LineEnding::is_mixed(&chunk);
+                }
+                self.trailing_newline = chunk.ends_with('\n');
+                self.append_rows(&chunk)
+            }
+            Ok(None) => self.loader = None,
+            Err(err) => {
+                self.loader = None;
+                return Err(err);
+            }
         }
+        Ok(())
     }
 
-    fn number_of_rows(&self) -> usize {
-        self.row_contents.len()
+    /// Stops loading, adding a line saying how much was left out and then
+    /// the last lines of the file.
+    fn keep_tail(&mut self) -> io::Result<()> {
+        let Some(mut loader) = self.loader.take() else {
+            return Ok(());
+        };
+        let (skipped, tail) = loader.tail(TAIL_CHUNK)?;
+        if skipped > 0 {
+            self.append_rows(&format!("[... {} not shown ...]", metrics::size(skipped as usize)));
+        }
+        self.append_rows(&tail);
+        self.trailing_newline = tail.ends_with('\n');
+        Ok(())
     }
 
-    fn get_row(&self, at: usize) -> &str {
-        &self.row_contents[at].row_content
+    fn load_all(&mut self) -> io::Result<()> {
+        while self.loader.is_some() {
+            self.load_more(LOAD_CHUNK)?;
+        }
+        Ok(())
     }
 
-    fn get_render(&self, at: usize) -> &String {
-        &self.row_contents[at].render
+    /// Whether the file has been written by someone else since we last
+    /// read or saved it.
+    fn changed_on_disk(&self) -> bool {
+        match (&self.filename, &self.disk_stamp) {
+            (Some(name), Some(stamp)) => name.exists() && journal::file_stamp(name) != *stamp,
+            _ => false,
+        }
     }
 
-    fn get_editor_row(&self, at: usize) -> &Row {
-        &self.row_contents[at]
+    /// Replaces the text with what is on disk now, read as `encoding` or as
+    /// what it looks to be in. Like any other change of the whole text, it
+    /// can be undone.
+    fn reload(&mut self, encoding: Option<FileEncoding>) -> io::Result<()> {
+        let name = self.filename.clone().ok_or(io::ErrorKind::NotFound)?;
+        let (file_contents, encoding) = encoding::read(&name, encoding)?;
+        self.replace_contents(&file_contents);
+        self.encoding = encoding;
+        self.line_ending = LineEnding::detect(&file_contents);
+        self.mixed_endings = LineEnding::is_mixed(&file_contents);
+        self.trailing_newline = file_contents.ends_with('\n');
+        self.disk_stamp = Some(journal::file_stamp(&name));
+        if let Some(journal) = &mut self.journal {
+            if journal.reset(&name).is_err() {
+                self.journal = None;
+            }
+        }
+        self.mark_saved();
+        Ok(())
     }
 
-    fn get_editor_row_mut(&mut self, at: usize) -> &mut Row {
-        &mut self.row_contents[at]
+    /// Takes the text as it is now for what is on disk.
+    fn mark_saved(&mut self) {
+        self.dirty = 0;
+        self.saved = self.text.snapshot();
+        // A commit may have come since, so HEAD is read again.
+        self.head = None;
+        self.changes = None;
     }
 
-    fn render_row(row: &mut Row) {
-        let mut index = 0;
-        let capacity = row
-            .row_content
-            .chars()
-            .fold(0, |acc, next| acc + if next == '\t' { TAB_STOP } else { 1 });
-        row.render = String::with_capacity(capacity);
-        row.row_content.chars().for_each(|c| {
-            index += 1;
-            if c == '\t' {
-                row.render.push(' ');
-                while index % TAB_STOP != 0 {
-                    row.render.push(' ');
-                    index += 1
+    /// Works out which lines differ from what `marks` says, unless nothing
+    /// has changed since it was last done. Scratch buffers and large files
+    /// aren't marked.
+    fn update_changes(&mut self, marks: ChangeMarks) {
+        if self.changes.as_ref().is_some_and(|(dirty, _)| *dirty == self.dirty) {
+            return;
+        }
+        let Some(name) = &self.filename else {
+            self.changes = Some((self.dirty, Vec::new()));
+            return;
+        };
+        if marks == ChangeMarks::Off || self.large_file || self.loader.is_some() {
+            self.changes = Some((self.dirty, Vec::new()));
+            return;
+        }
+        if marks == ChangeMarks::Head && self.head.is_none() {
+            let head = git::head(name).map(|bytes| text::from_lines(&self.encoding.decode(&bytes)));
+            self.head = Some(head);
+        }
+        let old = match (&self.head, marks) {
+            (Some(Some(head)), ChangeMarks::Head) => &**head,
+            _ => &*self.saved,
+        };
+        let new = &*self.text;
+        let (old_rows, new_rows) = (old.rows(), new.rows());
+        let common = old_rows.min(new_rows);
+        // Only the lines between those the two have in common at either
+        // end are copied out for diffing.
+        let prefix = (0..common)
+            .take_while(|&at| old.row(at) == new.row(at))
+            .count();
+        let suffix = (0..common - prefix)
+            .take_while(|&back| old.row(old_rows - 1 - back) == new.row(new_rows - 1 - back))
+            .count();
+        let lines = |text: &dyn TextBuffer, end: usize| -> Vec<String> {
+            (prefix..end - suffix).map(|at| text.row(at).into_owned()).collect()
+        };
+        let (old_lines, new_lines) = (lines(old, old_rows), lines(new, new_rows));
+        let old_lines: Vec<&str> = old_lines.iter().map(String::as_str).collect();
+        let new_lines: Vec<&str> = new_lines.iter().map(String::as_str).collect();
+        let hunks = diff::diff(&old_lines, &new_lines)
+            .into_iter()
+            .map(|hunk| Hunk {
+                old: hunk.old.start + prefix..hunk.old.end + prefix,
+                new: hunk.new.start + prefix..hunk.new.end + prefix,
+            })
+            .collect();
+        self.changes = Some((self.dirty, hunks));
+    }
+
+    /// How row `at` differs from what it is marked against: `+` added,
+    /// `~` changed, or `-` for lines removed just above it.
+    fn change_mark(&self, at: usize) -> Option<char> {
+        let (_, hunks) = self.changes.as_ref()?;
+        let last = self.number_of_rows().saturating_sub(1);
+        hunks.iter().find_map(|hunk| match (hunk.old.is_empty(), hunk.new.is_empty()) {
+            (_, true) if hunk.new.start.min(last) == at => Some('-'),
+            (true, false) if hunk.new.contains(&at) => Some('+'),
+            (false, false) if hunk.new.contains(&at) => Some('~'),
+            _ => None,
+        })
+    }
+
+    fn has_changes(&self) -> bool {
+        self.changes.as_ref().is_some_and(|(_, hunks)| !hunks.is_empty())
+    }
+
+    /// Like `from_file`, but a path that doesn't exist yet gives an empty
+    /// buffer which will be created on the first save.
+    fn open(file: PathBuf) -> io::Result<Self> {
+        if file.exists() {
+            Self::from_file(file)
+        } else {
+            Ok(Self {
+                filename: Some(file),
+                ..Self::empty()
+            })
+        }
+    }
+
+    fn number_of_rows(&self) -> usize {
+        self.text.rows()
+    }
+
+    fn get_row(&self, at: usize) -> Cow<'_, str> {
+        self.text.row(at)
+    }
+
+    fn get_render(&self, at: usize) -> String {
+        self.get_editor_row(at).render(self.tab_stop)
+    }
+
+    /// The text from byte `at` of the buffer to the end of the chunk
+    /// holding it, for readers that take the text in pieces. The newline
+    /// after the last row isn't part of the buffer and is left out.
+    #[cfg(feature = "tree-sitter")]
+    fn chunk_from(&self, at: usize) -> &str {
+        let end = self.text.len_bytes().saturating_sub(1);
+        if at >= end {
+            return "";
+        }
+        let (chunk, chunk_start) = self.text.chunk_at(at);
+        let chunk_end = cmp::min(chunk_start + chunk.len(), end);
+        &chunk[at - chunk_start..chunk_end - chunk_start]
+    }
+
+    /// The inline hints of row `at`, by offset into its render. Long rows
+    /// are drawn raw and get none.
+    fn render_hints(&self, at: usize) -> Vec<(usize, &str)> {
+        let row = self.get_editor_row(at);
+        if row.is_long() {
+            return Vec::new();
+        }
+        let content = &row.row_content;
+        self.annotations
+            .hints(at)
+            .into_iter()
+            .map(|(mut offset, text)| {
+                offset = offset.min(content.len());
+                while !content.is_char_boundary(offset) {
+                    offset -= 1;
                 }
-            } else {
-                row.render.push(c);
+                (row.render_offset(offset, self.tab_stop), text)
+            })
+            .collect()
+    }
+
+    /// How many columns of inline hints row `at` shows at screen columns
+    /// in `range`.
+    fn hint_width(&self, at: usize, range: RangeInclusive<usize>) -> usize {
+        if at >= self.number_of_rows() {
+            return 0;
+        }
+        let hints = self.render_hints(at);
+        if hints.is_empty() {
+            return 0;
+        }
+        let render = self.get_render(at);
+        hints
+            .into_iter()
+            .filter(|&(offset, _)| range.contains(&width::str_width(&render[..offset])))
+            .map(|(_, text)| width::str_width(text))
+            .sum()
+    }
+
+    fn get_editor_row(&self, at: usize) -> Row<'_> {
+        Row {
+            row_content: self.get_row(at),
+        }
+    }
+
+    fn set_tab_stop(&mut self, tab_stop: usize) {
+        if self.tab_stop != tab_stop {
+            self.tab_stop = tab_stop;
+            self.highlights.invalidate(0);
+        }
+    }
+
+    /// Appends `edit` to the journal, if this buffer keeps one. The edit is
+    /// only built when needed so unjournaled buffers don't pay for copies.
+    fn record(&mut self, edit: impl FnOnce() -> Edit) {
+        if let Some(journal) = &mut self.journal {
+            if journal.record(&edit()).is_err() {
+                self.journal = None;
             }
-        });
+        }
+    }
+
+    /// Replays a journaled edit, returning false if it doesn't fit the
+    /// current contents.
+    fn apply(&mut self, edit: &Edit) -> bool {
+        let number_of_rows = self.number_of_rows();
+        let fits = |y: usize, x: usize, rows: &Self| {
+            y < number_of_rows && rows.get_row(y).is_char_boundary(x)
+        };
+        match edit {
+            Edit::InsertChar { y, x, ch } if fits(*y, *x, self) => self.insert_char(*y, *x, *ch),
+            Edit::DeleteChar { y, x } if fits(*y, *x, self) && *x < self.get_row(*y).len() => {
+                self.delete_char(*y, *x)
+            }
+            Edit::InsertRow { y, text } if *y <= number_of_rows => {
+                self.insert_row(*y, text.clone())
+            }
+            Edit::DeleteRow { y } if *y < number_of_rows => self.delete_row(*y),
+            Edit::SetRow { y, text } if *y < number_of_rows => self.set_row(*y, text.clone()),
+            Edit::JoinRows { y } if *y > 0 && *y < number_of_rows => self.join_adjacent_rows(*y),
+            Edit::Replace { text } => self.replace_contents(text),
+            Edit::SetFormat { crlf, bom, mixed } => self.set_format(*crlf, *bom, *mixed),
+            Edit::SetEncoding { encoding } => self.set_encoding(*encoding),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Called before every change to the rows: the text from `start` to
+    /// `old_end` (row, byte column) is about to be replaced by `inserted`
+    /// bytes ending at `new_end`.
+    #[cfg_attr(not(feature = "tree-sitter"), allow(unused_variables))]
+    fn note_edit(
+        &mut self,
+        start: (usize, usize),
+        old_end: (usize, usize),
+        new_end: (usize, usize),
+        inserted: usize,
+    ) {
+        self.dirty += 1;
+        self.highlights.invalidate(start.0);
+        #[cfg(feature = "tree-sitter")]
+        if let Some(tree) = &mut self.syntax_tree {
+            let bytes = self.text.offset(start)..self.text.offset(old_end);
+            let new_end_byte = bytes.start + inserted;
+            tree.edit(bytes, new_end_byte, start, old_end, new_end);
+        }
     }
 
     fn insert_row(&mut self, at: usize, contents: String) {
-        let mut new_row = Row::new(contents, String::new());
-        EditorRows::render_row(&mut new_row);
-        self.row_contents.insert(at, new_row);
+        let number_of_rows = self.number_of_rows();
+        if at < number_of_rows {
+            self.note_edit((at, 0), (at, 0), (at + 1, 0), contents.len() + 1);
+        } else if at > 0 {
+            let end = (at - 1, self.get_row(at - 1).len());
+            self.note_edit(end, end, (at, contents.len()), contents.len() + 1);
+        } else {
+            self.note_edit((0, 0), (0, 0), (0, contents.len()), contents.len());
+        }
+        self.history.record(Edit::DeleteRow { y: at });
+        self.record(|| Edit::InsertRow {
+            y: at,
+            text: contents.clone(),
+        });
+        self.long_line_mode |= contents.len() > LONG_LINE_THRESHOLD;
+        self.text.insert((at, 0), &format!("{}\n", contents));
+        self.annotations.insert_line(at);
+    }
+
+    fn insert_char(&mut self, at_row: usize, at: usize, ch: char) {
+        let len = ch.len_utf8();
+        self.note_edit((at_row, at), (at_row, at), (at_row, at + len), len);
+        self.history.record(Edit::DeleteChar { y: at_row, x: at });
+        self.record(|| Edit::InsertChar {
+            y: at_row,
+            x: at,
+            ch,
+        });
+        self.text.insert((at_row, at), ch.encode_utf8(&mut [0; 4]));
+        self.long_line_mode |= self.get_editor_row(at_row).is_long();
+    }
+
+    fn delete_char(&mut self, at_row: usize, at: usize) {
+        let Some(ch) = self.get_row(at_row)[at..].chars().next() else {
+            return;
+        };
+        let len = ch.len_utf8();
+        self.note_edit((at_row, at), (at_row, at + len), (at_row, at), 0);
+        self.record(|| Edit::DeleteChar { y: at_row, x: at });
+        self.text.remove((at_row, at), (at_row, at + len));
+        self.history.record(Edit::InsertChar { y: at_row, x: at, ch });
+    }
+
+    fn set_row(&mut self, at: usize, contents: String) {
+        let old_len = self.get_row(at).len();
+        self.note_edit((at, 0), (at, old_len), (at, contents.len()), contents.len());
+        self.record(|| Edit::SetRow {
+            y: at,
+            text: contents.clone(),
+        });
+        let previous = self.get_row(at).into_owned();
+        self.text.remove((at, 0), (at, previous.len()));
+        self.text.insert((at, 0), &contents);
+        self.long_line_mode |= contents.len() > LONG_LINE_THRESHOLD;
+        self.history.record(Edit::SetRow {
+            y: at,
+            text: previous,
+        });
     }
 
-    fn save(&mut self) -> io::Result<usize> {
+    /// Writes the text to the file, ending it with a newline as
+    /// `trailing_newline` says or else as the file did.
+    fn save(
+        &mut self,
+        progress: &mut Progress,
+        trailing_newline: Option<bool>,
+    ) -> io::Result<usize> {
         match &self.filename {
-            None => Err(io::Error::new(ErrorKind::Other, "no file name specified")),
+            None => Err(io::Error::other("no file name specified")),
             Some(name) => {
-                let mut file = fs::OpenOptions::new().write(true).create(true).open(name)?;
-                let contents: String = self
-                    .row_contents
-                    .iter()
-                    .map(|it| it.row_content.as_str())
-                    .collect::<Vec<&str>>()
-                    .join("\n");
-                file.set_len(contents.len() as u64)?;
-                file.write_all(contents.as_bytes())?;
-                Ok(contents.as_bytes().len())
+                if !self.text.chunks().all(|chunk| self.encoding.fits(chunk)) {
+                    let name = self.encoding.name();
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{} can't hold all of the text (:utf8 converts it)", name),
+                    ));
+                }
+                let rows = self.number_of_rows();
+                let final_newline = trailing_newline.unwrap_or(self.trailing_newline) && rows > 0;
+                // Every row but the last is followed by a newline, and that
+                // one only if the file is to end with one.
+                let len = self.text.len_bytes().saturating_sub(1) + final_newline as usize;
+                let size = atomic::write(name, |file| {
+                    let bom = self.encoding.bom_bytes();
+                    file.write_all(bom)?;
+                    let mut size = bom.len();
+                    let mut written = 0;
+                    for chunk in self.text.chunks() {
+                        let chunk = &chunk[..chunk.len().min(len - written)];
+                        let text = self.line_ending.apply(chunk);
+                        let bytes = self.encoding.encode(&text);
+                        file.write_all(&bytes)?;
+                        size += bytes.len();
+                        written += chunk.len();
+                        progress.update(written as u64, len as u64);
+                    }
+                    Ok(size)
+                })?;
+                self.disk_stamp = Some(journal::file_stamp(name));
+                if let Some(journal) = &mut self.journal {
+                    if journal.reset(name).is_err() {
+                        self.journal = None;
+                    }
+                }
+                self.mixed_endings = false;
+                self.mark_saved();
+                Ok(size)
+            }
+        }
+    }
+
+    /// Drops the highlighting worked out so far, as for a new theme.
+    fn forget_highlights(&mut self) {
+        self.highlights = RowCache::default();
+        #[cfg(feature = "tree-sitter")]
+        {
+            self.syntax_tree_for = None;
+            self.syntax_tree = None;
+        }
+    }
+
+    /// Brings syntax highlighting up to date for the rows before `to`.
+    /// Buffers with huge lines, and huge files, are never highlighted.
+    fn highlight(&mut self, highlighter: &Highlighter, visible: Range<usize>) {
+        if self.long_line_mode || self.large_file {
+            self.highlights = RowCache::default();
+            #[cfg(feature = "tree-sitter")]
+            {
+                self.syntax_tree = None;
+            }
+            return;
+        }
+        #[cfg(feature = "tree-sitter")]
+        {
+            if self.syntax_tree_for != self.filename {
+                self.syntax_tree_for = self.filename.clone();
+                self.syntax_tree = self.filename.as_deref().and_then(SyntaxTree::for_file);
+            }
+            if let Some(mut tree) = self.syntax_tree.take() {
+                tree.highlight(highlighter, self, self.tab_stop, visible);
+                self.syntax_tree = Some(tree);
+                return;
             }
         }
+        let mut highlights = mem::take(&mut self.highlights);
+        highlights.update(
+            highlighter,
+            self.filename.as_deref(),
+            self,
+            self.tab_stop,
+            visible.end,
+        );
+        self.highlights = highlights;
+    }
+
+    /// Highlighting for row `at`, if it has been worked out.
+    fn row_spans(&self, at: usize) -> Option<&[Span]> {
+        #[cfg(feature = "tree-sitter")]
+        if let Some(tree) = &self.syntax_tree {
+            return tree.row(at);
+        }
+        self.highlights.row(at)
+    }
+
+    fn contents(&self) -> String {
+        let mut contents = self.text.contents();
+        // Drop the newline ending the last row.
+        contents.pop();
+        contents
+    }
+
+    fn replace_contents(&mut self, text: &str) {
+        self.dirty += 1;
+        self.highlights.invalidate(0);
+        #[cfg(feature = "tree-sitter")]
+        if let Some(tree) = &mut self.syntax_tree {
+            tree.reset();
+        }
+        self.record(|| Edit::Replace { text: text.into() });
+        self.history.record(Edit::Replace {
+            text: self.contents(),
+        });
+        self.text = text::for_size(text.len());
+        self.append_rows(text);
+    }
+
+    /// Changes how the file is written back, as an edit of its own so that
+    /// it can be undone.
+    fn set_format(&mut self, crlf: bool, bom: bool, mixed: bool) {
+        self.dirty += 1;
+        self.record(|| Edit::SetFormat { crlf, bom, mixed });
+        self.history.record(Edit::SetFormat {
+            crlf: self.line_ending == LineEnding::Crlf,
+            bom: self.encoding.bom,
+            mixed: self.mixed_endings,
+        });
+        self.line_ending = if crlf { LineEnding::Crlf } else { LineEnding::Lf };
+        self.encoding.bom = bom;
+        self.mixed_endings = mixed;
+    }
+
+    /// Changes the encoding the file is written back in, as an edit of its
+    /// own so that it can be undone.
+    fn set_encoding(&mut self, encoding: FileEncoding) {
+        self.dirty += 1;
+        self.record(|| Edit::SetEncoding { encoding });
+        self.history.record(Edit::SetEncoding {
+            encoding: self.encoding,
+        });
+        self.encoding = encoding;
+    }
+
+    /// Adds the lines of `text` after the last row, without recording an
+    /// edit.
+    fn append_rows(&mut self, text: &str) {
+        let mut rows = String::with_capacity(text.len() + 1);
+        for line in text.lines() {
+            self.long_line_mode |= line.len() > LONG_LINE_THRESHOLD;
+            rows.push_str(line);
+            rows.push('\n');
+        }
+        self.text.insert((self.text.rows(), 0), &rows);
+    }
+
+    fn delete_row(&mut self, at: usize) {
+        let len = self.get_row(at).len();
+        if at + 1 < self.number_of_rows() {
+            self.note_edit((at, 0), (at + 1, 0), (at, 0), 0);
+        } else if at > 0 {
+            let end = (at - 1, self.get_row(at - 1).len());
+            self.note_edit(end, (at, len), end, 0);
+        } else {
+            self.note_edit((0, 0), (0, len), (0, 0), 0);
+        }
+        self.record(|| Edit::DeleteRow { y: at });
+        let row = self.get_row(at).into_owned();
+        self.text.remove((at, 0), (at + 1, 0));
+        self.annotations.delete_line(at);
+        self.history.record(Edit::InsertRow { y: at, text: row });
     }
 
     fn join_adjacent_rows(&mut self, at: usize) {
-        let current_row = self.row_contents.remove(at);
-        let previous_row = self.get_editor_row_mut(at - 1);
-        previous_row.row_content.push_str(&current_row.row_content);
-        Self::render_row(previous_row);
+        let end = (at - 1, self.get_row(at - 1).len());
+        self.note_edit(end, (at, 0), end, 0);
+        self.record(|| Edit::JoinRows { y: at });
+        self.history.record(Edit::InsertRow {
+            y: at,
+            text: self.get_row(at).into(),
+        });
+        self.history.record(Edit::SetRow {
+            y: at - 1,
+            text: self.get_row(at - 1).into(),
+        });
+        // Joining is just dropping the newline between the rows.
+        self.text.remove((at - 1, self.get_row(at - 1).len()), (at, 0));
+        self.annotations.delete_line(at);
+        self.long_line_mode |= self.get_editor_row(at - 1).is_long();
+    }
+
+    /// The text between two `(x, y)` positions, `start` first.
+    fn text_range(&self, (start_x, start_y): (usize, usize), (end_x, end_y): (usize, usize)) -> String {
+        self.text.slice((start_y, start_x), (end_y, end_x))
+    }
+
+    fn delete_range(&mut self, (start_x, start_y): (usize, usize), (end_x, end_y): (usize, usize)) {
+        let mut contents = String::from(&self.get_row(start_y)[..start_x]);
+        contents.push_str(&self.get_row(end_y)[end_x..]);
+        for _ in start_y..end_y {
+            self.delete_row(start_y + 1);
+        }
+        self.set_row(start_y, contents);
+    }
+
+    /// Inserts `text` at `(x, y)`, returning the position just after it.
+    fn insert_text(&mut self, (x, y): (usize, usize), text: &str) -> (usize, usize) {
+        if y == self.number_of_rows() {
+            self.insert_row(y, String::new());
+        }
+        let row = &self.get_row(y);
+        let mut lines: Vec<String> = text.split('\n').map(String::from).collect();
+        let end_y = y + lines.len() - 1;
+        let end_x = lines[lines.len() - 1].len() + if end_y == y { x } else { 0 };
+        lines[0].insert_str(0, &row[..x]);
+        let after = String::from(&row[x..]);
+        lines.last_mut().unwrap().push_str(&after);
+        let mut lines = lines.into_iter();
+        self.set_row(y, lines.next().unwrap());
+        for (i, line) in lines.enumerate() {
+            self.insert_row(y + 1 + i, line);
+        }
+        (end_x, end_y)
     }
 }
 
@@ -234,6 +1212,8 @@ struct CursorController {
     row_offset: usize,
     column_offset: usize,
     render_x: usize,
+    /// The other end of the selection, if there is one.
+    anchor: Option<(usize, usize)>,
 }
 
 impl CursorController {
@@ -246,33 +1226,47 @@ impl CursorController {
             row_offset: 0,
             column_offset: 0,
             render_x: 0,
+            anchor: None,
         }
     }
 
-    fn get_render_x(&self, row: &Row) -> usize {
+    fn get_render_x(&self, row: &Row, tab_stop: usize) -> usize {
+        if row.is_long() {
+            return self.cursor_x;
+        }
         row.row_content[..self.cursor_x]
             .chars()
             .fold(0, |render_x, c| {
                 if c == '\t' {
-                    render_x + (TAB_STOP - 1) - (render_x % TAB_STOP) + 1
+                    render_x + (tab_stop - 1) - (render_x % tab_stop) + 1
                 } else {
-                    render_x + 1
+                    render_x + width::char_width(c)
                 }
             })
     }
 
     fn scroll(&mut self, editor_rows: &EditorRows) {
         self.render_x = 0;
+        // Columns taken by the character under the cursor, all of which
+        // should be on screen.
+        let mut cursor_width = 1;
         if self.cursor_y < editor_rows.number_of_rows() {
-            self.render_x = self.get_render_x(editor_rows.get_editor_row(self.cursor_y));
+            let row = editor_rows.get_editor_row(self.cursor_y);
+            self.render_x = self.get_render_x(&row, editor_rows.tab_stop);
+            if !row.is_long() {
+                cursor_width = row.row_content[self.cursor_x..]
+                    .chars()
+                    .next()
+                    .map_or(1, |c| width::char_width(c).max(1));
+            }
         }
         self.row_offset = cmp::min(self.row_offset, self.cursor_y);
         if self.cursor_y >= self.row_offset + self.screen_rows {
             self.row_offset = self.cursor_y - self.screen_rows + 1;
         }
         self.column_offset = cmp::min(self.column_offset, self.render_x);
-        if self.render_x >= self.column_offset + self.screen_columns {
-            self.column_offset = self.render_x - self.screen_columns + 1;
+        if self.render_x + cursor_width > self.column_offset + self.screen_columns {
+            self.column_offset = self.render_x + cursor_width - self.screen_columns;
         }
     }
 
@@ -280,26 +1274,44 @@ impl CursorController {
         let number_of_rows = editor_rows.number_of_rows();
 
         match direction {
-            KeyCode::Up => {
-                self.cursor_y = self.cursor_y.saturating_sub(1);
+            KeyCode::Up | KeyCode::Down => {
+                // Stay in the same screen column rather than at the same
+                // byte, which with multi-byte characters or tabs on either
+                // row could be somewhere else entirely.
+                let column = if self.cursor_y < number_of_rows {
+                    let row = editor_rows.get_editor_row(self.cursor_y);
+                    self.get_render_x(&row, editor_rows.tab_stop)
+                } else {
+                    0
+                };
+                if direction == KeyCode::Up {
+                    self.cursor_y = self.cursor_y.saturating_sub(1);
+                } else if self.cursor_y < number_of_rows {
+                    self.cursor_y += 1;
+                }
+                self.cursor_x = if self.cursor_y < number_of_rows {
+                    let row = editor_rows.get_editor_row(self.cursor_y);
+                    row.content_offset(column, editor_rows.tab_stop)
+                } else {
+                    0
+                };
             }
             KeyCode::Left => {
                 if self.cursor_x != 0 {
-                    self.cursor_x -= 1;
+                    let row = &editor_rows.get_row(self.cursor_y);
+                    self.cursor_x -= grapheme_before(row, self.cursor_x).map_or(1, str::len);
                 } else if self.cursor_y > 0 {
                     self.cursor_y -= 1;
                     self.cursor_x = editor_rows.get_row(self.cursor_y).len();
                 }
             }
-            KeyCode::Down => {
-                if self.cursor_y < number_of_rows {
-                    self.cursor_y += 1;
-                }
-            }
             KeyCode::Right => {
                 if self.cursor_y < number_of_rows {
-                    match self.cursor_x.cmp(&editor_rows.get_row(self.cursor_y).len()) {
-                        Ordering::Less => self.cursor_x += 1,
+                    let row = &editor_rows.get_row(self.cursor_y);
+                    match self.cursor_x.cmp(&row.len()) {
+                        Ordering::Less => {
+                            self.cursor_x += grapheme_after(row, self.cursor_x).map_or(1, str::len)
+                        }
                         Ordering::Equal => {
                             self.cursor_y += 1;
                             self.cursor_x = 0
@@ -316,12 +1328,15 @@ impl CursorController {
             KeyCode::Home => self.cursor_x = 0,
             _ => unimplemented!(),
         }
-        let row_len = if self.cursor_y < number_of_rows {
-            editor_rows.get_row(self.cursor_y).len()
+        let row = if self.cursor_y < number_of_rows {
+            editor_rows.get_row(self.cursor_y)
         } else {
-            0
+            Cow::Borrowed("")
         };
-        self.cursor_x = cmp::min(self.cursor_x, row_len);
+        self.cursor_x = cmp::min(self.cursor_x, row.len());
+        while !row.is_char_boundary(self.cursor_x) {
+            self.cursor_x -= 1;
+        }
     }
 }
 
@@ -357,67 +1372,434 @@ impl io::Write for EditorContents {
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        let out = write!(stdout(), "{}", self.content);
-        stdout().flush()?;
+        let out = write!(screen(), "{}", self.content);
+        screen().flush()?;
         self.content.clear();
         out
     }
 }
 
-struct Output {
-    win_size: (usize, usize),
-    editor_contents: EditorContents,
-    cursor_controller: CursorController,
-    editor_rows: EditorRows,
-    status_message: StatusMessage,
-    dirty: u64,
+/// A buffer's name as shown on its tab, marked with `*` while modified.
+fn tab_label(buffer: &Buffer) -> String {
+    let modified = if buffer.editor_rows.dirty > 0 { "*" } else { "" };
+    format!(" {}{} ", buffer.display_name(), modified)
 }
 
-impl Output {
-    fn new() -> Self {
-        let win_size = terminal::size()
-            .map(|(x, y)| (x as usize, y as usize - 2))
-            .unwrap();
-        Self {
-            win_size,
-            editor_contents: EditorContents::new(),
-            cursor_controller: CursorController::new(win_size),
-            editor_rows: EditorRows::new(),
-            status_message: StatusMessage::new("HELP: Ctrl-S = Save | Ctrl-Q = Quit ".into()),
-            dirty: 0,
-        }
+/// `render[start..end]`, coloured if the row is highlighted.
+fn render_segment(render: &str, start: usize, end: usize, spans: Option<&[Span]>) -> String {
+    match spans {
+        Some(spans) => highlight::styled(render, start, end, spans),
+        None => render[start..end].into(),
     }
+}
 
-    fn clear_screen() -> crossterm::Result<()> {
-        execute!(stdout(), terminal::Clear(ClearType::All))?;
-        execute!(stdout(), cursor::MoveTo(0, 0))
+/// Like `render_segment`, with the inline hints at offsets in
+/// `start..end` drawn before the text they precede.
+fn render_hinted(
+    render: &str,
+    start: usize,
+    end: usize,
+    spans: Option<&[Span]>,
+    hints: &[(usize, &str)],
+) -> String {
+    let mut rendered = String::new();
+    let mut at = start;
+    for &(offset, text) in hints.iter().filter(|(offset, _)| (start..end).contains(offset)) {
+        rendered.push_str(&render_segment(render, at, offset, spans));
+        rendered.push_str(&render_hint(text));
+        at = offset;
     }
+    rendered.push_str(&render_segment(render, at, end, spans));
+    rendered
+}
 
-    fn draw_message_bar(&mut self) {
-        queue!(
-            self.editor_contents,
-            terminal::Clear(ClearType::UntilNewLine)
-        )
-        .unwrap();
-        if let Some(msg) = self.status_message.message() {
-            self.editor_contents
-                .push_str(&msg[..cmp::min(self.win_size.0, msg.len())]);
-        }
-    }
+fn render_hint(text: &str) -> String {
+    format!(
+        "{}{}{}",
+        style::SetForegroundColor(style::Color::DarkGrey),
+        text,
+        style::SetForegroundColor(style::Color::Reset)
+    )
+}
 
-    fn delete_char(&mut self) {
+/// A typographic substitution made in prose mode, and what was typed.
+struct Substitution {
+    start: (usize, usize),
+    end: (usize, usize),
+    typed: String,
+}
+
+/// Where the last paste went, and the text it could have been instead:
+/// the clipboard as copied, or re-indented for the cursor's line.
+struct LastPaste {
+    start: (usize, usize),
+    end: (usize, usize),
+    other: String,
+}
+
+/// A cursor besides the main one, with its own selection anchor.
+#[derive(Clone, Copy, PartialEq)]
+struct Caret {
+    position: (usize, usize),
+    anchor: Option<(usize, usize)>,
+}
+
+/// Where a file given on the command line opens.
+enum StartAt {
+    /// The first match of a regex, from `+/pattern`.
+    Match(String),
+    /// A line and perhaps a column, counting from 1, from `+line` or
+    /// `file:line:column`.
+    Position { line: usize, column: Option<usize> },
+}
+
+impl StartAt {
+    /// Reads `+/pattern` or `+line`.
+    fn parse(arg: &str) -> Option<Self> {
+        let rest = arg.strip_prefix('+')?;
+        match rest.strip_prefix('/') {
+            Some(pattern) => Some(StartAt::Match(pattern.into())),
+            None => Some(StartAt::Position {
+                line: rest.parse().ok()?,
+                column: None,
+            }),
+        }
+    }
+}
+
+/// A path typed at a prompt, where `~/` stands for the home directory.
+fn typed_path(file: &str) -> PathBuf {
+    match file.strip_prefix("~/") {
+        Some(rest) => config::home_dir().join(rest),
+        None => PathBuf::from(file),
+    }
+}
+
+/// The buffer for a file named on the command line, where `-` is what was
+/// piped in.
+fn open_arg(file: PathBuf, stdin: Option<&[u8]>) -> io::Result<EditorRows> {
+    match stdin {
+        Some(bytes) if file == Path::new("-") => Ok(EditorRows::from_stdin(bytes)),
+        _ => EditorRows::open(file),
+    }
+}
+
+/// Splits `file:line` or `file:line:column`, as compilers and `grep -n`
+/// print them (with or without a colon after), into the file and where to
+/// open it. A file that exists under the whole name is taken as it is.
+fn file_position(arg: &str) -> (PathBuf, Option<StartAt>) {
+    let whole = (PathBuf::from(arg), None);
+    if Path::new(arg).exists() {
+        return whole;
+    }
+    let trimmed = arg.strip_suffix(':').unwrap_or(arg);
+    let mut numbers = Vec::new();
+    let mut file = trimmed;
+    while numbers.len() < 2 {
+        let Some((rest, number)) = file.rsplit_once(':') else {
+            break;
+        };
+        let Ok(number) = number.parse() else {
+            break;
+        };
+        numbers.insert(0, number);
+        file = rest;
+    }
+    match numbers[..] {
+        [line] => (file.into(), Some(StartAt::Position { line, column: None })),
+        [line, column] => {
+            let column = Some(column);
+            (file.into(), Some(StartAt::Position { line, column }))
+        }
+        _ => whole,
+    }
+}
+
+/// A view-only pane over another part of a buffer, shown above the rows
+/// being edited and scrolled on its own.
+#[derive(Clone, Copy)]
+struct View {
+    row_offset: usize,
+}
+
+/// The `:pipe` playground: the pipeline last run over the buffer and what
+/// it printed, or why it failed, shown in place of the text.
+struct Pipe {
+    command: String,
+    output: Result<String, String>,
+}
+
+/// The `--diff` view: two buffers side by side, lined up so the lines they
+/// share are across from each other, and scrolled together.
+struct Compare {
+    old: usize,
+    new: usize,
+    rows: Vec<Pair>,
+    /// The first of `rows` on screen.
+    top: usize,
+}
+
+/// How a buffer's last save went, for `:lastsave` and the status bar.
+struct LastSave {
+    at: SystemTime,
+    /// The bytes written, or why the file wasn't.
+    result: Result<usize, String>,
+}
+
+impl LastSave {
+    fn new(result: Result<usize, String>) -> Self {
+        Self {
+            at: SystemTime::now(),
+            result,
+        }
+    }
+
+    fn ago(&self) -> String {
+        notify::ago(self.at.elapsed().unwrap_or_default())
+    }
+}
+
+struct Buffer {
+    editor_rows: EditorRows,
+    cursor_controller: CursorController,
+    /// Further cursors, created by `:select`. Motions and plain edits
+    /// happen at all of them; anything else drops them.
+    extra_cursors: Vec<Caret>,
+    last_paste: Option<LastPaste>,
+    /// Whether typed quotes, dashes and dots become their typographic
+    /// forms.
+    prose: bool,
+    last_substitution: Option<Substitution>,
+    scratch: bool,
+    /// Set for the buffer `:help` shows its text in.
+    help: bool,
+    read_only: bool,
+    lock: Option<FileLock>,
+    /// Set for files opened with merge conflicts in them, which are then
+    /// looked for again as they are resolved.
+    merging: bool,
+    /// The split's view, while one is open.
+    view: Option<View>,
+    /// The recovery copy of a buffer without a file, once it has changes.
+    swap: Option<Swap>,
+    /// The version on disk (as `journal::file_stamp` has it) last asked
+    /// about, so each change is only brought up once.
+    disk_asked: Option<String>,
+    /// Set while `:follow` reloads the file whenever it is written.
+    follow: Option<Follow>,
+    /// Set while `:blame` shows who last changed lines.
+    blame: Option<Blame>,
+    /// The text as it was when the editor last went idle, for
+    /// `:sincebreak`.
+    before_break: Option<Box<dyn TextBuffer>>,
+    last_save: Option<LastSave>,
+}
+
+impl Buffer {
+    fn new(mut editor_rows: EditorRows, win_size: (usize, usize), config: &Config) -> Self {
+        let indentation = Indentation::for_file(editor_rows.filename.as_deref(), config);
+        editor_rows.set_tab_stop(indentation.tab_stop);
+        editor_rows
+            .history
+            .set_limits(config.undo_max_entries, config.undo_max_bytes);
+        let prose = config.prose && FileType::allows_prose(editor_rows.filename.as_deref());
+        // Files with merge conflicts (as `git mergetool` hands over) open
+        // at the first one.
+        let conflicts = if editor_rows.large_file {
+            Vec::new()
+        } else {
+            conflict::find((0..editor_rows.number_of_rows()).map(|at| editor_rows.get_row(at)))
+        };
+        let mut cursor_controller = CursorController::new(win_size);
+        if let Some(first) = conflicts.first() {
+            cursor_controller.cursor_y = first.start;
+        }
+        Self {
+            editor_rows,
+            cursor_controller,
+            extra_cursors: Vec::new(),
+            last_paste: None,
+            prose,
+            last_substitution: None,
+            scratch: false,
+            help: false,
+            read_only: false,
+            lock: None,
+            merging: !conflicts.is_empty(),
+            view: None,
+            swap: None,
+            disk_asked: None,
+            follow: None,
+            blame: None,
+            before_break: None,
+            last_save: None,
+        }
+    }
+
+    /// The buffer's type if it is JSON, YAML or TOML and small enough to
+    /// parse whole after a paste or before a save.
+    fn structured_type(&self) -> Option<&'static str> {
+        if self.editor_rows.large_file || self.editor_rows.loader.is_some() {
+            return None;
+        }
+        let filetype = FileType::detect(self.editor_rows.filename.as_deref()?)?;
+        validate::is_structured(filetype.name).then_some(filetype.name)
+    }
+
+    fn conflicts(&self) -> Vec<Conflict> {
+        if !self.merging {
+            return Vec::new();
+        }
+        let editor_rows = &self.editor_rows;
+        conflict::find((0..editor_rows.number_of_rows()).map(|at| editor_rows.get_row(at)))
+    }
+
+    /// The keys for dealing with the conflict at the cursor, while any are
+    /// left.
+    fn conflict_hint(&self) -> Option<String> {
+        let conflicts = self.conflicts();
+        let cursor_y = self.cursor_controller.cursor_y;
+        let hint = match conflicts.iter().position(|conflict| conflict.contains(cursor_y)) {
+            Some(at) => format!(
+                "Conflict {}/{}: Alt-1 ours, Alt-2 theirs, Alt-3 both{}, Alt-n/p move",
+                at + 1,
+                conflicts.len(),
+                if conflicts[at].base.is_some() { ", Alt-4 base" } else { "" }
+            ),
+            None if conflicts.is_empty() => return None,
+            None => format!("{} conflicts left: Alt-n/p to move between them", conflicts.len()),
+        };
+        Some(hint)
+    }
+
+    /// Replaces the conflict around the cursor with the rows of `side`,
+    /// giving what went wrong if it can't.
+    fn resolve_conflict(&mut self, side: Side) -> Result<(), &'static str> {
+        let cursor_y = self.cursor_controller.cursor_y;
+        let conflicts = self.conflicts();
+        let conflict = conflicts
+            .iter()
+            .find(|conflict| conflict.contains(cursor_y))
+            .ok_or("Not in a merge conflict")?;
+        let kept = conflict
+            .kept(side)
+            .ok_or("This conflict has no base (see git's diff3 conflict style)")?;
+        for at in (conflict.start..=conflict.end).rev() {
+            if !kept.contains(&at) {
+                self.editor_rows.delete_row(at);
+            }
+        }
+        self.cursor_controller.cursor_y = conflict.start;
+        self.cursor_controller.cursor_x = 0;
+        self.clamp_cursor();
+        Ok(())
+    }
+
+    /// Moves to the start of the next or previous conflict, if there is
+    /// one.
+    fn goto_conflict(&mut self, forward: bool) -> bool {
+        let cursor_y = self.cursor_controller.cursor_y;
+        let conflicts = self.conflicts();
+        let target = if forward {
+            conflicts.iter().find(|conflict| conflict.start > cursor_y)
+        } else {
+            conflicts.iter().rev().find(|conflict| conflict.start < cursor_y)
+        };
+        match target {
+            Some(conflict) => {
+                self.cursor_controller.cursor_y = conflict.start;
+                self.cursor_controller.cursor_x = 0;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// An unnamed buffer for throwaway text. Unsaved changes in a scratch
+    /// buffer never hold up quitting; it is only written out on request.
+    fn scratch(win_size: (usize, usize), config: &Config) -> Self {
+        Self {
+            scratch: true,
+            ..Self::new(EditorRows::empty(), win_size, config)
+        }
+    }
+
+    fn display_name(&self) -> &str {
+        self.editor_rows
+            .filename
+            .as_ref()
+            .and_then(|path| path.file_name())
+            .and_then(|name| name.to_str())
+            .unwrap_or(match (self.help, self.scratch) {
+                (true, _) => "[Help]",
+                (false, true) => "[Scratch]",
+                (false, false) => "[No Name]",
+            })
+    }
+
+    /// Whether the file is still loading and should be read further now.
+    fn wants_more(&self) -> bool {
+        let editor_rows = &self.editor_rows;
+        editor_rows.loader.is_some()
+            && (!editor_rows.on_demand
+                || self.cursor_controller.cursor_y + READ_AHEAD > editor_rows.number_of_rows())
+    }
+
+    fn has_unsaved_changes(&self) -> bool {
+        self.editor_rows.dirty > 0 && !self.scratch
+    }
+
+    /// Whether this is the empty, untouched buffer the editor starts with
+    /// when given no files, which opening one replaces.
+    fn is_blank(&self) -> bool {
+        self.editor_rows.filename.is_none()
+            && !self.scratch
+            && !self.help
+            && self.editor_rows.dirty == 0
+            && self.editor_rows.number_of_rows() == 0
+    }
+
+    /// Whether the buffer needs a swap file: it has changes, but no file
+    /// for the journal to keep them against.
+    fn wants_swap(&self) -> bool {
+        self.editor_rows.filename.is_none() && self.has_unsaved_changes() && !self.help
+    }
+
+    /// Whether the buffer has changes its swap file hasn't caught up with.
+    fn swap_behind(&self) -> bool {
+        self.wants_swap()
+            && self
+                .swap
+                .as_ref()
+                .is_none_or(|swap| swap.is_behind(self.editor_rows.dirty))
+    }
+
+    /// Whether autosaving should write the buffer: a modified file that
+    /// can be saved without asking anything.
+    fn wants_autosave(&self) -> bool {
+        self.editor_rows.filename.is_some()
+            && self.has_unsaved_changes()
+            && !self.read_only
+            && self.editor_rows.loader.is_none()
+            && !self.editor_rows.changed_on_disk()
+    }
+
+    fn delete_char(&mut self) {
         if self.cursor_controller.cursor_y == self.editor_rows.number_of_rows() {
             return;
         }
         if self.cursor_controller.cursor_y == 0 && self.cursor_controller.cursor_x == 0 {
             return;
         }
-        let row = self
-            .editor_rows
-            .get_editor_row_mut(self.cursor_controller.cursor_y);
         if self.cursor_controller.cursor_x > 0 {
-            row.delete_char(self.cursor_controller.cursor_x - 1);
-            self.cursor_controller.cursor_x -= 1;
+            let (x, y) = (self.cursor_controller.cursor_x, self.cursor_controller.cursor_y);
+            let row = &self.editor_rows.get_row(y);
+            let grapheme = grapheme_before(row, x).unwrap_or_default();
+            let (len, chars) = (grapheme.len(), grapheme.chars().count());
+            for _ in 0..chars {
+                self.editor_rows.delete_char(y, x - len);
+            }
+            self.cursor_controller.cursor_x -= len;
         } else {
             let previous_row_content = self
                 .editor_rows
@@ -427,268 +1809,5547 @@ impl Output {
                 .join_adjacent_rows(self.cursor_controller.cursor_y);
             self.cursor_controller.cursor_y -= 1;
         }
-        self.dirty += 1;
     }
 
-    fn insert_newline(&mut self) {
-        if self.cursor_controller.cursor_x == 0 {
-            self.editor_rows
-                .insert_row(self.cursor_controller.cursor_y, String::new())
+    /// Breaks the line at the cursor. With `auto_indent` the new line
+    /// starts with the indentation of the old one, as much of it as lies
+    /// before the cursor.
+    fn insert_newline(&mut self, auto_indent: bool) {
+        let (x, y) = (self.cursor_controller.cursor_x, self.cursor_controller.cursor_y);
+        let mut indent = String::new();
+        if x == 0 {
+            self.editor_rows.insert_row(y, String::new())
         } else {
-            let current_row = self
-                .editor_rows
-                .get_editor_row_mut(self.cursor_controller.cursor_y);
-            let new_row_content = current_row.row_content[self.cursor_controller.cursor_x..].into();
-            current_row
-                .row_content
-                .truncate(self.cursor_controller.cursor_x);
-            EditorRows::render_row(current_row);
-            self.editor_rows
-                .insert_row(self.cursor_controller.cursor_y + 1, new_row_content);
+            let current_row = &self.editor_rows.get_row(y);
+            if auto_indent {
+                let leading = indent::leading_whitespace(current_row);
+                indent = leading[..leading.len().min(x)].into();
+            }
+            let new_row_content = format!("{}{}", indent, &current_row[x..]);
+            let current_row_content = current_row[..x].into();
+            self.editor_rows.set_row(y, current_row_content);
+            self.editor_rows.insert_row(y + 1, new_row_content);
         }
-        self.cursor_controller.cursor_x = 0;
+        self.cursor_controller.cursor_x = indent.len();
         self.cursor_controller.cursor_y += 1;
-        self.dirty += 1;
     }
 
     fn insert_char(&mut self, ch: char) {
         if self.cursor_controller.cursor_y == self.editor_rows.number_of_rows() {
             self.editor_rows
                 .insert_row(self.editor_rows.number_of_rows(), String::new());
-            self.dirty += 1;
         }
-        self.editor_rows
-            .get_editor_row_mut(self.cursor_controller.cursor_y)
-            .insert_char(self.cursor_controller.cursor_x, ch);
-        self.cursor_controller.cursor_x += 1;
-        self.dirty += 1;
-    }
-
-    fn draw_status_bar(&mut self) {
-        self.editor_contents
-            .push_str(&style::Attribute::Reverse.to_string());
-        let info = format!(
-            "{} {} -- {} lines",
-            self.editor_rows
-                .filename
-                .as_ref()
-                .and_then(|path| path.file_name())
-                .and_then(|name| name.to_str())
-                .unwrap_or("[No Name]"),
-            if self.dirty > 0 { "(modified)" } else { "" },
-            self.editor_rows.number_of_rows()
-        );
-        let info_len = cmp::min(info.len(), self.win_size.0);
-        let line_info = format!(
-            "{}/{}",
-            self.cursor_controller.cursor_y + 1,
-            self.editor_rows.number_of_rows()
+        self.editor_rows.insert_char(
+            self.cursor_controller.cursor_y,
+            self.cursor_controller.cursor_x,
+            ch,
         );
-        self.editor_contents.push_str(&info[..info_len]);
-        for i in info_len..self.win_size.0 {
-            if self.win_size.0 - i == line_info.len() {
-                self.editor_contents.push_str(&line_info);
-                break;
-            } else {
-                self.editor_contents.push(' ')
-            }
-        }
-        self.editor_contents
-            .push_str(&style::Attribute::Reset.to_string());
-        self.editor_contents.push_str("\r\n");
+        self.cursor_controller.cursor_x += ch.len_utf8();
     }
 
-    fn draw_rows(&mut self) {
-        let screen_rows = self.win_size.1;
-        let screen_columns = self.win_size.0;
-    
-        // Draw the title "Rustext" at the top
-        let title = "Rustext";
-        let padding = screen_columns.saturating_sub(title.len()) / 2;
-        let space = " ".repeat(padding);
-        self.editor_contents.push_str(&format!("{}{}\r\n", space, title));
-    
-        // Start rendering the file content from the second row
-        for i in 1..screen_rows {
-            let file_row = i + self.cursor_controller.row_offset - 1;
-            if file_row < self.editor_rows.number_of_rows() {
-                let row = self.editor_rows.get_render(file_row);
-                let column_offset = self.cursor_controller.column_offset;
-                let len = cmp::min(row.len().saturating_sub(column_offset), screen_columns);
-                let start = if len == 0 { 0 } else { column_offset };
-                self.editor_contents.push_str(&row[start..start + len]);
-            } else {
-                self.editor_contents.push_str(&format!("{:<5}", file_row));
-            }
-            queue!(
-                self.editor_contents,
-                terminal::Clear(ClearType::UntilNewLine)
-            )
-            .unwrap();
-            self.editor_contents.push_str("\r\n");
+    /// Inserts a tab, or with `expand_tab` enough spaces to reach the next
+    /// multiple of `indent_width`, as the file's type has them.
+    fn insert_tab(&mut self, config: &Config) {
+        let indentation = Indentation::for_file(self.editor_rows.filename.as_deref(), config);
+        if !indentation.expand_tab {
+            return self.insert_char('\t');
         }
+        let cursor = &self.cursor_controller;
+        let render_x = if cursor.cursor_y < self.editor_rows.number_of_rows() {
+            cursor.get_render_x(
+                &self.editor_rows.get_editor_row(cursor.cursor_y),
+                self.editor_rows.tab_stop,
+            )
+        } else {
+            0
+        };
+        let width = indentation.indent_width - render_x % indentation.indent_width;
+        (0..width).for_each(|_| self.insert_char(' '));
     }
-    
-    
-    
 
-    fn move_cursor(&mut self, direction: KeyCode) {
-        self.cursor_controller
-            .move_cursor(direction, &self.editor_rows);
+    /// The selected range, start first, kept within the rows in case the
+    /// buffer changed under it.
+    fn selection(&self) -> Option<((usize, usize), (usize, usize))> {
+        self.ordered_range(self.cursor_controller.anchor?, self.cursor_position())
     }
 
-    fn refresh_screen(&mut self) -> crossterm::Result<()> {
-        self.cursor_controller.scroll(&self.editor_rows);
-        queue!(self.editor_contents, cursor::Hide, cursor::MoveTo(0, 0))?;
-        self.draw_rows();
-        self.draw_status_bar();
-        self.draw_message_bar();
-        let cursor_x = self.cursor_controller.render_x - self.cursor_controller.column_offset;
-        let cursor_y = self.cursor_controller.cursor_y - self.cursor_controller.row_offset;
-        queue!(
-            self.editor_contents,
-            cursor::MoveTo(cursor_x as u16, cursor_y as u16),
-            cursor::Show
-        )?;
-        self.editor_contents.flush()
+    /// The range between two positions, start first, or `None` if it is
+    /// empty.
+    fn ordered_range(
+        &self,
+        anchor: (usize, usize),
+        cursor: (usize, usize),
+    ) -> Option<((usize, usize), (usize, usize))> {
+        let number_of_rows = self.editor_rows.number_of_rows();
+        let clamp = |(x, y): (usize, usize)| {
+            if y >= number_of_rows {
+                let last = number_of_rows.checked_sub(1)?;
+                return Some((self.editor_rows.get_row(last).len(), last));
+            }
+            let row = &self.editor_rows.get_row(y);
+            let mut x = cmp::min(x, row.len());
+            while !row.is_char_boundary(x) {
+                x -= 1;
+            }
+            Some((x, y))
+        };
+        let anchor = clamp(anchor)?;
+        let cursor = clamp(cursor)?;
+        match (anchor.1, anchor.0).cmp(&(cursor.1, cursor.0)) {
+            Ordering::Less => Some((anchor, cursor)),
+            Ordering::Greater => Some((cursor, anchor)),
+            Ordering::Equal => None,
+        }
     }
-}
-
-struct Reader;
 
-impl Reader {
-    fn read_key(&self) -> crossterm::Result<KeyEvent> {
-        loop {
-            if event::poll(Duration::from_millis(500))? {
-                if let Event::Key(event) = event::read()? {
-                    return Ok(event);
+    /// What `draw_rows` shows in reverse video: every selection, and the
+    /// character under each extra cursor that has none.
+    fn marked_ranges(&self) -> Vec<((usize, usize), (usize, usize))> {
+        let number_of_rows = self.editor_rows.number_of_rows();
+        let mut marked: Vec<_> = self.selection().into_iter().collect();
+        for caret in &self.extra_cursors {
+            let (x, y) = caret.position;
+            match caret.anchor.and_then(|anchor| self.ordered_range(anchor, caret.position)) {
+                Some(range) => marked.push(range),
+                None if y < number_of_rows => {
+                    let row = &self.editor_rows.get_row(y);
+                    match row.get(x..).and_then(|rest| rest.chars().next()) {
+                        Some(ch) => marked.push(((x, y), (x + ch.len_utf8(), y))),
+                        None => marked.push(((x, y), (0, y + 1))),
+                    }
                 }
+                None => {}
             }
         }
+        marked
     }
-}
-
-struct Editor {
-    reader: Reader,
-    output: Output,
-    quit_times: u8,
-}
 
-impl Editor {
-    fn new() -> Self {
-        Self {
-            reader: Reader,
-            output: Output::new(),
-            quit_times: QUIT_TIMES,
+    /// Puts a cursor at the end of each match of `matcher` within the
+    /// selection (or the whole buffer), selecting the match, and returns
+    /// how many there are.
+    fn select_matches(&mut self, matcher: &Matcher) -> usize {
+        let number_of_rows = self.editor_rows.number_of_rows();
+        let ((start_x, start_y), (end_x, end_y)) = match self.selection() {
+            Some(range) => range,
+            None if number_of_rows > 0 => {
+                let last = number_of_rows - 1;
+                ((0, 0), (self.editor_rows.get_row(last).len(), last))
+            }
+            None => return 0,
+        };
+        let mut carets = Vec::new();
+        for y in start_y..=end_y {
+            let row = &self.editor_rows.get_row(y);
+            let from = if y == start_y { start_x } else { 0 };
+            let to = if y == end_y { end_x } else { row.len() };
+            carets.extend(
+                matcher
+                    .find_ranges(row)
+                    .into_iter()
+                    .filter(|range| !range.is_empty() && range.start >= from && range.end <= to)
+                    .map(|range| Caret {
+                        position: (range.end, y),
+                        anchor: Some((range.start, y)),
+                    }),
+            );
+        }
+        if carets.is_empty() {
+            return 0;
         }
+        let main = carets.remove(0);
+        (self.cursor_controller.cursor_x, self.cursor_controller.cursor_y) = main.position;
+        self.cursor_controller.anchor = main.anchor;
+        self.extra_cursors = carets;
+        self.extra_cursors.len() + 1
     }
 
-    fn process_keypress(&mut self) -> crossterm::Result<bool> {
-        match self.reader.read_key()? {
-            KeyEvent {
-                code: KeyCode::Char('q'),
-                modifiers: KeyModifiers::CONTROL,
-            } => {
-                if self.output.dirty > 0 && self.quit_times > 0 {
-                    self.output.status_message.set_message(format!(
-                        "WARNING!!! File has unsaved changes. Press Ctrl-Q {} more times to quit.",
-                        self.quit_times
-                    ));
-                    self.quit_times -= 1;
-                    return Ok(true);
-                }
-                return Ok(false);
+    fn selected_text(&self) -> Option<String> {
+        let (start, end) = self.selection()?;
+        Some(self.editor_rows.text_range(start, end))
+    }
+
+    /// The lines of the block the selection spans, from the screen column
+    /// of one end to that of the other, so tabs and wide characters before
+    /// it don't shift it. With both ends in the same column the lines are
+    /// taken whole.
+    fn selected_block(&self) -> Option<Vec<String>> {
+        let ((start_x, start_y), (end_x, end_y)) = self.selection()?;
+        let tab_stop = self.editor_rows.tab_stop;
+        let column = |x: usize, y: usize| {
+            let row = self.editor_rows.get_editor_row(y);
+            if row.is_long() {
+                return x;
             }
-            KeyEvent {
-                code:
-                    direction @ (KeyCode::Up
-                    | KeyCode::Down
-                    | KeyCode::Left
-                    | KeyCode::Right
-                    | KeyCode::Home
-                    | KeyCode::End),
-                modifiers: KeyModifiers::NONE,
-            } => self.output.move_cursor(direction),
-            KeyEvent {
-                code: val @ (KeyCode::PageUp | KeyCode::PageDown),
-                modifiers: KeyModifiers::NONE,
-            } => {
-                if matches!(val, KeyCode::PageUp) {
-                    self.output.cursor_controller.cursor_y =
-                        self.output.cursor_controller.row_offset
+            row.row_content[..x].chars().fold(0, |render_x, c| {
+                if c == '\t' {
+                    render_x + (tab_stop - 1) - (render_x % tab_stop) + 1
                 } else {
-                    self.output.cursor_controller.cursor_y = cmp::min(
-                        self.output.win_size.1 + self.output.cursor_controller.row_offset - 1,
-                        self.output.editor_rows.number_of_rows(),
-                    );
+                    render_x + width::char_width(c)
                 }
-                (0..self.output.win_size.1).for_each(|_| {
-                    self.output.move_cursor(if matches!(val, KeyCode::PageUp) {
-                        KeyCode::Up
-                    } else {
-                        KeyCode::Down
-                    });
-                })
-            }
-            KeyEvent {
-                code: KeyCode::Char('s'),
-                modifiers: KeyModifiers::CONTROL,
-            } => {
-                if matches!(self.output.editor_rows.filename, None) {
-                    let prompt = prompt!(&mut self.output, "Save as : {} (ESC to cancel)")
-                        .map(|it| it.into());
-                    if let None = prompt {
-                        self.output
-                            .status_message
-                            .set_message("Save Aborted".into());
-                        return Ok(true);
-                    }
-                    self.output.editor_rows.filename = prompt
-                }
-                self.output.editor_rows.save().map(|len| {
-                    self.output
-                        .status_message
-                        .set_message(format!("{} bytes written to disk", len));
-                    self.output.dirty = 0
-                })?;
-            }
-            KeyEvent {
-                code: key @ (KeyCode::Backspace | KeyCode::Delete),
-                modifiers: KeyModifiers::NONE,
-            } => {
-                if matches!(key, KeyCode::Delete) {
-                    self.output.move_cursor(KeyCode::Right)
+            })
+        };
+        let (left, right) = (column(start_x, start_y), column(end_x, end_y));
+        let (left, right) = (left.min(right), left.max(right));
+        let end_y = match left == right && end_x == 0 && end_y > start_y {
+            true => end_y - 1,
+            false => end_y,
+        };
+        let block = (start_y..=end_y)
+            .map(|y| {
+                let row = self.editor_rows.get_editor_row(y);
+                if left == right {
+                    return row.row_content.into_owned();
                 }
-                self.output.delete_char()
+                // Cut by screen column but from the text itself, so the
+                // tabs export splits fields at are still there.
+                let from = row.content_offset(left, tab_stop);
+                let to = row.content_offset(right, tab_stop);
+                row.row_content[from..to].to_string()
+            })
+            .collect();
+        Some(block)
+    }
+
+    /// Deletes the selection, if any, leaving the cursor where it started.
+    fn delete_selection(&mut self) -> bool {
+        let selection = self.selection();
+        self.cursor_controller.anchor = None;
+        match selection {
+            Some((start, end)) => {
+                self.editor_rows.delete_range(start, end);
+                (self.cursor_controller.cursor_x, self.cursor_controller.cursor_y) = start;
+                true
             }
-            KeyEvent {
-                code: KeyCode::Enter,
-                modifiers: KeyModifiers::NONE,
-            } => self.output.insert_newline(),
-            KeyEvent {
-                code: code @ (KeyCode::Char(..) | KeyCode::Tab),
-                modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
-            } => self.output.insert_char(match code {
-                KeyCode::Tab => '\t',
-                KeyCode::Char(ch) => ch,
-                _ => unreachable!(),
-            }),
-            _ => {}
+            None => false,
         }
-        self.quit_times = QUIT_TIMES;
-        Ok(true)
     }
 
-    fn run(&mut self) -> crossterm::Result<bool> {
-        self.output.refresh_screen()?;
-        self.process_keypress()
+    /// Moves the cursor a character along its line, without going past
+    /// either end.
+    fn move_along_line(&mut self, forward: bool) {
+        let (x, y) = self.cursor_position();
+        let len = if y < self.editor_rows.number_of_rows() {
+            self.editor_rows.get_row(y).len()
+        } else {
+            0
+        };
+        self.cursor_controller.anchor = None;
+        if forward && x < len {
+            self.move_cursor(KeyCode::Right);
+        } else if !forward && x > 0 {
+            self.move_cursor(KeyCode::Left);
+        }
     }
-}
 
-fn main() -> crossterm::Result<()> {
-    let _clean_up = CleanUp;
-    terminal::enable_raw_mode()?;
-    let mut editor = Editor::new();
-    while editor.run()? {}
+    /// Deletes up to `count` characters from the cursor on, stopping at the
+    /// end of the line.
+    fn delete_chars(&mut self, count: usize) {
+        let (x, y) = self.cursor_position();
+        if y >= self.editor_rows.number_of_rows() {
+            return;
+        }
+        let row = self.editor_rows.get_row(y);
+        let end = row[x..]
+            .char_indices()
+            .nth(count)
+            .map_or(row.len(), |(at, _)| x + at);
+        if end > x {
+            self.editor_rows.delete_range((x, y), (end, y));
+        }
+    }
+
+    /// The `count` lines from the cursor's on, each with its line break.
+    fn lines_text(&self, count: usize) -> Option<String> {
+        let y = self.cursor_controller.cursor_y;
+        let end = cmp::min(y + count, self.editor_rows.number_of_rows());
+        if y >= end {
+            return None;
+        }
+        let mut text = String::new();
+        for row in y..end {
+            text.push_str(&self.editor_rows.get_row(row));
+            text.push('\n');
+        }
+        Some(text)
+    }
+
+    /// Deletes the `count` lines from the cursor's on, returning them.
+    fn delete_lines(&mut self, count: usize) -> Option<String> {
+        let text = self.lines_text(count)?;
+        let rows = &mut self.editor_rows;
+        let y = self.cursor_controller.cursor_y;
+        let last = cmp::min(y + count, rows.number_of_rows()) - 1;
+        if last + 1 < rows.number_of_rows() {
+            rows.delete_range((0, y), (0, last + 1));
+        } else if y > 0 {
+            // The last line has no break of its own to take; take the one
+            // before the first line instead.
+            let end = rows.get_row(last).len();
+            rows.delete_range((rows.get_row(y - 1).len(), y - 1), (end, last));
+        } else {
+            let end = rows.get_row(last).len();
+            rows.delete_range((0, 0), (end, last));
+        }
+        let last = self.editor_rows.number_of_rows().saturating_sub(1);
+        self.cursor_controller.anchor = None;
+        self.cursor_controller.cursor_x = 0;
+        self.cursor_controller.cursor_y = cmp::min(y, last);
+        self.clamp_cursor();
+        Some(text)
+    }
+
+    /// Puts whole `lines` (each ending in a line break) below the cursor's
+    /// line or above it, with the cursor at the first of them.
+    fn put_lines(&mut self, lines: &str, below: bool) {
+        let rows = &mut self.editor_rows;
+        let y = cmp::min(self.cursor_controller.cursor_y, rows.number_of_rows());
+        let y = if below { cmp::min(y + 1, rows.number_of_rows()) } else { y };
+        if y == rows.number_of_rows() && y > 0 {
+            // Below the last line, which has no break to put them after.
+            let end = rows.get_row(y - 1).len();
+            let text = format!("\n{}", lines.strip_suffix('\n').unwrap_or(lines));
+            rows.insert_text((end, y - 1), &text);
+        } else {
+            rows.insert_text((0, y), lines);
+        }
+        self.cursor_controller.anchor = None;
+        (self.cursor_controller.cursor_x, self.cursor_controller.cursor_y) = (0, y);
+        self.clamp_cursor();
+    }
+
+    /// Replaces the selection, if any, with `text`, re-indenting it first
+    /// if `smart` is set.
+    fn paste(&mut self, text: &str, smart: bool) {
+        self.delete_selection();
+        let start = self.cursor_position();
+        let adjusted = if smart {
+            let indent = if start.1 < self.editor_rows.number_of_rows() {
+                let row = &self.editor_rows.get_row(start.1);
+                let indent = indent::leading_whitespace(row);
+                String::from(&indent[..cmp::min(indent.len(), start.0)])
+            } else {
+                String::new()
+            };
+            indent::reindent(text, &indent)
+        } else {
+            text.into()
+        };
+        let end = self.editor_rows.insert_text(start, &adjusted);
+        (self.cursor_controller.cursor_x, self.cursor_controller.cursor_y) = end;
+        self.last_paste = (adjusted != text).then(|| LastPaste {
+            start,
+            end,
+            other: text.into(),
+        });
+    }
+
+    /// Swaps the text just pasted for its other version, returning false
+    /// if the cursor has moved on since the paste.
+    fn cycle_paste(&mut self) -> bool {
+        let last = match self.last_paste.take() {
+            Some(last) if last.end == self.cursor_position() => last,
+            _ => return false,
+        };
+        let pasted = self.editor_rows.text_range(last.start, last.end);
+        self.editor_rows.delete_range(last.start, last.end);
+        let end = self.editor_rows.insert_text(last.start, &last.other);
+        (self.cursor_controller.cursor_x, self.cursor_controller.cursor_y) = end;
+        self.last_paste = Some(LastPaste {
+            start: last.start,
+            end,
+            other: pasted,
+        });
+        true
+    }
+
+    /// Runs `action` at every cursor. The cursors are visited last to
+    /// first, so an edit only ever shifts cursors already visited; those
+    /// are kept as distances from the end of the buffer, which edits
+    /// before them don't change.
+    fn at_each_cursor(&mut self, mut action: impl FnMut(&mut Buffer)) {
+        if self.extra_cursors.is_empty() {
+            return action(self);
+        }
+        let main = Caret {
+            position: self.cursor_position(),
+            anchor: self.cursor_controller.anchor,
+        };
+        let mut carets = mem::take(&mut self.extra_cursors);
+        carets.push(main);
+        carets.sort_by_key(|caret| (caret.position.1, caret.position.0));
+        carets.dedup_by_key(|caret| caret.position);
+        let mut visited = Vec::with_capacity(carets.len());
+        for caret in carets.into_iter().rev() {
+            (self.cursor_controller.cursor_x, self.cursor_controller.cursor_y) = caret.position;
+            self.cursor_controller.anchor = caret.anchor;
+            action(self);
+            let is_main = caret == main;
+            let position = self.distance_from_end(self.cursor_position());
+            let anchor = self
+                .cursor_controller
+                .anchor
+                .map(|anchor| self.distance_from_end(anchor));
+            visited.push((is_main, position, anchor));
+        }
+        let mut carets: Vec<(bool, Caret)> = visited
+            .into_iter()
+            .map(|(is_main, position, anchor)| {
+                let caret = Caret {
+                    position: self.position_from_end(position),
+                    anchor: anchor.map(|anchor| self.position_from_end(anchor)),
+                };
+                (is_main, caret)
+            })
+            .collect();
+        carets.dedup_by_key(|(_, caret)| caret.position);
+        let main = carets
+            .iter()
+            .position(|(is_main, _)| *is_main)
+            .unwrap_or(0);
+        let (_, main) = carets.remove(main);
+        (self.cursor_controller.cursor_x, self.cursor_controller.cursor_y) = main.position;
+        self.cursor_controller.anchor = main.anchor;
+        self.extra_cursors = carets
+            .into_iter()
+            .map(|(_, caret)| caret)
+            .filter(|caret| caret.position != main.position)
+            .collect();
+    }
+
+    /// `(x, y)` as rows from the end of the buffer and bytes from the end
+    /// of its row.
+    fn distance_from_end(&self, (x, y): (usize, usize)) -> (usize, usize) {
+        let number_of_rows = self.editor_rows.number_of_rows();
+        if y >= number_of_rows {
+            return (0, 0);
+        }
+        (self.editor_rows.get_row(y).len().saturating_sub(x), number_of_rows - y)
+    }
+
+    fn position_from_end(&self, (x, y): (usize, usize)) -> (usize, usize) {
+        let y = self.editor_rows.number_of_rows().saturating_sub(y);
+        if y >= self.editor_rows.number_of_rows() {
+            return (0, y);
+        }
+        (self.editor_rows.get_row(y).len().saturating_sub(x), y)
+    }
+
+    /// Performs a motion or a plain edit at the cursor; see
+    /// `Action::is_cursor_local`. `paste` is the text for `Action::Paste`.
+    fn perform_at_cursor(&mut self, action: Action, config: &Config, paste: &str) {
+        let substitution = self.last_substitution.take();
+        let cursor = &mut self.cursor_controller;
+        match action.extends_selection() {
+            Some(true) => {
+                cursor.anchor.get_or_insert((cursor.cursor_x, cursor.cursor_y));
+            }
+            Some(false) => cursor.anchor = None,
+            None => {}
+        }
+        match action {
+            Action::MoveUp | Action::SelectUp => self.move_cursor(KeyCode::Up),
+            Action::MoveDown | Action::SelectDown => self.move_cursor(KeyCode::Down),
+            Action::MoveLeft | Action::SelectLeft => self.move_cursor(KeyCode::Left),
+            Action::MoveRight | Action::SelectRight => self.move_cursor(KeyCode::Right),
+            Action::WordLeft
+            | Action::WordRight
+            | Action::SelectWordLeft
+            | Action::SelectWordRight => {
+                let forward = matches!(action, Action::WordRight | Action::SelectWordRight);
+                self.move_word(forward, &self.word_chars(config))
+            }
+            Action::SubwordLeft
+            | Action::SubwordRight
+            | Action::SelectSubwordLeft
+            | Action::SelectSubwordRight => {
+                let forward = matches!(action, Action::SubwordRight | Action::SelectSubwordRight);
+                self.move_subword(forward)
+            }
+            Action::LineStart | Action::SelectLineStart => self.move_cursor(KeyCode::Home),
+            Action::LineEnd | Action::SelectLineEnd => self.move_cursor(KeyCode::End),
+            Action::Paste if !paste.is_empty() => self.paste(paste, config.smart_paste),
+            Action::DeleteBackward
+            | Action::DeleteForward
+            | Action::DeleteSubwordBackward
+            | Action::DeleteSubwordForward
+                if self.delete_selection() => {}
+            Action::DeleteBackward
+                if substitution
+                    .as_ref()
+                    .is_some_and(|substitution| substitution.end == self.cursor_position()) =>
+            {
+                let substitution = substitution.unwrap();
+                self.editor_rows
+                    .delete_range(substitution.start, substitution.end);
+                let end = self
+                    .editor_rows
+                    .insert_text(substitution.start, &substitution.typed);
+                (self.cursor_controller.cursor_x, self.cursor_controller.cursor_y) = end;
+            }
+            Action::DeleteSubwordBackward => self.delete_subword(false),
+            Action::DeleteSubwordForward => self.delete_subword(true),
+            Action::DeleteBackward => self.delete_char(),
+            Action::DeleteForward => {
+                self.move_cursor(KeyCode::Right);
+                self.delete_char()
+            }
+            Action::InsertNewline => {
+                self.delete_selection();
+                self.insert_newline(config.auto_indent)
+            }
+            Action::InsertTab => {
+                self.delete_selection();
+                self.insert_tab(config)
+            }
+            Action::InsertChar(ch) => {
+                self.delete_selection();
+                if !self.prose || !self.insert_typographic(ch) {
+                    self.insert_char(ch)
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Types `ch` in its typographic form if it has one here, remembering
+    /// what was typed so an immediate Backspace can restore it.
+    fn insert_typographic(&mut self, ch: char) -> bool {
+        let (x, y) = self.cursor_position();
+        let rows = &self.editor_rows;
+        let before = if y < rows.number_of_rows() {
+            &rows.get_row(y)[..x]
+        } else {
+            ""
+        };
+        let above = (0..y.min(rows.number_of_rows())).map(|at| rows.get_row(at));
+        if typography::in_code(above, before) {
+            return false;
+        }
+        let Some((replace, with)) = typography::substitute(before, ch) else {
+            return false;
+        };
+        let start = (x - replace, y);
+        let typed = format!("{}{}", &before[x - replace..], ch);
+        if replace > 0 {
+            self.editor_rows.delete_range(start, (x, y));
+        }
+        let end = self.editor_rows.insert_text(start, with);
+        (self.cursor_controller.cursor_x, self.cursor_controller.cursor_y) = end;
+        self.last_substitution = Some(Substitution { start, end, typed });
+        true
+    }
+
+    fn word_chars(&self, config: &Config) -> WordChars {
+        WordChars::for_file(self.editor_rows.filename.as_deref(), config)
+    }
+
+    fn move_word(&mut self, forward: bool, word_chars: &WordChars) {
+        self.move_to_boundary(forward, |row, x| {
+            if forward {
+                word_chars.next_word(row, x)
+            } else {
+                word_chars.previous_word(row, x)
+            }
+        })
+    }
+
+    fn move_subword(&mut self, forward: bool) {
+        self.move_to_boundary(forward, |row, x| {
+            if forward {
+                filetype::next_subword(row, x)
+            } else {
+                filetype::previous_subword(row, x)
+            }
+        })
+    }
+
+    /// Moves to the next or previous position `boundary` finds on the
+    /// cursor's row, crossing line ends like a single character would.
+    fn move_to_boundary(&mut self, forward: bool, boundary: impl Fn(&str, usize) -> usize) {
+        let cursor = &mut self.cursor_controller;
+        if cursor.cursor_y >= self.editor_rows.number_of_rows() {
+            if !forward {
+                cursor.move_cursor(KeyCode::Left, &self.editor_rows);
+            }
+            return;
+        }
+        let row = &self.editor_rows.get_row(cursor.cursor_y);
+        if (forward && cursor.cursor_x < row.len()) || (!forward && cursor.cursor_x > 0) {
+            cursor.cursor_x = boundary(row, cursor.cursor_x);
+        } else {
+            let direction = if forward { KeyCode::Right } else { KeyCode::Left };
+            cursor.move_cursor(direction, &self.editor_rows);
+        }
+    }
+
+    /// Deletes to the start of the next or previous sub-word; at either
+    /// end of a line this joins it with its neighbour.
+    fn delete_subword(&mut self, forward: bool) {
+        let (x, y) = self.cursor_position();
+        let number_of_rows = self.editor_rows.number_of_rows();
+        if y >= number_of_rows {
+            if !forward {
+                self.delete_char();
+            }
+            return;
+        }
+        let row = &self.editor_rows.get_row(y);
+        if forward && x < row.len() {
+            let end = filetype::next_subword(row, x);
+            self.editor_rows.delete_range((x, y), (end, y));
+        } else if forward && y + 1 < number_of_rows {
+            self.editor_rows.delete_range((x, y), (0, y + 1));
+        } else if !forward && x > 0 {
+            let start = filetype::previous_subword(row, x);
+            self.editor_rows.delete_range((start, y), (x, y));
+            self.cursor_controller.cursor_x = start;
+        } else if !forward {
+            self.delete_char();
+        }
+    }
+
+    fn cursor_position(&self) -> (usize, usize) {
+        (self.cursor_controller.cursor_x, self.cursor_controller.cursor_y)
+    }
+
+    /// Starts a new undo step at the current cursor position.
+    fn checkpoint(&mut self) {
+        let cursor = self.cursor_position();
+        self.editor_rows.history.checkpoint(cursor);
+    }
+
+    fn undo(&mut self) -> bool {
+        let cursor = self.cursor_position();
+        match self.editor_rows.history.take_undo(cursor) {
+            Some(step) => self.replay(step),
+            None => return false,
+        }
+        true
+    }
+
+    fn redo(&mut self) -> bool {
+        let cursor = self.cursor_position();
+        match self.editor_rows.history.take_redo(cursor) {
+            Some(step) => self.replay(step),
+            None => return false,
+        }
+        true
+    }
+
+    /// Applies an undo or redo step, last edit first.
+    fn replay(&mut self, step: Step) {
+        step.edits.iter().rev().for_each(|edit| {
+            self.editor_rows.apply(edit);
+        });
+        (self.cursor_controller.cursor_x, self.cursor_controller.cursor_y) = step.cursor;
+        self.clamp_cursor();
+        let cursor = self.cursor_position();
+        self.editor_rows.history.finish(cursor);
+    }
+
+    fn stats(&self) -> BufferStats {
+        let rows = &self.editor_rows;
+        let bytes = rows.text.len_bytes().saturating_sub(1);
+        let row_memory = rows.text.memory();
+        BufferStats {
+            rows: rows.number_of_rows(),
+            bytes,
+            undo_depth: rows.history.undo_depth(),
+            redo_depth: rows.history.redo_depth(),
+            highlighted_rows: rows.highlights.cached_rows(),
+            memory: row_memory + rows.history.bytes() + rows.highlights.memory(),
+        }
+    }
+
+    fn move_cursor(&mut self, direction: KeyCode) {
+        self.cursor_controller
+            .move_cursor(direction, &self.editor_rows);
+    }
+
+    /// Moves the cursor to what is shown at `column` and `row` of the text
+    /// area, dropping any selection and extra cursors.
+    fn click(&mut self, column: usize, row: usize) {
+        self.cursor_controller.anchor = None;
+        self.extra_cursors.clear();
+        self.move_to_point(column, row);
+    }
+
+    /// Extends the selection to where the mouse is dragged, from where the
+    /// cursor was if nothing is selected yet.
+    fn drag(&mut self, column: usize, row: usize) {
+        if self.cursor_controller.anchor.is_none() {
+            self.cursor_controller.anchor = Some(self.cursor_position());
+        }
+        self.move_to_point(column, row);
+    }
+
+    /// Moves the cursor to a column and row of the text area.
+    fn move_to_point(&mut self, column: usize, row: usize) {
+        let number_of_rows = self.editor_rows.number_of_rows();
+        let cursor = &mut self.cursor_controller;
+        cursor.cursor_y = cmp::min(cursor.row_offset + row, number_of_rows);
+        cursor.cursor_x = if cursor.cursor_y < number_of_rows {
+            self.editor_rows
+                .get_editor_row(cursor.cursor_y)
+                .content_offset(cursor.column_offset + column, self.editor_rows.tab_stop)
+        } else {
+            0
+        };
+    }
+
+    fn clamp_cursor(&mut self) {
+        let number_of_rows = self.editor_rows.number_of_rows();
+        let cursor = &mut self.cursor_controller;
+        cursor.cursor_y = cmp::min(cursor.cursor_y, number_of_rows);
+        cursor.cursor_x = if cursor.cursor_y < number_of_rows {
+            let row = self.editor_rows.get_row(cursor.cursor_y);
+            // Text replaced under the cursor may have put a multi-byte
+            // character where it is.
+            let mut x = cmp::min(cursor.cursor_x, row.len());
+            while !row.is_char_boundary(x) {
+                x -= 1;
+            }
+            x
+        } else {
+            0
+        };
+    }
+}
+
+struct Output {
+    win_size: (usize, usize),
+    editor_contents: EditorContents,
+    buffers: Vec<Buffer>,
+    current_buffer: usize,
+    status_message: StatusMessage,
+    config: Config,
+    search: SearchState,
+    theme: Theme,
+    highlighter: Highlighter,
+    metrics: Metrics,
+    /// The git branch the status bar shows.
+    repo: git::Repo,
+    /// What this session has done, for `:stats`.
+    stats: Stats,
+    /// The title and directory last given to the terminal, which are only
+    /// sent again once they change.
+    told_terminal: Option<(String, PathBuf)>,
+    /// The last key pressed, if it was typing, and when, for gathering
+    /// typing into undo steps as `undo_group` says.
+    last_typed: Option<(Action, Instant)>,
+    notifications: Notifications,
+    key_echo: KeyEcho,
+    clipboard: Clipboard,
+    /// The picker shown in place of the text, while one is open.
+    picker: Option<Picker>,
+    /// The spell checker, once a spelling command has loaded it.
+    spell: Option<Spell>,
+    /// A popup shown until the next key is pressed.
+    popup: Option<Popup>,
+    /// Where the mouse came to rest in the text area, until its tooltip
+    /// is shown.
+    hover: Option<(usize, usize)>,
+    /// The details of the annotated line under the mouse, shown until it
+    /// moves.
+    tooltip: Option<Popup>,
+    /// What is kept from one session to the next.
+    state: State,
+    /// Vi-style modal editing, when it is on.
+    modal: Option<Modal>,
+    /// Whether jumps of more than a row are animated: as the config says,
+    /// except over SSH, where the extra frames would only add lag.
+    smooth_scroll: bool,
+    /// The last `:run`, its output shown below the text until `:stop`.
+    job: Option<Job>,
+    /// The `:pipe` playground, while it is open.
+    pipe: Option<Pipe>,
+    /// The `--diff` view, while it is open.
+    compare: Option<Compare>,
+    /// The session's settings changed with `:set`, as they now are, for
+    /// `:session export`.
+    overrides: BTreeMap<String, String>,
+}
+
+impl Output {
+    /// Starts with a buffer for each of `files`, or an empty one.
+    fn new(
+        config: Config,
+        theme: Theme,
+        highlighter: Highlighter,
+        files: Vec<EditorRows>,
+    ) -> Self {
+        // The tab line, status bar and message bar take a row each, leaving
+        // at least one for the text however small the terminal is.
+        let win_size = terminal::size()
+            .map(|(x, y)| (x as usize, (y as usize).saturating_sub(3).max(1)))
+            .unwrap();
+        let modal = config.modal.then(Modal::new);
+        let smooth_scroll = config.smooth_scroll
+            && env::var_os("SSH_CONNECTION").is_none()
+            && env::var_os("SSH_TTY").is_none();
+        Self {
+            win_size,
+            editor_contents: EditorContents::new(),
+            buffers: if files.is_empty() {
+                vec![Buffer::new(EditorRows::empty(), win_size, &config)]
+            } else {
+                files
+                    .into_iter()
+                    .map(|editor_rows| Buffer::new(editor_rows, win_size, &config))
+                    .collect()
+            },
+            current_buffer: 0,
+            status_message: StatusMessage::new(
+                "HELP: Ctrl-S = Save | Ctrl-Q = Quit | Ctrl-F = Find | Ctrl-E = Command".into(),
+            ),
+            config,
+            search: SearchState::default(),
+            theme,
+            highlighter,
+            metrics: Metrics::default(),
+            repo: git::Repo::default(),
+            stats: Stats::default(),
+            told_terminal: None,
+            last_typed: None,
+            notifications: Notifications::default(),
+            key_echo: KeyEcho::default(),
+            clipboard: Clipboard::default(),
+            picker: None,
+            spell: None,
+            popup: None,
+            hover: None,
+            tooltip: None,
+            state: State::default(),
+            modal,
+            smooth_scroll,
+            job: None,
+            pipe: None,
+            compare: None,
+            overrides: BTreeMap::new(),
+        }
+    }
+
+    fn start_search(&mut self) {
+        let cursor = &self.buffer().cursor_controller;
+        self.search = SearchState {
+            origin: Some(SavedPosition {
+                cursor_x: cursor.cursor_x,
+                cursor_y: cursor.cursor_y,
+                row_offset: cursor.row_offset,
+                column_offset: cursor.column_offset,
+            }),
+            last_match: None,
+            match_end: 0,
+            regex: self.search.regex,
+            indicator: None,
+        };
+    }
+
+    fn end_search(&mut self) {
+        self.search.origin = None;
+        self.search.last_match = None;
+        self.search.indicator = None;
+        self.buffer_mut().editor_rows.annotations.clear(SEARCH_LAYER);
+    }
+
+    /// Called by the search prompt after every key: typing jumps to the
+    /// first match at or after where the search started, arrows step
+    /// between matches, Ctrl-R toggles regex mode, and Esc (or clearing
+    /// the query) goes back.
+    fn find_callback(&mut self, keyword: &str, key: KeyEvent) {
+        let origin = match self.search.origin {
+            Some(origin) => origin,
+            None => return,
+        };
+        self.buffer_mut().editor_rows.annotations.clear(SEARCH_LAYER);
+        let restore = |output: &mut Output| {
+            let cursor = &mut output.buffer_mut().cursor_controller;
+            cursor.cursor_x = origin.cursor_x;
+            cursor.cursor_y = origin.cursor_y;
+            cursor.row_offset = origin.row_offset;
+            cursor.column_offset = origin.column_offset;
+        };
+        match key {
+            KeyEvent {
+                code: KeyCode::Esc, ..
+            } => {
+                restore(self);
+                self.end_search();
+                return;
+            }
+            KeyEvent {
+                code: KeyCode::Enter,
+                ..
+            } => {
+                self.extend_selection_over_match(keyword);
+                self.end_search();
+                return;
+            }
+            KeyEvent {
+                code: KeyCode::Char('r'),
+                modifiers: KeyModifiers::CONTROL,
+            } => {
+                self.search.regex = !self.search.regex;
+                self.search.last_match = None;
+            }
+            _ => {}
+        }
+        let matcher = match Matcher::new(keyword, self.search.regex) {
+            Ok(matcher) if !keyword.is_empty() => matcher,
+            result => {
+                self.search.last_match = None;
+                self.search.indicator = result.is_err().then(|| "invalid regex".into());
+                return restore(self);
+            }
+        };
+        let (start, forward) = match (key.code, self.search.last_match) {
+            (KeyCode::Right | KeyCode::Down, Some((x, y))) => ((x + 1, y), true),
+            (KeyCode::Left | KeyCode::Up, Some(last)) => (last, false),
+            _ => ((origin.cursor_x, origin.cursor_y), true),
+        };
+        // Esc stops a search that's taking a while (a second one ends the
+        // prompt as usual), and so does typing on, which makes it moot.
+        let mut progress = self.progress(format!("Searching for {}", keyword)).cancelable();
+        let mut busy = |done, total| {
+            progress.update(done as u64, total as u64) && !progress::typed_ahead_pending()
+        };
+        let editor_rows = &self.buffer().editor_rows;
+        let found = search::find(editor_rows, &matcher, start, forward, &mut busy).unwrap_or(None);
+        self.search.last_match = found;
+        match found {
+            Some((x, y)) => {
+                let editor_rows = &mut self.buffer_mut().editor_rows;
+                let end = matcher.match_end(&editor_rows.get_row(y), x);
+                let count = search::count(editor_rows, &matcher, (x, y), &mut busy);
+                if let Ok((index, total)) = count {
+                    let count = Annotation {
+                        hint: Some((end, format!(" [{}/{}]", index, total))),
+                        ..Annotation::default()
+                    };
+                    editor_rows.annotations.set(SEARCH_LAYER, y, count);
+                }
+                self.search.indicator = count.ok().map(|(index, total)| match total {
+                    1 => "1 match".into(),
+                    _ => format!("{} of {} matches", index, total),
+                });
+                self.search.match_end = end;
+                let cursor = &mut self.buffer_mut().cursor_controller;
+                cursor.cursor_x = x;
+                cursor.cursor_y = y;
+            }
+            None => {
+                self.search.indicator = Some("no matches".into());
+                restore(self);
+            }
+        }
+    }
+
+    /// When a search ends inside a selection that started before the match,
+    /// moves the cursor past the match so the selection includes it.
+    fn extend_selection_over_match(&mut self, keyword: &str) {
+        let (Some((x, y)), Ok(matcher)) = (
+            self.search.last_match,
+            Matcher::new(keyword, self.search.regex),
+        ) else {
+            return;
+        };
+        let buffer = self.buffer_mut();
+        if buffer
+            .cursor_controller
+            .anchor
+            .is_some_and(|(anchor_x, anchor_y)| (anchor_y, anchor_x) <= (y, x))
+        {
+            let row = &buffer.editor_rows.get_row(y);
+            buffer.cursor_controller.cursor_x = matcher.match_end(row, x);
+        }
+    }
+
+    /// Jumps to the next occurrence of the word under the cursor, matching
+    /// whole words only.
+    fn find_word_under_cursor(&mut self) {
+        let word_chars = self.buffer().word_chars(&self.config);
+        let buffer = self.buffer();
+        let (x, y) = buffer.cursor_position();
+        let word = (y < buffer.editor_rows.number_of_rows())
+            .then(|| buffer.editor_rows.get_row(y))
+            .and_then(|row| {
+                word_chars
+                    .word_at(&row, x)
+                    .map(|(start, end)| (row[start..end].to_string(), end))
+            });
+        let (word, end) = match word {
+            Some(word) => word,
+            None => {
+                self.status_message
+                    .set_message("No word under cursor".into());
+                return;
+            }
+        };
+        let matcher = Matcher::Word(word.clone(), word_chars);
+        let mut progress = self.progress(format!("Searching for {}", word)).cancelable();
+        let mut busy = |done, total| progress.update(done as u64, total as u64);
+        let editor_rows = &self.buffer().editor_rows;
+        if let Ok(Some((x, y))) = search::find(editor_rows, &matcher, (end, y), true, &mut busy) {
+            let cursor = &mut self.buffer_mut().cursor_controller;
+            cursor.cursor_x = x;
+            cursor.cursor_y = y;
+        }
+        self.status_message.set_message(format!("Search: {}", word));
+    }
+
+    fn buffer(&self) -> &Buffer {
+        &self.buffers[self.current_buffer]
+    }
+
+    fn buffer_mut(&mut self) -> &mut Buffer {
+        &mut self.buffers[self.current_buffer]
+    }
+
+    fn open_buffer(&mut self, buffer: Buffer) {
+        self.buffers.push(buffer);
+        self.current_buffer = self.buffers.len() - 1;
+    }
+
+    fn switch_buffer(&mut self, forward: bool) {
+        let count = self.buffers.len();
+        self.current_buffer = if forward {
+            (self.current_buffer + 1) % count
+        } else {
+            (self.current_buffer + count - 1) % count
+        };
+    }
+
+    fn close_buffer(&mut self, index: usize) {
+        self.remember_file(index);
+        let closed = self.buffers.remove(index);
+        if let Some(journal) = closed.editor_rows.journal {
+            journal.discard();
+        }
+        if let Some(swap) = closed.swap {
+            swap.discard();
+        }
+        if self.buffers.is_empty() {
+            let buffer = Buffer::new(EditorRows::empty(), self.win_size, &self.config);
+            self.buffers.push(buffer);
+        }
+        if index < self.current_buffer {
+            self.current_buffer -= 1;
+        }
+        self.current_buffer = cmp::min(self.current_buffer, self.buffers.len() - 1);
+    }
+
+    /// Copies whole lines, remembering they were so `p` puts them back as
+    /// lines.
+    fn yank_lines(&mut self, lines: String) {
+        if let Some(modal) = &mut self.modal {
+            modal.lines = Some(lines.clone());
+        }
+        self.clipboard.set(lines);
+    }
+
+    /// The open buffer `name` refers to: the one with that file name or
+    /// path, or else the only one whose path contains it.
+    fn find_buffer(&self, name: &str) -> Result<usize, String> {
+        let paths: Vec<String> = self
+            .buffers
+            .iter()
+            .map(|buffer| match &buffer.editor_rows.filename {
+                Some(file) => file.display().to_string(),
+                None => buffer.display_name().into(),
+            })
+            .collect();
+        let exact = self
+            .buffers
+            .iter()
+            .zip(&paths)
+            .position(|(buffer, path)| path == name || buffer.display_name() == name);
+        if let Some(index) = exact {
+            return Ok(index);
+        }
+        let mut containing = paths.iter().enumerate().filter(|(_, path)| path.contains(name));
+        match (containing.next(), containing.next()) {
+            (Some((index, _)), None) => Ok(index),
+            (Some(_), Some(_)) => Err(format!("More than one buffer matches {}", name)),
+            (None, _) => Err(format!("No buffer matches {}", name)),
+        }
+    }
+
+    /// Switches to the theme `name`, a theme file or a syntax theme, and
+    /// highlights the buffers again in it.
+    fn set_theme(&mut self, name: &str) {
+        let mut errors = Vec::new();
+        let depth = self.config.color_depth.unwrap_or_else(Depth::detect);
+        self.theme = Theme::load(Some(name), &self.config.colors, depth, &mut errors);
+        self.highlighter.set_theme(&self.theme, &mut errors);
+        errors.iter().for_each(|error| log::write(error));
+        self.config.theme = Some(name.into());
+        for buffer in &mut self.buffers {
+            buffer.editor_rows.forget_highlights();
+        }
+    }
+
+    /// Switches to the key binding preset `name`: `modal` for vi-style
+    /// modes, else the standard keys.
+    fn set_keymap(&mut self, name: &str) {
+        self.config.modal = name == "modal";
+        self.modal = self.config.modal.then(Modal::new);
+    }
+
+    /// The current value of the `:set` setting `name`.
+    fn setting(&self, name: &str) -> Option<String> {
+        match name {
+            "numbers" => Some(self.config.line_numbers.name().into()),
+            "smartpaste" => Some(if self.config.smart_paste { "on" } else { "off" }.into()),
+            "checkpaste" => Some(if self.config.check_paste { "on" } else { "off" }.into()),
+            "checksave" => Some(self.config.check_save.name().into()),
+            "fileencoding" => Some(self.buffer().editor_rows.encoding.name().to_lowercase()),
+            "fileformat" => Some(self.buffer().editor_rows.line_ending.format().into()),
+            "autoindent" => Some(if self.config.auto_indent { "on" } else { "off" }.into()),
+            "undogroup" => Some(self.config.undo_group.name().into()),
+            "changemarks" => Some(self.config.change_marks.name().into()),
+            "modal" => Some(if self.modal.is_some() { "on" } else { "off" }.into()),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let invalid = || format!("Invalid value for {}: {}", name, value);
+        match name {
+            "numbers" => self.config.line_numbers = LineNumbers::parse(value).ok_or_else(invalid)?,
+            "smartpaste" => {
+                self.config.smart_paste = match value {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err(invalid()),
+                }
+            }
+            "checkpaste" => {
+                self.config.check_paste = match value {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err(invalid()),
+                }
+            }
+            "checksave" => self.config.check_save = CheckSave::parse(value).ok_or_else(invalid)?,
+            "fileencoding" => {
+                let encoding = FileEncoding::for_label(value).ok_or_else(invalid)?;
+                let editor_rows = &mut self.buffer_mut().editor_rows;
+                if !editor_rows.text.chunks().all(|chunk| encoding.fits(chunk)) {
+                    return Err(format!("{} can't hold all of the text", encoding.name()));
+                }
+                if editor_rows.encoding.encoding != encoding.encoding {
+                    editor_rows.set_encoding(encoding);
+                }
+            }
+            "fileformat" => {
+                let line_ending = LineEnding::for_format(value).ok_or_else(invalid)?;
+                let editor_rows = &mut self.buffer_mut().editor_rows;
+                if editor_rows.line_ending != line_ending || editor_rows.mixed_endings {
+                    let bom = editor_rows.encoding.bom;
+                    editor_rows.set_format(line_ending == LineEnding::Crlf, bom, false);
+                }
+            }
+            "autoindent" => {
+                self.config.auto_indent = match value {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err(invalid()),
+                }
+            }
+            "undogroup" => self.config.undo_group = UndoGroup::parse(value).ok_or_else(invalid)?,
+            "changemarks" => {
+                self.config.change_marks = ChangeMarks::parse(value).ok_or_else(invalid)?;
+                for buffer in &mut self.buffers {
+                    buffer.editor_rows.changes = None;
+                }
+            }
+            "modal" => {
+                self.modal = match value {
+                    "on" => Some(Modal::new()),
+                    "off" => None,
+                    _ => return Err(invalid()),
+                }
+            }
+            _ => return Err(format!("Unknown setting: {}", name)),
+        }
+        Ok(())
+    }
+
+    /// Shows the annotations of the line at `point` of the text area in
+    /// full, beside it.
+    fn show_hover(&mut self, (column, row): (usize, usize)) {
+        let split = self.split_rows();
+        let text_end = split + self.text_rows();
+        let buffer = self.buffer();
+        let file_row = match buffer.view {
+            Some(view) if row + 1 < split => view.row_offset + row,
+            _ if row < split || row >= text_end => return,
+            _ => row - split + buffer.cursor_controller.row_offset,
+        };
+        let lines = buffer.editor_rows.annotations.describe(file_row);
+        if !lines.is_empty() {
+            self.tooltip = Some(Popup::new(Anchor::Point(column, row), lines));
+        }
+    }
+
+    /// Shows `text` in the help buffer, which is opened (read-only, and
+    /// never asking to be saved) if it isn't already.
+    fn show_help(&mut self, text: &str) {
+        match self.buffers.iter().position(|buffer| buffer.help) {
+            Some(index) => self.current_buffer = index,
+            None => {
+                let buffer = Buffer {
+                    help: true,
+                    read_only: true,
+                    ..Buffer::scratch(self.win_size, &self.config)
+                };
+                self.open_buffer(buffer);
+            }
+        }
+        let win_size = self.win_size;
+        let buffer = self.buffer_mut();
+        buffer.editor_rows.replace_contents(text);
+        buffer.editor_rows.history.clear();
+        buffer.editor_rows.dirty = 0;
+        buffer.cursor_controller = CursorController::new(win_size);
+    }
+
+    /// What Tab can complete the end of command line `input` with: the
+    /// byte offset the word being completed starts at, and candidates for
+    /// it.
+    fn command_completions(&self, input: &str) -> Option<(usize, Vec<String>)> {
+        let (start, completing) = command::completing(input)?;
+        let word = &input[start..];
+        let candidates = match completing {
+            Completing::Command => command::COMMANDS
+                .iter()
+                .map(|(name, ..)| name.to_string())
+                .chain(self.config.commands.keys().cloned())
+                .chain(self.config.aliases.keys().cloned())
+                .collect(),
+            Completing::File => return Some((start, complete::paths(word))),
+            Completing::Buffer => self
+                .buffers
+                .iter()
+                .filter_map(|buffer| buffer.editor_rows.filename.as_deref())
+                .map(|name| name.display().to_string())
+                .collect(),
+            Completing::Picker => self.config.pickers.keys().cloned().collect(),
+            Completing::Language => spell::installed(),
+            Completing::HelpTopic => help::topics(&self.config),
+            Completing::Setting => command::SETTINGS
+                .iter()
+                .map(|(name, ..)| format!("{}=", name))
+                .collect(),
+            Completing::Value(values) => values.iter().map(|value| value.to_string()).collect(),
+        };
+        Some((start, complete::matching(word, candidates)))
+    }
+
+    /// Notes where the cursor and bookmarks are in the buffer at `index`,
+    /// for the next time its file is opened.
+    fn remember_file(&mut self, index: usize) {
+        let buffer = &self.buffers[index];
+        let Some(name) = buffer.editor_rows.filename.as_deref() else {
+            return;
+        };
+        let cursor = &buffer.cursor_controller;
+        self.state.remember_file(FileState {
+            path: config::absolute(name),
+            cursor: (cursor.cursor_x, cursor.cursor_y),
+            bookmarks: buffer.editor_rows.annotations.lines(BOOKMARK_LAYER),
+        });
+    }
+
+    /// Puts the cursor and bookmarks of the current buffer back where an
+    /// earlier session left them. A file with merge conflicts keeps its
+    /// cursor on the first one.
+    fn restore_file_state(&mut self) {
+        let Some(name) = self.buffer().editor_rows.filename.as_deref() else {
+            return;
+        };
+        let Some(file) = self.state.file(&config::absolute(name)).cloned() else {
+            return;
+        };
+        let buffer = self.buffer_mut();
+        for line in file.bookmarks {
+            buffer.editor_rows.annotations.set(BOOKMARK_LAYER, line, bookmark());
+        }
+        if !buffer.merging {
+            (buffer.cursor_controller.cursor_x, buffer.cursor_controller.cursor_y) = file.cursor;
+            buffer.clamp_cursor();
+        }
+    }
+
+    /// Where each tab goes on the tab line, as buffer index and column
+    /// range. Tabs scroll off to the left when the current one would not
+    /// fit otherwise.
+    fn tab_layout(&self) -> Vec<(usize, Range<usize>)> {
+        let widths: Vec<usize> = self
+            .buffers
+            .iter()
+            .map(|buffer| tab_label(buffer).chars().count() + 1)
+            .collect();
+        let mut first = 0;
+        while first < self.current_buffer
+            && widths[first..=self.current_buffer].iter().sum::<usize>() > self.win_size.0
+        {
+            first += 1;
+        }
+        let mut layout = Vec::new();
+        let mut column = 0;
+        for (index, &width) in widths.iter().enumerate().skip(first) {
+            if column >= self.win_size.0 {
+                break;
+            }
+            let end = cmp::min(column + width - 1, self.win_size.0);
+            layout.push((index, column..end));
+            column += width;
+        }
+        layout
+    }
+
+    /// Draws the top line: the editor's name while there is one buffer,
+    /// then a tab for each buffer, the current one highlighted.
+    fn draw_tab_line(&mut self) {
+        if self.buffers.len() == 1 {
+            let title = "Rustext";
+            let padding = self.win_size.0.saturating_sub(title.len()) / 2;
+            self.editor_contents
+                .push_str(&format!("{}{}", " ".repeat(padding), title));
+        } else {
+            self.draw_tabs();
+        }
+        queue!(
+            self.editor_contents,
+            terminal::Clear(ClearType::UntilNewLine)
+        )
+        .unwrap();
+        self.editor_contents.push_str("\r\n");
+    }
+
+    fn draw_tabs(&mut self) {
+        for (index, columns) in self.tab_layout() {
+            let label: String = tab_label(&self.buffers[index])
+                .chars()
+                .take(columns.len())
+                .collect();
+            if index == self.current_buffer {
+                self.editor_contents.push_str(&format!(
+                    "{}{}{}",
+                    style::Attribute::Reverse,
+                    label,
+                    style::Attribute::NoReverse
+                ));
+            } else {
+                self.editor_contents.push_str(&label);
+            }
+            if columns.end < self.win_size.0 {
+                self.editor_contents.push('|');
+            }
+        }
+    }
+
+    /// The buffer whose tab is at `column` of the tab line.
+    fn tab_at(&self, column: usize) -> Option<usize> {
+        if self.buffers.len() == 1 {
+            return None;
+        }
+        self.tab_layout()
+            .into_iter()
+            .find(|(_, columns)| columns.contains(&column))
+            .map(|(index, _)| index)
+    }
+
+    fn clear_screen() -> crossterm::Result<()> {
+        execute!(screen(), terminal::Clear(ClearType::All))?;
+        execute!(screen(), cursor::MoveTo(0, 0))
+    }
+
+    fn draw_message_bar(&mut self) {
+        queue!(
+            self.editor_contents,
+            terminal::Clear(ClearType::UntilNewLine)
+        )
+        .unwrap();
+        let hint = self.buffer().conflict_hint();
+        // While searching, how many matches the query has goes at the end.
+        let indicator = self.search.origin.and(self.search.indicator.as_deref());
+        let room = indicator.map_or(self.win_size.0, |indicator| {
+            self.win_size.0.saturating_sub(indicator.len() + 1)
+        });
+        let mut used = 0;
+        if let Some(msg) = self.status_message.message().or(hint.as_ref()) {
+            let msg = &msg[..width::fit(msg, 0, room)];
+            used = width::str_width(msg);
+            self.editor_contents.push_str(msg);
+        }
+        if let Some(indicator) = indicator.filter(|_| room > 0) {
+            let gap = self.win_size.0 - used - indicator.len();
+            self.editor_contents.push_str(&" ".repeat(gap));
+            self.editor_contents.push_str(indicator);
+        }
+    }
+
+    fn draw_status_bar(&mut self) {
+        self.editor_contents.push_str(&self.theme.status.start());
+        let buffer = self.buffer();
+        let mut info = match &self.modal {
+            Some(modal) => match modal.pending() {
+                pending if pending.is_empty() => format!("[{}] ", modal.mode.name()),
+                pending => format!("[{} {}] ", modal.mode.name(), pending),
+            },
+            None => String::new(),
+        };
+        info += &format!(
+            "{}{} {} -- {} lines",
+            buffer.display_name(),
+            if buffer.read_only { " [RO]" } else { "" },
+            if buffer.editor_rows.dirty > 0 { "(modified)" } else { "" },
+            buffer.editor_rows.number_of_rows()
+        );
+        let filetype = buffer.editor_rows.filename.as_deref().and_then(FileType::detect);
+        if let (true, Some(filetype)) = (self.config.status_bar.filetype, filetype) {
+            info.push_str(&format!(" [{}]", filetype.name));
+        }
+        if let (true, Some(branch)) = (self.config.status_bar.branch, self.repo.label()) {
+            info.push_str(&format!(" [{}]", branch));
+        }
+        if let (true, Some(last_save)) = (self.config.status_bar.last_save, &buffer.last_save) {
+            match last_save.result {
+                Ok(_) => info.push_str(&format!(" [saved {} ago]", last_save.ago())),
+                Err(_) => info.push_str(&format!(" [save failed {} ago]", last_save.ago())),
+            }
+        }
+        let editor_rows = &buffer.editor_rows;
+        info.push_str(&format!(
+            " [{} {}]",
+            editor_rows.encoding.name(),
+            editor_rows.line_ending.name()
+        ));
+        if editor_rows.mixed_endings {
+            info.push_str(" [mixed endings]");
+        }
+        if editor_rows.encoding.bom && editor_rows.encoding.encoding == encoding_rs::UTF_8 {
+            info.push_str(" [BOM]");
+        }
+        if self.search.regex {
+            info.push_str(" [regex]");
+        }
+        if buffer.editor_rows.long_line_mode {
+            info.push_str(" [long lines]");
+        }
+        match &buffer.editor_rows.loader {
+            Some(loader) if buffer.editor_rows.on_demand => {
+                let (read, size) = loader.progress();
+                info.push_str(&format!(" [{}% read]", read * 100 / size.max(1)));
+            }
+            Some(loader) => {
+                let (read, size) = loader.progress();
+                info.push_str(&format!(" [{}]", progress::describe("loading", read, size)));
+            }
+            None => {}
+        }
+        if self.buffers.len() > 1 {
+            info.push_str(&format!(
+                " [{}/{}]",
+                self.current_buffer + 1,
+                self.buffers.len()
+            ));
+        }
+        let info = &info[..width::fit(&info, 0, self.win_size.0)];
+        let info_len = width::str_width(info);
+        let mut line_info = format!(
+            "{}/{}",
+            buffer.cursor_controller.cursor_y + 1,
+            buffer.editor_rows.number_of_rows()
+        );
+        if self.config.status_bar.column {
+            line_info.push_str(&format!(" col {}", buffer.cursor_controller.render_x + 1));
+        }
+        self.editor_contents.push_str(info);
+        for i in info_len..self.win_size.0 {
+            if self.win_size.0 - i == line_info.len() {
+                self.editor_contents.push_str(&line_info);
+                break;
+            } else {
+                self.editor_contents.push(' ')
+            }
+        }
+        self.editor_contents
+            .push_str(&style::Attribute::Reset.to_string());
+        queue!(self.editor_contents, style::ResetColor).unwrap();
+        self.editor_contents.push_str("\r\n");
+    }
+
+    /// Draws the text area, returning how many file rows were drawn: the
+    /// split's view, if there is one, then the rows being edited.
+    fn draw_rows(&mut self) -> usize {
+        let split = self.split_rows();
+        let cursor = &self.buffer().cursor_controller;
+        let (row_offset, column_offset) = (cursor.row_offset, cursor.column_offset);
+        let mut rows_drawn = 0;
+        if let Some(view) = self.buffer().view {
+            rows_drawn += self.draw_pane(view.row_offset, 0, split - 1);
+            self.draw_divider(view.row_offset, split - 1);
+        }
+        rows_drawn += self.draw_pane(row_offset, column_offset, self.text_rows());
+        self.draw_job();
+        rows_drawn
+    }
+
+    /// The line under the split's view, saying which lines it shows.
+    fn draw_divider(&mut self, row_offset: usize, rows: usize) {
+        let last = cmp::min(row_offset + rows, self.buffer().editor_rows.number_of_rows());
+        let label = format!("── {}-{} ", row_offset + 1, last);
+        let line: String = label.chars().chain(iter::repeat('─')).take(self.win_size.0).collect();
+        let color = self.theme.line_numbers;
+        queue!(self.editor_contents, style::SetForegroundColor(color)).unwrap();
+        self.editor_contents.push_str(&line);
+        queue!(self.editor_contents, style::ResetColor).unwrap();
+        self.editor_contents.push_str("\r\n");
+    }
+
+    /// Rows of the text area above the ones being edited: the split's
+    /// view and the line under it, or none without one.
+    fn split_rows(&self) -> usize {
+        match self.buffer().view {
+            Some(_) => self.win_size.1 / 2,
+            None => 0,
+        }
+    }
+
+    /// Rows of the text area below the ones being edited: the output pane
+    /// of `:run`, while it is open.
+    fn job_rows(&self) -> usize {
+        match self.job {
+            Some(_) => self.win_size.1 / 3,
+            None => 0,
+        }
+    }
+
+    /// Rows of the text area showing the rows being edited.
+    fn text_rows(&self) -> usize {
+        self.win_size.1 - self.split_rows() - self.job_rows()
+    }
+
+    /// Draws the output pane: a line naming the job and whether it is
+    /// still running, then the last lines it printed.
+    fn draw_job(&mut self) {
+        let rows = self.job_rows();
+        let Some(job) = &self.job else {
+            return;
+        };
+        if rows == 0 {
+            return;
+        }
+        let label = format!("── {}: {} ", job.command, job.state());
+        let title: String = label.chars().chain(iter::repeat('─')).take(self.win_size.0).collect();
+        let shown = &job.lines[job.lines.len().saturating_sub(rows - 1)..];
+        let mut text = String::new();
+        for line in shown {
+            text.push_str(&line[..width::fit(line, 0, self.win_size.0)]);
+            text.push_str(&terminal::Clear(ClearType::UntilNewLine).to_string());
+            text.push_str("\r\n");
+        }
+        for _ in shown.len()..rows - 1 {
+            text.push_str(&terminal::Clear(ClearType::UntilNewLine).to_string());
+            text.push_str("\r\n");
+        }
+        let color = self.theme.line_numbers;
+        queue!(self.editor_contents, style::SetForegroundColor(color)).unwrap();
+        self.editor_contents.push_str(&title);
+        queue!(self.editor_contents, style::ResetColor).unwrap();
+        self.editor_contents.push_str("\r\n");
+        self.editor_contents.push_str(&text);
+    }
+
+    /// Draws `screen_rows` rows of the buffer from `row_offset`.
+    fn draw_pane(&mut self, row_offset: usize, column_offset: usize, screen_rows: usize) -> usize {
+        let (sign_width, number_width) = self.gutter();
+        let screen_columns = self.win_size.0 - sign_width - number_width;
+        let line_numbers = self.config.line_numbers;
+        let number_color = self.theme.line_numbers;
+        let buffer = &self.buffers[self.current_buffer];
+        let annotations = &buffer.editor_rows.annotations;
+        let cursor_y = buffer.cursor_controller.cursor_y;
+        let selection = self.theme.selection;
+        let mut marked: Vec<_> = buffer
+            .marked_ranges()
+            .into_iter()
+            .map(|range| (range, selection))
+            .collect();
+        if let (Some(_), Some((x, y))) = (self.search.origin, self.search.last_match) {
+            marked.push((((x, y), (self.search.match_end, y)), self.theme.search));
+        }
+        let mut rows_drawn = 0;
+        for i in 0..screen_rows {
+            let file_row = i + row_offset;
+            let change = buffer.editor_rows.change_mark(file_row).map(|mark| {
+                let color = match mark {
+                    '+' => style::Color::DarkGreen,
+                    '~' => style::Color::DarkYellow,
+                    _ => style::Color::DarkRed,
+                };
+                let color = self.theme.depth.fit(color);
+                format!(
+                    "{}{}{}",
+                    style::SetForegroundColor(color),
+                    mark,
+                    style::SetForegroundColor(style::Color::Reset)
+                )
+            });
+            if sign_width > 0 {
+                match (annotations.sign(file_row), &change) {
+                    (Some(sign), _) => self.editor_contents.push_str(&format!("{} ", sign)),
+                    (None, Some(change)) if number_width == 0 => {
+                        self.editor_contents.push_str(&format!("{} ", change))
+                    }
+                    (None, _) => self.editor_contents.push_str("  "),
+                }
+            }
+            if number_width > 0 {
+                let number = match line_numbers {
+                    _ if file_row >= buffer.editor_rows.number_of_rows() => String::new(),
+                    LineNumbers::Relative if file_row != cursor_y => {
+                        file_row.abs_diff(cursor_y).to_string()
+                    }
+                    _ => (file_row + 1).to_string(),
+                };
+                let color = if file_row == cursor_y {
+                    style::Color::Reset
+                } else {
+                    number_color
+                };
+                self.editor_contents.push_str(&format!(
+                    "{}{:>width$}{}{}",
+                    style::SetForegroundColor(color),
+                    number,
+                    style::SetForegroundColor(style::Color::Reset),
+                    change.as_deref().unwrap_or(" "),
+                    width = number_width - 1
+                ));
+            }
+            let tint = annotations.tint(file_row).map(|tint| self.theme.depth.fit(tint));
+            if let Some(tint) = tint {
+                queue!(self.editor_contents, style::SetBackgroundColor(tint)).unwrap();
+            }
+            if file_row < buffer.editor_rows.number_of_rows() {
+                rows_drawn += 1;
+                let editor_row = buffer.editor_rows.get_editor_row(file_row);
+                if editor_row.is_long() {
+                    self.editor_contents
+                        .push_str(&editor_row.render_chunk(column_offset, screen_columns));
+                } else {
+                    let row = &buffer.editor_rows.get_render(file_row);
+                    // A wide character cut by the left edge is drawn as
+                    // blanks for the half still on screen.
+                    let row_width = width::str_width(row);
+                    let (start, skipped, room) = if row_width > column_offset {
+                        let (start, skipped) = width::offset_at(row, column_offset);
+                        (start, skipped, screen_columns.saturating_sub(skipped))
+                    } else {
+                        (0, 0, 0)
+                    };
+                    self.editor_contents.push_str(&" ".repeat(skipped));
+                    let mut end = width::fit(row, start, room);
+                    // Inline hints take room from the text, so the row
+                    // still fits the screen.
+                    let mut hints = buffer.editor_rows.render_hints(file_row);
+                    hints.retain(|&(offset, _)| (start..=end).contains(&offset));
+                    let hint_width: usize =
+                        hints.iter().map(|(_, text)| width::str_width(text)).sum();
+                    end = width::fit(row, start, room.saturating_sub(hint_width)).min(end);
+                    hints.retain(|&(offset, _)| offset <= end);
+                    let spans = buffer.editor_rows.row_spans(file_row);
+                    let tab_stop = buffer.editor_rows.tab_stop;
+                    let mut painted = Vec::new();
+                    let mut newline = false;
+                    for &(((start_x, start_y), (end_x, end_y)), style) in &marked {
+                        if !(start_y..=end_y).contains(&file_row) {
+                            continue;
+                        }
+                        let from = if file_row == start_y {
+                            editor_row.render_offset(start_x, tab_stop)
+                        } else {
+                            0
+                        };
+                        let to = if file_row == end_y {
+                            editor_row.render_offset(end_x, tab_stop)
+                        } else {
+                            row.len()
+                        };
+                        painted.push((from..to, style));
+                        newline |= file_row < end_y;
+                    }
+                    painted.sort_by_key(|(range, _)| range.start);
+                    let mut at = start;
+                    for (range, style) in painted {
+                        let from = range.start.clamp(at, end);
+                        let to = range.end.clamp(from, end);
+                        self.editor_contents
+                            .push_str(&render_hinted(row, at, from, spans, &hints));
+                        // A style's own foreground wins over syntax colours.
+                        let spans = if style.fg.is_some() { None } else { spans };
+                        self.editor_contents
+                            .push_str(&style.paint(&render_hinted(row, from, to, spans, &hints)));
+                        at = to;
+                    }
+                    self.editor_contents
+                        .push_str(&render_hinted(row, at, end, spans, &hints));
+                    for (_, text) in hints.iter().filter(|&&(offset, _)| offset == end) {
+                        self.editor_contents.push_str(&render_hint(text));
+                    }
+                    let mut used = skipped + width::str_width(&row[start..end]);
+                    used += hints.iter().map(|(_, text)| width::str_width(text)).sum::<usize>();
+                    if newline && row_width < column_offset + screen_columns {
+                        self.editor_contents.push_str(&selection.paint(" "));
+                        used += 1;
+                    }
+                    if let Some(text) = annotations.text(file_row) {
+                        let room = screen_columns.saturating_sub(used + 2);
+                        let text = &text[..width::fit(&text, 0, room)];
+                        self.editor_contents.push_str(&format!("  {}", render_hint(text)));
+                    }
+                }
+            } else if number_width == 0 {
+                self.editor_contents.push_str(&format!("{:<5}", file_row));
+            }
+            queue!(
+                self.editor_contents,
+                terminal::Clear(ClearType::UntilNewLine)
+            )
+            .unwrap();
+            if tint.is_some() {
+                queue!(self.editor_contents, style::ResetColor).unwrap();
+            }
+            self.editor_contents.push_str("\r\n");
+        }
+        rows_drawn
+    }
+
+    /// The widths of the gutter's columns left of the text: annotation
+    /// signs, then line numbers. Changed lines are marked after their
+    /// numbers, or without numbers in the sign column.
+    fn gutter(&self) -> (usize, usize) {
+        let editor_rows = &self.buffer().editor_rows;
+        let number_width = match self.config.line_numbers {
+            LineNumbers::Off => 0,
+            _ => cmp::max(editor_rows.number_of_rows().to_string().len(), 3) + 1,
+        };
+        let sign_width = if editor_rows.annotations.has_signs()
+            || (number_width == 0 && editor_rows.has_changes())
+        {
+            2
+        } else {
+            0
+        };
+        (sign_width, number_width)
+    }
+
+    fn gutter_width(&self) -> usize {
+        let (sign_width, number_width) = self.gutter();
+        sign_width + number_width
+    }
+
+    /// Reports progress on the status bar for an operation about to keep
+    /// the editor busy.
+    fn progress(&self, label: String) -> Progress {
+        Progress::new(label, (self.win_size.0, self.win_size.1 as u16 + 1))
+    }
+
+    /// Draws the open picker over the text area: the matching items, then
+    /// a bar naming the highlighted one and a preview of the lines around
+    /// its target, that line highlighted.
+    fn draw_picker(&mut self, picker: &mut Picker) {
+        let screen_columns = self.win_size.0;
+        let list_rows = cmp::max(1, self.win_size.1 / 3);
+        let preview_rows = self.win_size.1.saturating_sub(list_rows + 1);
+        let mut lines: Vec<String> = picker
+            .visible(list_rows)
+            .map(|(item, selected)| {
+                let label: String = item.label.chars().take(screen_columns - 2).collect();
+                if selected {
+                    format!("> {}", self.theme.selection.paint(&label))
+                } else {
+                    format!("  {}", label)
+                }
+            })
+            .collect();
+        lines.resize(list_rows, String::new());
+        let target = picker.selected().map(|item| item.target.clone());
+        let command_preview = picker.command_preview().map(<[String]>::to_vec);
+        let line = match (&target, &command_preview) {
+            (Some(Target::Buffer(_, line)), None) => Some(*line),
+            (Some(Target::File(_, line)), None) => *line,
+            _ => None,
+        };
+        let first = line.map_or(0, |line| line.saturating_sub(preview_rows / 2));
+        let numbered = command_preview.is_none()
+            && !matches!(target, Some(Target::Journal(_) | Target::Command(_)));
+        let (name, preview): (String, Vec<String>) = match &target {
+            Some(_) if command_preview.is_some() => {
+                let label = picker.selected().unwrap().label.clone();
+                (label, command_preview.unwrap_or_default())
+            }
+            Some(Target::Buffer(index, _)) => {
+                let editor_rows = &self.buffers[*index].editor_rows;
+                let end = cmp::min(first + preview_rows, editor_rows.number_of_rows());
+                let rows = (first..end)
+                    .map(|at| editor_rows.get_row(at).to_string())
+                    .collect();
+                (self.buffers[*index].display_name().into(), rows)
+            }
+            Some(Target::File(path, _)) => {
+                let rows = picker.file_lines(path, first, preview_rows).to_vec();
+                (path.display().to_string(), rows)
+            }
+            Some(Target::Swap(path)) => {
+                let rows = picker.file_lines(path, 0, preview_rows).to_vec();
+                ("[No Name]".into(), rows)
+            }
+            Some(Target::Journal(file)) => {
+                let key = format!("journal {}", file.display());
+                let diff = picker.cached_preview(key, || recovery_diff(file));
+                (file.display().to_string(), diff.to_vec())
+            }
+            Some(Target::Script(item)) => (item.clone(), Vec::new()),
+            Some(Target::Command(name)) => {
+                let steps = self.config.commands.get(name).into_iter().flatten();
+                (name.clone(), steps.map(CommandStep::describe).collect())
+            }
+            None => (String::new(), Vec::new()),
+        };
+        let bar = match (&target, line) {
+            (None, _) => " No matches".into(),
+            (Some(_), Some(line)) => format!(" {}:{}", name, line + 1),
+            (Some(_), None) => format!(" {}", name),
+        };
+        let bar = format!("{}  ({}/{})", bar, picker.match_count(), picker.item_count());
+        let bar: String = bar.chars().take(screen_columns).collect();
+        lines.push(format!(
+            "{}{:<width$}{}",
+            self.theme.status.start(),
+            bar,
+            style::Attribute::Reset,
+            width = screen_columns
+        ));
+        for number in first..first + preview_rows {
+            let Some(text) = preview.get(number - first) else {
+                lines.push(String::new());
+                continue;
+            };
+            let row = Row {
+                row_content: Cow::Borrowed(text),
+            };
+            let render = if row.is_long() {
+                row.render_chunk(0, screen_columns)
+            } else {
+                row.render(self.config.tab_stop)
+            };
+            let text = if numbered {
+                format!("{:>5} {}", number + 1, render)
+            } else {
+                render
+            };
+            let text: String = text
+                .chars()
+                .take(screen_columns)
+                .collect();
+            lines.push(if Some(number) == line {
+                self.theme.selection.paint(&text)
+            } else {
+                text
+            });
+        }
+        for line in lines {
+            self.editor_contents.push_str(&line);
+            queue!(
+                self.editor_contents,
+                terminal::Clear(ClearType::UntilNewLine)
+            )
+            .unwrap();
+            self.editor_contents.push_str("\r\n");
+        }
+    }
+
+    /// Draws the `:pipe` playground: the pipeline at the top, then under a
+    /// line saying how it went, the start of what it printed.
+    fn draw_pipe(&mut self) {
+        let Some(pipe) = &self.pipe else {
+            return;
+        };
+        let screen_columns = self.win_size.0;
+        let (state, output) = match &pipe.output {
+            Ok(output) => (format!("{} lines", output.lines().count()), output.as_str()),
+            Err(err) => ("failed".into(), err.as_str()),
+        };
+        let label = format!("── {} ", state);
+        let mut lines = vec![
+            format!("| {}", pipe.command),
+            label.chars().chain(iter::repeat('─')).take(screen_columns).collect(),
+        ];
+        for text in output.lines().take(self.win_size.1.saturating_sub(2)) {
+            let row = Row {
+                row_content: Cow::Borrowed(text),
+            };
+            let render = if row.is_long() {
+                row.render_chunk(0, screen_columns)
+            } else {
+                row.render(self.config.tab_stop)
+            };
+            lines.push(render.chars().take(screen_columns).collect());
+        }
+        lines.resize(self.win_size.1, String::new());
+        let color = self.theme.line_numbers;
+        for (number, line) in lines.into_iter().enumerate() {
+            if number == 1 {
+                queue!(self.editor_contents, style::SetForegroundColor(color)).unwrap();
+            }
+            self.editor_contents.push_str(&line);
+            queue!(
+                self.editor_contents,
+                style::ResetColor,
+                terminal::Clear(ClearType::UntilNewLine)
+            )
+            .unwrap();
+            self.editor_contents.push_str("\r\n");
+        }
+    }
+
+    /// Draws the `--diff` view: the old buffer on the left and the new one
+    /// on the right, each line after its number, with the lines that differ
+    /// in red and green.
+    fn draw_compare(&mut self) {
+        let Some(compare) = &self.compare else {
+            return;
+        };
+        let half = self.win_size.0.saturating_sub(1) / 2;
+        let sides = [
+            (compare.old, style::Color::DarkRed),
+            (compare.new, style::Color::DarkGreen),
+        ];
+        let rows = |index: usize| self.buffers[index].editor_rows.number_of_rows();
+        let number_width = cmp::max(rows(compare.old).max(rows(compare.new)).to_string().len(), 3);
+        let divider = self.theme.line_numbers;
+        for row in compare.top..compare.top + self.win_size.1 {
+            let pair = compare.rows.get(row);
+            let lines = [pair.and_then(|pair| pair.old), pair.and_then(|pair| pair.new)];
+            for (side, ((index, color), line)) in sides.into_iter().zip(lines).enumerate() {
+                if side == 1 {
+                    queue!(self.editor_contents, style::SetForegroundColor(divider)).unwrap();
+                    self.editor_contents.push_str("│");
+                    queue!(self.editor_contents, style::ResetColor).unwrap();
+                }
+                let room = half.saturating_sub(number_width + 1);
+                let cell = match line {
+                    Some(line) => {
+                        let editor_row = self.buffers[index].editor_rows.get_editor_row(line);
+                        let render = if editor_row.is_long() {
+                            editor_row.render_chunk(0, room)
+                        } else {
+                            editor_row.render(self.config.tab_stop)
+                        };
+                        let text = &render[..width::fit(&render, 0, room)];
+                        let padding = room - width::str_width(text);
+                        let number = format!("{:>width$}", line + 1, width = number_width);
+                        format!("{} {}{}", number, text, " ".repeat(padding))
+                    }
+                    None => " ".repeat(half),
+                };
+                if pair.is_some_and(|pair| pair.changed) {
+                    let color = self.theme.depth.fit(color);
+                    queue!(self.editor_contents, style::SetForegroundColor(color)).unwrap();
+                }
+                self.editor_contents.push_str(&cell);
+                queue!(self.editor_contents, style::ResetColor).unwrap();
+            }
+            queue!(
+                self.editor_contents,
+                terminal::Clear(ClearType::UntilNewLine)
+            )
+            .unwrap();
+            self.editor_contents.push_str("\r\n");
+        }
+    }
+
+    /// The overlays on the text area: the metrics and the echoed keys, when
+    /// they are on.
+    fn overlays(&self) -> Vec<Popup> {
+        let mut popups = Vec::new();
+        if self.metrics.visible {
+            let lines = self.metrics.lines(&self.buffer().stats());
+            popups.push(Popup::new(Anchor::TopRight, lines).titled("metrics"));
+        }
+        if self.key_echo.visible {
+            let mut lines = self.key_echo.lines();
+            // Keep the latest keys, which are last.
+            let shown = lines.len().min(self.win_size.1.saturating_sub(2));
+            lines.drain(..lines.len() - shown);
+            popups.push(Popup::new(Anchor::BottomRight, lines).titled("keys"));
+        }
+        let toasts = self.notifications.toasts();
+        if !toasts.is_empty() {
+            popups.push(Popup::new(Anchor::TopRight, toasts));
+        }
+        popups
+    }
+
+    fn refresh_screen(&mut self) -> crossterm::Result<()> {
+        self.tell_terminal()?;
+        let gutter = self.gutter_width();
+        let screen_rows = self.text_rows();
+        let buffer = &mut self.buffers[self.current_buffer];
+        buffer.cursor_controller.screen_rows = screen_rows;
+        // Scroll as if the cursor's row were as wide as its hints make it.
+        let cursor_y = buffer.cursor_controller.cursor_y;
+        let hint_width = buffer.editor_rows.hint_width(cursor_y, 0..=usize::MAX);
+        buffer.cursor_controller.screen_columns =
+            (self.win_size.0 - gutter).saturating_sub(hint_width).max(1);
+        let from = buffer.cursor_controller.row_offset;
+        buffer.cursor_controller.scroll(&buffer.editor_rows);
+        let to = buffer.cursor_controller.row_offset;
+        if self.smooth_scroll
+            && from.abs_diff(to) > 1
+            && self.metrics.last_frame() < SLOW_FRAME
+            && !event::poll(Duration::ZERO)?
+        {
+            for frame in 1..SCROLL_FRAMES {
+                let offset = from as isize + (to as isize - from as isize) * frame as isize
+                    / SCROLL_FRAMES as isize;
+                self.buffer_mut().cursor_controller.row_offset = offset as usize;
+                self.draw_screen()?;
+                thread::sleep(SCROLL_FRAME_TIME);
+                // Keys typed meanwhile cut the animation short.
+                if event::poll(Duration::ZERO)? {
+                    break;
+                }
+            }
+            self.buffer_mut().cursor_controller.row_offset = to;
+        }
+        self.draw_screen()
+    }
+
+    /// With `terminal_title` on, sets the window title to the current
+    /// buffer's name and tells the terminal its directory.
+    fn tell_terminal(&mut self) -> crossterm::Result<()> {
+        if !self.config.terminal_title {
+            return Ok(());
+        }
+        let buffer = self.buffer();
+        let title = format!("{} - rustext", buffer.display_name());
+        let dir = match &buffer.editor_rows.filename {
+            Some(file) => config::absolute(file).parent().map(Path::to_path_buf),
+            None => env::current_dir().ok(),
+        };
+        let Some(dir) = dir else {
+            return Ok(());
+        };
+        let told = (title, dir);
+        if self.told_terminal.as_ref() != Some(&told) {
+            let (title, dir) = (osc::title(&told.0), osc::directory(&told.1));
+            execute!(screen(), style::Print(title), style::Print(dir))?;
+            self.told_terminal = Some(told);
+        }
+        Ok(())
+    }
+
+    /// Draws everything as scrolled, with the cursor shown if it is in
+    /// view (which it may not be part way through a smooth scroll).
+    fn draw_screen(&mut self) -> crossterm::Result<()> {
+        let start = Instant::now();
+        let marks = self.config.change_marks;
+        self.buffers[self.current_buffer].editor_rows.update_changes(marks);
+        let gutter = self.gutter_width();
+        let split = self.split_rows();
+        let text_rows = self.text_rows();
+        let buffer = &mut self.buffers[self.current_buffer];
+        let row_offset = buffer.cursor_controller.row_offset;
+        if let Some(view) = buffer.view {
+            let rows = view.row_offset..view.row_offset + split;
+            buffer.editor_rows.highlight(&self.highlighter, rows);
+        }
+        buffer
+            .editor_rows
+            .highlight(&self.highlighter, row_offset..row_offset + text_rows);
+        queue!(self.editor_contents, cursor::Hide, cursor::MoveTo(0, 0))?;
+        self.draw_tab_line();
+        let rows_drawn = match self.picker.take() {
+            Some(mut picker) => {
+                self.draw_picker(&mut picker);
+                self.picker = Some(picker);
+                0
+            }
+            None if self.pipe.is_some() => {
+                self.draw_pipe();
+                0
+            }
+            None if self.compare.is_some() => {
+                self.draw_compare();
+                0
+            }
+            None => self.draw_rows(),
+        };
+        self.draw_status_bar();
+        self.draw_message_bar();
+        let buffer = self.buffer();
+        let cursor_controller = &buffer.cursor_controller;
+        let hint_width = buffer.editor_rows.hint_width(
+            cursor_controller.cursor_y,
+            cursor_controller.column_offset..=cursor_controller.render_x,
+        );
+        let cursor_x =
+            cursor_controller.render_x - cursor_controller.column_offset + gutter + hint_width;
+        let cursor_y = cursor_controller
+            .cursor_y
+            .checked_sub(cursor_controller.row_offset)
+            .filter(|&y| y < text_rows)
+            .map(|y| y + split);
+        let area = (self.win_size.0, self.win_size.1);
+        let cursor = (cursor_x, cursor_y.unwrap_or(0));
+        for popup in self.overlays().iter().chain(&self.popup).chain(&self.tooltip) {
+            popup.draw(&mut self.editor_contents, 1, area, cursor)?;
+        }
+        if let Some(cursor_y) = cursor_y {
+            queue!(
+                self.editor_contents,
+                cursor::MoveTo(cursor_x as u16, cursor_y as u16 + 1),
+                cursor::Show
+            )?;
+        }
+        self.editor_contents.flush()?;
+        self.metrics.record_frame(start.elapsed(), rows_drawn);
+        Ok(())
+    }
+}
+
+struct Reader;
+
+impl Reader {
+    fn read_key(&self) -> crossterm::Result<KeyEvent> {
+        loop {
+            if let Event::Key(event) = self.read_event()? {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// The next key press or mouse event.
+    fn read_event(&self) -> crossterm::Result<Event> {
+        if let Some(event) = progress::typed_ahead() {
+            return Ok(event);
+        }
+        loop {
+            if event::poll(Duration::from_millis(500))? {
+                match event::read()? {
+                    event @ (Event::Key(_) | Event::Mouse(_)) => return Ok(event),
+                    Event::Resize(..) => {}
+                }
+            }
+        }
+    }
+}
+
+struct Editor {
+    reader: Reader,
+    output: Output,
+    pending_trust: Option<PendingTrust>,
+    started: bool,
+    /// A file being opened to restore its journal, which needs no asking.
+    restoring: Option<PathBuf>,
+    /// Whether the session's state is saved for the next, which
+    /// `--no-state` turns off (along with loading it).
+    keep_state: bool,
+    /// Where each buffer opens, by index, as the command line asks.
+    start_at: Vec<Option<StartAt>>,
+    /// How many user commands are running inside one another.
+    nesting: usize,
+    breaks: Breaks,
+    /// Where `--record` writes the actions taken.
+    recorder: Option<Recorder>,
+    /// The `--replay` trace, performed once the questions on starting are
+    /// answered.
+    replay: Option<Vec<Recorded>>,
+    /// Set by `--diff`, to compare the two files once the questions on
+    /// starting are answered.
+    compare: bool,
+    /// The user's config file, if `--config` named one other than the usual.
+    config_file: Option<PathBuf>,
+}
+
+/// Keeps track of breaks for `idle_snapshot`: going idle snapshots the
+/// buffers, and the first key after says what has changed since.
+struct Breaks {
+    last_key: Instant,
+    /// Set from when the snapshots are taken until a key comes.
+    away: bool,
+    /// When the key ending the last break came.
+    returned: Option<Instant>,
+}
+
+impl Editor {
+    /// Opens the files `args` names; `-` among them stands for `stdin`,
+    /// read before the terminal was taken over.
+    fn new(args: Args, stdin: Option<Vec<u8>>) -> Self {
+        let mut loaded = Config::load(args.config.as_deref());
+        let theme = Theme::load(
+            loaded.config.theme.as_deref(),
+            &loaded.config.colors,
+            loaded.config.color_depth.unwrap_or_else(Depth::detect),
+            &mut loaded.errors,
+        );
+        let highlighter = Highlighter::load(&theme, &mut loaded.errors);
+        let mut problems = Vec::new();
+        if !loaded.errors.is_empty() {
+            problems.push(format!("Config error: {}", loaded.errors.join("; ")));
+        }
+        let keep_state = !args.no_state;
+        let state = if keep_state {
+            State::load()
+        } else {
+            State::default()
+        };
+        let mut start = None;
+        let mut paths = Vec::new();
+        for arg in &args.files {
+            match StartAt::parse(arg) {
+                Some(at) => start = Some(at),
+                None => paths.push(file_position(arg)),
+            }
+        }
+        // `+/pattern` and `+line` are for the first file.
+        if let (Some(start), Some((_, first))) = (start, paths.first_mut()) {
+            *first = Some(start);
+        }
+        let mut current = 0;
+        let resume = args.resume || loaded.config.resume;
+        if let (true, true, Ok(directory)) = (resume, paths.is_empty(), env::current_dir()) {
+            if let Some(session) = state.session(&directory) {
+                paths = session
+                    .files
+                    .iter()
+                    .filter(|file| file.exists())
+                    .map(|file| (file.clone(), None))
+                    .collect();
+                current = session.current.min(paths.len().saturating_sub(1));
+            }
+        }
+        let (files, start_at): (Vec<_>, Vec<_>) = paths
+            .into_iter()
+            .filter_map(|(file, at)| match open_arg(file.clone(), stdin.as_deref()) {
+                Ok(editor_rows) => Some((editor_rows, at)),
+                Err(err) => {
+                    problems.push(format!("Could not open {}: {}", file.display(), err));
+                    None
+                }
+            })
+            .unzip();
+        current = current.min(files.len().saturating_sub(1));
+        let mut output = Output::new(loaded.config, theme, highlighter, files);
+        for buffer in &mut output.buffers {
+            buffer.read_only = args.readonly;
+        }
+        output.current_buffer = current;
+        output.state = state;
+        if let Some(text) = output.state.registers.get("\"") {
+            output.clipboard.restore(text.clone());
+        }
+        if !problems.is_empty() {
+            problems.iter().for_each(|problem| log::write(problem));
+            output.status_message.set_message(problems.join("; "));
+        }
+        Self {
+            reader: Reader,
+            output,
+            pending_trust: loaded.pending_trust,
+            started: false,
+            restoring: None,
+            keep_state,
+            start_at,
+            nesting: 0,
+            breaks: Breaks {
+                last_key: Instant::now(),
+                away: false,
+                returned: None,
+            },
+            recorder: None,
+            replay: None,
+            compare: false,
+            config_file: args.config,
+        }
+    }
+
+    /// Questions that need answering before editing starts.
+    fn startup(&mut self) -> crossterm::Result<()> {
+        if let Some(pending) = self.pending_trust.take() {
+            self.confirm_trust(pending)?;
+        }
+        let current = self.output.current_buffer;
+        for index in 0..self.output.buffers.len() {
+            self.output.current_buffer = index;
+            self.confirm_large_file()?;
+            self.attach_file()?;
+            self.output.restore_file_state();
+        }
+        for (index, at) in mem::take(&mut self.start_at).into_iter().enumerate() {
+            if let Some(at) = at {
+                self.output.current_buffer = index;
+                self.go_to_start(at);
+            }
+        }
+        self.output.current_buffer = current;
+        // Rather than the help, start with what to do about conflicts.
+        if let Some(hint) = self.output.buffer().conflict_hint() {
+            self.output.status_message.set_message(hint);
+        }
+        self.offer_recovery()
+    }
+
+    /// Puts the current buffer's cursor where the command line asked,
+    /// with its line in the middle of the screen.
+    fn go_to_start(&mut self, at: StartAt) {
+        let (line, column) = match at {
+            StartAt::Match(pattern) => {
+                let matcher = match Matcher::new(&pattern, true) {
+                    Ok(matcher) => matcher,
+                    Err(err) => return self.output.status_message.set_message(err),
+                };
+                let editor_rows = &self.output.buffer().editor_rows;
+                match search::find(editor_rows, &matcher, (0, 0), true, &mut |_, _| true) {
+                    Ok(Some((x, y))) => (y, x),
+                    _ => {
+                        let message = format!("Pattern not found: {}", pattern);
+                        return self.output.status_message.set_message(message);
+                    }
+                }
+            }
+            StartAt::Position { line, column } => {
+                let editor_rows = &self.output.buffer().editor_rows;
+                let y = cmp::min(line, editor_rows.number_of_rows()).saturating_sub(1);
+                // Columns count characters from 1, as compilers give them.
+                let x = match column {
+                    Some(column) if y < editor_rows.number_of_rows() => {
+                        let row = editor_rows.get_row(y);
+                        let at = row.char_indices().nth(column.saturating_sub(1));
+                        at.map_or(row.len(), |(x, _)| x)
+                    }
+                    _ => 0,
+                };
+                (y, x)
+            }
+        };
+        let buffer = self.output.buffer_mut();
+        let cursor = &mut buffer.cursor_controller;
+        (cursor.cursor_x, cursor.cursor_y) = (column, line);
+        cursor.row_offset = line.saturating_sub(cursor.screen_rows / 2);
+        buffer.clamp_cursor();
+    }
+
+    /// Lists the files crashed sessions left unsaved edits to, besides
+    /// those open now (which are offered as they open), previewing what
+    /// restoring each would change, and the buffers without files they
+    /// left swap files for.
+    fn offer_recovery(&mut self) -> crossterm::Result<()> {
+        let open: Vec<PathBuf> = self
+            .output
+            .buffers
+            .iter()
+            .filter_map(|buffer| buffer.editor_rows.filename.as_deref().map(config::absolute))
+            .collect();
+        let stale =
+            journal::stale(|file| lock::is_locked(file) || open.iter().any(|path| path == file));
+        let mut items: Vec<picker::Item> = stale
+            .into_iter()
+            .map(|stale| picker::Item {
+                label: format!("{} ({} unsaved edits)", stale.file.display(), stale.edits),
+                target: Target::Journal(stale.file),
+            })
+            .collect();
+        items.extend(swap::stale().into_iter().map(|stale| picker::Item {
+            label: format!("[No Name] ({} lines)", stale.lines),
+            target: Target::Swap(stale.path),
+        }));
+        if items.is_empty() {
+            return Ok(());
+        }
+        self.pick(Picker::new("Recover", items))
+    }
+
+    /// Asks whether to restore the buffer without a file that a swap file
+    /// holds, or discard it, then offers the rest.
+    fn recover_swap(&mut self, path: PathBuf) -> crossterm::Result<()> {
+        let dialog = Dialog::new(
+            "Recover",
+            vec![
+                "Restore this unsaved buffer from a crashed session?".into(),
+                "No discards it for good.".into(),
+            ],
+        );
+        match self.confirm(dialog)? {
+            Answer::Yes => match fs::read_to_string(&path) {
+                Ok(text) => {
+                    let mut buffer =
+                        Buffer::new(EditorRows::empty(), self.output.win_size, &self.output.config);
+                    buffer.editor_rows.replace_contents(&text);
+                    self.output.open_buffer(buffer);
+                    let _ = fs::remove_file(&path);
+                }
+                Err(err) => self
+                    .output
+                    .status_message
+                    .set_message(format!("Could not read {}: {}", path.display(), err)),
+            },
+            Answer::No => {
+                let _ = fs::remove_file(&path);
+                self.output
+                    .status_message
+                    .set_message("Discarded the unsaved buffer".into());
+            }
+            Answer::Cancel => {}
+        }
+        self.offer_recovery()
+    }
+
+    /// Asks whether to restore or discard the edits left behind for
+    /// `file`, then offers the rest.
+    fn recover_journal(&mut self, file: PathBuf) -> crossterm::Result<()> {
+        let dialog = Dialog::new(
+            "Recover",
+            vec![
+                format!("Restore the unsaved edits to {}?", file.display()),
+                "No discards them for good.".into(),
+            ],
+        );
+        match self.confirm(dialog)? {
+            Answer::Yes => {
+                self.restoring = Some(file.clone());
+                self.open_file(file)?;
+                self.restoring = None;
+            }
+            Answer::No => {
+                journal::discard_stale(&file);
+                self.output
+                    .status_message
+                    .set_message(format!("Discarded the edits to {}", file.display()));
+            }
+            Answer::Cancel => {}
+        }
+        self.offer_recovery()
+    }
+
+    /// Switches to the buffer for `file`, opening it if it isn't open yet,
+    /// in place of the current buffer if that is blank. A file that doesn't
+    /// exist opens empty, to be created on the first save.
+    fn open_file(&mut self, file: PathBuf) -> crossterm::Result<()> {
+        let path = config::absolute(&file);
+        let open = self.output.buffers.iter().position(|buffer| {
+            buffer
+                .editor_rows
+                .filename
+                .as_deref()
+                .is_some_and(|name| config::absolute(name) == path)
+        });
+        if let Some(index) = open {
+            self.output.current_buffer = index;
+            return Ok(());
+        }
+        let new_file = !file.exists();
+        match EditorRows::open(file.clone()) {
+            Ok(editor_rows) => {
+                let buffer = Buffer::new(editor_rows, self.output.win_size, &self.output.config);
+                if self.output.buffer().is_blank() {
+                    let current = self.output.current_buffer;
+                    self.output.buffers[current] = buffer;
+                } else {
+                    self.output.open_buffer(buffer);
+                }
+                if new_file {
+                    self.output
+                        .status_message
+                        .set_message(format!("{} is a new file", file.display()));
+                }
+                self.confirm_large_file()?;
+                self.attach_file()?;
+                self.output.restore_file_state();
+            }
+            Err(err) => self
+                .output
+                .status_message
+                .set_message(format!("Could not open {}: {}", file.display(), err)),
+        }
+        Ok(())
+    }
+
+    /// Asks how to open the current buffer's file if it is bigger than
+    /// `confirm_open_mb`: all of it, only as far as the cursor goes, or
+    /// just its head and tail. Either of the last two leaves it read-only;
+    /// Esc loads all of it, as if nothing had been asked.
+    fn confirm_large_file(&mut self) -> crossterm::Result<()> {
+        let buffer = self.output.buffer();
+        let size = match (&buffer.editor_rows.loader, self.output.config.confirm_open) {
+            (Some(loader), Some(limit)) if loader.progress().1 > limit => loader.progress().1,
+            _ => return Ok(()),
+        };
+        let dialog = Dialog::choice(
+            "Large file",
+            vec![
+                format!("{} is {}.", buffer.display_name(), metrics::size(size as usize)),
+                "Load all of it, read it only as far as you go, or just look at".into(),
+                "its head and tail? Either of the last two is read-only.".into(),
+            ],
+            vec!["Load all", "Read as needed", "Head and tail"],
+            0,
+        );
+        let picked = self.choose(dialog)?;
+        let buffer = self.output.buffer_mut();
+        match picked {
+            0 => {}
+            1 => {
+                buffer.editor_rows.on_demand = true;
+                buffer.read_only = true;
+            }
+            _ => {
+                buffer.read_only = true;
+                if let Err(err) = buffer.editor_rows.keep_tail() {
+                    let name = buffer.display_name();
+                    let message = format!("Could not read the end of {}: {}", name, err);
+                    self.output.status_message.set_message(message);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Shows `picker` until an item is chosen or Esc is pressed, then goes
+    /// to the chosen item's target.
+    fn pick(&mut self, picker: Picker) -> crossterm::Result<()> {
+        let page = cmp::max(1, self.output.win_size.1 / 3) as isize;
+        self.output.picker = Some(picker);
+        let target = loop {
+            let picker = self.output.picker.as_ref().unwrap();
+            let message = format!("{}: {} (Use ESC / Arrows / Enter)", picker.title, picker.query);
+            self.output.status_message.set_message(message);
+            self.output.refresh_screen()?;
+            let key = self.reader.read_key()?;
+            self.output.key_echo.record(&key, None);
+            let picker = self.output.picker.as_mut().unwrap();
+            match (key.code, key.modifiers) {
+                (KeyCode::Esc, _) => break None,
+                (KeyCode::Enter, _) => break picker.selected().map(|item| item.target.clone()),
+                (KeyCode::Up, _) => picker.move_selection(-1),
+                (KeyCode::Down, _) => picker.move_selection(1),
+                (KeyCode::PageUp, _) => picker.move_selection(-page),
+                (KeyCode::PageDown, _) => picker.move_selection(page),
+                (KeyCode::Backspace, _) => picker.pop(),
+                (KeyCode::Char(ch), KeyModifiers::NONE | KeyModifiers::SHIFT) => picker.push(ch),
+                _ => {}
+            }
+        };
+        let picker = self.output.picker.take().unwrap();
+        self.output.status_message.set_message(String::new());
+        match target {
+            Some(Target::Script(item)) => {
+                let accept = picker.source.and_then(|source| source.accept).unwrap_or_default();
+                let command = accept.replace("{item}", &shell::quote(&item));
+                // Report the command's last line of output, if it has any.
+                let message = match shell::filter(&command, "") {
+                    Ok(output) => match output.lines().rev().find(|line| !line.trim().is_empty()) {
+                        Some(line) => line.into(),
+                        None => format!("{}: {}", picker.title, item),
+                    },
+                    Err(err) => err,
+                };
+                self.output.status_message.set_message(message);
+            }
+            Some(Target::Buffer(index, line)) => {
+                self.output.current_buffer = index;
+                let buffer = self.output.buffer_mut();
+                if buffer.cursor_controller.cursor_y != line {
+                    buffer.cursor_controller.anchor = None;
+                    buffer.cursor_controller.cursor_y = line;
+                    buffer.cursor_controller.cursor_x = 0;
+                    buffer.clamp_cursor();
+                }
+            }
+            Some(Target::File(path, line)) => {
+                self.open_file(path.clone())?;
+                let buffer = self.output.buffer_mut();
+                let opened = buffer
+                    .editor_rows
+                    .filename
+                    .as_deref()
+                    .is_some_and(|name| config::absolute(name) == config::absolute(&path));
+                if let (true, Some(line)) = (opened, line) {
+                    buffer.cursor_controller.anchor = None;
+                    buffer.cursor_controller.cursor_y = line;
+                    buffer.cursor_controller.cursor_x = 0;
+                    buffer.clamp_cursor();
+                }
+            }
+            Some(Target::Journal(file)) => self.recover_journal(file)?,
+            Some(Target::Swap(path)) => self.recover_swap(path)?,
+            Some(Target::Command(command)) => self.execute_command(&command)?,
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Shows buffers `old` and `new` side by side until Enter or Esc, the
+    /// arrows scrolling both and n and p going to the next and previous
+    /// change. Enter goes on to edit the new one at the first change on
+    /// screen; Esc goes back to the buffer that was current.
+    fn compare_buffers(&mut self, old: usize, new: usize) -> crossterm::Result<()> {
+        for index in [old, new] {
+            let buffer = &mut self.output.buffers[index];
+            if let Err(err) = buffer.editor_rows.load_all() {
+                buffer.read_only = true;
+                let name = buffer.display_name();
+                let message = format!("Could not read all of {}: {}", name, err);
+                self.output.status_message.set_message(message);
+                return Ok(());
+            }
+        }
+        let lines = |index: usize| -> Vec<String> {
+            let editor_rows = &self.output.buffers[index].editor_rows;
+            (0..editor_rows.number_of_rows())
+                .map(|at| editor_rows.get_row(at).into_owned())
+                .collect()
+        };
+        let (old_lines, new_lines) = (lines(old), lines(new));
+        let old_lines: Vec<&str> = old_lines.iter().map(String::as_str).collect();
+        let new_lines: Vec<&str> = new_lines.iter().map(String::as_str).collect();
+        let rows = diff::side_by_side(&old_lines, &new_lines);
+        let changes = diff::changes(&rows);
+        let page = cmp::max(1, self.output.win_size.1 - 1);
+        // A change gone to is shown with a few of the lines before it.
+        let context = cmp::min(3, self.output.win_size.1 / 4);
+        self.output.compare = Some(Compare {
+            old,
+            new,
+            rows,
+            top: 0,
+        });
+        let edit = loop {
+            let compare = self.output.compare.as_ref().unwrap();
+            let (top, last) = (compare.top, compare.rows.len().saturating_sub(1));
+            let seen = changes.iter().filter(|&&start| start <= top + context);
+            let position = match (changes.len(), seen.count()) {
+                (0, _) => "no differences".into(),
+                (count, 0) => format!("{} changes", count),
+                (count, at) => format!("change {} of {}", at, count),
+            };
+            let message = format!(
+                "Diff, {} (Use n / p / Arrows / Enter to edit / ESC)",
+                position
+            );
+            self.output.status_message.set_message(message);
+            self.output.refresh_screen()?;
+            let key = self.reader.read_key()?;
+            self.output.key_echo.record(&key, None);
+            let compare = self.output.compare.as_mut().unwrap();
+            let mut tops = changes.iter().map(|&start| start.saturating_sub(context));
+            compare.top = match key.code {
+                KeyCode::Esc => break false,
+                KeyCode::Enter => break true,
+                KeyCode::Up => top.saturating_sub(1),
+                KeyCode::Down => top + 1,
+                KeyCode::PageUp => top.saturating_sub(page),
+                KeyCode::PageDown => top + page,
+                KeyCode::Home => 0,
+                KeyCode::End => last.saturating_sub(page),
+                KeyCode::Char('n') => tops.find(|&next| next > top).unwrap_or(top),
+                KeyCode::Char('p') => tops.rev().find(|&previous| previous < top).unwrap_or(top),
+                _ => top,
+            }
+            .min(last);
+        };
+        let compare = self.output.compare.take().unwrap();
+        self.output.status_message.set_message(String::new());
+        if edit {
+            let shown = &compare.rows[compare.top..];
+            let row = shown
+                .iter()
+                .take(self.output.win_size.1)
+                .position(|pair| pair.changed)
+                .unwrap_or(0);
+            let line = shown[row..].iter().find_map(|pair| pair.new);
+            self.output.current_buffer = new;
+            let buffer = self.output.buffer_mut();
+            let last = buffer.editor_rows.number_of_rows().saturating_sub(1);
+            buffer.cursor_controller.anchor = None;
+            buffer.cursor_controller.cursor_y = line.unwrap_or(last);
+            buffer.cursor_controller.cursor_x = 0;
+            buffer.clamp_cursor();
+        }
+        Ok(())
+    }
+
+    /// Opens the picker configured as `[picker.<name>]`.
+    fn pick_from_source(&mut self, name: &str) -> crossterm::Result<()> {
+        let source = match self.output.config.pickers.get(name) {
+            Some(source) => source,
+            None => {
+                let mut names: Vec<&str> =
+                    self.output.config.pickers.keys().map(String::as_str).collect();
+                names.sort_unstable();
+                let message = if names.is_empty() {
+                    format!("No picker named {}", name)
+                } else {
+                    format!("No picker named {} (have: {})", name, names.join(", "))
+                };
+                self.output.status_message.set_message(message);
+                return Ok(());
+            }
+        };
+        match Picker::from_source(name, source) {
+            Ok(picker) if picker.item_count() > 0 => self.pick(picker)?,
+            Ok(_) => self
+                .output
+                .status_message
+                .set_message(format!("{}: nothing to pick", name)),
+            Err(err) => self.output.status_message.set_message(err),
+        }
+        Ok(())
+    }
+
+    /// Fuzzy-finds a file under the project root and opens it.
+    fn find_file(&mut self) -> crossterm::Result<()> {
+        let items = picker::file_items(&project_root());
+        if items.is_empty() {
+            self.output
+                .status_message
+                .set_message("No files found".into());
+            return Ok(());
+        }
+        self.pick(Picker::new("Find file", items))
+    }
+
+    fn pick_buffer(&mut self) -> crossterm::Result<()> {
+        let items = self
+            .output
+            .buffers
+            .iter()
+            .enumerate()
+            .map(|(index, buffer)| picker::Item {
+                label: tab_label(buffer).trim().into(),
+                target: Target::Buffer(index, buffer.cursor_controller.cursor_y),
+            })
+            .collect();
+        self.pick(Picker::new("Buffer", items))
+    }
+
+    /// Locks the current buffer's file and starts journaling it. If another
+    /// editor has the file open, the user can fall back to read-only; if a
+    /// crashed session left edits behind, they are offered for replay.
+    fn attach_file(&mut self) -> crossterm::Result<()> {
+        let buffer = self.output.buffer();
+        let file = match &buffer.editor_rows.filename {
+            Some(file) if buffer.editor_rows.journal.is_none() && !buffer.read_only => {
+                file.clone()
+            }
+            _ => return Ok(()),
+        };
+        if buffer.lock.is_none() {
+            if let Some(conflict) = lock::find_conflict(&file) {
+                let answer = prompt!(
+                    &mut self.output,
+                    "{} {}. Open [r]ead-only or [e]dit anyway? {}",
+                    file.display(),
+                    conflict.describe()
+                );
+                if !matches!(answer.as_deref(), Some("e" | "E")) {
+                    self.output.buffer_mut().read_only = true;
+                    self.output
+                        .status_message
+                        .set_message(format!("{} opened read-only", file.display()));
+                    return Ok(());
+                }
+            }
+            self.output.buffer_mut().lock = FileLock::acquire(&file);
+        }
+        let mut recovered = Journal::recover(&file);
+        let chosen = self.restoring.as_deref().map(config::absolute);
+        if let (Some(edits), false) = (&recovered, chosen == Some(config::absolute(&file))) {
+            let answer = prompt!(
+                &mut self.output,
+                "Recover {} unsaved edits to {} from the journal? (y/N): {}",
+                edits.len(),
+                file.display()
+            );
+            if !matches!(answer.as_deref(), Some("y" | "Y" | "yes")) {
+                recovered = None;
+            }
+        }
+        let buffer = self.output.buffer_mut();
+        buffer.editor_rows.journal = Journal::start(&file).ok();
+        if let Some(edits) = recovered {
+            // The edits may be anywhere in the file.
+            if let Err(err) = buffer.editor_rows.load_all() {
+                buffer.read_only = true;
+                self.output
+                    .status_message
+                    .set_message(format!("Could not load {}: {}", file.display(), err));
+                return Ok(());
+            }
+            let applied = edits
+                .iter()
+                .take_while(|edit| buffer.editor_rows.apply(edit))
+                .count();
+            buffer.clamp_cursor();
+            self.output.status_message.set_message(if applied == edits.len() {
+                format!("Recovered {} edits", applied)
+            } else {
+                format!("Recovered {} of {} edits", applied, edits.len())
+            });
+        }
+        Ok(())
+    }
+
+    /// Saves what the next session should remember, with the current
+    /// buffer as the most recently edited file and the open files as this
+    /// directory's session.
+    fn save_state(&mut self) {
+        if !self.keep_state {
+            return;
+        }
+        let output = &mut self.output;
+        let named: Vec<(usize, PathBuf)> = output
+            .buffers
+            .iter()
+            .enumerate()
+            .filter_map(|(index, buffer)| {
+                let name = buffer.editor_rows.filename.as_deref()?;
+                Some((index, config::absolute(name)))
+            })
+            .collect();
+        if let (false, Ok(directory)) = (named.is_empty(), env::current_dir()) {
+            let current = named
+                .iter()
+                .position(|(index, _)| *index == output.current_buffer)
+                .unwrap_or(0);
+            output.state.remember_session(Session {
+                directory,
+                files: named.into_iter().map(|(_, path)| path).collect(),
+                current,
+            });
+        }
+        for index in (0..output.buffers.len()).rev() {
+            if index != output.current_buffer {
+                output.remember_file(index);
+            }
+        }
+        output.remember_file(output.current_buffer);
+        let register = output.clipboard.register();
+        if !register.is_empty() {
+            output.state.registers.insert("\"".into(), register.into());
+        }
+        // There is nowhere left to report a failure; the next session
+        // just starts with less.
+        let _ = output.state.save();
+    }
+
+    /// Removes the journals and swap files kept for recovery, which a
+    /// clean exit has no use for.
+    fn discard_recovery(&mut self) {
+        for buffer in &mut self.output.buffers {
+            if let Some(journal) = buffer.editor_rows.journal.take() {
+                journal.discard();
+            }
+            if let Some(swap) = buffer.swap.take() {
+                swap.discard();
+            }
+        }
+    }
+
+    /// Brings the swap file of every buffer without a file up to date: at
+    /// most every `swap::INTERVAL`, unless `now`. Those of buffers that
+    /// no longer need one are removed.
+    fn update_swaps(&mut self, now: bool) {
+        for buffer in &mut self.output.buffers {
+            if !buffer.wants_swap() {
+                if let Some(swap) = buffer.swap.take() {
+                    swap.discard();
+                }
+                continue;
+            }
+            let editor_rows = &buffer.editor_rows;
+            let swap = buffer.swap.get_or_insert_with(Swap::new);
+            // A failed write is tried again the next time round.
+            let _ = swap.update(editor_rows.dirty, || editor_rows.contents(), now);
+        }
+    }
+
+    /// Shows `dialog` until it is answered.
+    fn confirm(&mut self, dialog: Dialog) -> crossterm::Result<Answer> {
+        self.choose(dialog).map(dialog::answer)
+    }
+
+    /// Shows `dialog` until one of its buttons is picked, giving its index.
+    fn choose(&mut self, mut dialog: Dialog) -> crossterm::Result<usize> {
+        let picked = loop {
+            self.output.popup = Some(dialog.popup());
+            self.output.refresh_screen()?;
+            let key = self.reader.read_key()?;
+            self.output.key_echo.record(&key, None);
+            if let Some(picked) = dialog.key(key) {
+                break picked;
+            }
+        };
+        self.output.popup = None;
+        Ok(picked)
+    }
+
+    /// Saves every buffer with unsaved changes, reporting whether they all
+    /// were saved.
+    fn save_all(&mut self) -> crossterm::Result<bool> {
+        let current = self.output.current_buffer;
+        for index in 0..self.output.buffers.len() {
+            if self.output.buffers[index].has_unsaved_changes() {
+                self.output.current_buffer = index;
+                self.save()?;
+            }
+        }
+        let saved = !self.output.buffers.iter().any(Buffer::has_unsaved_changes);
+        if saved {
+            self.output.current_buffer = current;
+        }
+        Ok(saved)
+    }
+
+    fn confirm_trust(&mut self, pending: PendingTrust) -> crossterm::Result<()> {
+        let mut lines = vec![
+            format!(
+                "{} wants to run shell commands (formatter/hooks/pickers):",
+                pending.path.display()
+            ),
+            String::new(),
+        ];
+        lines.extend(pending.commands().into_iter().map(|command| format!("  {}", command)));
+        lines.push(String::new());
+        lines.push("Trust it?".into());
+        if self.confirm(Dialog::new("Trust", lines))? != Answer::Yes {
+            self.output
+                .status_message
+                .set_message("Project formatter, hooks and pickers disabled".into());
+            return Ok(());
+        }
+        match pending.accept() {
+            Ok(config) => self.output.config = config,
+            Err(err) => self
+                .output
+                .status_message
+                .set_message(format!("Could not record trust: {}", err)),
+        }
+        Ok(())
+    }
+
+    fn process_keypress(&mut self) -> crossterm::Result<bool> {
+        let event = self.reader.read_event()?;
+        self.output.tooltip = None;
+        // Popups stay up while the mouse merely moves.
+        if !matches!(
+            event,
+            Event::Mouse(MouseEvent {
+                kind: MouseEventKind::Moved,
+                ..
+            })
+        ) {
+            self.output.popup = None;
+        }
+        let key = match event {
+            Event::Key(key) => key,
+            Event::Mouse(mouse) => {
+                self.output.last_typed = None;
+                self.process_mouse(mouse);
+                return Ok(true);
+            }
+            Event::Resize(..) => return Ok(true),
+        };
+        let effects = match &mut self.output.modal {
+            Some(modal) => modal.key(key),
+            None => vec![Effect::Key(key)],
+        };
+        let action = match effects.first() {
+            Some(Effect::Key(key)) => self.output.config.keymap.action(*key),
+            Some(Effect::Action(action)) => Some(*action),
+            _ => None,
+        };
+        self.output.key_echo.record(&key, action);
+        if let (Some(recorder), Some(action)) = (&mut self.recorder, action) {
+            let cursor = &self.output.buffer().cursor_controller;
+            recorder.record((cursor.cursor_x, cursor.cursor_y), action);
+        }
+        self.output.stats.key();
+        let now = Instant::now();
+        let typed = action.filter(Action::is_typing);
+        let config = &self.output.config;
+        let joins = match (self.output.last_typed, typed) {
+            (Some((last, at)), Some(next)) => {
+                config.undo_group.joins(last, now - at, config.undo_pause, next)
+            }
+            _ => false,
+        };
+        if !joins {
+            self.output.buffer_mut().checkpoint();
+        }
+        self.output.last_typed = typed.map(|typed| (typed, now));
+        let (current, buffers) = (self.output.current_buffer, self.output.buffers.len());
+        let before = self.output.buffer().editor_rows.text.len_chars();
+        for effect in effects {
+            if !self.apply(effect)? {
+                return Ok(false);
+            }
+        }
+        // Keys that switch or close buffers are left uncounted.
+        let output = &mut self.output;
+        if (output.current_buffer, output.buffers.len()) == (current, buffers) {
+            let after = output.buffer().editor_rows.text.len_chars();
+            output.stats.changed(before, after);
+        }
+        Ok(true)
+    }
+
+    /// Does what a key came to in modal editing, or without it, returning
+    /// false once the editor should quit.
+    fn apply(&mut self, effect: Effect) -> crossterm::Result<bool> {
+        let edits = matches!(
+            effect,
+            Effect::DeleteChars(_) | Effect::DeleteLines(_) | Effect::Put { .. }
+        );
+        if edits && !self.ensure_writable() {
+            return Ok(true);
+        }
+        let output = &mut self.output;
+        match effect {
+            Effect::Key(key) => return self.process_key(key),
+            Effect::Action(action) => return self.perform(action),
+            Effect::AlongLine { forward } => output.buffer_mut().move_along_line(forward),
+            Effect::DeleteChars(count) => output.buffer_mut().delete_chars(count),
+            Effect::DeleteLines(count) => {
+                if let Some(lines) = output.buffer_mut().delete_lines(count) {
+                    output.yank_lines(lines);
+                }
+            }
+            Effect::YankLines(count) => {
+                if let Some(lines) = output.buffer().lines_text(count) {
+                    output.yank_lines(lines);
+                }
+            }
+            Effect::Put { after } => {
+                let text = output.clipboard.get();
+                let modal = output.modal.as_ref();
+                if modal.is_some_and(|modal| modal.lines.as_deref() == Some(text.as_str())) {
+                    output.buffer_mut().put_lines(&text, after);
+                } else {
+                    if after {
+                        output.buffer_mut().move_along_line(true);
+                    }
+                    return self.perform(Action::Paste);
+                }
+            }
+            Effect::GotoLine(line) => {
+                let buffer = output.buffer_mut();
+                let last = buffer.editor_rows.number_of_rows().saturating_sub(1);
+                let cursor = &mut buffer.cursor_controller;
+                cursor.anchor = None;
+                cursor.cursor_y = line.map_or(last, |line| cmp::min(line.saturating_sub(1), last));
+                cursor.cursor_x = 0;
+                buffer.clamp_cursor();
+            }
+        }
+        Ok(true)
+    }
+
+    /// Clicking a tab switches to its buffer and middle-clicking closes
+    /// it; clicking in the text moves the cursor there, dragging selects,
+    /// and the wheel scrolls (over the split's view, just the view).
+    /// Resting the mouse on an annotated line shows what it says. With
+    /// `primary_selection`, what the mouse selects goes to the primary
+    /// selection and middle-clicking pastes it.
+    fn process_mouse(&mut self, mouse: MouseEvent) {
+        let (column, row) = (mouse.column as usize, mouse.row as usize);
+        let split = self.output.split_rows();
+        let text_end = split + self.output.text_rows();
+        self.output.hover = None;
+        match mouse.kind {
+            MouseEventKind::Moved if (1..=self.output.win_size.1).contains(&row) => {
+                self.output.hover = Some((column, row - 1));
+            }
+            MouseEventKind::Down(button) if row == 0 => {
+                match (button, self.output.tab_at(column)) {
+                    (MouseButton::Left, Some(index)) => self.output.current_buffer = index,
+                    (MouseButton::Middle, Some(index)) => self.close_tab(index),
+                    _ => {}
+                }
+            }
+            MouseEventKind::Down(MouseButton::Left)
+                if (split + 1..=text_end).contains(&row) =>
+            {
+                let gutter = self.output.gutter_width();
+                self.output
+                    .buffer_mut()
+                    .click(column.saturating_sub(gutter), row - 1 - split);
+            }
+            MouseEventKind::Drag(MouseButton::Left)
+                if (1..=self.output.win_size.1).contains(&row) =>
+            {
+                let gutter = self.output.gutter_width();
+                self.output
+                    .buffer_mut()
+                    .drag(column.saturating_sub(gutter), (row - 1).saturating_sub(split));
+            }
+            MouseEventKind::Up(MouseButton::Left) if self.output.config.primary_selection => {
+                if let Some(text) = self.output.buffer().selected_text() {
+                    self.output.clipboard.set_primary(text);
+                }
+            }
+            MouseEventKind::Down(MouseButton::Middle)
+                if self.output.config.primary_selection
+                    && (split + 1..=text_end).contains(&row) =>
+            {
+                let Some(text) = self.output.clipboard.primary() else {
+                    return;
+                };
+                if !self.ensure_writable() {
+                    return;
+                }
+                let gutter = self.output.gutter_width();
+                let buffer = self.output.buffer_mut();
+                buffer.click(column.saturating_sub(gutter), row - 1 - split);
+                buffer.checkpoint();
+                buffer.paste(&text, false);
+            }
+            MouseEventKind::ScrollUp | MouseEventKind::ScrollDown => {
+                let direction = if mouse.kind == MouseEventKind::ScrollUp {
+                    KeyCode::Up
+                } else {
+                    KeyCode::Down
+                };
+                let buffer = self.output.buffer_mut();
+                let last = buffer.editor_rows.number_of_rows().saturating_sub(1);
+                if let Some(view) = buffer.view.as_mut().filter(|_| row < split) {
+                    view.row_offset = match direction {
+                        KeyCode::Up => view.row_offset.saturating_sub(SCROLL_ROWS),
+                        _ => cmp::min(view.row_offset + SCROLL_ROWS, last),
+                    };
+                    return;
+                }
+                buffer.cursor_controller.anchor = None;
+                buffer.extra_cursors.clear();
+                (0..SCROLL_ROWS).for_each(|_| buffer.move_cursor(direction));
+            }
+            _ => {}
+        }
+    }
+
+    /// Closes the buffer at `index` unless it has unsaved changes.
+    fn close_tab(&mut self, index: usize) {
+        let buffer = &self.output.buffers[index];
+        if buffer.has_unsaved_changes() {
+            let message = format!(
+                "{} has unsaved changes (:bd! to close anyway)",
+                buffer.display_name()
+            );
+            self.output.status_message.set_message(message);
+        } else {
+            self.output.close_buffer(index);
+        }
+    }
+
+    /// Reports whether the current buffer may be modified, telling the user
+    /// why not if it can't.
+    fn ensure_writable(&mut self) -> bool {
+        if self.output.buffer().editor_rows.loader.is_some() {
+            self.output
+                .status_message
+                .set_message("Buffer is still loading".into());
+            return false;
+        }
+        if self.output.buffer().read_only {
+            self.output
+                .status_message
+                .set_message("Buffer is read-only".into());
+        }
+        !self.output.buffer().read_only
+    }
+
+    fn process_key(&mut self, key: KeyEvent) -> crossterm::Result<bool> {
+        match self.output.config.keymap.action(key) {
+            Some(action) => self.perform(action),
+            None => Ok(true),
+        }
+    }
+
+    fn perform(&mut self, action: Action) -> crossterm::Result<bool> {
+        if action.is_edit() && !self.ensure_writable() {
+            return Ok(true);
+        }
+        let output = &mut self.output;
+        let buffer = &mut output.buffers[output.current_buffer];
+        if !matches!(action, Action::Paste | Action::CyclePaste) {
+            buffer.last_paste = None;
+        }
+        if action.is_cursor_local() {
+            let paste = match action {
+                Action::Paste => output.clipboard.get(),
+                _ => String::new(),
+            };
+            let config = &output.config;
+            // A paste is only blamed for breaking a document that was whole.
+            let structured = match buffer.structured_type() {
+                Some(filetype) if action == Action::Paste && config.check_paste => {
+                    let text = buffer.editor_rows.contents();
+                    validate::check(filetype, &text).is_ok().then_some(filetype)
+                }
+                _ => None,
+            };
+            buffer.at_each_cursor(|buffer| buffer.perform_at_cursor(action, config, &paste));
+            let broken = structured.and_then(|filetype| {
+                let text = buffer.editor_rows.contents();
+                validate::check(filetype, &text).err().map(|err| (filetype, err))
+            });
+            if !buffer.extra_cursors.is_empty() {
+                buffer.last_paste = None;
+            }
+            if let Some((filetype, err)) = broken {
+                output.status_message.set_message(format!(
+                    "Paste breaks the {}: {}",
+                    filetype.to_uppercase(),
+                    err
+                ));
+            } else if buffer.last_paste.is_some() {
+                output
+                    .status_message
+                    .set_message("Pasted with adjusted indentation (Alt-V: as copied)".into());
+            }
+            return Ok(true);
+        }
+        if !matches!(action, Action::Save | Action::Copy | Action::ToggleMetrics) {
+            buffer.extra_cursors.clear();
+        }
+        if action.extends_selection() == Some(false) {
+            buffer.cursor_controller.anchor = None;
+        }
+        match action {
+            Action::Quit => {
+                let unsaved: Vec<&str> = self
+                    .output
+                    .buffers
+                    .iter()
+                    .filter(|buffer| buffer.has_unsaved_changes())
+                    .map(Buffer::display_name)
+                    .collect();
+                if !unsaved.is_empty() {
+                    let dialog = Dialog::new(
+                        "Quit",
+                        vec![format!("Save changes to {}?", unsaved.join(", "))],
+                    );
+                    match self.confirm(dialog)? {
+                        Answer::Yes => {
+                            if !self.save_all()? {
+                                return Ok(true);
+                            }
+                        }
+                        Answer::No => {}
+                        Answer::Cancel => return Ok(true),
+                    }
+                }
+                self.discard_recovery();
+                self.save_state();
+                return Ok(false);
+            }
+            Action::Cut | Action::Copy => match self.output.buffer().selected_text() {
+                Some(text) => {
+                    self.output.clipboard.set(text);
+                    if action == Action::Cut {
+                        self.output.buffer_mut().delete_selection();
+                    }
+                }
+                None => self
+                    .output
+                    .status_message
+                    .set_message("Nothing selected".into()),
+            },
+            Action::PageUp | Action::PageDown => {
+                let up = action == Action::PageUp;
+                let screen_rows = self.output.text_rows();
+                let buffer = self.output.buffer_mut();
+                if up {
+                    buffer.cursor_controller.cursor_y = buffer.cursor_controller.row_offset
+                } else {
+                    buffer.cursor_controller.cursor_y = cmp::min(
+                        screen_rows + buffer.cursor_controller.row_offset - 1,
+                        buffer.editor_rows.number_of_rows(),
+                    );
+                }
+                (0..screen_rows).for_each(|_| {
+                    buffer.move_cursor(if up { KeyCode::Up } else { KeyCode::Down });
+                })
+            }
+            Action::Save => self.save()?,
+            Action::Open => {
+                if let Some(file) = prompt!(
+                    &mut self.output,
+                    history = Vec::new(),
+                    complete = |_: &Output, input: &str| Some((0, complete::paths(input))),
+                    "Open: {} (Tab completes, ESC to cancel)"
+                ) {
+                    self.open_file(typed_path(&file))?;
+                }
+            }
+            Action::CyclePaste => {
+                if !self.output.buffer_mut().cycle_paste() {
+                    self.output
+                        .status_message
+                        .set_message("Alt-V only works straight after a paste".into());
+                }
+            }
+            Action::Collapse => self.output.buffer_mut().cursor_controller.anchor = None,
+            Action::NextBuffer => self.output.switch_buffer(true),
+            Action::PreviousBuffer => self.output.switch_buffer(false),
+            Action::CloseBuffer => self.close_tab(self.output.current_buffer),
+            Action::FindFile => self.find_file()?,
+            Action::PickBuffer => self.pick_buffer()?,
+            Action::ToggleMetrics => self.output.metrics.visible = !self.output.metrics.visible,
+            Action::Undo => {
+                self.output.buffer_mut().cursor_controller.anchor = None;
+                let changed = self.output.buffer_mut().undo();
+                if !changed {
+                    self.output
+                        .status_message
+                        .set_message("Already at oldest change".into());
+                }
+            }
+            Action::Redo => {
+                self.output.buffer_mut().cursor_controller.anchor = None;
+                let changed = self.output.buffer_mut().redo();
+                if !changed {
+                    self.output
+                        .status_message
+                        .set_message("Already at newest change".into());
+                }
+            }
+            Action::Find => {
+                self.output.start_search();
+                let history = self.output.state.searches.clone();
+                let query = prompt!(
+                    &mut self.output,
+                    history = history,
+                    callback = Output::find_callback,
+                    "Search: {} (Use ESC / Arrows / Enter, Ctrl-R: regex)"
+                );
+                if let Some(query) = query {
+                    state::remember(&mut self.output.state.searches, &query);
+                }
+            }
+            Action::FindWord => self.output.find_word_under_cursor(),
+            Action::ResolveConflict(side) => {
+                if let Err(message) = self.output.buffer_mut().resolve_conflict(side) {
+                    self.output.status_message.set_message(message.into());
+                }
+            }
+            Action::NextConflict | Action::PreviousConflict => {
+                let forward = action == Action::NextConflict;
+                if !self.output.buffer_mut().goto_conflict(forward) {
+                    self.output
+                        .status_message
+                        .set_message("No more merge conflicts".into());
+                }
+            }
+            Action::NormalizeEndings => {
+                let editor_rows = &mut self.output.buffer_mut().editor_rows;
+                let ending = editor_rows.line_ending;
+                let message = if editor_rows.mixed_endings {
+                    let (crlf, bom) = (ending == LineEnding::Crlf, editor_rows.encoding.bom);
+                    editor_rows.set_format(crlf, bom, false);
+                    format!("Will be saved with {} line endings throughout", ending.name())
+                } else {
+                    format!("Line endings are already all {}", ending.name())
+                };
+                self.output.status_message.set_message(message);
+            }
+            Action::StripBom => {
+                let editor_rows = &mut self.output.buffer_mut().editor_rows;
+                let encoding = editor_rows.encoding;
+                let message = if !encoding.bom {
+                    "No byte order mark to strip"
+                } else if encoding.encoding != encoding_rs::UTF_8 {
+                    "Only a UTF-8 byte order mark is stripped"
+                } else {
+                    let crlf = editor_rows.line_ending == LineEnding::Crlf;
+                    editor_rows.set_format(crlf, false, editor_rows.mixed_endings);
+                    "Will be saved without a byte order mark"
+                };
+                self.output.status_message.set_message(message.into());
+            }
+            Action::FindChar { forward, till } => self.find_char(forward, till)?,
+            Action::CommandLine => {
+                let history = self.output.state.commands.clone();
+                let input = prompt!(
+                    &mut self.output,
+                    history = history,
+                    complete = Output::command_completions,
+                    ":{}"
+                );
+                if let Some(input) = input {
+                    state::remember(&mut self.output.state.commands, &input);
+                    self.execute_command(&input)?;
+                }
+            }
+            _ => unreachable!("cursor-local actions are performed by the buffer"),
+        }
+        Ok(true)
+    }
+
+    /// Reads a character and moves to its next occurrence on the line,
+    /// either onto it (so a selection includes it) or with `till` just
+    /// short of it. The selection, if any, is extended rather than cleared.
+    fn find_char(&mut self, forward: bool, till: bool) -> crossterm::Result<()> {
+        self.output
+            .status_message
+            .set_message("Find character: ".into());
+        self.output.refresh_screen()?;
+        let key = self.reader.read_key()?;
+        self.output.key_echo.record(&key, None);
+        self.output.status_message.set_message(String::new());
+        let ch = match key {
+            KeyEvent {
+                code: KeyCode::Char(ch),
+                modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+            } => ch,
+            KeyEvent {
+                code: KeyCode::Tab,
+                modifiers: KeyModifiers::NONE,
+            } => '\t',
+            _ => return Ok(()),
+        };
+        let buffer = self.output.buffer_mut();
+        let (x, y) = buffer.cursor_position();
+        if y >= buffer.editor_rows.number_of_rows() {
+            return Ok(());
+        }
+        let row = &buffer.editor_rows.get_row(y);
+        // Skip the character next to the cursor so that repeating a till
+        // motion moves on instead of finding the same one again.
+        let found = if forward {
+            row[x..]
+                .char_indices()
+                .skip(usize::from(till))
+                .find(|&(_, c)| c == ch)
+                .map(|(at, c)| if till { x + at } else { x + at + c.len_utf8() })
+        } else {
+            row[..x]
+                .char_indices()
+                .rev()
+                .skip(usize::from(till))
+                .find(|&(_, c)| c == ch)
+                .map(|(at, c)| if till { at + c.len_utf8() } else { at })
+        };
+        match found {
+            Some(at) => buffer.cursor_controller.cursor_x = at,
+            None => self
+                .output
+                .status_message
+                .set_message(format!("{:?} not found on this line", ch)),
+        }
+        Ok(())
+    }
+
+    fn save(&mut self) -> crossterm::Result<()> {
+        if !self.ensure_writable() {
+            return Ok(());
+        }
+        while self.output.buffer().editor_rows.filename.is_none() {
+            let Some(name) = prompt!(&mut self.output, "Save as : {} (ESC to cancel)") else {
+                self.output
+                    .status_message
+                    .set_message("Save Aborted".into());
+                return Ok(());
+            };
+            let name = PathBuf::from(name);
+            if name.exists() {
+                let dialog = Dialog::new(
+                    "Overwrite",
+                    vec![format!("{} already exists. Overwrite it?", name.display())],
+                );
+                match self.confirm(dialog)? {
+                    Answer::Yes => {}
+                    Answer::No => continue,
+                    Answer::Cancel => {
+                        self.output
+                            .status_message
+                            .set_message("Save Aborted".into());
+                        return Ok(());
+                    }
+                }
+            }
+            let indentation = Indentation::for_file(Some(&name), &self.output.config);
+            let editor_rows = &mut self.output.buffer_mut().editor_rows;
+            editor_rows.filename = Some(name);
+            editor_rows.set_tab_stop(indentation.tab_stop);
+        }
+        if self.output.buffer().editor_rows.changed_on_disk() {
+            let name = self.output.buffer().display_name().to_string();
+            let dialog = Dialog::new(
+                "Changed on disk",
+                vec![
+                    format!("{} has changed on disk since it was opened.", name),
+                    "Overwrite it with this buffer (No reloads it from disk)?".into(),
+                ],
+            );
+            match self.confirm(dialog)? {
+                Answer::Yes => {}
+                Answer::No => {
+                    let buffer = self.output.buffer_mut();
+                    let message = match buffer.editor_rows.reload(None) {
+                        Ok(()) => format!("Reloaded {}", name),
+                        Err(err) => format!("Could not reload {}: {}", name, err),
+                    };
+                    buffer.clamp_cursor();
+                    self.output.notifications.push(message);
+                    return Ok(());
+                }
+                Answer::Cancel => {
+                    self.output
+                        .status_message
+                        .set_message("Save Aborted".into());
+                    return Ok(());
+                }
+            }
+        }
+        let syntax_error = match self.check_syntax()? {
+            Some(error) => error,
+            None => return Ok(()),
+        };
+        let lint_error = match self.run_linter()? {
+            Some(error) => error,
+            None => return Ok(()),
+        };
+        if let Err(err) = self.back_up() {
+            let message = format!("Backup failed, not saved: {}", err);
+            self.output.buffer_mut().last_save = Some(LastSave::new(Err(message.clone())));
+            self.output.status_message.set_message(message);
+            return Ok(());
+        }
+        let name = self.output.buffer().display_name().to_string();
+        let mut progress = self.output.progress(format!("Saving {}", name));
+        let trailing_newline = self.output.config.trailing_newline;
+        let buffer = self.output.buffer_mut();
+        let saved = buffer.editor_rows.save(&mut progress, trailing_newline);
+        let result = saved.as_ref().copied().map_err(io::Error::to_string);
+        buffer.last_save = Some(LastSave::new(result));
+        let len = match saved {
+            Ok(len) => len,
+            Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                self.output.status_message.set_message(err.to_string());
+                return Ok(());
+            }
+            Err(err) => return Err(err),
+        };
+        buffer.scratch = false;
+        self.attach_file()?;
+        let output = &mut self.output;
+        output.stats.saves += 1;
+        output.repo.expire();
+        output.notifications.push(format!("{} bytes written to disk", len));
+        if let Some(error) = syntax_error.or(lint_error) {
+            output.status_message.set_message(error);
+        }
+        if let Some(hook) = &output.config.hooks.on_save {
+            let file = output.buffers[output.current_buffer].editor_rows.filename.as_ref();
+            let command = hook.replace("{file}", &shell::quote(&file.unwrap().to_string_lossy()));
+            if let Err(err) = shell::run(&command) {
+                output.notifications.push(err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses a JSON, YAML or TOML buffer about to be saved, marking the
+    /// line of the first syntax error and, with `check_save = "confirm"`,
+    /// asking whether to save anyway. Returns `None` if the save is called
+    /// off, else the error to report once saved, if there is one.
+    fn check_syntax(&mut self) -> crossterm::Result<Option<Option<String>>> {
+        let check_save = self.output.config.check_save;
+        let buffer = self.output.buffer_mut();
+        buffer.editor_rows.annotations.clear(SYNTAX_LAYER);
+        let filetype = match buffer.structured_type() {
+            Some(filetype) if check_save != CheckSave::Off => filetype,
+            _ => return Ok(Some(None)),
+        };
+        let Err(error) = validate::check(filetype, &buffer.editor_rows.contents()) else {
+            return Ok(Some(None));
+        };
+        let annotation = Annotation {
+            sign: Some('!'),
+            text: Some(error.message.clone()),
+            tint: Some(style::Color::AnsiValue(52)),
+            ..Annotation::default()
+        };
+        // An error at the very end may be reported past the last line.
+        let line = cmp::min(error.line, buffer.editor_rows.number_of_rows()).saturating_sub(1);
+        buffer.editor_rows.annotations.set(SYNTAX_LAYER, line, annotation);
+        let message = format!("{} error: {}", filetype.to_uppercase(), error);
+        if check_save == CheckSave::Confirm {
+            let dialog = Dialog::new(
+                "Syntax error",
+                vec![message.clone(), "Save it anyway?".into()],
+            );
+            if self.confirm(dialog)? != Answer::Yes {
+                self.output.status_message.set_message(message);
+                return Ok(None);
+            }
+        }
+        Ok(Some(Some(message)))
+    }
+
+    /// Runs the filetype's `lint` command on the buffer about to be saved,
+    /// marking the lines it reports and asking as `check_syntax` does. The
+    /// text goes in on stdin and, for `{file}`, as a copy in a private
+    /// temporary directory with the file's name, so linters going by the
+    /// extension work. The directory is removed once the linter is done.
+    fn run_linter(&mut self) -> crossterm::Result<Option<Option<String>>> {
+        let check_save = self.output.config.check_save;
+        let buffer = self.output.buffer_mut();
+        buffer.editor_rows.annotations.clear(LINT_LAYER);
+        let Some(file) = buffer.editor_rows.filename.clone() else {
+            return Ok(Some(None));
+        };
+        let lint = FileType::detect(&file)
+            .and_then(|filetype| self.output.config.filetypes.get(filetype.name))
+            .and_then(|settings| settings.lint.clone());
+        let lint = match lint {
+            Some(lint) if check_save != CheckSave::Off => lint,
+            _ => return Ok(Some(None)),
+        };
+        let contents = self.output.buffer().editor_rows.contents();
+        let mut dir = None;
+        let mut copy = PathBuf::new();
+        let command = if lint.contains("{file}") {
+            let copied = atomic::private_dir("rustext-lint").and_then(|private| {
+                copy = private.join(file.file_name().unwrap_or_default());
+                dir = Some(private);
+                let mut new = fs::OpenOptions::new().write(true).create_new(true).open(&copy)?;
+                new.write_all(contents.as_bytes())
+            });
+            if let Err(err) = copied {
+                if let Some(dir) = &dir {
+                    let _ = fs::remove_dir_all(dir);
+                }
+                let message = format!("Could not copy the file for {}: {}", lint, err);
+                self.output.status_message.set_message(message);
+                return Ok(Some(None));
+            }
+            lint.replace("{file}", &shell::quote(&copy.to_string_lossy()))
+        } else {
+            lint.clone()
+        };
+        let mut progress = self.output.progress(format!("Linting with {}", lint)).cancelable();
+        let result = shell::check_with(&command, &contents, || progress.update(0, 0));
+        if let Some(dir) = &dir {
+            let _ = fs::remove_dir_all(dir);
+        }
+        let report = match result {
+            Ok(None) => return Ok(Some(None)),
+            Ok(Some(report)) => report,
+            Err(err) => {
+                self.output.status_message.set_message(err);
+                return Ok(Some(None));
+            }
+        };
+        let real = file.canonicalize().ok();
+        let buffer = self.output.buffer_mut();
+        let rows = buffer.editor_rows.number_of_rows();
+        let mut first = None;
+        for (path, line, text) in report.lines().filter_map(annotations::parse_report) {
+            let ours = path == copy
+                || matches!(path.to_str(), Some("-" | "stdin" | "<stdin>"))
+                || (real.is_some() && path.canonicalize().ok() == real);
+            if !ours {
+                continue;
+            }
+            first.get_or_insert_with(|| format!("line {}: {}", line + 1, text));
+            let annotation = Annotation {
+                sign: Some('!'),
+                text: Some(text),
+                tint: Some(style::Color::AnsiValue(52)),
+                ..Annotation::default()
+            };
+            let line = cmp::min(line, rows.saturating_sub(1));
+            buffer.editor_rows.annotations.set(LINT_LAYER, line, annotation);
+        }
+        let first = first.or_else(|| {
+            let line = report.lines().map(str::trim).find(|line| !line.is_empty());
+            line.map(|line| line.replace(&copy.to_string_lossy().into_owned(), "{file}"))
+        });
+        let program = lint.split_whitespace().next().unwrap_or_default();
+        let message = match first {
+            Some(first) => format!("{} failed: {}", program, first),
+            None => format!("{} failed", program),
+        };
+        if check_save == CheckSave::Confirm {
+            let dialog = Dialog::new(
+                "Lint failed",
+                vec![message.clone(), "Save it anyway?".into()],
+            );
+            if self.confirm(dialog)? != Answer::Yes {
+                self.output.status_message.set_message(message);
+                return Ok(None);
+            }
+        }
+        Ok(Some(Some(message)))
+    }
+
+    /// With `backup` on, copies the file as it is on disk to its backup
+    /// path before it is saved over.
+    fn back_up(&self) -> io::Result<()> {
+        let config = &self.output.config;
+        let file = match &self.output.buffer().editor_rows.filename {
+            Some(file) if config.backup && file.is_file() => file,
+            _ => return Ok(()),
+        };
+        let backup = config.backup_path(file);
+        if let Some(dir) = backup.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::copy(file, backup).map(|_| ())
+    }
+
+    /// Pipes the whole buffer through the configured formatter and replaces
+    /// it with the result.
+    fn format_buffer(&mut self) {
+        let formatter = match &self.output.config.formatter {
+            Some(formatter) => formatter.clone(),
+            None => {
+                self.output
+                    .status_message
+                    .set_message("No formatter configured".into());
+                return;
+            }
+        };
+        let label = format!("Formatting with {}", formatter);
+        let mut progress = self.output.progress(label).cancelable();
+        let buffer = self.output.buffer_mut();
+        let contents = buffer.editor_rows.contents();
+        match shell::filter_with(&formatter, &contents, || progress.update(0, 0)) {
+            Ok(formatted) => {
+                if formatted != contents {
+                    buffer.editor_rows.replace_contents(&formatted);
+                    buffer.clamp_cursor();
+                }
+                self.output
+                    .status_message
+                    .set_message("Buffer formatted".into());
+            }
+            Err(err) => self.output.status_message.set_message(err),
+        }
+    }
+
+    /// `:read`: runs a shell command with the terminal handed over to it and
+    /// inserts what it prints at the cursor, or with `scratch` opens that in
+    /// a scratch buffer of its own.
+    fn read_command(&mut self, command: Option<String>, scratch: bool) -> crossterm::Result<()> {
+        if !scratch && !self.ensure_writable() {
+            return Ok(());
+        }
+        let command = match command {
+            Some(command) => command,
+            None => match prompt!(&mut self.output, "Read from: {} (ESC to cancel)") {
+                Some(command) if !command.trim().is_empty() => command,
+                _ => return Ok(()),
+            },
+        };
+        let text = match release_terminal(|| shell::interactive(&command))? {
+            Ok(text) => text,
+            Err(err) => {
+                self.output.status_message.set_message(err);
+                return Ok(());
+            }
+        };
+        let lines = text.lines().count();
+        if scratch {
+            let mut buffer = Buffer::scratch(self.output.win_size, &self.output.config);
+            buffer.editor_rows.replace_contents(&text);
+            buffer.editor_rows.history.clear();
+            self.output.open_buffer(buffer);
+        } else {
+            let buffer = self.output.buffer_mut();
+            buffer.checkpoint();
+            buffer.paste(text.strip_suffix('\n').unwrap_or(&text), false);
+        }
+        let plural = if lines == 1 { "" } else { "s" };
+        let message = format!("Read {} line{} from {}", lines, plural, command);
+        self.output.status_message.set_message(message);
+        Ok(())
+    }
+
+    /// `:filter`: pipes the lines the selection spans through `command`
+    /// and puts what it prints in their place, selected, so it can be
+    /// filtered again.
+    fn filter_selection(&mut self, command: &str) {
+        if !self.ensure_writable() {
+            return;
+        }
+        let Some(((_, start), (end_x, end_y))) = self.output.buffer().selection() else {
+            self.output
+                .status_message
+                .set_message("Select the lines to filter first".into());
+            return;
+        };
+        // A selection ending at the start of a line leaves that line out.
+        let last = if end_x == 0 && end_y > start { end_y - 1 } else { end_y };
+        let editor_rows = &self.output.buffer().editor_rows;
+        let mut input = String::new();
+        for y in start..=last.min(editor_rows.number_of_rows().saturating_sub(1)) {
+            input.push_str(&editor_rows.get_row(y));
+            input.push('\n');
+        }
+        let label = format!("Filtering through {}", command);
+        let mut progress = self.output.progress(label).cancelable();
+        let output = match shell::filter_with(command, &input, || progress.update(0, 0)) {
+            Ok(output) => output,
+            Err(err) => return self.output.status_message.set_message(err),
+        };
+        let buffer = self.output.buffer_mut();
+        let rows = buffer.editor_rows.number_of_rows();
+        // The lines are replaced from the start of the first to the start of
+        // the line after the last, or the end of the last if it is the end of
+        // the buffer.
+        let (end, text) = if last + 1 < rows {
+            let mut text = output.clone();
+            if !text.is_empty() && !text.ends_with('\n') {
+                text.push('\n');
+            }
+            ((0, last + 1), text)
+        } else {
+            let end = (buffer.editor_rows.get_row(last).len(), last);
+            (end, output.strip_suffix('\n').unwrap_or(&output).to_string())
+        };
+        buffer.checkpoint();
+        buffer.cursor_controller.anchor = Some((0, start));
+        (buffer.cursor_controller.cursor_x, buffer.cursor_controller.cursor_y) = end;
+        buffer.paste(&text, false);
+        buffer.cursor_controller.anchor = Some((0, start));
+        let lines = last + 1 - start;
+        let plural = if lines == 1 { "" } else { "s" };
+        let message = format!("Filtered {} line{} through {}", lines, plural, command);
+        self.output.status_message.set_message(message);
+    }
+
+    /// Prompts for a shell pipeline, showing the buffer as filtered through
+    /// it in place of the text each time typing pauses, and replaces the
+    /// buffer with the result when Enter is pressed.
+    fn pipe_playground(&mut self) -> crossterm::Result<()> {
+        let contents = self.output.buffer().editor_rows.contents();
+        self.output.pipe = Some(Pipe {
+            command: String::new(),
+            output: Ok(contents.clone()),
+        });
+        let key_waiting = || event::poll(Duration::ZERO).unwrap_or(true);
+        let command = prompt!(
+            &mut self.output,
+            callback = |output: &mut Output, input: &str, key: KeyEvent| {
+                if matches!(key.code, KeyCode::Enter | KeyCode::Esc)
+                    || event::poll(PIPE_DELAY).unwrap_or(true)
+                {
+                    return;
+                }
+                let pipe = output.pipe.as_mut().unwrap();
+                if pipe.command == input {
+                    return;
+                }
+                let result = if input.trim().is_empty() {
+                    Ok(contents.clone())
+                } else {
+                    shell::filter_with(input, &contents, || !key_waiting())
+                };
+                // A key pressed meanwhile cut the run short; the next one
+                // starts it again.
+                if !key_waiting() {
+                    pipe.command = input.into();
+                    pipe.output = result;
+                }
+            },
+            "Pipe through: {} (Enter applies, ESC cancels)"
+        );
+        let pipe = self.output.pipe.take().unwrap();
+        let Some(command) = command else {
+            self.output
+                .status_message
+                .set_message("Pipe cancelled".into());
+            return Ok(());
+        };
+        let output = match pipe.output {
+            output if pipe.command == command => output,
+            _ => shell::filter(&command, &contents),
+        };
+        let message = match output {
+            Ok(output) => {
+                let buffer = self.output.buffer_mut();
+                if output != contents {
+                    buffer.editor_rows.replace_contents(&output);
+                    buffer.clamp_cursor();
+                }
+                format!("Piped through {}", command)
+            }
+            Err(err) => err,
+        };
+        self.output.status_message.set_message(message);
+        Ok(())
+    }
+
+    fn execute_command(&mut self, input: &str) -> crossterm::Result<()> {
+        let input = command::expand_alias(input, &self.output.config.aliases);
+        let (name, args) = input.split_once(' ').unwrap_or((&input, ""));
+        if let Some(steps) = self.output.config.commands.get(name).cloned() {
+            if self.nesting == MAX_NESTING {
+                self.output
+                    .status_message
+                    .set_message(format!("Commands nested too deeply at {}", name));
+                return Ok(());
+            }
+            self.nesting += 1;
+            let result = self.run_steps(&steps, args.trim());
+            self.nesting -= 1;
+            return result;
+        }
+        let command = Command::parse(&input);
+        if matches!(
+            command,
+            Ok(Command::Global { .. }
+                | Command::Format
+                | Command::Persist
+                | Command::Utf8
+                | Command::Pipe)
+        ) && !self.ensure_writable()
+        {
+            return Ok(());
+        }
+        match command {
+            Ok(Command::Global {
+                pattern,
+                invert,
+                action,
+            }) => self.run_global(&pattern, invert, &action)?,
+            Ok(Command::New) => {
+                let buffer = Buffer::scratch(self.output.win_size, &self.output.config);
+                self.output.open_buffer(buffer);
+            }
+            Ok(Command::Edit { file }) => self.open_file(typed_path(&file))?,
+            Ok(Command::Buffer { name }) => match self.output.find_buffer(&name) {
+                Ok(index) => self.output.current_buffer = index,
+                Err(err) => self.output.status_message.set_message(err),
+            },
+            Ok(Command::BufferNext) => self.output.switch_buffer(true),
+            Ok(Command::BufferPrevious) => self.output.switch_buffer(false),
+            Ok(Command::BufferDelete { force }) => {
+                if self.output.buffer().has_unsaved_changes() && !force {
+                    self.output
+                        .status_message
+                        .set_message("Buffer has unsaved changes (add ! to override)".into());
+                } else {
+                    self.output.close_buffer(self.output.current_buffer);
+                }
+            }
+            Ok(Command::Notes) => self.open_notes()?,
+            Ok(Command::Persist) => self.persist_scratch()?,
+            Ok(Command::Format) => self.format_buffer(),
+            Ok(Command::EnclosingNode) => self.jump_to_node(None),
+            Ok(Command::NextFunction) => self.jump_to_node(Some(true)),
+            Ok(Command::PreviousFunction) => self.jump_to_node(Some(false)),
+            Ok(Command::SelectMatches { pattern }) => match Matcher::new(&pattern, true) {
+                Ok(matcher) => {
+                    let count = self.output.buffer_mut().select_matches(&matcher);
+                    self.output.status_message.set_message(match count {
+                        0 => format!("Pattern not found: {}", pattern),
+                        1 => "1 cursor".into(),
+                        _ => format!("{} cursors", count),
+                    });
+                }
+                Err(err) => self.output.status_message.set_message(err),
+            },
+            Ok(Command::Filter { command }) => self.filter_selection(&command),
+            Ok(Command::Files) => self.find_file()?,
+            Ok(Command::Pick { name }) => self.pick_from_source(&name)?,
+            Ok(Command::Buffers) => self.pick_buffer()?,
+            Ok(Command::Grep { pattern }) => match Matcher::new(&pattern, true) {
+                Ok(matcher) => {
+                    let label = format!("Searching for {}", pattern);
+                    let mut progress = self.output.progress(label).cancelable();
+                    let items = picker::grep_items(&project_root(), &matcher, |done, total| {
+                        progress.update(done as u64, total as u64)
+                    });
+                    let Some(items) = items else {
+                        self.output
+                            .status_message
+                            .set_message("Search cancelled".into());
+                        return Ok(());
+                    };
+                    if items.is_empty() {
+                        self.output
+                            .status_message
+                            .set_message(format!("Pattern not found: {}", pattern));
+                    } else {
+                        self.pick(Picker::new("Grep", items))?;
+                    }
+                }
+                Err(err) => self.output.status_message.set_message(err),
+            },
+            Ok(Command::KeyEcho) => {
+                let key_echo = &mut self.output.key_echo;
+                key_echo.visible = !key_echo.visible;
+                key_echo.clear();
+            }
+            Ok(Command::SpellLanguage { language: None }) => {
+                let language = match &self.output.spell {
+                    Some(spell) => spell.language.clone(),
+                    None => self.output.config.spell_language.clone(),
+                };
+                self.output
+                    .status_message
+                    .set_message(format!("Spell language: {}", language));
+            }
+            Ok(Command::SpellLanguage {
+                language: Some(language),
+            }) => match Spell::load(&language) {
+                Ok(spell) => {
+                    self.output.spell = Some(spell);
+                    self.output
+                        .status_message
+                        .set_message(format!("Spell language: {}", language));
+                }
+                Err(err) => self.output.status_message.set_message(err),
+            },
+            Ok(Command::SpellInstall { language }) => {
+                let language = language.unwrap_or_else(|| match &self.output.spell {
+                    Some(spell) => spell.language.clone(),
+                    None => self.output.config.spell_language.clone(),
+                });
+                self.output
+                    .status_message
+                    .set_message(format!("Downloading the {} dictionary...", language));
+                self.output.refresh_screen()?;
+                let message = match spell::install(&language) {
+                    Ok(path) => format!("Installed {}", path.display()),
+                    Err(err) => err,
+                };
+                self.output.status_message.set_message(message);
+            }
+            Ok(Command::Prose) => {
+                let buffer = self.output.buffer_mut();
+                let message = if !FileType::allows_prose(buffer.editor_rows.filename.as_deref()) {
+                    "Prose mode is not available for code"
+                } else {
+                    buffer.prose = !buffer.prose;
+                    if buffer.prose {
+                        "Prose mode on"
+                    } else {
+                        "Prose mode off"
+                    }
+                };
+                self.output.status_message.set_message(message.into());
+            }
+            Ok(Command::SpellGood) => self.add_spelling(),
+            Ok(Command::SpellNext) => self.next_misspelling(),
+            Ok(Command::Mark) => {
+                let buffer = self.output.buffer_mut();
+                let y = buffer.cursor_controller.cursor_y;
+                let annotations = &mut buffer.editor_rows.annotations;
+                let message = if annotations.remove(BOOKMARK_LAYER, y) {
+                    "Bookmark removed"
+                } else {
+                    annotations.set(BOOKMARK_LAYER, y, bookmark());
+                    "Bookmark set"
+                };
+                self.output.status_message.set_message(message.into());
+            }
+            Ok(Command::Marks) => self.pick_bookmark()?,
+            Ok(Command::Recent) => self.pick_recent()?,
+            Ok(Command::Theme) => self.pick_theme()?,
+            Ok(Command::Keymap) => self.pick_keymap()?,
+            Ok(Command::Commands) => self.pick_command()?,
+            Ok(Command::Help { topic }) => {
+                match help::text(topic.as_deref(), &self.output.config) {
+                    Ok(text) => self.output.show_help(&text),
+                    Err(err) => self.output.status_message.set_message(err),
+                }
+            }
+            Ok(Command::Info) => {
+                let buffer = self.output.buffer();
+                let y = buffer.cursor_controller.cursor_y;
+                let lines = buffer.editor_rows.annotations.describe(y);
+                if lines.is_empty() {
+                    self.output
+                        .status_message
+                        .set_message("Nothing annotated on this line".into());
+                } else {
+                    self.output.popup = Some(Popup::new(Anchor::Cursor, lines));
+                }
+            }
+            Ok(Command::Notifications) => {
+                let mut lines = self.output.notifications.history();
+                if lines.is_empty() {
+                    self.output
+                        .status_message
+                        .set_message("No notifications yet".into());
+                } else {
+                    // Keep the latest, which are last.
+                    let shown = lines.len().min(self.output.win_size.1.saturating_sub(2));
+                    lines.drain(..lines.len() - shown);
+                    let popup = Popup::new(Anchor::Center, lines).titled("notifications");
+                    self.output.popup = Some(popup);
+                }
+            }
+            Ok(Command::Numbers { style }) => {
+                let current = self.output.config.line_numbers;
+                let style = match style.as_deref() {
+                    Some(name) => LineNumbers::parse(name),
+                    None => Some(match current {
+                        LineNumbers::Off => LineNumbers::Absolute,
+                        LineNumbers::Absolute => LineNumbers::Relative,
+                        LineNumbers::Relative => LineNumbers::Off,
+                    }),
+                };
+                let message = match style {
+                    Some(style) => {
+                        self.output.config.line_numbers = style;
+                        format!("Line numbers: {}", style.name())
+                    }
+                    None => "Usage: numbers [off|absolute|relative]".into(),
+                };
+                self.output.status_message.set_message(message);
+            }
+            Ok(Command::Annotate { command }) => self.annotate(command.as_deref())?,
+            Ok(Command::Blame { all }) => self.toggle_blame(all),
+            Ok(Command::Convert) => self.convert_number()?,
+            Ok(Command::Utf8) => {
+                let editor_rows = &mut self.output.buffer_mut().editor_rows;
+                let message = if editor_rows.encoding.encoding == encoding_rs::UTF_8 {
+                    "Already UTF-8"
+                } else {
+                    editor_rows.set_encoding(FileEncoding::default());
+                    "Will be saved as UTF-8"
+                };
+                self.output.status_message.set_message(message.into());
+            }
+            Ok(Command::Export { format, file }) => self.export_block(&format, file.as_deref()),
+            Ok(Command::Split { line }) => self.split(line),
+            Ok(Command::Follow) => self.toggle_follow(),
+            Ok(Command::Run) => self.run_file()?,
+            Ok(Command::Pipe) => self.pipe_playground()?,
+            Ok(Command::Read { command, scratch }) => self.read_command(command, scratch)?,
+            Ok(Command::Reopen { encoding }) => self.reopen(&encoding),
+            Ok(Command::SinceBreak) => self.show_since_break(),
+            Ok(Command::LastSave) => self.show_last_save(),
+            Ok(Command::Stats) => {
+                let lines = self.output.stats.lines();
+                self.output.popup = Some(Popup::new(Anchor::Center, lines).titled("session"));
+            }
+            Ok(Command::ExportSession { file }) => self.export_session(Path::new(&file)),
+            Ok(Command::ImportSession { file }) => self.import_session(Path::new(&file))?,
+            Ok(Command::Stop) => {
+                let message = match self.output.job.take() {
+                    Some(job) if job.is_running() => "Stopped the job",
+                    Some(_) => "Closed the output",
+                    None => "Nothing is running",
+                };
+                self.output.status_message.set_message(message.into());
+            }
+            Ok(Command::Set { name, value }) => {
+                let output = &mut self.output;
+                let message = match (name, value) {
+                    (None, _) => command::SETTINGS
+                        .iter()
+                        .filter_map(|(name, ..)| {
+                            Some(format!("{}={}", name, output.setting(name)?))
+                        })
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    (Some(name), None) => match output.setting(&name) {
+                        Some(value) => format!("{}={}", name, value),
+                        None => format!("Unknown setting: {}", name),
+                    },
+                    (Some(name), Some(value)) => match output.set(&name, &value) {
+                        Ok(()) => {
+                            if !command::BUFFER_SETTINGS.contains(&name.as_str()) {
+                                let value = output.setting(&name).unwrap_or(value.clone());
+                                output.overrides.insert(name.clone(), value);
+                            }
+                            format!("{}={}", name, value)
+                        }
+                        Err(err) => err,
+                    },
+                };
+                output.status_message.set_message(message);
+            }
+            Err(err) => self.output.status_message.set_message(err),
+        }
+        Ok(())
+    }
+
+    /// Opens the split's view at `line`, or at the top of the screen so what
+    /// is there stays in sight as editing moves on; without a line, closes
+    /// the open view.
+    fn split(&mut self, line: Option<usize>) {
+        let too_small = self.output.win_size.1 < 4;
+        let buffer = self.output.buffer_mut();
+        let message = match (line, buffer.view) {
+            _ if too_small => "The window is too small to split",
+            (None, Some(_)) => {
+                buffer.view = None;
+                "Closed the view"
+            }
+            (line, _) => {
+                let last = buffer.editor_rows.number_of_rows().saturating_sub(1);
+                let top = line.map_or(buffer.cursor_controller.row_offset, |line| line - 1);
+                buffer.view = Some(View {
+                    row_offset: top.min(last),
+                });
+                "Scroll the view with the mouse wheel; :split closes it"
+            }
+        };
+        self.output.status_message.set_message(message.into());
+    }
+
+    /// Saves the current file and runs it in the background, with the
+    /// interpreter its `#!` line names or else its filetype's `run`
+    /// command, replacing any job already running. What it prints is shown
+    /// in the output pane as it comes.
+    fn run_file(&mut self) -> crossterm::Result<()> {
+        let buffer = self.output.buffer();
+        if buffer.editor_rows.filename.is_none() || buffer.has_unsaved_changes() {
+            self.save()?;
+        }
+        let buffer = self.output.buffer();
+        let file = match &buffer.editor_rows.filename {
+            Some(file) if !buffer.has_unsaved_changes() => file,
+            _ => return Ok(()),
+        };
+        let path = shell::quote(&file.to_string_lossy());
+        let rows = buffer.editor_rows.number_of_rows();
+        let first_line = (rows > 0).then(|| buffer.editor_rows.get_row(0));
+        let command = match first_line.as_deref().and_then(|line| line.strip_prefix("#!")) {
+            Some(interpreter) => Some(format!("{} {}", interpreter.trim(), path)),
+            None => FileType::detect(file)
+                .and_then(|filetype| self.output.config.filetypes.get(filetype.name))
+                .and_then(|settings| settings.run.as_ref())
+                .map(|run| run.replace("{file}", &path)),
+        };
+        let Some(command) = command else {
+            let message = "No #! line, and no run command for this filetype in the config";
+            self.output.status_message.set_message(message.into());
+            return Ok(());
+        };
+        // Dropping the last job kills it if it is still going.
+        self.output.job = None;
+        let message = match Job::start(&command) {
+            Ok(job) => {
+                self.output.job = Some(job);
+                format!("Running {} (:stop stops it)", command)
+            }
+            Err(err) => err,
+        };
+        self.output.status_message.set_message(message);
+        Ok(())
+    }
+
+    /// Writes the open files under the project root to `file`, with where
+    /// each was left and the settings changed with `:set`. Paths are kept
+    /// relative to the root, so the session opens in anyone's checkout.
+    fn export_session(&mut self, file: &Path) {
+        let root = project_root();
+        let output = &mut self.output;
+        let mut session = SharedSession {
+            settings: output.overrides.clone(),
+            ..SharedSession::default()
+        };
+        let mut outside = 0;
+        for (index, buffer) in output.buffers.iter().enumerate() {
+            let Some(name) = buffer.editor_rows.filename.as_deref() else {
+                continue;
+            };
+            let path = config::absolute(name);
+            let Ok(path) = path.strip_prefix(&root).map(Path::to_path_buf) else {
+                outside += 1;
+                continue;
+            };
+            if index == output.current_buffer {
+                session.current = session.files.len();
+            }
+            let cursor = &buffer.cursor_controller;
+            session.files.push(SharedFile {
+                path,
+                cursor: (cursor.cursor_x, cursor.cursor_y),
+                split: buffer.view.map(|view| view.row_offset),
+                bookmarks: buffer.editor_rows.annotations.lines(BOOKMARK_LAYER),
+            });
+        }
+        let message = match session.write(file) {
+            Ok(()) if outside > 0 => format!(
+                "Wrote {} files to {}, leaving out {} outside the project",
+                session.files.len(),
+                file.display(),
+                outside
+            ),
+            Ok(()) => format!("Wrote {} files to {}", session.files.len(), file.display()),
+            Err(err) => format!("Could not write {}: {}", file.display(), err),
+        };
+        output.status_message.set_message(message);
+    }
+
+    /// Opens the files of a session `:session export` wrote, from this
+    /// project's root, putting each back as it was left, and applies its
+    /// settings. Files the checkout doesn't have are skipped.
+    fn import_session(&mut self, file: &Path) -> crossterm::Result<()> {
+        let session = match SharedSession::read(file) {
+            Ok(session) => session,
+            Err(err) => {
+                let message = format!("Could not read {}: {}", file.display(), err);
+                self.output.status_message.set_message(message);
+                return Ok(());
+            }
+        };
+        let root = project_root();
+        let mut missing = Vec::new();
+        let mut current = None;
+        for (index, shared) in session.files.iter().enumerate() {
+            let path = root.join(&shared.path);
+            if !path.is_file() {
+                missing.push(shared.path.display().to_string());
+                continue;
+            }
+            self.open_file(path)?;
+            if index == session.current || current.is_none() {
+                current = Some(self.output.current_buffer);
+            }
+            let buffer = self.output.buffer_mut();
+            for &line in &shared.bookmarks {
+                buffer.editor_rows.annotations.set(BOOKMARK_LAYER, line, bookmark());
+            }
+            if !buffer.merging {
+                (buffer.cursor_controller.cursor_x, buffer.cursor_controller.cursor_y) =
+                    shared.cursor;
+                buffer.clamp_cursor();
+            }
+            let last = buffer.editor_rows.number_of_rows().saturating_sub(1);
+            buffer.view = shared.split.map(|top| View {
+                row_offset: top.min(last),
+            });
+        }
+        if let Some(current) = current {
+            self.output.current_buffer = current;
+        }
+        let mut problems = Vec::new();
+        for (name, value) in &session.settings {
+            let result = if command::BUFFER_SETTINGS.contains(&name.as_str()) {
+                Err(format!("{} belongs to a file, not a session", name))
+            } else {
+                self.output.set(name, value)
+            };
+            match result {
+                Ok(()) => {
+                    self.output.overrides.insert(name.clone(), value.clone());
+                }
+                Err(err) => problems.push(err),
+            }
+        }
+        if !missing.is_empty() {
+            problems.push(format!("not in this checkout: {}", missing.join(", ")));
+        }
+        let opened = session.files.len() - missing.len();
+        let mut message = format!("Opened {} files from {}", opened, file.display());
+        if !problems.is_empty() {
+            message = format!("{}; {}", message, problems.join("; "));
+        }
+        self.output.status_message.set_message(message);
+        Ok(())
+    }
+
+    /// Says when the current buffer was last saved this session, and the
+    /// bytes written or why the save failed.
+    fn show_last_save(&mut self) {
+        let buffer = self.output.buffer();
+        let name = buffer.display_name();
+        let message = match &buffer.last_save {
+            None => format!("{} hasn't been saved this session", name),
+            Some(last_save) => {
+                let seconds = last_save.at.duration_since(UNIX_EPOCH).map_or(0, |at| at.as_secs());
+                let when = format!("{} ago, at {} UTC", last_save.ago(), convert::utc(seconds));
+                match &last_save.result {
+                    Ok(len) => format!("Saved {} {}: {} bytes", name, when, len),
+                    Err(err) => format!("Saving {} failed {}: {}", name, when, err),
+                }
+            }
+        };
+        self.output.status_message.set_message(message);
+    }
+
+    /// Shows how the current buffer differs from its snapshot from before
+    /// the last break.
+    fn show_since_break(&mut self) {
+        let output = &mut self.output;
+        let buffer = output.buffer();
+        let message = match (&buffer.before_break, output.config.idle_snapshot) {
+            (_, None) => "Snapshots are off; idle_snapshot in the config turns them on",
+            (None, Some(_)) => "No break since the buffer was opened",
+            (Some(before), Some(_)) => {
+                let (old, new) = (before.contents(), buffer.editor_rows.text.contents());
+                let old: Vec<&str> = old.lines().collect();
+                let new: Vec<&str> = new.lines().collect();
+                let mut lines = diff::unified(&old, &new, 3);
+                if lines.is_empty() {
+                    "Nothing has changed since the break"
+                } else {
+                    lines.truncate(output.win_size.1.saturating_sub(2));
+                    let popup = Popup::new(Anchor::Center, lines).titled("since the break");
+                    output.popup = Some(popup);
+                    return;
+                }
+            }
+        };
+        output.status_message.set_message(message.into());
+    }
+
+    /// Reads the current file again as `label` names, for a file whose
+    /// encoding was guessed wrong.
+    fn reopen(&mut self, label: &str) {
+        let Some(encoding) = FileEncoding::for_label(label) else {
+            let message = format!("Unknown encoding: {}", label);
+            return self.output.status_message.set_message(message);
+        };
+        let buffer = self.output.buffer_mut();
+        let name = buffer.display_name().to_string();
+        let message = match buffer.editor_rows.reload(Some(encoding)) {
+            Ok(()) => format!("Reopened {} as {}", name, encoding.name()),
+            Err(err) => format!("Could not reopen {}: {}", name, err),
+        };
+        buffer.clamp_cursor();
+        self.output.status_message.set_message(message);
+    }
+
+    /// Starts or stops reloading the current file whenever another program
+    /// writes it.
+    fn toggle_follow(&mut self) {
+        let buffer = self.output.buffer_mut();
+        let name = buffer.display_name().to_string();
+        let message = match (&buffer.follow, &buffer.editor_rows.filename) {
+            (Some(_), _) => {
+                buffer.follow = None;
+                format!("Stopped following {}", name)
+            }
+            (None, None) => "Only a file can be followed".into(),
+            (None, Some(file)) => match Follow::new(file) {
+                Ok(follow) => {
+                    buffer.follow = Some(follow);
+                    format!("Following {}: it reloads when written", name)
+                }
+                Err(err) => format!("Could not follow {}: {}", name, err),
+            },
+        };
+        self.output.status_message.set_message(message);
+    }
+
+    /// Shows the conversions of the number under the cursor, numbered, and
+    /// replaces the number with the one whose number is typed.
+    fn convert_number(&mut self) -> crossterm::Result<()> {
+        let buffer = self.output.buffer();
+        let (x, y) = buffer.cursor_position();
+        let word = (y < buffer.editor_rows.number_of_rows())
+            .then(|| buffer.editor_rows.get_row(y))
+            .and_then(|row| {
+                let (start, end) = WordChars::default().word_at(&row, x)?;
+                let conversions = convert::conversions(&row[start..end])?;
+                Some((start, end, conversions))
+            });
+        let Some((start, end, conversions)) = word else {
+            self.output
+                .status_message
+                .set_message("No number under cursor".into());
+            return Ok(());
+        };
+        let lines = conversions
+            .iter()
+            .enumerate()
+            .map(|(at, (label, text))| format!("{} {:<5} {}", at + 1, label, text))
+            .collect();
+        self.output.popup = Some(Popup::new(Anchor::Cursor, lines).titled("convert"));
+        self.output
+            .status_message
+            .set_message("Type a number to replace it, any other key to close".into());
+        self.output.refresh_screen()?;
+        let key = self.reader.read_key()?;
+        self.output.key_echo.record(&key, None);
+        self.output.popup = None;
+        self.output.status_message.set_message(String::new());
+        let chosen = match key.code {
+            KeyCode::Char(ch) => ch
+                .to_digit(10)
+                .and_then(|n| conversions.get((n as usize).checked_sub(1)?)),
+            _ => None,
+        };
+        if let Some((_, text)) = chosen {
+            if !self.ensure_writable() {
+                return Ok(());
+            }
+            let buffer = self.output.buffer_mut();
+            buffer.checkpoint();
+            buffer.cursor_controller.anchor = Some((start, y));
+            (buffer.cursor_controller.cursor_x, buffer.cursor_controller.cursor_y) = (end, y);
+            buffer.paste(text, false);
+        }
+        Ok(())
+    }
+
+    /// Writes the selected block as a table, to `file` or else to the
+    /// clipboard.
+    fn export_block(&mut self, format: &str, file: Option<&str>) {
+        let Some(format) = table::Format::parse(format) else {
+            self.output
+                .status_message
+                .set_message(format!("Unknown table format: {}", format));
+            return;
+        };
+        let Some(lines) = self.output.buffer().selected_block() else {
+            self.output
+                .status_message
+                .set_message("Select the columns to export first".into());
+            return;
+        };
+        let rows = table::fields(&lines);
+        let text = table::write(&rows, format);
+        let message = match file {
+            Some(file) => match fs::write(file, text) {
+                Ok(()) => format!("{} rows written to {}", rows.len(), file),
+                Err(err) => format!("Can't write {}: {}", file, err),
+            },
+            None => {
+                self.output.clipboard.set(text);
+                format!("{} rows copied", rows.len())
+            }
+        };
+        self.output.status_message.set_message(message);
+    }
+
+    fn pick_bookmark(&mut self) -> crossterm::Result<()> {
+        let mut items = Vec::new();
+        for (index, buffer) in self.output.buffers.iter().enumerate() {
+            let name = tab_label(buffer);
+            for line in buffer.editor_rows.annotations.lines(BOOKMARK_LAYER) {
+                let text = match line < buffer.editor_rows.number_of_rows() {
+                    true => buffer.editor_rows.get_row(line).trim().to_string(),
+                    false => String::new(),
+                };
+                items.push(picker::Item {
+                    label: format!("{}:{}: {}", name.trim(), line + 1, text),
+                    target: Target::Buffer(index, line),
+                });
+            }
+        }
+        if items.is_empty() {
+            self.output
+                .status_message
+                .set_message("No bookmarks (:mark sets one)".into());
+            return Ok(());
+        }
+        self.pick(Picker::new("Bookmark", items))
+    }
+
+    /// Runs the steps of a user command with `args` filled in, and the
+    /// file name quoted in steps that run a shell command.
+    fn run_steps(&mut self, steps: &[CommandStep], args: &str) -> crossterm::Result<()> {
+        let file = match &self.output.buffer().editor_rows.filename {
+            Some(file) => file.display().to_string(),
+            None => String::new(),
+        };
+        for step in steps {
+            match step {
+                CommandStep::Run(run) => match (run.strip_prefix(':'), Action::from_name(run)) {
+                    (Some(line), _) => {
+                        let expanded = command::expand_alias(line, &self.output.config.aliases);
+                        let file = match command::runs_shell(&expanded) {
+                            true => shell::quote(&file),
+                            false => file.clone(),
+                        };
+                        self.execute_command(&command::substitute(line, args, &file))?
+                    }
+                    (None, Some(Action::Quit)) => self
+                        .output
+                        .status_message
+                        .set_message("Commands can't quit".into()),
+                    (None, Some(action)) => {
+                        self.perform(action)?;
+                    }
+                    (None, None) => self
+                        .output
+                        .status_message
+                        .set_message(format!("Unknown action: {}", run)),
+                },
+                CommandStep::Insert { insert } => {
+                    if self.ensure_writable() {
+                        let text = command::substitute(insert, args, &file);
+                        self.output.buffer_mut().paste(&text, false);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Lists the built-in commands, the user's own and their aliases, to
+    /// run the one chosen.
+    fn pick_command(&mut self) -> crossterm::Result<()> {
+        let config = &self.output.config;
+        let mut items: Vec<picker::Item> = command::COMMANDS
+            .iter()
+            .map(|(name, ..)| picker::Item {
+                label: name.to_string(),
+                target: Target::Command(name.to_string()),
+            })
+            .collect();
+        for name in config.commands.keys() {
+            items.push(picker::Item {
+                label: format!("{} (user command)", name),
+                target: Target::Command(name.clone()),
+            });
+        }
+        for (name, command) in &config.aliases {
+            items.push(picker::Item {
+                label: format!("{} (alias for {})", name, command),
+                target: Target::Command(name.clone()),
+            });
+        }
+        items.sort_by(|a, b| a.label.cmp(&b.label));
+        self.pick(Picker::new("Command", items))
+    }
+
+    /// Lists the themes, the theme files first and then the syntax themes,
+    /// and writes the one chosen to the config.
+    fn pick_theme(&mut self) -> crossterm::Result<()> {
+        let mut names = theme::file_names();
+        let syntax_themes: Vec<String> = self.output.highlighter.theme_names()
+            .filter(|name| !names.iter().any(|file| file == name))
+            .map(String::from)
+            .collect();
+        names.extend(syntax_themes);
+        let current = self.output.config.theme.as_deref().unwrap_or(highlight::DEFAULT_THEME);
+        let current = current.to_string();
+        if let Some(name) = self.pick_live("theme", &names, &current, Output::set_theme)? {
+            self.keep_setting("theme", &toml::Value::from(name.as_str()).to_string());
+        }
+        Ok(())
+    }
+
+    /// Lists the key binding presets, the standard Ctrl keys or vi-style
+    /// modes, and writes the one chosen to the config.
+    fn pick_keymap(&mut self) -> crossterm::Result<()> {
+        let names = ["standard".to_string(), "modal".to_string()];
+        let current = if self.output.modal.is_some() { "modal" } else { "standard" };
+        if let Some(name) = self.pick_live("keymap", &names, current, Output::set_keymap)? {
+            self.keep_setting("modal", if name == "modal" { "true" } else { "false" });
+        }
+        Ok(())
+    }
+
+    /// Lists `names` over the text, applying each with `apply` as it is
+    /// highlighted so it shows in place, until Enter keeps one or Esc puts
+    /// `current` back. Returns the name kept.
+    fn pick_live(
+        &mut self,
+        title: &str,
+        names: &[String],
+        current: &str,
+        apply: fn(&mut Output, &str),
+    ) -> crossterm::Result<Option<String>> {
+        if names.is_empty() {
+            return Ok(None);
+        }
+        let rows = cmp::max(1, self.output.win_size.1.saturating_sub(2));
+        let mut selected = names.iter().position(|name| name == current).unwrap_or(0);
+        let kept = loop {
+            let first = (selected + 1).saturating_sub(rows);
+            let lines = names
+                .iter()
+                .enumerate()
+                .skip(first)
+                .take(rows)
+                .map(|(at, name)| format!("{} {}", if at == selected { '>' } else { ' ' }, name))
+                .collect();
+            self.output.popup = Some(Popup::new(Anchor::TopRight, lines).titled(title));
+            let message = format!("{}: {} (Use ESC / Arrows / Enter)", title, names[selected]);
+            self.output.status_message.set_message(message);
+            self.output.refresh_screen()?;
+            let key = self.reader.read_key()?;
+            self.output.key_echo.record(&key, None);
+            let last = names.len() - 1;
+            let previous = selected;
+            selected = match key.code {
+                KeyCode::Esc => break None,
+                KeyCode::Enter => break Some(names[selected].clone()),
+                KeyCode::Up => selected.saturating_sub(1),
+                KeyCode::Down => selected + 1,
+                KeyCode::PageUp => selected.saturating_sub(rows),
+                KeyCode::PageDown => selected + rows,
+                _ => selected,
+            }
+            .min(last);
+            if selected != previous {
+                apply(&mut self.output, &names[selected]);
+            }
+        };
+        self.output.popup = None;
+        self.output.status_message.set_message(String::new());
+        if kept.is_none() {
+            apply(&mut self.output, current);
+        }
+        Ok(kept)
+    }
+
+    /// Writes a top-level setting to the user's config, saying where.
+    fn keep_setting(&mut self, key: &str, value: &str) {
+        let message = match config::write_setting(self.config_file.as_deref(), key, value) {
+            Ok(path) => format!("{} = {} written to {}", key, value, path.display()),
+            Err(err) => format!("Could not write {} to the config: {}", key, err),
+        };
+        self.output.status_message.set_message(message);
+    }
+
+    /// Lists the files edited in earlier sessions, most recent first.
+    fn pick_recent(&mut self) -> crossterm::Result<()> {
+        let items: Vec<picker::Item> = self
+            .output
+            .state
+            .files
+            .iter()
+            .filter(|file| file.path.is_file())
+            .map(|file| picker::Item {
+                label: file.path.display().to_string(),
+                target: Target::File(file.path.clone(), None),
+            })
+            .collect();
+        if items.is_empty() {
+            self.output
+                .status_message
+                .set_message("No recent files".into());
+            return Ok(());
+        }
+        self.pick(Picker::new("Recent", items))
+    }
+
+    /// Runs `command` and annotates the lines of open buffers it reports
+    /// on, replacing the previous run's annotations. With no command, the
+    /// annotations are just cleared.
+    /// Turns `:blame` on for the cursor line or every line, or off when it
+    /// is already on that way.
+    fn toggle_blame(&mut self, all: bool) {
+        let output = &mut self.output;
+        let buffer = &mut output.buffers[output.current_buffer];
+        if buffer.editor_rows.filename.is_none() {
+            output.status_message.set_message("Only files can be blamed".into());
+            return;
+        }
+        let message = match &mut buffer.blame {
+            Some(blame) if blame.all == all => {
+                buffer.blame = None;
+                buffer.editor_rows.annotations.clear(BLAME_LAYER);
+                "Blame off"
+            }
+            Some(blame) => {
+                blame.all = all;
+                blame.shown = None;
+                buffer.editor_rows.annotations.clear(BLAME_LAYER);
+                if all { "Blaming every line" } else { "Blaming the cursor line" }
+            }
+            None => {
+                buffer.blame = Some(Blame::new(all));
+                "Running git blame..."
+            }
+        };
+        output.status_message.set_message(message.into());
+    }
+
+    /// Keeps the branch on the status bar up to date for the directory of
+    /// the current file, or without one the working directory.
+    fn update_repo(&mut self) {
+        if !self.output.config.status_bar.branch {
+            return;
+        }
+        let file = self.output.buffer().editor_rows.filename.as_deref();
+        let dir = match file.map(config::absolute) {
+            Some(file) => file.parent().map(Path::to_path_buf),
+            None => env::current_dir().ok(),
+        };
+        if let Some(dir) = dir {
+            self.output.repo.refresh(&dir);
+        }
+    }
+
+    /// Keeps `:blame`'s annotations up to date with the text and the
+    /// cursor, blaming the text again once it has changed.
+    fn update_blame(&mut self) {
+        let output = &mut self.output;
+        let buffer = &mut output.buffers[output.current_buffer];
+        let Some(blame) = &mut buffer.blame else {
+            return;
+        };
+        let editor_rows = &mut buffer.editor_rows;
+        match blame.poll() {
+            Ok(true) => blame.shown = None,
+            Ok(false) => {}
+            Err(err) => {
+                buffer.blame = None;
+                editor_rows.annotations.clear(BLAME_LAYER);
+                output.status_message.set_message(err);
+                return;
+            }
+        }
+        let dirty = editor_rows.dirty;
+        if let Some(file) = &editor_rows.filename {
+            blame.refresh(file, dirty, || editor_rows.contents());
+        }
+        // Until the new lines come, those shown move with the text.
+        let cursor_y = buffer.cursor_controller.cursor_y;
+        if !blame.is_current(dirty) || blame.shown == Some((dirty, cursor_y)) {
+            return;
+        }
+        blame.shown = Some((dirty, cursor_y));
+        let lines = if blame.all {
+            0..editor_rows.number_of_rows()
+        } else {
+            cursor_y..cursor_y + 1
+        };
+        let annotations = &mut editor_rows.annotations;
+        annotations.clear(BLAME_LAYER);
+        for at in lines {
+            if let Some(line) = blame.line(at) {
+                let annotation = Annotation {
+                    text: Some(line.into()),
+                    ..Annotation::default()
+                };
+                annotations.set(BLAME_LAYER, at, annotation);
+            }
+        }
+    }
+
+    fn annotate(&mut self, command: Option<&str>) -> crossterm::Result<()> {
+        for buffer in &mut self.output.buffers {
+            buffer.editor_rows.annotations.clear(SCRIPT_LAYER);
+        }
+        let Some(command) = command else {
+            self.output
+                .status_message
+                .set_message("Annotations cleared".into());
+            return Ok(());
+        };
+        self.output
+            .status_message
+            .set_message(format!("Running {}...", command));
+        self.output.refresh_screen()?;
+        let output = match shell::capture(command) {
+            Ok(output) => output,
+            Err(err) => {
+                self.output.status_message.set_message(err);
+                return Ok(());
+            }
+        };
+        let mut count = 0;
+        for (file, line, message) in output.lines().filter_map(annotations::parse_report) {
+            let file = config::absolute(&file);
+            let buffer = self.output.buffers.iter_mut().find(|buffer| {
+                buffer
+                    .editor_rows
+                    .filename
+                    .as_deref()
+                    .is_some_and(|name| config::absolute(name) == file)
+            });
+            let Some(buffer) = buffer else {
+                continue;
+            };
+            let annotation = Annotation {
+                sign: Some('!'),
+                text: Some(message),
+                tint: Some(style::Color::AnsiValue(52)),
+                ..Annotation::default()
+            };
+            buffer.editor_rows.annotations.set(SCRIPT_LAYER, line, annotation);
+            count += 1;
+        }
+        self.output.status_message.set_message(String::new());
+        self.output.notifications.push(format!(
+            "{}: {} line{} annotated",
+            command,
+            count,
+            if count == 1 { "" } else { "s" }
+        ));
+        Ok(())
+    }
+
+    /// Loads the configured language's dictionary unless one is loaded,
+    /// reporting whether the spell checker is ready.
+    fn ensure_spell(&mut self) -> bool {
+        if self.output.spell.is_none() {
+            match Spell::load(&self.output.config.spell_language) {
+                Ok(spell) => self.output.spell = Some(spell),
+                Err(err) => self.output.status_message.set_message(err),
+            }
+        }
+        self.output.spell.is_some()
+    }
+
+    /// Moves to the next misspelled word after the cursor, wrapping around
+    /// the end of the buffer.
+    fn next_misspelling(&mut self) {
+        if !self.ensure_spell() {
+            return;
+        }
+        let output = &mut self.output;
+        let spell = output.spell.as_ref().unwrap();
+        let buffer = &output.buffers[output.current_buffer];
+        let (x, y) = buffer.cursor_position();
+        let number_of_rows = buffer.editor_rows.number_of_rows();
+        if number_of_rows == 0 {
+            output
+                .status_message
+                .set_message("No misspelled words".into());
+            return;
+        }
+        let found = (0..=number_of_rows).find_map(|step| {
+            let at = (y + step) % number_of_rows;
+            let row = &buffer.editor_rows.get_row(at);
+            spell
+                .misspelled(row)
+                .into_iter()
+                .find(|range| step > 0 || range.start > x)
+                .map(|range| (at, range))
+        });
+        let Some((at, range)) = found else {
+            output
+                .status_message
+                .set_message("No misspelled words".into());
+            return;
+        };
+        let word = buffer.editor_rows.get_row(at)[range.clone()].to_string();
+        let cursor = &mut output.buffer_mut().cursor_controller;
+        cursor.anchor = None;
+        cursor.cursor_y = at;
+        cursor.cursor_x = range.start;
+        output
+            .status_message
+            .set_message(format!("Misspelled: {} (:spellgood to accept it)", word));
+    }
+
+    /// Adds the word under the cursor to the project's word list.
+    fn add_spelling(&mut self) {
+        if !self.ensure_spell() {
+            return;
+        }
+        let buffer = self.output.buffer();
+        let (x, y) = buffer.cursor_position();
+        let word = (y < buffer.editor_rows.number_of_rows())
+            .then(|| buffer.editor_rows.get_row(y))
+            .and_then(|row| {
+                WordChars::default()
+                    .word_at(&row, x)
+                    .map(|(start, end)| row[start..end].to_string())
+            });
+        let message = match word {
+            Some(word) => match self.output.spell.as_mut().unwrap().add_project_word(&word) {
+                Ok(()) => format!("Added {} to {}", word, spell::PROJECT_WORDS_FILE),
+                Err(err) => format!("Could not add {}: {}", word, err),
+            },
+            None => "No word under cursor".into(),
+        };
+        self.output.status_message.set_message(message);
+    }
+
+    /// Moves to the enclosing syntax node, or with `function` set to the
+    /// next or previous function.
+    #[cfg(feature = "tree-sitter")]
+    fn jump_to_node(&mut self, function: Option<bool>) {
+        let buffer = self.output.buffer_mut();
+        let cursor = buffer.cursor_position();
+        let rows = &mut buffer.editor_rows;
+        let found = match rows.syntax_tree.take() {
+            Some(mut tree) => {
+                tree.reparse(rows);
+                let found = match function {
+                    None => tree.enclosing(cursor),
+                    Some(forward) => tree.function(cursor, forward),
+                };
+                rows.syntax_tree = Some(tree);
+                found
+            }
+            None => {
+                self.output
+                    .status_message
+                    .set_message("No syntax tree for this buffer".into());
+                return;
+            }
+        };
+        let message = match found {
+            Some(node) => {
+                let controller = &mut buffer.cursor_controller;
+                (controller.cursor_x, controller.cursor_y) = node.start;
+                buffer.clamp_cursor();
+                format!("{} (lines {}-{})", node.kind, node.start.1 + 1, node.end_row + 1)
+            }
+            None if function.is_some() => "No more functions".into(),
+            None => "No enclosing node".into(),
+        };
+        self.output.status_message.set_message(message);
+    }
+
+    #[cfg(not(feature = "tree-sitter"))]
+    fn jump_to_node(&mut self, _function: Option<bool>) {
+        self.output
+            .status_message
+            .set_message("Built without tree-sitter support".into());
+    }
+
+    fn open_notes(&mut self) -> crossterm::Result<()> {
+        self.open_file(project_root().join(NOTES_FILE))
+    }
+
+    /// Appends the current scratch buffer to the project notes file so its
+    /// contents survive the session. If the notes can't be saved, the
+    /// scratch buffer stays as it was.
+    fn persist_scratch(&mut self) -> crossterm::Result<()> {
+        if !self.output.buffer().scratch {
+            self.output
+                .status_message
+                .set_message("Only scratch buffers can be persisted".into());
+            return Ok(());
+        }
+        let path = project_root().join(NOTES_FILE);
+        let mut notes = match EditorRows::open(path) {
+            Ok(notes) => notes,
+            Err(err) => {
+                self.output
+                    .status_message
+                    .set_message(format!("Could not read {}: {}", NOTES_FILE, err));
+                return Ok(());
+            }
+        };
+        let indentation = Indentation::for_file(notes.filename.as_deref(), &self.output.config);
+        notes.set_tab_stop(indentation.tab_stop);
+        let buffer = self.output.buffer_mut();
+        if notes.number_of_rows() > 0 {
+            notes.insert_row(notes.number_of_rows(), String::new());
+        }
+        let end = notes.number_of_rows();
+        notes.text.insert((end, 0), &buffer.editor_rows.text.contents());
+        let scratch = mem::replace(&mut buffer.editor_rows, notes);
+        let cursor = &mut buffer.cursor_controller;
+        let scratch_cursor = (cursor.cursor_x, cursor.cursor_y);
+        (cursor.cursor_x, cursor.cursor_y) = (0, end);
+        buffer.scratch = false;
+        let mut progress = self.output.progress(format!("Saving {}", NOTES_FILE));
+        let trailing_newline = self.output.config.trailing_newline;
+        let buffer = self.output.buffer_mut();
+        let saved = buffer.editor_rows.save(&mut progress, trailing_newline);
+        let result = saved.as_ref().copied().map_err(io::Error::to_string);
+        buffer.last_save = Some(LastSave::new(result));
+        let len = match saved {
+            Ok(len) => len,
+            Err(err) => {
+                buffer.editor_rows = scratch;
+                buffer.scratch = true;
+                let cursor = &mut buffer.cursor_controller;
+                (cursor.cursor_x, cursor.cursor_y) = scratch_cursor;
+                let message = format!("Could not save {}, scratch kept: {}", NOTES_FILE, err);
+                self.output.status_message.set_message(message);
+                return Ok(());
+            }
+        };
+        self.attach_file()?;
+        self.output
+            .status_message
+            .set_message(format!("Scratch appended to {} ({} bytes)", NOTES_FILE, len));
+        Ok(())
+    }
+
+    /// Applies `action` to every line matching `pattern` (or, when `invert`
+    /// is set, every line not matching it). The pattern, and what `s`
+    /// replaces, are always regexes. Lines are visited bottom-up so
+    /// deletions and splits don't shift the lines still to come, and only
+    /// the lines actually changed are counted.
+    fn run_global(
+        &mut self,
+        pattern: &str,
+        invert: bool,
+        action: &LineAction,
+    ) -> crossterm::Result<()> {
+        let compiled = Matcher::new(pattern, true).and_then(|matcher| match action {
+            LineAction::Substitute { from, .. } => {
+                Matcher::new(from, true).map(|from| (matcher, Some(from)))
+            }
+            _ => Ok((matcher, None)),
+        });
+        let (matcher, substitute) = match compiled {
+            Ok(compiled) => compiled,
+            Err(err) => {
+                self.output.status_message.set_message(err);
+                return Ok(());
+            }
+        };
+        // Nothing has changed until every line has been matched, so Esc can
+        // stop a slow match without leaving anything half done.
+        let mut progress = self.output.progress(format!("Matching {}", pattern)).cancelable();
+        let editor_rows = &self.output.buffer().editor_rows;
+        let number_of_rows = editor_rows.number_of_rows();
+        let mut matches = Vec::new();
+        for at in 0..number_of_rows {
+            if at % search::CHECK_EVERY == 0 && !progress.update(at as u64, number_of_rows as u64) {
+                self.output.status_message.set_message("Cancelled".into());
+                return Ok(());
+            }
+            if matcher.is_match(&editor_rows.get_row(at)) != invert {
+                matches.push(at);
+            }
+        }
+        let mut changed = 0;
+        for &at in matches.iter().rev() {
+            let buffer = self.output.buffer_mut();
+            let before = buffer.editor_rows.dirty;
+            match action {
+                LineAction::Delete => {
+                    buffer.editor_rows.delete_row(at);
+                }
+                LineAction::Substitute { to, all, .. } => {
+                    let from = substitute.as_ref().unwrap();
+                    let row = buffer.editor_rows.get_row(at).to_string();
+                    let replaced = from.replace(&row, to, *all);
+                    if replaced != row {
+                        buffer.editor_rows.set_row(at, replaced);
+                    }
+                }
+                LineAction::Normal(keys) => {
+                    buffer.cursor_controller.cursor_y = at;
+                    buffer.cursor_controller.cursor_x = 0;
+                    for key in keys {
+                        self.process_key(*key)?;
+                    }
+                }
+            }
+            if self.output.buffer().editor_rows.dirty != before {
+                changed += 1;
+            }
+        }
+        self.output.buffer_mut().clamp_cursor();
+        self.output
+            .status_message
+            .set_message(format!("{} lines changed", changed));
+        Ok(())
+    }
+
+    /// Performs the actions of a `--replay` trace, each from the cursor
+    /// position it was recorded at.
+    fn replay_trace(&mut self, steps: Vec<Recorded>) -> crossterm::Result<()> {
+        let mut skipped = 0;
+        for step in &steps {
+            if !trace::replayable(step.action) {
+                skipped += 1;
+                continue;
+            }
+            let buffer = self.output.buffer_mut();
+            buffer.checkpoint();
+            let cursor = &mut buffer.cursor_controller;
+            (cursor.cursor_x, cursor.cursor_y) = step.at;
+            buffer.clamp_cursor();
+            self.perform(step.action)?;
+            self.output.refresh_screen()?;
+        }
+        let mut message = format!("Replayed {} actions", steps.len() - skipped);
+        if skipped > 0 {
+            message += &format!(", leaving out {} that ask for input or save", skipped);
+        }
+        self.output.status_message.set_message(message);
+        Ok(())
+    }
+
+    fn run(&mut self) -> crossterm::Result<bool> {
+        if !self.started {
+            self.started = true;
+            self.startup()?;
+            if let Some(steps) = self.replay.take() {
+                self.replay_trace(steps)?;
+            }
+            if self.compare {
+                self.compare_buffers(0, 1)?;
+            }
+        }
+        for buffer in &mut self.output.buffers {
+            if let Some(journal) = &mut buffer.editor_rows.journal {
+                if journal.sync_if_due().is_err() {
+                    buffer.editor_rows.journal = None;
+                }
+            }
+        }
+        self.update_swaps(false);
+        self.reload_followed();
+        if let Some(job) = &mut self.output.job {
+            job.poll();
+        }
+        self.update_blame();
+        self.update_repo();
+        self.check_disk()?;
+        self.check_break();
+        if self.output.buffers.iter().any(Buffer::wants_more) && !event::poll(Duration::ZERO)?
+        {
+            self.load_more();
+            self.output.refresh_screen()?;
+            return Ok(true);
+        }
+        self.output.refresh_screen()?;
+        // Come back to take notifications down once their time is up.
+        if !self.output.notifications.toasts().is_empty() && !event::poll(TOAST_POLL)? {
+            return Ok(true);
+        }
+        if let Some(point) = self.output.hover {
+            if !event::poll(HOVER_DELAY)? {
+                self.output.hover = None;
+                self.output.show_hover(point);
+                return Ok(true);
+            }
+        }
+        if self.output.buffers.iter().any(Buffer::swap_behind)
+            && !progress::typed_ahead_pending()
+            && !event::poll(swap::INTERVAL)?
+        {
+            self.update_swaps(true);
+            return Ok(true);
+        }
+        if let Some(delay) = self.output.config.autosave {
+            if self.output.buffers.iter().any(Buffer::wants_autosave)
+                && !progress::typed_ahead_pending()
+                && !event::poll(delay)?
+            {
+                self.autosave();
+                return Ok(true);
+            }
+        }
+        let following = self.output.buffers.iter().any(|buffer| buffer.follow.is_some());
+        let blaming = self.output.buffer().blame.as_ref().is_some_and(Blame::is_running)
+            || self.output.repo.is_running();
+        let running = self.output.job.as_ref().is_some_and(Job::is_running);
+        let mut wait = match (running, following) {
+            _ if blaming => git::POLL,
+            (true, _) => job::POLL,
+            (false, true) => follow::POLL,
+            (false, false) => DISK_CHECK,
+        };
+        let break_due = self.break_due();
+        if let Some(due) = break_due {
+            wait = wait.min(due);
+        }
+        if (running
+            || blaming
+            || following
+            || break_due.is_some()
+            || self.output.buffer().editor_rows.filename.is_some())
+            && !progress::typed_ahead_pending()
+            && !event::poll(wait)?
+        {
+            return Ok(true);
+        }
+        let more = self.process_keypress()?;
+        self.end_break();
+        Ok(more)
+    }
+
+    /// How long until the editor counts as idle and snapshots the buffers,
+    /// unless that is off or done already.
+    fn break_due(&self) -> Option<Duration> {
+        let idle = self.output.config.idle_snapshot?;
+        (!self.breaks.away).then(|| idle.saturating_sub(self.breaks.last_key.elapsed()))
+    }
+
+    /// Snapshots every buffer once no key has come for `idle_snapshot`. A
+    /// break after less typing than that keeps the snapshots from before
+    /// the last one, so a cat crossing the keyboard is still measured from
+    /// before it came.
+    fn check_break(&mut self) {
+        let Some(idle) = self.output.config.idle_snapshot else {
+            return;
+        };
+        let breaks = &mut self.breaks;
+        if breaks.away || breaks.last_key.elapsed() < idle {
+            return;
+        }
+        breaks.away = true;
+        let brief = breaks.returned.is_some_and(|returned| breaks.last_key - returned < idle);
+        for buffer in &mut self.output.buffers {
+            if !brief || buffer.before_break.is_none() {
+                buffer.before_break = Some(buffer.editor_rows.text.snapshot());
+            }
+        }
+    }
+
+    /// Notes that a key came, and if it ended a break, names the buffers
+    /// that have changed since their snapshots.
+    fn end_break(&mut self) {
+        let breaks = &mut self.breaks;
+        breaks.last_key = Instant::now();
+        if !mem::take(&mut breaks.away) {
+            return;
+        }
+        breaks.returned = Some(breaks.last_key);
+        let changed: Vec<&str> = self
+            .output
+            .buffers
+            .iter()
+            .filter(|buffer| {
+                let before = buffer.before_break.as_ref();
+                before.is_some_and(|before| !before.same_as(&*buffer.editor_rows.text))
+            })
+            .map(Buffer::display_name)
+            .collect();
+        if !changed.is_empty() {
+            let message = format!(
+                "Changed over the break: {} (:sincebreak shows how)",
+                changed.join(", ")
+            );
+            self.output.status_message.set_message(message);
+        }
+    }
+
+    /// Reloads the followed files other programs have written since the
+    /// last look. A cursor on the last line stays on the last line, as it
+    /// would following a log; anywhere else it keeps its place. Buffers
+    /// with changes of their own are left to `check_disk` to ask about.
+    fn reload_followed(&mut self) {
+        for buffer in &mut self.output.buffers {
+            if !buffer.follow.as_ref().is_some_and(Follow::changed)
+                || !buffer.editor_rows.changed_on_disk()
+                || buffer.has_unsaved_changes()
+                || buffer.editor_rows.loader.is_some()
+            {
+                continue;
+            }
+            let at_end = buffer.cursor_controller.cursor_y + 1
+                >= buffer.editor_rows.number_of_rows();
+            if let Err(err) = buffer.editor_rows.reload(None) {
+                let name = buffer.display_name();
+                self.output
+                    .notifications
+                    .push(format!("Could not reload {}: {}", name, err));
+                continue;
+            }
+            if at_end {
+                let rows = buffer.editor_rows.number_of_rows();
+                buffer.cursor_controller.cursor_y = rows.saturating_sub(1);
+            }
+            buffer.clamp_cursor();
+        }
+    }
+
+    /// Asks whether to reload the current file when it has changed on disk
+    /// since it was read or saved, once for each change. Keeping the
+    /// buffer leaves saving to ask again before it overwrites the file.
+    fn check_disk(&mut self) -> crossterm::Result<()> {
+        let buffer = self.output.buffer();
+        let Some(name) = buffer.editor_rows.filename.clone() else {
+            return Ok(());
+        };
+        if buffer.editor_rows.loader.is_some() || !buffer.editor_rows.changed_on_disk() {
+            return Ok(());
+        }
+        let stamp = journal::file_stamp(&name);
+        if buffer.disk_asked.as_ref() == Some(&stamp) {
+            return Ok(());
+        }
+        self.output.buffer_mut().disk_asked = Some(stamp);
+        let name = self.output.buffer().display_name().to_string();
+        let mut lines = vec![format!("{} has changed on disk.", name)];
+        if self.output.buffer().has_unsaved_changes() {
+            lines.push("Reloading it loses the changes made here (undo brings them back).".into());
+        }
+        lines.push("Reload it (No keeps the buffer as it is)?".into());
+        if self.confirm(Dialog::new("Changed on disk", lines))? == Answer::Yes {
+            let buffer = self.output.buffer_mut();
+            let message = match buffer.editor_rows.reload(None) {
+                Ok(()) => format!("Reloaded {}", name),
+                Err(err) => format!("Could not reload {}: {}", name, err),
+            };
+            buffer.clamp_cursor();
+            self.output.notifications.push(message);
+        }
+        Ok(())
+    }
+
+    /// Saves every buffer `wants_autosave`, saying on the status bar which
+    /// were saved. A failure is reported and tried again next time.
+    fn autosave(&mut self) {
+        let current = self.output.current_buffer;
+        let mut saved = Vec::new();
+        for index in 0..self.output.buffers.len() {
+            if self.output.buffers[index].wants_autosave() {
+                self.output.current_buffer = index;
+                let name = self.output.buffer().display_name().to_string();
+                match self.save() {
+                    Ok(()) if !self.output.buffer().has_unsaved_changes() => saved.push(name),
+                    Ok(()) => {}
+                    Err(err) => self
+                        .output
+                        .notifications
+                        .push(format!("Could not autosave {}: {}", name, err)),
+                }
+            }
+        }
+        self.output.current_buffer = current;
+        if !saved.is_empty() {
+            self.output
+                .status_message
+                .set_message(format!("Autosaved {}", saved.join(", ")));
+        }
+    }
+
+    /// Reads the next chunk of each file still loading. A file that can't
+    /// be read to the end is left read-only, since saving the part that
+    /// was read would lose the rest.
+    fn load_more(&mut self) {
+        for buffer in &mut self.output.buffers {
+            if !buffer.wants_more() {
+                continue;
+            }
+            let notifications = &mut self.output.notifications;
+            match buffer.editor_rows.load_more(LOAD_CHUNK) {
+                Ok(()) if buffer.editor_rows.loader.is_none() => {
+                    notifications.push(format!("Loaded {}", buffer.display_name()))
+                }
+                Ok(()) => {}
+                Err(err) => {
+                    buffer.read_only = true;
+                    notifications.push(format!(
+                        "Could not finish loading {}: {}",
+                        buffer.display_name(),
+                        err
+                    ));
+                }
+            }
+        }
+    }
+}
+
+fn main() -> crossterm::Result<()> {
+    let mut args = Args::parse();
+    if let Some(path) = args.config.as_deref().filter(|path| !path.is_file()) {
+        eprintln!("rustext: no config file at {}", path.display());
+        process::exit(2);
+    }
+    if args.health {
+        process::exit(if health::check(args.config.as_deref()) { 0 } else { 1 });
+    }
+    if let Some(path) = &args.log {
+        if let Err(err) = log::open(path) {
+            eprintln!("rustext: can't write the log {}: {}", path.display(), err);
+            process::exit(2);
+        }
+    }
+    let mut recorder = None;
+    if let Some(path) = &args.record {
+        match Recorder::create(path) {
+            Ok(created) => recorder = Some(created),
+            Err(err) => {
+                eprintln!("rustext: can't write the trace {}: {}", path.display(), err);
+                process::exit(2);
+            }
+        }
+    }
+    let mut replay = None;
+    if let Some(path) = &args.replay {
+        match trace::read(path) {
+            Ok(steps) => replay = Some(steps),
+            Err(err) => {
+                eprintln!("rustext: can't read the trace {}: {}", path.display(), err);
+                process::exit(2);
+            }
+        }
+    }
+    let compare = args.diff.take();
+    if let Some(files) = &compare {
+        if let Some(file) = files.iter().find(|file| !file.is_file()) {
+            eprintln!("rustext: can't compare {}: no such file", file.display());
+            process::exit(2);
+        }
+        args.files = files.iter().map(|file| file.display().to_string()).collect();
+    }
+    let mut stdin = None;
+    if args.files.iter().any(|file| file == "-") {
+        let mut bytes = Vec::new();
+        if let Err(err) = io::stdin().read_to_end(&mut bytes) {
+            eprintln!("rustext: can't read stdin: {}", err);
+            process::exit(2);
+        }
+        stdin = Some(bytes);
+    }
+    // Once stdin is a pipe, crossterm reads keys from /dev/tty instead, so
+    // that has to be there.
+    if !io::stdin().is_terminal() && fs::File::open("/dev/tty").is_err() {
+        eprintln!("rustext: stdin isn't a terminal and /dev/tty can't be opened");
+        process::exit(2);
+    }
+    // Drawing into a pipe would only garble whatever reads it.
+    if !io::stdout().is_terminal() {
+        if !args.stdout {
+            eprintln!(
+                "rustext: stdout isn't a terminal (with --stdout the editor is drawn on the \
+                 terminal and the first buffer written to stdout on quitting)"
+            );
+            process::exit(2);
+        }
+        if let Err(err) = tty::draw_on_tty() {
+            eprintln!("rustext: stdout isn't a terminal and /dev/tty can't be opened: {}", err);
+            process::exit(2);
+        }
+    }
+    let to_stdout = args.stdout;
+    let _clean_up = CleanUp;
+    terminal::enable_raw_mode()?;
+    execute!(screen(), EnableMouseCapture, style::Print(HOVER_TRACKING_ON))?;
+    let mut editor = Editor::new(args, stdin);
+    editor.recorder = recorder;
+    editor.replay = replay;
+    editor.compare = compare.is_some();
+    if to_stdout {
+        // Text piped in only to be piped on isn't asked about on quitting.
+        let first = &mut editor.output.buffers[0];
+        first.scratch |= first.editor_rows.filename.is_none();
+    }
+    let tell_terminal = editor.output.config.terminal_title;
+    if tell_terminal {
+        execute!(screen(), style::Print(osc::PUSH_TITLE))?;
+    }
+    while editor.run()? {}
+    if tell_terminal {
+        // The shell's title and directory, back as they were.
+        execute!(screen(), style::Print(osc::POP_TITLE))?;
+        if let Ok(dir) = env::current_dir() {
+            execute!(screen(), style::Print(osc::directory(&dir)))?;
+        }
+    }
+    let (config, stats) = (&editor.output.config, &editor.output.stats);
+    if config.stats_log {
+        if let Err(err) = stats.log() {
+            log::write(&format!("Could not log the session's stats: {}", err));
+        }
+    }
+    // Printed once the screen is put back, so it stays in the terminal.
+    let summary = config.stats_on_exit.then(|| stats.summary());
+    drop(_clean_up);
+    if let Some(summary) = summary {
+        if to_stdout {
+            eprintln!("rustext: {}", summary);
+        } else {
+            println!("rustext: {}", summary);
+        }
+    }
+    if to_stdout {
+        let editor_rows = &editor.output.buffers[0].editor_rows;
+        let mut text = editor_rows.contents();
+        if editor_rows.trailing_newline && editor_rows.number_of_rows() > 0 {
+            text.push('\n');
+        }
+        io::stdout().write_all(text.as_bytes())?;
+    }
     Ok(())
 }
@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+/// Figures for the debug overlay toggled with F12. Frame figures describe
+/// the previous refresh, since the current one isn't finished when the
+/// overlay is drawn.
+#[derive(Default)]
+pub struct Metrics {
+    pub visible: bool,
+    frames: u64,
+    frame_time: Duration,
+    slowest_frame: Duration,
+    rows_drawn: usize,
+}
+
+/// What the overlay reports about the current buffer.
+pub struct BufferStats {
+    pub rows: usize,
+    pub bytes: usize,
+    pub undo_depth: usize,
+    pub redo_depth: usize,
+    pub highlighted_rows: usize,
+    pub memory: usize,
+}
+
+impl Metrics {
+    pub fn record_frame(&mut self, frame_time: Duration, rows_drawn: usize) {
+        self.frames += 1;
+        self.frame_time = frame_time;
+        self.slowest_frame = self.slowest_frame.max(frame_time);
+        self.rows_drawn = rows_drawn;
+    }
+
+    /// How long the last refresh took.
+    pub fn last_frame(&self) -> Duration {
+        self.frame_time
+    }
+
+    pub fn lines(&self, stats: &BufferStats) -> Vec<String> {
+        vec![
+            format!("frame #{}", self.frames),
+            format!(
+                "render {:.2}ms (max {:.2}ms)",
+                millis(self.frame_time),
+                millis(self.slowest_frame)
+            ),
+            format!("rows drawn {}", self.rows_drawn),
+            format!("buffer {} rows, {}", stats.rows, size(stats.bytes)),
+            format!("undo {} / redo {}", stats.undo_depth, stats.redo_depth),
+            format!("highlighted {} rows", stats.highlighted_rows),
+            format!("memory ~{}", size(stats.memory)),
+        ]
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+pub fn size(bytes: usize) -> String {
+    match bytes {
+        0..=1023 => format!("{} B", bytes),
+        1024..=1_048_575 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        1_048_576..=1_073_741_823 => format!("{:.1} MiB", bytes as f64 / 1_048_576.0),
+        _ => format!("{:.1} GiB", bytes as f64 / 1_073_741_824.0),
+    }
+}
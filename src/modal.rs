@@ -0,0 +1,220 @@
+use crate::action::Action;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// Counts are capped so a stray run of digits can't queue up millions of
+/// motions.
+const MAX_COUNT: usize = 9999;
+
+/// The states of vi-style modal editing.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// Keys move and edit rather than type.
+    Normal,
+    /// Keys type, as they do without modal editing.
+    Insert,
+    /// Motions extend the selection.
+    Visual,
+}
+
+impl Mode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Normal => "NORMAL",
+            Mode::Insert => "INSERT",
+            Mode::Visual => "VISUAL",
+        }
+    }
+}
+
+/// What a key comes to in modal editing.
+#[derive(Clone, Copy)]
+pub enum Effect {
+    /// The key means what it does without modal editing.
+    Key(KeyEvent),
+    Action(Action),
+    /// Moves a character along the line, stopping at either end of it
+    /// rather than going on to the next line.
+    AlongLine { forward: bool },
+    /// Deletes characters from the cursor on, up to the end of the line.
+    DeleteChars(usize),
+    /// Cuts this many lines from the cursor's on (`dd`).
+    DeleteLines(usize),
+    /// Copies this many lines from the cursor's on (`yy`).
+    YankLines(usize),
+    /// Pastes after the cursor (`p`) or before it (`P`); whole lines go
+    /// below or above the cursor's line.
+    Put { after: bool },
+    /// Goes to a line, counting from 1, or to the last one.
+    GotoLine(Option<usize>),
+}
+
+/// The mode, and the count and operator typed so far in normal mode.
+pub struct Modal {
+    pub mode: Mode,
+    count: Option<usize>,
+    /// An operator key (`d`, `y`, `g`) waiting for its second key, with the
+    /// count typed before it.
+    pending: Option<(char, Option<usize>)>,
+    /// What `dd` or `yy` last copied, so `p` knows to put it back as whole
+    /// lines while the clipboard still holds it.
+    pub lines: Option<String>,
+}
+
+impl Modal {
+    pub fn new() -> Self {
+        Self {
+            mode: Mode::Normal,
+            count: None,
+            pending: None,
+            lines: None,
+        }
+    }
+
+    /// Whether a count or operator is half typed, to show on the status
+    /// bar.
+    pub fn pending(&self) -> String {
+        let count = |count: Option<usize>| count.map(|n| n.to_string()).unwrap_or_default();
+        match self.pending {
+            Some((operator, before)) => {
+                format!("{}{}{}", count(before), operator, count(self.count))
+            }
+            None => count(self.count),
+        }
+    }
+
+    /// What `key` does in the current mode, switching modes as it says.
+    pub fn key(&mut self, key: KeyEvent) -> Vec<Effect> {
+        if self.mode == Mode::Insert {
+            if key.code != KeyCode::Esc {
+                return vec![Effect::Key(key)];
+            }
+            self.mode = Mode::Normal;
+            return vec![Effect::AlongLine { forward: false }];
+        }
+        let ch = match (key.code, key.modifiers) {
+            (KeyCode::Char(ch), KeyModifiers::NONE | KeyModifiers::SHIFT) => ch,
+            (KeyCode::Char('r'), KeyModifiers::CONTROL) => {
+                self.reset();
+                return vec![Effect::Action(Action::Redo)];
+            }
+            (KeyCode::Esc, _) => {
+                self.reset();
+                self.mode = Mode::Normal;
+                return vec![Effect::Action(Action::Collapse)];
+            }
+            // Arrows, Ctrl-S and the like work in every mode.
+            _ => {
+                self.reset();
+                return vec![Effect::Key(key)];
+            }
+        };
+        if let Some(digit) = ch.to_digit(10).filter(|&digit| digit > 0 || self.count.is_some()) {
+            let count = self.count.unwrap_or(0) * 10 + digit as usize;
+            self.count = Some(count.min(MAX_COUNT));
+            return Vec::new();
+        }
+        let count = self.count.take();
+        if let Some((operator, before)) = self.pending.take() {
+            // `2d3d` deletes six lines, as in vi.
+            let count = match (before, count) {
+                (None, None) => None,
+                (before, count) => Some(before.unwrap_or(1) * count.unwrap_or(1)),
+            };
+            let times = count.unwrap_or(1).min(MAX_COUNT);
+            return match (operator, ch) {
+                ('d', 'd') => vec![Effect::DeleteLines(times)],
+                ('y', 'y') => vec![Effect::YankLines(times)],
+                ('g', 'g') => vec![Effect::GotoLine(Some(count.unwrap_or(1)))],
+                _ => Vec::new(),
+            };
+        }
+        let times = count.unwrap_or(1);
+        let visual = self.mode == Mode::Visual;
+        let motion = match (ch, visual) {
+            ('h' | 'l', false) => {
+                return vec![Effect::AlongLine { forward: ch == 'l' }; times];
+            }
+            ('j', false) => Some(Action::MoveDown),
+            ('k', false) => Some(Action::MoveUp),
+            ('w', false) => Some(Action::WordRight),
+            ('b', false) => Some(Action::WordLeft),
+            ('0', false) => Some(Action::LineStart),
+            ('$', false) => Some(Action::LineEnd),
+            ('h', true) => Some(Action::SelectLeft),
+            ('j', true) => Some(Action::SelectDown),
+            ('k', true) => Some(Action::SelectUp),
+            ('l', true) => Some(Action::SelectRight),
+            ('w', true) => Some(Action::SelectWordRight),
+            ('b', true) => Some(Action::SelectWordLeft),
+            ('0', true) => Some(Action::SelectLineStart),
+            ('$', true) => Some(Action::SelectLineEnd),
+            _ => None,
+        };
+        if let Some(motion) = motion {
+            return vec![Effect::Action(motion); times];
+        }
+        if visual {
+            return match ch {
+                'd' | 'x' => {
+                    self.mode = Mode::Normal;
+                    vec![Effect::Action(Action::Cut)]
+                }
+                'y' => {
+                    self.mode = Mode::Normal;
+                    vec![Effect::Action(Action::Copy), Effect::Action(Action::Collapse)]
+                }
+                'v' => {
+                    self.mode = Mode::Normal;
+                    vec![Effect::Action(Action::Collapse)]
+                }
+                ':' => vec![Effect::Action(Action::CommandLine)],
+                _ => Vec::new(),
+            };
+        }
+        let insert = |modal: &mut Modal, effects: &[Effect]| {
+            modal.mode = Mode::Insert;
+            effects.to_vec()
+        };
+        match ch {
+            'd' | 'y' | 'g' => {
+                self.pending = Some((ch, count));
+                Vec::new()
+            }
+            'G' => vec![Effect::GotoLine(count)],
+            'x' => vec![Effect::DeleteChars(times)],
+            'p' | 'P' => vec![Effect::Put { after: ch == 'p' }; times],
+            'u' => vec![Effect::Action(Action::Undo); times],
+            'v' => {
+                self.mode = Mode::Visual;
+                Vec::new()
+            }
+            'i' => insert(self, &[]),
+            'a' => insert(self, &[Effect::AlongLine { forward: true }]),
+            'I' => insert(self, &[Effect::Action(Action::LineStart)]),
+            'A' => insert(self, &[Effect::Action(Action::LineEnd)]),
+            'o' => insert(
+                self,
+                &[
+                    Effect::Action(Action::LineEnd),
+                    Effect::Action(Action::InsertNewline),
+                ],
+            ),
+            'O' => insert(
+                self,
+                &[
+                    Effect::Action(Action::LineStart),
+                    Effect::Action(Action::InsertNewline),
+                    Effect::Action(Action::MoveUp),
+                ],
+            ),
+            '/' => vec![Effect::Action(Action::Find)],
+            ':' => vec![Effect::Action(Action::CommandLine)],
+            _ => Vec::new(),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.count = None;
+        self.pending = None;
+    }
+}
@@ -0,0 +1,59 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How long a notification stays on screen.
+const TOAST_TIME: Duration = Duration::from_secs(4);
+/// How many notifications are shown at once; older ones stay in the
+/// history.
+const MAX_TOASTS: usize = 3;
+const MAX_HISTORY: usize = 200;
+
+/// Reports of things that finished on their own time (a save, a hook, a
+/// file done loading), shown for a few seconds in a corner and kept for
+/// `:notifications`. Unlike the message bar, a new one doesn't replace the
+/// last.
+#[derive(Default)]
+pub struct Notifications {
+    history: VecDeque<(Instant, String)>,
+}
+
+impl Notifications {
+    pub fn push(&mut self, message: String) {
+        crate::log::write(&message);
+        if self.history.len() == MAX_HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back((Instant::now(), message));
+    }
+
+    /// The notifications still on screen, oldest first.
+    pub fn toasts(&self) -> Vec<String> {
+        let mut toasts: Vec<String> = self
+            .history
+            .iter()
+            .rev()
+            .take(MAX_TOASTS)
+            .take_while(|(time, _)| time.elapsed() < TOAST_TIME)
+            .map(|(_, message)| message.clone())
+            .collect();
+        toasts.reverse();
+        toasts
+    }
+
+    /// Every notification kept, oldest first, with how long ago it came.
+    pub fn history(&self) -> Vec<String> {
+        self.history
+            .iter()
+            .map(|(time, message)| format!("{:>4} {}", ago(time.elapsed()), message))
+            .collect()
+    }
+}
+
+/// `elapsed` as a short age: `12s`, `5m` or `3h`.
+pub fn ago(elapsed: Duration) -> String {
+    match elapsed.as_secs() {
+        seconds @ 0..=59 => format!("{}s", seconds),
+        seconds @ 60..=3599 => format!("{}m", seconds / 60),
+        seconds => format!("{}h", seconds / 3600),
+    }
+}
@@ -0,0 +1,35 @@
+use std::fs;
+use std::path::Path;
+
+/// Saves the window title on the terminal's stack, and puts it back, so
+/// quitting restores the title the shell had.
+pub const PUSH_TITLE: &str = "\x1b[22;0t";
+pub const POP_TITLE: &str = "\x1b[23;0t";
+
+pub fn title(title: &str) -> String {
+    let title: String = title.chars().filter(|ch| !ch.is_control()).collect();
+    format!("\x1b]2;{}\x07", title)
+}
+
+/// OSC 7, telling the terminal the working directory is `dir`, as a
+/// `file://` URL naming this host. Terminals open new tabs and windows
+/// there.
+pub fn directory(dir: &Path) -> String {
+    let host = fs::read_to_string("/proc/sys/kernel/hostname")
+        .or_else(|_| fs::read_to_string("/etc/hostname"))
+        .unwrap_or_default();
+    format!("\x1b]7;file://{}{}\x1b\\", host.trim(), encode(&dir.to_string_lossy()))
+}
+
+/// Percent-encodes a path for a URL, leaving the slashes alone.
+fn encode(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
@@ -0,0 +1,124 @@
+use crossterm::style::Color;
+use serde::Deserialize;
+use std::env;
+
+/// The levels of each channel in the 6x6x6 cube of the 256-colour palette.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// The 16 basic colours, as xterm shows them by default.
+const BASIC: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::DarkRed, (205, 0, 0)),
+    (Color::DarkGreen, (0, 205, 0)),
+    (Color::DarkYellow, (205, 205, 0)),
+    (Color::DarkBlue, (0, 0, 238)),
+    (Color::DarkMagenta, (205, 0, 205)),
+    (Color::DarkCyan, (0, 205, 205)),
+    (Color::Grey, (229, 229, 229)),
+    (Color::DarkGrey, (127, 127, 127)),
+    (Color::Red, (255, 0, 0)),
+    (Color::Green, (0, 255, 0)),
+    (Color::Yellow, (255, 255, 0)),
+    (Color::Blue, (92, 92, 255)),
+    (Color::Magenta, (255, 0, 255)),
+    (Color::Cyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+/// How many colours the terminal can show, from the config's
+/// `color_depth` or else guessed from the environment.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Depth {
+    #[serde(rename = "truecolor")]
+    TrueColor,
+    #[serde(rename = "256")]
+    Ansi256,
+    #[serde(rename = "16")]
+    Ansi16,
+}
+
+impl Depth {
+    /// Terminals that take 24-bit colours say so in `COLORTERM` (or, for
+    /// a few, in the name of their terminfo entry); others are assumed to
+    /// manage 256 colours if their `TERM` says that, and 16 if not.
+    pub fn detect() -> Self {
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        let term = env::var("TERM").unwrap_or_default();
+        if colorterm == "truecolor"
+            || colorterm == "24bit"
+            || term.ends_with("-direct")
+            || term.ends_with("-truecolor")
+        {
+            Depth::TrueColor
+        } else if term.contains("256") {
+            Depth::Ansi256
+        } else {
+            Depth::Ansi16
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Depth::TrueColor => "truecolor",
+            Depth::Ansi256 => "256",
+            Depth::Ansi16 => "16",
+        }
+    }
+
+    /// `color` as near as the terminal can show it.
+    pub fn fit(self, color: Color) -> Color {
+        match (self, color) {
+            (Depth::TrueColor, _) => color,
+            (Depth::Ansi256, Color::Rgb { r, g, b }) => Color::AnsiValue(ansi256((r, g, b))),
+            (Depth::Ansi16, Color::Rgb { r, g, b }) => basic((r, g, b)),
+            (Depth::Ansi16, Color::AnsiValue(value)) => basic(ansi256_rgb(value)),
+            _ => color,
+        }
+    }
+}
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let channel = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+    channel(r1, r2) + channel(g1, g2) + channel(b1, b2)
+}
+
+/// The nearest entry of the 256-colour palette's cube or grey ramp.
+fn ansi256(rgb: (u8, u8, u8)) -> u8 {
+    let level = |channel: u8| {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|&at| (CUBE_LEVELS[at] as i32 - channel as i32).abs())
+            .unwrap_or(0) as u8
+    };
+    let cube = 16 + 36 * level(rgb.0) + 6 * level(rgb.1) + level(rgb.2);
+    let average = (rgb.0 as u32 + rgb.1 as u32 + rgb.2 as u32) / 3;
+    let grey = 232 + (average.saturating_sub(3) / 10).min(23) as u8;
+    if distance(rgb, ansi256_rgb(grey)) < distance(rgb, ansi256_rgb(cube)) {
+        grey
+    } else {
+        cube
+    }
+}
+
+/// What an entry of the 256-colour palette looks like.
+fn ansi256_rgb(value: u8) -> (u8, u8, u8) {
+    match value {
+        0..=15 => BASIC[value as usize].1,
+        16..=231 => {
+            let at = value - 16;
+            let level = |index: u8| CUBE_LEVELS[index as usize];
+            (level(at / 36), level(at / 6 % 6), level(at % 6))
+        }
+        _ => {
+            let level = 8 + 10 * (value - 232);
+            (level, level, level)
+        }
+    }
+}
+
+/// The nearest of the 16 basic colours.
+fn basic(rgb: (u8, u8, u8)) -> Color {
+    BASIC
+        .iter()
+        .min_by_key(|(_, basic)| distance(rgb, *basic))
+        .map_or(Color::Reset, |(color, _)| *color)
+}
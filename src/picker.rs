@@ -0,0 +1,331 @@
+use crate::config::PickerSource;
+use crate::search::Matcher;
+use crate::shell;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+/// Most files the file finder and grep will look through, so pointing
+/// them at a huge tree can't hang the editor.
+const MAX_FILES: usize = 20_000;
+/// Directories the file finder and grep never descend into, besides
+/// hidden ones.
+const SKIPPED_DIRS: &[&str] = &["target", "node_modules"];
+/// Biggest file grep reads, and how far into a file a preview goes.
+const MAX_READ_BYTES: u64 = 4 << 20;
+/// How much of a file's start is looked at for a NUL byte, which means
+/// it's binary and neither grepped nor previewed.
+const BINARY_CHECK_BYTES: usize = 8 << 10;
+
+/// Where a picker item leads: a file on disk, possibly a particular line
+/// of it, a line of an open buffer (which may have unsaved changes), an
+/// item for a configured picker's `accept` command, the edits a crashed
+/// session left behind for a file or the swap file it left for a buffer
+/// without one, or a command line to run.
+#[derive(Clone)]
+pub enum Target {
+    File(PathBuf, Option<usize>),
+    Buffer(usize, usize),
+    Script(String),
+    Journal(PathBuf),
+    Swap(PathBuf),
+    Command(String),
+}
+
+pub struct Item {
+    pub label: String,
+    pub target: Target,
+}
+
+/// A list of items narrowed down by fuzzy-matching a typed query. The
+/// editor draws it in place of the text, with a preview of the
+/// highlighted item's target.
+pub struct Picker {
+    pub title: String,
+    pub query: String,
+    /// The commands behind a configured picker.
+    pub source: Option<PickerSource>,
+    items: Vec<Item>,
+    /// Indices into `items` that match the query, best first.
+    matches: Vec<usize>,
+    selected: usize,
+    /// The last preview read, keyed by the file or command it came from,
+    /// so it isn't read every frame.
+    preview: Option<(String, Vec<String>)>,
+}
+
+impl Picker {
+    pub fn new(title: &str, items: Vec<Item>) -> Self {
+        let mut picker = Self {
+            title: title.into(),
+            query: String::new(),
+            source: None,
+            matches: Vec::new(),
+            items,
+            selected: 0,
+            preview: None,
+        };
+        picker.filter();
+        picker
+    }
+
+    /// A picker over the lines printed by `source.items`.
+    pub fn from_source(name: &str, source: &PickerSource) -> Result<Self, String> {
+        let output = shell::filter(&source.items, "")?;
+        let items = output
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| Item {
+                label: line.into(),
+                target: match source.accept {
+                    Some(_) => Target::Script(line.into()),
+                    None => file_target(line),
+                },
+            })
+            .collect();
+        Ok(Self {
+            source: Some(source.clone()),
+            ..Self::new(name, items)
+        })
+    }
+
+    pub fn push(&mut self, ch: char) {
+        self.query.push(ch);
+        self.filter();
+    }
+
+    pub fn pop(&mut self) {
+        self.query.pop();
+        self.filter();
+    }
+
+    fn filter(&mut self) {
+        let mut scored: Vec<(usize, usize)> = self
+            .items
+            .iter()
+            .enumerate()
+            .filter_map(|(index, item)| Some((fuzzy_score(&self.query, &item.label)?, index)))
+            .collect();
+        scored.sort();
+        self.matches = scored.into_iter().map(|(_, index)| index).collect();
+        self.selected = 0;
+    }
+
+    /// Moves the highlight by `delta` items, stopping at either end.
+    pub fn move_selection(&mut self, delta: isize) {
+        let last = self.matches.len().saturating_sub(1);
+        self.selected = self.selected.saturating_add_signed(delta).min(last);
+    }
+
+    pub fn selected(&self) -> Option<&Item> {
+        self.matches.get(self.selected).map(|&index| &self.items[index])
+    }
+
+    /// The matching items to show in `rows` rows, scrolled so the
+    /// highlighted one is visible, each with whether it is highlighted.
+    pub fn visible(&self, rows: usize) -> impl Iterator<Item = (&Item, bool)> {
+        let first = (self.selected + 1).saturating_sub(rows);
+        self.matches
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(rows)
+            .map(move |(position, &index)| (&self.items[index], position == self.selected))
+    }
+
+    pub fn match_count(&self) -> usize {
+        self.matches.len()
+    }
+
+    pub fn item_count(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Lines `first..first + count` of `path`, read once and kept while it
+    /// stays previewed. Unreadable and binary files preview as a single
+    /// line saying why.
+    pub fn file_lines(&mut self, path: &Path, first: usize, count: usize) -> &[String] {
+        let key = format!("{}:{}", path.display(), first);
+        self.cached_preview(key, || {
+            read_lines(path, first, count).map_err(|err| err.to_string())
+        })
+    }
+
+    /// The output of the configured preview command for the highlighted
+    /// item, if there is one.
+    pub fn command_preview(&mut self) -> Option<&[String]> {
+        let command = self.source.as_ref()?.preview.as_ref()?;
+        let item = &self.selected()?.label;
+        let command = command.replace("{item}", &shell::quote(item));
+        Some(self.cached_preview(command.clone(), || {
+            shell::filter(&command, "").map(|output| output.lines().map(String::from).collect())
+        }))
+    }
+
+    /// The preview read by `read`, unless the one kept is already for
+    /// `key`. Errors preview as a single line.
+    pub fn cached_preview(
+        &mut self,
+        key: String,
+        read: impl FnOnce() -> Result<Vec<String>, String>,
+    ) -> &[String] {
+        if self.preview.as_ref().is_none_or(|(cached, _)| *cached != key) {
+            let lines = read().unwrap_or_else(|err| vec![format!("Cannot preview: {}", err)]);
+            self.preview = Some((key, lines));
+        }
+        &self.preview.as_ref().unwrap().1
+    }
+}
+
+/// How well `query` matches `candidate`, lower being better, or `None` if
+/// its characters don't all appear in order. Matches that start early and
+/// have few gaps rank first.
+fn fuzzy_score(query: &str, candidate: &str) -> Option<usize> {
+    let mut score = 0;
+    let mut last = None;
+    let mut chars = candidate.char_indices();
+    for wanted in query.chars().flat_map(char::to_lowercase) {
+        let (at, _) = chars
+            .by_ref()
+            .find(|(_, ch)| ch.to_lowercase().any(|ch| ch == wanted))?;
+        score += match last {
+            Some(last) => at - last - 1,
+            None => at,
+        };
+        last = Some(at);
+    }
+    Some(score)
+}
+
+/// Every file under `root`, skipping hidden and build directories.
+pub fn project_files(root: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let mut entries: Vec<_> = match fs::read_dir(&dir) {
+            Ok(entries) => entries.flatten().collect(),
+            Err(_) => continue,
+        };
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries.into_iter().rev() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with('.') {
+                continue;
+            }
+            match entry.file_type() {
+                Ok(kind) if kind.is_dir() && !SKIPPED_DIRS.contains(&name.as_ref()) => {
+                    pending.push(entry.path())
+                }
+                Ok(kind) if kind.is_file() => files.push(entry.path()),
+                _ => {}
+            }
+            if files.len() == MAX_FILES {
+                return files;
+            }
+        }
+    }
+    files
+}
+
+/// The file finder's items: every project file, labelled relative to
+/// `root`.
+pub fn file_items(root: &Path) -> Vec<Item> {
+    project_files(root)
+        .into_iter()
+        .map(|path| Item {
+            label: relative(root, &path),
+            target: Target::File(path, None),
+        })
+        .collect()
+}
+
+/// An item for each line of each project file matching `matcher`, labelled
+/// `file:line: text`. Files that aren't UTF-8 text are skipped. `busy` is
+/// told how many files have been searched, of how many, and stops the
+/// search (giving `None`) if it returns false.
+pub fn grep_items(
+    root: &Path,
+    matcher: &Matcher,
+    mut busy: impl FnMut(usize, usize) -> bool,
+) -> Option<Vec<Item>> {
+    let mut items = Vec::new();
+    let files = project_files(root);
+    for (done, path) in files.iter().enumerate() {
+        if !busy(done, files.len()) {
+            return None;
+        }
+        let text = match read_text(path) {
+            Some(text) => text,
+            None => continue,
+        };
+        let name = relative(root, path);
+        for (number, line) in text.lines().enumerate() {
+            if matcher.is_match(line) {
+                items.push(Item {
+                    label: format!("{}:{}: {}", name, number + 1, line.trim()),
+                    target: Target::File(path.clone(), Some(number)),
+                });
+            }
+        }
+    }
+    Some(items)
+}
+
+/// The text of the file at `path` for grep, unless it is unreadable, too
+/// big, binary or not UTF-8.
+fn read_text(path: &Path) -> Option<String> {
+    if fs::metadata(path).ok()?.len() > MAX_READ_BYTES {
+        return None;
+    }
+    let bytes = fs::read(path).ok()?;
+    if looks_binary(&bytes) {
+        return None;
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Lines `first..first + count` of the file at `path`, read no further
+/// than `MAX_READ_BYTES` into it.
+fn read_lines(path: &Path, first: usize, count: usize) -> io::Result<Vec<String>> {
+    let mut reader = BufReader::new(File::open(path)?.take(MAX_READ_BYTES));
+    if looks_binary(reader.fill_buf()?) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "binary file"));
+    }
+    let mut lines = Vec::new();
+    let mut line = Vec::new();
+    for number in 0..first + count {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        if number >= first {
+            let text = String::from_utf8_lossy(&line);
+            lines.push(text.trim_end_matches(['\n', '\r']).to_string());
+        }
+    }
+    Ok(lines)
+}
+
+fn looks_binary(bytes: &[u8]) -> bool {
+    bytes[..bytes.len().min(BINARY_CHECK_BYTES)].contains(&0)
+}
+
+/// A file name as printed by a command, optionally followed by `:line` and
+/// more (as `grep -n` prints matches).
+fn file_target(line: &str) -> Target {
+    let mut fields = line.splitn(3, ':');
+    let path = PathBuf::from(fields.next().unwrap_or_default());
+    let number = fields
+        .next()
+        .and_then(|number| number.trim().parse::<usize>().ok())
+        .map(|number| number.saturating_sub(1));
+    Target::File(path, number)
+}
+
+fn relative(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
@@ -0,0 +1,125 @@
+use crossterm::{cursor, queue, style};
+use std::io::{self, Write};
+
+/// Where a popup sits within the text area.
+#[derive(Clone, Copy)]
+pub enum Anchor {
+    /// Just below the cursor, or above it when there's no room below.
+    Cursor,
+    /// Likewise for a column and row of the text area, such as where the
+    /// mouse is.
+    Point(usize, usize),
+    Center,
+    TopRight,
+    BottomRight,
+    /// At the bottom, from the given column (or as near as fits).
+    Bottom(usize),
+}
+
+/// A bordered box of text drawn over the text area. Popups are drawn after
+/// the rows, in the order they are given, so later ones cover earlier ones.
+pub struct Popup {
+    title: Option<String>,
+    lines: Vec<String>,
+    anchor: Anchor,
+}
+
+impl Popup {
+    pub fn new(anchor: Anchor, lines: Vec<String>) -> Self {
+        Self {
+            title: None,
+            lines,
+            anchor,
+        }
+    }
+
+    pub fn titled(self, title: &str) -> Self {
+        Self {
+            title: Some(title.into()),
+            ..self
+        }
+    }
+
+    /// The lines to show, wrapped to fit `width` columns.
+    fn wrapped(&self, width: usize) -> Vec<String> {
+        let width = width.max(1);
+        let mut wrapped = Vec::new();
+        for line in &self.lines {
+            let chars: Vec<char> = line.chars().collect();
+            if chars.is_empty() {
+                wrapped.push(String::new());
+            }
+            wrapped.extend(chars.chunks(width).map(|chunk| chunk.iter().collect()));
+        }
+        wrapped
+    }
+
+    /// Queues the popup for a text area of `columns` by `rows` starting at
+    /// screen row `top`, with the cursor at `cursor` within it. Areas too
+    /// small for a border and a line of text get nothing.
+    pub fn draw(
+        &self,
+        out: &mut impl Write,
+        top: usize,
+        (columns, rows): (usize, usize),
+        (cursor_x, cursor_y): (usize, usize),
+    ) -> io::Result<()> {
+        if columns < 3 || rows < 3 {
+            return Ok(());
+        }
+        let mut lines = self.wrapped(columns - 2);
+        lines.truncate(rows - 2);
+        let title_width = self.title.as_ref().map_or(0, |title| title.chars().count() + 2);
+        let inner = lines
+            .iter()
+            .map(|line| line.chars().count())
+            .chain([title_width])
+            .max()
+            .unwrap_or(0)
+            .min(columns - 2);
+        let (width, height) = (inner + 2, lines.len() + 2);
+        let below = |x: usize, y: usize| {
+            let y = if y + 1 + height <= rows {
+                y + 1
+            } else {
+                y.saturating_sub(height)
+            };
+            (x.min(columns - width), y)
+        };
+        let (x, y) = match self.anchor {
+            Anchor::Cursor => below(cursor_x, cursor_y),
+            Anchor::Point(x, y) => below(x, y),
+            Anchor::Center => ((columns - width) / 2, (rows - height) / 2),
+            Anchor::TopRight => (columns - width, 0),
+            Anchor::BottomRight => (columns - width, rows - height),
+            Anchor::Bottom(x) => (x.min(columns - width), rows - height),
+        };
+        let title: String = match &self.title {
+            Some(title) => format!(" {} ", title).chars().take(inner).collect(),
+            None => String::new(),
+        };
+        let border = format!(
+            "\u{250c}{}{}\u{2510}",
+            title,
+            "\u{2500}".repeat(inner - title.chars().count())
+        );
+        queue!(
+            out,
+            cursor::MoveTo(x as u16, (top + y) as u16),
+            style::SetAttribute(style::Attribute::Reset),
+            style::Print(border)
+        )?;
+        for (i, line) in lines.iter().enumerate() {
+            queue!(
+                out,
+                cursor::MoveTo(x as u16, (top + y + 1 + i) as u16),
+                style::Print(format!("\u{2502}{:<width$}\u{2502}", line, width = inner))
+            )?;
+        }
+        queue!(
+            out,
+            cursor::MoveTo(x as u16, (top + y + height - 1) as u16),
+            style::Print(format!("\u{2514}{}\u{2518}", "\u{2500}".repeat(inner)))
+        )
+    }
+}
@@ -0,0 +1,120 @@
+use crossterm::event::{self, Event, KeyCode};
+use crossterm::{cursor, queue, style};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use crate::tty::screen;
+use std::io::{self, Write};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+/// Operations done quicker than this show nothing, so a small save doesn't
+/// flash the status bar.
+const DELAY: Duration = Duration::from_millis(200);
+const REDRAW: Duration = Duration::from_millis(50);
+
+thread_local! {
+    /// Keys and clicks read while looking for Esc, to be handled once the
+    /// operation is over.
+    static TYPED_AHEAD: RefCell<VecDeque<Event>> = RefCell::default();
+}
+
+/// Reports how an operation that keeps the editor busy is getting on, in
+/// place of the status bar. It is drawn straight to the terminal, since
+/// the screen isn't refreshed until the operation is over.
+pub struct Progress {
+    label: String,
+    cancelable: bool,
+    started: Instant,
+    drawn: Option<Instant>,
+    /// The status bar's width and screen row.
+    columns: usize,
+    row: u16,
+}
+
+impl Progress {
+    pub fn new(label: String, (columns, row): (usize, u16)) -> Self {
+        Self {
+            label,
+            cancelable: false,
+            started: Instant::now(),
+            drawn: None,
+            columns,
+            row,
+        }
+    }
+
+    /// Lets Esc cancel the operation; see `update`.
+    pub fn cancelable(self) -> Self {
+        Self {
+            cancelable: true,
+            ..self
+        }
+    }
+
+    /// Reports that `done` of `total` is done (a `total` of 0 meaning it
+    /// isn't known), redrawing now and then. Gives false once the
+    /// operation is cancelable and Esc has been pressed.
+    pub fn update(&mut self, done: u64, total: u64) -> bool {
+        if self.cancelable && cancel_requested() {
+            return false;
+        }
+        let due = self.drawn.is_none_or(|drawn| drawn.elapsed() >= REDRAW);
+        if self.started.elapsed() >= DELAY && due {
+            let _ = self.draw(done, total);
+            self.drawn = Some(Instant::now());
+        }
+        true
+    }
+
+    fn draw(&self, done: u64, total: u64) -> io::Result<()> {
+        let mut text = describe(&self.label, done, total);
+        if self.cancelable {
+            text.push_str(" (Esc to cancel)");
+        }
+        let text: String = text.chars().take(self.columns).collect();
+        let mut out = screen();
+        queue!(
+            out,
+            cursor::MoveTo(0, self.row),
+            style::SetAttribute(style::Attribute::Reverse),
+            style::Print(format!("{:<width$}", text, width = self.columns)),
+            style::SetAttribute(style::Attribute::Reset)
+        )?;
+        out.flush()
+    }
+}
+
+/// `label` after a spinner, and followed by how far along it is when
+/// `total` is known.
+pub fn describe(label: &str, done: u64, total: u64) -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let spinner = SPINNER[(now.as_millis() / 100) as usize % SPINNER.len()];
+    match (done * 100).checked_div(total) {
+        Some(percent) => format!("{} {} {}%", spinner, label, percent.min(100)),
+        None => format!("{} {}", spinner, label),
+    }
+}
+
+/// Whether Esc has been pressed. Anything else is kept for `typed_ahead`.
+fn cancel_requested() -> bool {
+    while event::poll(Duration::ZERO).unwrap_or(false) {
+        match event::read() {
+            Ok(Event::Key(key)) if key.code == KeyCode::Esc => return true,
+            Ok(event @ (Event::Key(_) | Event::Mouse(_))) => {
+                TYPED_AHEAD.with(|typed| typed.borrow_mut().push_back(event))
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Whether anything came in while an operation was busy.
+pub fn typed_ahead_pending() -> bool {
+    TYPED_AHEAD.with(|typed| !typed.borrow().is_empty())
+}
+
+/// The next event that came in while an operation was busy.
+pub fn typed_ahead() -> Option<Event> {
+    TYPED_AHEAD.with(|typed| typed.borrow_mut().pop_front())
+}
@@ -0,0 +1,204 @@
+use crate::filetype::WordChars;
+use crate::EditorRows;
+use regex::Regex;
+use std::ops::Range;
+
+/// How many rows are searched between calls to `busy`.
+pub const CHECK_EVERY: usize = 4096;
+
+/// A search over the buffer stopped early because `busy` said so.
+pub struct Cancelled;
+
+/// Cursor and viewport as they were when the search prompt opened, so Esc
+/// can put everything back.
+#[derive(Clone, Copy)]
+pub struct SavedPosition {
+    pub cursor_x: usize,
+    pub cursor_y: usize,
+    pub row_offset: usize,
+    pub column_offset: usize,
+}
+
+#[derive(Default)]
+pub struct SearchState {
+    pub origin: Option<SavedPosition>,
+    pub last_match: Option<(usize, usize)>,
+    /// Where on its row the last match ends, for highlighting it.
+    pub match_end: usize,
+    /// Whether queries are regular expressions rather than literal text.
+    /// Unlike the rest of the state this outlives a single search.
+    pub regex: bool,
+    /// What the prompt says about the query's matches: which one the
+    /// cursor is on of how many, or that there are none.
+    pub indicator: Option<String>,
+}
+
+/// A compiled search pattern: literal text, a regular expression, or a
+/// whole word as delimited by the buffer's word characters.
+pub enum Matcher {
+    Literal(String),
+    Regex(Regex),
+    Word(String, WordChars),
+}
+
+impl Matcher {
+    pub fn new(pattern: &str, regex: bool) -> Result<Self, String> {
+        if !regex {
+            return Ok(Matcher::Literal(pattern.into()));
+        }
+        Regex::new(pattern)
+            .map(Matcher::Regex)
+            .map_err(|_| format!("Invalid regex: {}", pattern))
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        match self {
+            Matcher::Literal(text) => line.contains(text.as_str()),
+            Matcher::Regex(regex) => regex.is_match(line),
+            Matcher::Word(..) => !self.match_starts(line).is_empty(),
+        }
+    }
+
+    /// Start of the first match beginning at or after byte `from`.
+    fn find_at(&self, line: &str, from: usize) -> Option<usize> {
+        let mut from = from.min(line.len());
+        while !line.is_char_boundary(from) {
+            from += 1;
+        }
+        match self {
+            Matcher::Literal(text) => line[from..].find(text.as_str()).map(|at| at + from),
+            Matcher::Regex(regex) => regex.find_at(line, from).map(|found| found.start()),
+            Matcher::Word(..) => self.match_starts(line).into_iter().find(|&at| at >= from),
+        }
+    }
+
+    /// End of the match starting at byte `at`.
+    pub fn match_end(&self, line: &str, at: usize) -> usize {
+        match self {
+            Matcher::Literal(text) | Matcher::Word(text, _) => at + text.len(),
+            Matcher::Regex(regex) => regex.find_at(line, at).map_or(at, |found| found.end()),
+        }
+    }
+
+    /// The byte ranges of every match in `line`.
+    pub fn find_ranges(&self, line: &str) -> Vec<Range<usize>> {
+        match self {
+            Matcher::Regex(regex) => regex.find_iter(line).map(|found| found.range()).collect(),
+            Matcher::Literal(text) | Matcher::Word(text, _) => self
+                .match_starts(line)
+                .into_iter()
+                .map(|at| at..at + text.len())
+                .collect(),
+        }
+    }
+
+    fn match_starts(&self, line: &str) -> Vec<usize> {
+        match self {
+            Matcher::Literal(text) => line.match_indices(text.as_str()).map(|(at, _)| at).collect(),
+            Matcher::Regex(regex) => regex.find_iter(line).map(|found| found.start()).collect(),
+            Matcher::Word(text, word_chars) => line
+                .match_indices(text.as_str())
+                .map(|(at, _)| at)
+                .filter(|&at| word_chars.is_whole_word(line, at, at + text.len()))
+                .collect(),
+        }
+    }
+
+    /// Replaces the first (or every) match; regex replacements may refer
+    /// to capture groups as `$1`.
+    pub fn replace(&self, line: &str, to: &str, all: bool) -> String {
+        match (self, all) {
+            (Matcher::Literal(text), true) => line.replace(text.as_str(), to),
+            (Matcher::Literal(text), false) => line.replacen(text.as_str(), to, 1),
+            (Matcher::Regex(regex), true) => regex.replace_all(line, to).into_owned(),
+            (Matcher::Regex(regex), false) => regex.replace(line, to).into_owned(),
+            (Matcher::Word(text, _), all) => {
+                let mut replaced = String::with_capacity(line.len());
+                let mut last = 0;
+                for at in self.match_starts(line) {
+                    replaced.push_str(&line[last..at]);
+                    replaced.push_str(to);
+                    last = at + text.len();
+                    if !all {
+                        break;
+                    }
+                }
+                replaced.push_str(&line[last..]);
+                replaced
+            }
+        }
+    }
+}
+
+/// Which match, counting from one, starts at `(x, y)`, and how many there
+/// are in the buffer.
+pub fn count(
+    editor_rows: &EditorRows,
+    matcher: &Matcher,
+    (x, y): (usize, usize),
+    busy: &mut impl FnMut(usize, usize) -> bool,
+) -> Result<(usize, usize), Cancelled> {
+    let mut index = 0;
+    let mut total = 0;
+    let number_of_rows = editor_rows.number_of_rows();
+    for at in 0..number_of_rows {
+        if at % CHECK_EVERY == 0 && !busy(at, number_of_rows) {
+            return Err(Cancelled);
+        }
+        let starts = matcher.match_starts(&editor_rows.get_row(at));
+        if at < y {
+            index += starts.len();
+        } else if at == y {
+            index += starts.iter().take_while(|&&start| start <= x).count();
+        }
+        total += starts.len();
+    }
+    Ok((index, total))
+}
+
+/// Finds the next match starting at `(x, y)`. Forward searches include the
+/// start position itself; backward searches only look strictly before it.
+/// Both wrap around the end of the buffer. Every so often `busy` is told
+/// how many rows have been searched, of how many, and can stop the search.
+pub fn find(
+    editor_rows: &EditorRows,
+    matcher: &Matcher,
+    (x, y): (usize, usize),
+    forward: bool,
+    busy: &mut impl FnMut(usize, usize) -> bool,
+) -> Result<Option<(usize, usize)>, Cancelled> {
+    let number_of_rows = editor_rows.number_of_rows();
+    if number_of_rows == 0 {
+        return Ok(None);
+    }
+    let y = y.min(number_of_rows - 1);
+    for step in 0..=number_of_rows {
+        if step % CHECK_EVERY == 0 && !busy(step, number_of_rows) {
+            return Err(Cancelled);
+        }
+        let at = if forward {
+            (y + step) % number_of_rows
+        } else {
+            (y + number_of_rows - step % number_of_rows) % number_of_rows
+        };
+        let row = &editor_rows.get_row(at);
+        let found = if forward {
+            let from = if step == 0 { x.min(row.len()) } else { 0 };
+            matcher
+                .find_at(row, from)
+                .filter(|&index| step < number_of_rows || index < x)
+        } else {
+            let before = if step == 0 { x } else { usize::MAX };
+            matcher
+                .match_starts(row)
+                .into_iter()
+                .take_while(|&index| index < before)
+                .last()
+                .filter(|&index| step < number_of_rows || index >= x)
+        };
+        if let Some(index) = found {
+            return Ok(Some((index, at)));
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,157 @@
+use std::fs::File;
+use std::io::{Read, Write};
+use std::process::{Command, ExitStatus, Stdio};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How often `filter_with` checks on the command.
+const POLL: Duration = Duration::from_millis(20);
+
+/// Runs `command` through `sh -c` with `input` on stdin and returns its
+/// stdout. A non-zero exit is reported with the first line of stderr.
+pub fn filter(command: &str, input: &str) -> Result<String, String> {
+    filter_with(command, input, || true)
+}
+
+/// Like `filter`, calling `busy` every so often until the command is done
+/// and killing it if `busy` gives false.
+pub fn filter_with(
+    command: &str,
+    input: &str,
+    busy: impl FnMut() -> bool,
+) -> Result<String, String> {
+    let (status, stdout, stderr) = feed(command, input, busy)?;
+    if status.success() {
+        String::from_utf8(stdout).map_err(|_| format!("{}: output is not UTF-8", command))
+    } else {
+        Err(failure(command, &stderr))
+    }
+}
+
+/// Runs a checker with `input` on stdin, as `filter_with` does. Gives
+/// `None` if it passes, else everything it printed, stdout then stderr.
+pub fn check_with(
+    command: &str,
+    input: &str,
+    busy: impl FnMut() -> bool,
+) -> Result<Option<String>, String> {
+    let (status, stdout, stderr) = feed(command, input, busy)?;
+    if status.success() {
+        return Ok(None);
+    }
+    let mut text = String::from_utf8_lossy(&stdout).into_owned();
+    text.push_str(&String::from_utf8_lossy(&stderr));
+    Ok(Some(text))
+}
+
+fn feed(
+    command: &str,
+    input: &str,
+    mut busy: impl FnMut() -> bool,
+) -> Result<(ExitStatus, Vec<u8>, Vec<u8>), String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("{}: {}", command, err))?;
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_owned();
+    // Feed stdin and drain stdout and stderr from other threads so a
+    // command that writes before it has read everything can't deadlock
+    // against us.
+    let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let stdout = read_all(child.stdout.take().unwrap());
+    let stderr = read_all(child.stderr.take().unwrap());
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if busy() => thread::sleep(POLL),
+            Ok(None) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("{}: cancelled", command));
+            }
+            Err(err) => return Err(format!("{}: {}", command, err)),
+        }
+    };
+    let _ = writer.join();
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+    Ok((status, stdout, stderr))
+}
+
+fn read_all(mut pipe: impl Read + Send + 'static) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        let _ = pipe.read_to_end(&mut bytes);
+        bytes
+    })
+}
+
+/// Runs `command` through `sh -c` without any terminal I/O.
+pub fn run(command: &str) -> Result<(), String> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|err| format!("{}: {}", command, err))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(failure(command, &output.stderr))
+    }
+}
+
+/// Runs `command` through `sh -c` and returns everything it printed,
+/// stdout then stderr, whatever its exit status: checkers exit non-zero
+/// precisely when they have something to report.
+pub fn capture(command: &str) -> Result<String, String> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|err| format!("{}: {}", command, err))?;
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    Ok(text)
+}
+
+/// Runs `command` through `sh -c` on the terminal, which it reads from and
+/// shows its errors on, so it can ask questions or draw a menu there, and
+/// returns its stdout.
+pub fn interactive(command: &str) -> Result<String, String> {
+    let tty = File::open("/dev/tty").map_err(|err| format!("/dev/tty: {}", err))?;
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(tty)
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|err| format!("{}: {}", command, err))?;
+    match output.status.code() {
+        Some(0) => String::from_utf8(output.stdout)
+            .map_err(|_| format!("{}: output is not UTF-8", command)),
+        Some(code) => Err(format!("{} exited with {}", command, code)),
+        None => Err(format!("{} was killed", command)),
+    }
+}
+
+/// `text` quoted for use as a single word in a `sh` command.
+pub fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+fn failure(command: &str, stderr: &[u8]) -> String {
+    let stderr = String::from_utf8_lossy(stderr);
+    match stderr.lines().find(|line| !line.trim().is_empty()) {
+        Some(line) => format!("{} failed: {}", command, line.trim()),
+        None => format!("{} failed", command),
+    }
+}
@@ -0,0 +1,278 @@
+use crate::config;
+use crate::shell;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Words the project accepts on top of the dictionary, one per line, kept
+/// at the project root so they can be committed with the code.
+pub const PROJECT_WORDS_FILE: &str = ".rustext-words";
+/// Where `:spellinstall` downloads dictionaries from; `{lang}` is the
+/// language name and `{ext}` either `dic` or `aff`.
+const DICTIONARY_URL: &str =
+    "https://raw.githubusercontent.com/wooorm/dictionaries/main/dictionaries/{lang}/index.{ext}";
+/// System directories searched for Hunspell dictionaries after the data
+/// directory.
+const SYSTEM_DIRS: &[&str] = &["/usr/share/hunspell", "/usr/share/myspell"];
+
+/// A loaded Hunspell dictionary plus the project's own words.
+pub struct Spell {
+    pub language: String,
+    words: HashSet<String>,
+    project_words: HashSet<String>,
+}
+
+impl Spell {
+    pub fn load(language: &str) -> Result<Self, String> {
+        check_language(language)?;
+        let dic = dictionary_path(language, "dic").ok_or_else(|| {
+            format!(
+                "No dictionary for {} (install one with :spellinstall {})",
+                language, language
+            )
+        })?;
+        let read = |path: &Path| {
+            fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))
+        };
+        let affixes = match dictionary_path(language, "aff") {
+            Some(aff) => Affixes::parse(&read(&aff)?),
+            None => Affixes::default(),
+        };
+        let mut words = HashSet::new();
+        // The first line is the (approximate) number of entries.
+        for entry in read(&dic)?.lines().skip(1) {
+            let entry = entry.split_whitespace().next().unwrap_or_default();
+            let (word, flags) = entry.split_once('/').unwrap_or((entry, ""));
+            if word.is_empty() {
+                continue;
+            }
+            affixes.expand(word, flags, &mut words);
+            words.insert(word.into());
+        }
+        Ok(Self {
+            language: language.into(),
+            words,
+            project_words: read_project_words(),
+        })
+    }
+
+    /// Whether `word` is spelled correctly. A capitalised word is also
+    /// accepted in lower case, for words starting a sentence.
+    pub fn is_correct(&self, word: &str) -> bool {
+        let known = |word: &str| self.words.contains(word) || self.project_words.contains(word);
+        known(word) || known(&word.to_lowercase())
+    }
+
+    /// The byte ranges of the misspelled words in `line`. Anything that
+    /// looks like an identifier (digits, underscores, inner capitals) is
+    /// left alone.
+    pub fn misspelled(&self, line: &str) -> Vec<Range<usize>> {
+        let mut ranges = Vec::new();
+        let mut start = None;
+        for (index, ch) in line.char_indices().chain([(line.len(), ' ')]) {
+            let in_word = ch.is_alphanumeric() || ch == '_' || ch == '\'';
+            match (start, in_word) {
+                (None, true) => start = Some(index),
+                (Some(from), false) => {
+                    let raw = &line[from..index];
+                    let from = from + raw.len() - raw.trim_start_matches('\'').len();
+                    let word = raw.trim_matches('\'');
+                    if is_checkable(word) && !self.is_correct(word) {
+                        ranges.push(from..from + word.len());
+                    }
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        ranges
+    }
+
+    /// Adds `word` to the project's word list.
+    pub fn add_project_word(&mut self, word: &str) -> io::Result<()> {
+        let path = crate::project_root().join(PROJECT_WORDS_FILE);
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", word)?;
+        self.project_words.insert(word.into());
+        Ok(())
+    }
+}
+
+/// Words worth checking: letters only, capitalised at most on the first
+/// letter, so identifiers like `parseHTTP`, `MAX_LEN` or `utf8` are skipped.
+fn is_checkable(word: &str) -> bool {
+    word.chars().count() > 1
+        && word.chars().all(|ch| ch.is_alphabetic() || ch == '\'')
+        && !word.chars().skip(1).any(char::is_uppercase)
+}
+
+fn read_project_words() -> HashSet<String> {
+    fs::read_to_string(crate::project_root().join(PROJECT_WORDS_FILE))
+        .map(|text| {
+            text.lines()
+                .map(str::trim)
+                .filter(|word| !word.is_empty() && !word.starts_with('#'))
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Language names end up in file names and URLs, so only letters, digits,
+/// `-` and `_` are allowed.
+fn check_language(language: &str) -> Result<(), String> {
+    let valid = !language.is_empty()
+        && language
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid language: {}", language))
+    }
+}
+
+fn spell_dir() -> PathBuf {
+    config::data_dir().join("spell")
+}
+
+fn dictionary_path(language: &str, extension: &str) -> Option<PathBuf> {
+    let file = format!("{}.{}", language, extension);
+    std::iter::once(spell_dir())
+        .chain(SYSTEM_DIRS.iter().map(PathBuf::from))
+        .map(|dir| dir.join(&file))
+        .find(|path| path.is_file())
+}
+
+/// The languages with a dictionary installed, for completion.
+pub fn installed() -> Vec<String> {
+    std::iter::once(spell_dir())
+        .chain(SYSTEM_DIRS.iter().map(PathBuf::from))
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name().into_string().ok()?;
+            name.strip_suffix(".dic").map(String::from)
+        })
+        .collect()
+}
+
+/// Downloads the dictionary for `language` into the data directory.
+pub fn install(language: &str) -> Result<PathBuf, String> {
+    check_language(language)?;
+    fs::create_dir_all(spell_dir()).map_err(|err| err.to_string())?;
+    for extension in ["aff", "dic"] {
+        let url = DICTIONARY_URL
+            .replace("{lang}", language)
+            .replace("{ext}", extension);
+        let path = spell_dir().join(format!("{}.{}", language, extension));
+        let command = format!(
+            "curl -fsSL -o {} {}",
+            shell::quote(&path.to_string_lossy()),
+            shell::quote(&url)
+        );
+        shell::run(&command)?;
+    }
+    Ok(spell_dir().join(format!("{}.dic", language)))
+}
+
+/// One prefix or suffix rule from an `.aff` file: strip `strip`, add `add`,
+/// if the word matches `condition`.
+struct AffixRule {
+    suffix: bool,
+    strip: String,
+    add: String,
+    condition: Option<Regex>,
+}
+
+/// The affix rules of a dictionary, by flag. Only plain `PFX` and `SFX`
+/// rules are applied; cross products and the more exotic Hunspell options
+/// are not.
+#[derive(Default)]
+struct Affixes {
+    long_flags: bool,
+    numeric_flags: bool,
+    rules: HashMap<String, Vec<AffixRule>>,
+}
+
+impl Affixes {
+    fn parse(text: &str) -> Self {
+        let mut affixes = Affixes::default();
+        for line in text.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                ["FLAG", "long", ..] => affixes.long_flags = true,
+                ["FLAG", "num", ..] => affixes.numeric_flags = true,
+                [kind @ ("PFX" | "SFX"), flag, strip, add, rest @ ..] => {
+                    // Header lines ("SFX A Y 4") have a count where the
+                    // affix goes.
+                    if rest.is_empty() && add.parse::<usize>().is_ok() {
+                        continue;
+                    }
+                    let suffix = *kind == "SFX";
+                    let condition = rest.first().filter(|&&condition| condition != ".");
+                    let condition = condition.and_then(|condition| {
+                        let pattern = if suffix {
+                            format!("{}$", condition)
+                        } else {
+                            format!("^{}", condition)
+                        };
+                        Regex::new(&pattern).ok()
+                    });
+                    let strip = if *strip == "0" { "" } else { strip };
+                    let add = add.split('/').next().unwrap_or_default();
+                    let add = if add == "0" { "" } else { add };
+                    affixes
+                        .rules
+                        .entry(flag.to_string())
+                        .or_default()
+                        .push(AffixRule {
+                            suffix,
+                            strip: strip.into(),
+                            add: add.into(),
+                            condition,
+                        });
+                }
+                _ => {}
+            }
+        }
+        affixes
+    }
+
+    fn flags(&self, flags: &str) -> Vec<String> {
+        if self.numeric_flags {
+            flags.split(',').map(String::from).collect()
+        } else if self.long_flags {
+            let chars: Vec<char> = flags.chars().collect();
+            chars.chunks(2).map(|pair| pair.iter().collect()).collect()
+        } else {
+            flags.chars().map(String::from).collect()
+        }
+    }
+
+    /// Adds the forms of `word` its flags allow.
+    fn expand(&self, word: &str, flags: &str, words: &mut HashSet<String>) {
+        for flag in self.flags(flags) {
+            for rule in self.rules.get(&flag).into_iter().flatten() {
+                if rule
+                    .condition
+                    .as_ref()
+                    .is_some_and(|condition| !condition.is_match(word))
+                {
+                    continue;
+                }
+                let form = if rule.suffix {
+                    word.strip_suffix(rule.strip.as_str())
+                        .map(|stem| format!("{}{}", stem, rule.add))
+                } else {
+                    word.strip_prefix(rule.strip.as_str())
+                        .map(|stem| format!("{}{}", rule.add, stem))
+                };
+                words.extend(form);
+            }
+        }
+    }
+}
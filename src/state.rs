@@ -0,0 +1,216 @@
+use crate::config;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const STATE_FILE: &str = "state.toml";
+/// Bumped whenever the layout changes; state written by another version
+/// is ignored rather than misread.
+const VERSION: u32 = 1;
+const MAX_HISTORY: usize = 100;
+const MAX_FILES: usize = 100;
+const MAX_SESSIONS: usize = 50;
+/// Registers bigger than this are forgotten, so the file stays small.
+const MAX_REGISTER_BYTES: usize = 64 * 1024;
+
+/// What the editor remembers from one session to the next, kept in one
+/// file in the data directory: registers, the search and command line
+/// histories, recently edited files with where the cursor was and their
+/// bookmarks, and the files each directory was last left with.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct State {
+    version: u32,
+    /// Histories are oldest first.
+    pub searches: Vec<String>,
+    pub commands: Vec<String>,
+    pub registers: BTreeMap<String, String>,
+    /// Most recently edited first.
+    pub files: Vec<FileState>,
+    /// Most recently left first.
+    pub sessions: Vec<Session>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FileState {
+    pub path: PathBuf,
+    /// Column and row.
+    pub cursor: (usize, usize),
+    #[serde(default)]
+    pub bookmarks: Vec<usize>,
+}
+
+/// The files open when the editor was last quit in a directory, for
+/// `--resume` to open again.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Session {
+    pub directory: PathBuf,
+    pub files: Vec<PathBuf>,
+    /// Which of the files was being edited.
+    pub current: usize,
+}
+
+/// A session written out by `:session export` for someone else to open
+/// with `:session import`: the open files, relative to the project root,
+/// and the settings changed with `:set`.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct SharedSession {
+    /// Which of the files was being edited.
+    pub current: usize,
+    pub settings: BTreeMap<String, String>,
+    pub files: Vec<SharedFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SharedFile {
+    pub path: PathBuf,
+    /// Column and row.
+    pub cursor: (usize, usize),
+    /// The top row of the split's view, if one was open.
+    pub split: Option<usize>,
+    #[serde(default)]
+    pub bookmarks: Vec<usize>,
+}
+
+impl SharedSession {
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        toml::from_str(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let text = toml::to_string(self)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        fs::write(path, text)
+    }
+}
+
+fn state_path() -> PathBuf {
+    config::data_dir().join(STATE_FILE)
+}
+
+impl State {
+    /// The state the last session left, or nothing if it can't be read.
+    pub fn load() -> Self {
+        fs::read_to_string(state_path())
+            .ok()
+            .and_then(|text| toml::from_str::<State>(&text).ok())
+            .filter(|state| state.version == VERSION)
+            .unwrap_or_default()
+    }
+
+    /// Writes the state out, merged with whatever other sessions saved
+    /// since it was loaded: this session's entries win and go last.
+    pub fn save(&self) -> io::Result<()> {
+        let disk = Self::load();
+        let mut merged = State {
+            version: VERSION,
+            searches: disk.searches,
+            commands: disk.commands,
+            registers: disk.registers,
+            files: self.files.clone(),
+            sessions: disk.sessions,
+        };
+        for session in self.sessions.iter().rev() {
+            merged.remember_session(session.clone());
+        }
+        for search in &self.searches {
+            remember(&mut merged.searches, search);
+        }
+        for command in &self.commands {
+            remember(&mut merged.commands, command);
+        }
+        merged.registers.extend(self.registers.clone());
+        merged
+            .registers
+            .retain(|_, text| text.len() <= MAX_REGISTER_BYTES);
+        for file in disk.files {
+            if !merged.files.iter().any(|kept| kept.path == file.path) {
+                merged.files.push(file);
+            }
+        }
+        merged.files.truncate(MAX_FILES);
+        let text = toml::to_string(&merged)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        // Write beside the file and move it into place, so a crash never
+        // leaves half a state file.
+        let path = state_path();
+        fs::create_dir_all(config::data_dir())?;
+        let partial = path.with_extension("toml.tmp");
+        fs::write(&partial, text)?;
+        fs::rename(partial, path)
+    }
+
+    pub fn remember_file(&mut self, file: FileState) {
+        self.files.retain(|kept| kept.path != file.path);
+        self.files.insert(0, file);
+        self.files.truncate(MAX_FILES);
+    }
+
+    pub fn file(&self, path: &Path) -> Option<&FileState> {
+        self.files.iter().find(|file| file.path == path)
+    }
+
+    pub fn remember_session(&mut self, session: Session) {
+        self.sessions.retain(|kept| kept.directory != session.directory);
+        self.sessions.insert(0, session);
+        self.sessions.truncate(MAX_SESSIONS);
+    }
+
+    pub fn session(&self, directory: &Path) -> Option<&Session> {
+        self.sessions.iter().find(|session| session.directory == directory)
+    }
+}
+
+/// Adds `entry` as the newest in `history`, dropping an older copy of it
+/// and the oldest entries past the limit.
+pub fn remember(history: &mut Vec<String>, entry: &str) {
+    history.retain(|kept| kept != entry);
+    history.push(entry.into());
+    if history.len() > MAX_HISTORY {
+        history.drain(..history.len() - MAX_HISTORY);
+    }
+}
+
+/// Steps through a history from a prompt: back through older entries and
+/// forward again to what was being typed.
+pub struct Recall {
+    entries: Vec<String>,
+    at: usize,
+    draft: String,
+}
+
+impl Recall {
+    pub fn new(entries: Vec<String>) -> Self {
+        Self {
+            at: entries.len(),
+            entries,
+            draft: String::new(),
+        }
+    }
+
+    /// Replaces `input` with the next older (`back`) or newer entry,
+    /// reporting whether there was one.
+    pub fn step(&mut self, back: bool, input: &mut String) -> bool {
+        if back && self.at > 0 {
+            if self.at == self.entries.len() {
+                self.draft = input.clone();
+            }
+            self.at -= 1;
+            *input = self.entries[self.at].clone();
+            true
+        } else if !back && self.at < self.entries.len() {
+            self.at += 1;
+            *input = match self.entries.get(self.at) {
+                Some(entry) => entry.clone(),
+                None => self.draft.clone(),
+            };
+            true
+        } else {
+            false
+        }
+    }
+}
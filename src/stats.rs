@@ -0,0 +1,111 @@
+use crate::config;
+use serde::Serialize;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Gaps between keys up to this long count as time spent editing; longer
+/// ones only count this much.
+const ACTIVE_GAP: Duration = Duration::from_secs(60);
+const STATS_FILE: &str = "stats.jsonl";
+
+/// What the session has done, for `:stats`, the summary on quitting and
+/// the log kept in the data directory.
+#[derive(Default)]
+pub struct Stats {
+    pub keys: u64,
+    /// Characters added to and taken out of buffers, by what each key
+    /// changed their length by.
+    pub inserted: u64,
+    pub deleted: u64,
+    pub saves: u64,
+    active: Duration,
+    last_key: Option<Instant>,
+}
+
+/// One session's line in the log.
+#[derive(Serialize)]
+struct Entry {
+    /// Seconds since the Unix epoch.
+    ended: u64,
+    keys: u64,
+    inserted: u64,
+    deleted: u64,
+    saves: u64,
+    active_seconds: u64,
+}
+
+impl Stats {
+    pub fn key(&mut self) {
+        let now = Instant::now();
+        if let Some(last) = self.last_key {
+            self.active += now.duration_since(last).min(ACTIVE_GAP);
+        }
+        self.last_key = Some(now);
+        self.keys += 1;
+    }
+
+    /// Counts a buffer going from `before` characters to `after`.
+    pub fn changed(&mut self, before: usize, after: usize) {
+        if after > before {
+            self.inserted += (after - before) as u64;
+        } else {
+            self.deleted += (before - after) as u64;
+        }
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        vec![
+            format!("active      {}", duration(self.active)),
+            format!("keys        {}", self.keys),
+            format!("inserted    {}", self.inserted),
+            format!("deleted     {}", self.deleted),
+            format!("saves       {}", self.saves),
+        ]
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "{} active, {}, {} inserted and {} deleted, {}",
+            duration(self.active),
+            count(self.keys, "key"),
+            count(self.inserted, "character"),
+            self.deleted,
+            count(self.saves, "save")
+        )
+    }
+
+    /// Appends the session to `stats.jsonl` in the data directory, a JSON
+    /// object a line.
+    pub fn log(&self) -> io::Result<()> {
+        let entry = Entry {
+            ended: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+            keys: self.keys,
+            inserted: self.inserted,
+            deleted: self.deleted,
+            saves: self.saves,
+            active_seconds: self.active.as_secs(),
+        };
+        fs::create_dir_all(config::data_dir())?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(config::data_dir().join(STATS_FILE))?;
+        writeln!(file, "{}", serde_json::to_string(&entry)?)
+    }
+}
+
+fn count(count: u64, what: &str) -> String {
+    format!("{} {}{}", count, what, if count == 1 { "" } else { "s" })
+}
+
+fn duration(duration: Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+    match minutes {
+        0 => format!("{}s", duration.as_secs()),
+        1..=59 => format!("{}m {:02}s", minutes, duration.as_secs() % 60),
+        _ => format!("{}h {:02}m", minutes / 60, minutes % 60),
+    }
+}
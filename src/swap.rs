@@ -0,0 +1,102 @@
+use crate::{atomic, config, lock};
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+/// The longest a swap file lags behind its buffer while keys are being
+/// typed; once they stop, it catches up after this long.
+pub const INTERVAL: Duration = Duration::from_secs(2);
+
+/// Numbers the swap files of this process.
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+/// A copy of a buffer with no file behind it, which the journal can't
+/// cover, kept up to date so a crash doesn't lose it. The file is named
+/// after the process, so once that has gone the copy is known to be left
+/// over.
+pub struct Swap {
+    path: PathBuf,
+    /// The buffer's change count as last written.
+    written: Option<u64>,
+    last_write: Option<Instant>,
+}
+
+impl Swap {
+    pub fn new() -> Self {
+        let number = NEXT.fetch_add(1, Ordering::Relaxed);
+        Self {
+            path: swap_dir().join(format!("{}-{}.swap", process::id(), number)),
+            written: None,
+            last_write: None,
+        }
+    }
+
+    /// Whether the buffer has changed since it was last written out.
+    pub fn is_behind(&self, version: u64) -> bool {
+        self.written != Some(version)
+    }
+
+    /// Writes out `text` if the buffer's change count `version` has moved
+    /// on, and either `now` is set or the last write was `INTERVAL` ago.
+    pub fn update(
+        &mut self,
+        version: u64,
+        text: impl FnOnce() -> String,
+        now: bool,
+    ) -> io::Result<()> {
+        let due = now || self.last_write.is_none_or(|time| time.elapsed() >= INTERVAL);
+        if !self.is_behind(version) || !due {
+            return Ok(());
+        }
+        fs::create_dir_all(swap_dir())?;
+        atomic::write(&self.path, |file| file.write_all(text().as_bytes()))?;
+        self.written = Some(version);
+        self.last_write = Some(Instant::now());
+        Ok(())
+    }
+
+    pub fn discard(self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// A swap file a session that didn't exit cleanly left behind.
+pub struct Stale {
+    pub path: PathBuf,
+    pub lines: usize,
+    pub modified: Option<SystemTime>,
+}
+
+/// Every swap file whose process is gone, most recent first.
+pub fn stale() -> Vec<Stale> {
+    let entries = match fs::read_dir(swap_dir()) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut stale: Vec<Stale> = entries
+        .flatten()
+        .filter(|entry| {
+            let name = entry.file_name();
+            let pid = name.to_str().and_then(|name| name.split('-').next()?.parse().ok());
+            pid.is_some_and(|pid| !lock::is_running(pid))
+        })
+        .filter_map(|entry| {
+            let path = entry.path();
+            let text = fs::read_to_string(&path).ok()?;
+            Some(Stale {
+                lines: text.lines().count(),
+                modified: entry.metadata().and_then(|metadata| metadata.modified()).ok(),
+                path,
+            })
+        })
+        .collect();
+    stale.sort_by_key(|stale| std::cmp::Reverse(stale.modified));
+    stale
+}
+
+fn swap_dir() -> PathBuf {
+    config::data_dir().join("swap")
+}
@@ -0,0 +1,302 @@
+use crate::highlight::{self, Highlighter, Span};
+use crate::EditorRows;
+use crossterm::style::Color;
+use std::ops::Range;
+use std::path::Path;
+use streaming_iterator::StreamingIterator;
+use tree_sitter::{InputEdit, Language, Node, Parser, Point, Query, QueryCursor, Tree};
+
+/// A grammar the tree-sitter backend knows, and which of its node kinds
+/// count as functions for navigation.
+struct Grammar {
+    extensions: &'static [&'static str],
+    language: fn() -> Language,
+    highlights: &'static str,
+    function_kinds: &'static [&'static str],
+}
+
+const GRAMMARS: &[Grammar] = &[Grammar {
+    extensions: &["rs"],
+    language: || tree_sitter_rust::LANGUAGE.into(),
+    highlights: tree_sitter_rust::HIGHLIGHTS_QUERY,
+    function_kinds: &["function_item", "function_signature_item"],
+}];
+
+/// Theme scopes for highlight capture names, most specific first; a
+/// capture such as `function.method` uses the entry for `function`.
+const CAPTURE_SCOPES: &[(&str, &str)] = &[
+    ("attribute", "entity.other.attribute-name"),
+    ("comment", "comment"),
+    ("constant.builtin", "constant.language"),
+    ("constant", "constant"),
+    ("constructor", "entity.name.type"),
+    ("escape", "constant.character.escape"),
+    ("function.macro", "support.function"),
+    ("function", "entity.name.function"),
+    ("keyword", "keyword"),
+    ("label", "entity.name.label"),
+    ("number", "constant.numeric"),
+    ("operator", "keyword.operator"),
+    ("property", "variable.other.member"),
+    ("punctuation", "punctuation"),
+    ("string", "string"),
+    ("type.builtin", "storage.type"),
+    ("type", "entity.name.type"),
+    ("variable.builtin", "variable.language"),
+    ("variable.parameter", "variable.parameter"),
+    ("variable", "variable"),
+];
+
+/// An incrementally maintained parse tree of a buffer. Edits are reported
+/// through `edit` as they happen and the tree is reparsed lazily, reusing
+/// everything the edits didn't touch.
+pub struct SyntaxTree {
+    grammar: &'static Grammar,
+    parser: Parser,
+    tree: Option<Tree>,
+    stale: bool,
+    query: Query,
+    capture_colors: Vec<Color>,
+    first_row: usize,
+    spans: Vec<Vec<Span>>,
+}
+
+impl SyntaxTree {
+    pub fn for_file(file: &Path) -> Option<Self> {
+        let extension = file.extension()?.to_str()?;
+        let grammar = GRAMMARS
+            .iter()
+            .find(|grammar| grammar.extensions.contains(&extension))?;
+        let language = (grammar.language)();
+        let mut parser = Parser::new();
+        parser.set_language(&language).ok()?;
+        let query = Query::new(&language, grammar.highlights).ok()?;
+        Some(Self {
+            grammar,
+            parser,
+            tree: None,
+            stale: true,
+            query,
+            capture_colors: Vec::new(),
+            first_row: 0,
+            spans: Vec::new(),
+        })
+    }
+
+    /// Records an edit replacing the text between `start` and `old_end`
+    /// (row and byte column) so that it now ends at `new_end`.
+    pub fn edit(
+        &mut self,
+        bytes: Range<usize>,
+        new_end_byte: usize,
+        start: (usize, usize),
+        old_end: (usize, usize),
+        new_end: (usize, usize),
+    ) {
+        let point = |(row, column)| Point { row, column };
+        if let Some(tree) = &mut self.tree {
+            tree.edit(&InputEdit {
+                start_byte: bytes.start,
+                old_end_byte: bytes.end,
+                new_end_byte,
+                start_position: point(start),
+                old_end_position: point(old_end),
+                new_end_position: point(new_end),
+            });
+        }
+        self.stale = true;
+    }
+
+    /// Forgets the tree, for changes too big to describe as an edit.
+    pub fn reset(&mut self) {
+        self.tree = None;
+        self.stale = true;
+    }
+
+    /// Brings the tree up to date with `rows`.
+    pub fn reparse(&mut self, rows: &EditorRows) {
+        if !self.stale {
+            return;
+        }
+        let tree = self.parser.parse_with(
+            &mut |byte, _| rows.chunk_from(byte).as_bytes(),
+            self.tree.as_ref(),
+        );
+        self.tree = tree;
+        self.stale = false;
+    }
+
+    /// Highlights `visible`, replacing whatever was highlighted before.
+    pub fn highlight(
+        &mut self,
+        highlighter: &Highlighter,
+        rows: &EditorRows,
+        tab_stop: usize,
+        visible: Range<usize>,
+    ) {
+        self.reparse(rows);
+        if self.capture_colors.is_empty() {
+            self.capture_colors = self
+                .query
+                .capture_names()
+                .iter()
+                .map(|name| {
+                    let scope = CAPTURE_SCOPES
+                        .iter()
+                        .find(|(capture, _)| {
+                            name.strip_prefix(capture)
+                                .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+                        })
+                        .map_or("source", |(_, scope)| scope);
+                    highlighter.scope_color(scope)
+                })
+                .collect();
+        }
+        let visible = visible.start..visible.end.min(rows.number_of_rows());
+        self.first_row = visible.start;
+        self.spans.clear();
+        let Some(tree) = self.tree.as_ref().filter(|_| !visible.is_empty()) else {
+            return;
+        };
+        let default = highlighter.scope_color("source");
+        let mut colors: Vec<Vec<Color>> = visible
+            .clone()
+            .map(|y| vec![default; rows.get_row(y).len()])
+            .collect();
+        let mut cursor = QueryCursor::new();
+        cursor.set_point_range(
+            Point {
+                row: visible.start,
+                column: 0,
+            }..Point {
+                row: visible.end,
+                column: 0,
+            },
+        );
+        let text = |node: Node| node_text(rows, node).into_iter();
+        let mut captures = cursor.captures(&self.query, tree.root_node(), text);
+        let mut last_range = None;
+        while let Some((query_match, index)) = captures.next() {
+            let capture = query_match.captures[*index];
+            let range = capture.node.byte_range();
+            // The first pattern to capture a node wins; nested nodes are
+            // painted over their parents.
+            if last_range.as_ref() == Some(&range) {
+                continue;
+            }
+            last_range = Some(range);
+            let color = self.capture_colors[capture.index as usize];
+            let (start, end) = (capture.node.start_position(), capture.node.end_position());
+            for row in start.row.max(visible.start)..=end.row.min(visible.end - 1) {
+                let line = &mut colors[row - visible.start];
+                let from = if row == start.row { start.column } else { 0 };
+                let len = line.len();
+                let to = if row == end.row { end.column } else { len };
+                line[from.min(len)..to.min(len)].fill(color);
+            }
+        }
+        self.spans = visible
+            .clone()
+            .zip(colors)
+            .map(|(y, colors)| {
+                let mut runs: Vec<Span> = Vec::new();
+                for (at, color) in colors.into_iter().enumerate() {
+                    match runs.last_mut() {
+                        Some((range, last)) if *last == color => range.end = at + 1,
+                        _ => runs.push((at..at + 1, color)),
+                    }
+                }
+                highlight::render_spans(&rows.get_row(y), &runs, tab_stop)
+            })
+            .collect();
+    }
+
+    pub fn row(&self, at: usize) -> Option<&[Span]> {
+        self.spans
+            .get(at.checked_sub(self.first_row)?)
+            .map(Vec::as_slice)
+    }
+
+    /// The smallest named node around `(x, y)` that starts before it.
+    /// Asking again from that node's start gives its parent.
+    pub fn enclosing(&self, (x, y): (usize, usize)) -> Option<NodeInfo> {
+        let cursor = Point { row: y, column: x };
+        let mut node = self
+            .tree
+            .as_ref()?
+            .root_node()
+            .named_descendant_for_point_range(cursor, cursor)?;
+        while node.start_position() >= cursor {
+            node = node.parent()?;
+        }
+        Some(NodeInfo::from(node))
+    }
+
+    /// The first function starting after `(x, y)`, or with `forward` unset
+    /// the last one starting before it.
+    pub fn function(&self, (x, y): (usize, usize), forward: bool) -> Option<NodeInfo> {
+        let cursor = Point { row: y, column: x };
+        let mut functions = Vec::new();
+        let mut walker = self.tree.as_ref()?.walk();
+        'walk: loop {
+            let node = walker.node();
+            if self.grammar.function_kinds.contains(&node.kind()) {
+                functions.push(node);
+            }
+            if walker.goto_first_child() {
+                continue;
+            }
+            while !walker.goto_next_sibling() {
+                if !walker.goto_parent() {
+                    break 'walk;
+                }
+            }
+        }
+        let found = if forward {
+            functions
+                .into_iter()
+                .find(|node| node.start_position() > cursor)
+        } else {
+            functions
+                .into_iter()
+                .rev()
+                .find(|node| node.start_position() < cursor)
+        };
+        found.map(NodeInfo::from)
+    }
+}
+
+/// Where a node is, for moving the cursor to it.
+pub struct NodeInfo {
+    pub kind: &'static str,
+    pub start: (usize, usize),
+    pub end_row: usize,
+}
+
+impl From<Node<'_>> for NodeInfo {
+    fn from(node: Node) -> Self {
+        let start = node.start_position();
+        Self {
+            kind: node.kind(),
+            start: (start.column, start.row),
+            end_row: node.end_position().row,
+        }
+    }
+}
+
+/// The text of `node`, one slice per chunk of the text it spans.
+fn node_text<'a>(rows: &'a EditorRows, node: Node) -> Vec<&'a [u8]> {
+    let range = node.byte_range();
+    let mut text = Vec::new();
+    let mut at = range.start;
+    while at < range.end {
+        let chunk = rows.chunk_from(at).as_bytes();
+        if chunk.is_empty() {
+            break;
+        }
+        let chunk = &chunk[..chunk.len().min(range.end - at)];
+        text.push(chunk);
+        at += chunk.len();
+    }
+    text
+}
@@ -0,0 +1,128 @@
+use crate::width;
+
+/// What `:export` writes a block of text as.
+#[derive(Clone, Copy)]
+pub enum Format {
+    Csv,
+    Tsv,
+    Markdown,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "csv" => Some(Format::Csv),
+            "tsv" => Some(Format::Tsv),
+            "markdown" | "md" => Some(Format::Markdown),
+            _ => None,
+        }
+    }
+}
+
+/// Splits lines of aligned text into fields: at tabs if there are any, and
+/// otherwise at the columns that are blank on every line, as they are
+/// between the columns of a log or fixed-width file. Blank lines are left
+/// out.
+pub fn fields(lines: &[String]) -> Vec<Vec<String>> {
+    let lines: Vec<&String> = lines.iter().filter(|line| !line.trim().is_empty()).collect();
+    if lines.iter().any(|line| line.contains('\t')) {
+        return lines
+            .iter()
+            .map(|line| line.split('\t').map(|field| field.trim().to_string()).collect())
+            .collect();
+    }
+    let chars: Vec<Vec<char>> = lines.iter().map(|line| line.chars().collect()).collect();
+    let width = chars.iter().map(Vec::len).max().unwrap_or(0);
+    let blank = |column: usize| {
+        chars
+            .iter()
+            .all(|line| line.get(column).is_none_or(|ch| ch.is_whitespace()))
+    };
+    let mut columns = Vec::new();
+    let mut start = None;
+    for column in 0..=width {
+        match (start, column == width || blank(column)) {
+            (None, false) => start = Some(column),
+            (Some(from), true) => {
+                columns.push(from..column);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    chars
+        .iter()
+        .map(|line| {
+            columns
+                .iter()
+                .map(|range| {
+                    let end = range.end.min(line.len());
+                    let start = range.start.min(end);
+                    line[start..end].iter().collect::<String>().trim().to_string()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// `rows` written out in `format`, a line for each. A Markdown table takes
+/// the first row as its header.
+pub fn write(rows: &[Vec<String>], format: Format) -> String {
+    let mut text = String::new();
+    match format {
+        Format::Csv => {
+            for row in rows {
+                let fields: Vec<String> = row.iter().map(|field| csv_field(field)).collect();
+                text.push_str(&fields.join(","));
+                text.push('\n');
+            }
+        }
+        Format::Tsv => {
+            for row in rows {
+                let fields: Vec<String> =
+                    row.iter().map(|field| field.replace('\t', " ")).collect();
+                text.push_str(&fields.join("\t"));
+                text.push('\n');
+            }
+        }
+        Format::Markdown => {
+            let rows: Vec<Vec<String>> = rows
+                .iter()
+                .map(|row| row.iter().map(|field| field.replace('|', "\\|")).collect())
+                .collect();
+            let count = rows.iter().map(Vec::len).max().unwrap_or(0);
+            let mut widths = vec![3; count];
+            for row in &rows {
+                for (at, field) in row.iter().enumerate() {
+                    widths[at] = widths[at].max(width::str_width(field));
+                }
+            }
+            let line = |cells: Vec<String>| format!("| {} |\n", cells.join(" | "));
+            for (at, row) in rows.iter().enumerate() {
+                let cells = widths
+                    .iter()
+                    .enumerate()
+                    .map(|(column, &width)| {
+                        let field = row.get(column).map_or("", String::as_str);
+                        let padding = width - width::str_width(field);
+                        format!("{}{}", field, " ".repeat(padding))
+                    })
+                    .collect();
+                text.push_str(&line(cells));
+                if at == 0 {
+                    text.push_str(&line(widths.iter().map(|&width| "-".repeat(width)).collect()));
+                }
+            }
+        }
+    }
+    text
+}
+
+/// A CSV field, quoted if it has to be.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.into()
+    }
+}
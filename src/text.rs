@@ -0,0 +1,256 @@
+use ropey::Rope;
+use std::borrow::Cow;
+use std::iter;
+
+/// Texts up to this many bytes are kept as a `Vec` of lines; bigger ones go
+/// in a rope, so inserting and deleting rows doesn't shift the rest.
+const ROPE_THRESHOLD: usize = 64 * 1024;
+
+/// What a buffer keeps its text in. Every row is followed by a newline,
+/// and positions are `(row, byte column)`; one past the last row is the
+/// empty line after its newline.
+pub trait TextBuffer {
+    fn rows(&self) -> usize;
+
+    fn row(&self, at: usize) -> Cow<'_, str>;
+
+    /// Length of the text, counting the newline after every row.
+    fn len_bytes(&self) -> usize;
+
+    fn len_chars(&self) -> usize;
+
+    /// Roughly how much memory the text takes up.
+    fn memory(&self) -> usize;
+
+    /// Inserts `text`, which may hold newlines, at `at`. Text added past
+    /// the last row gets a newline after it like any other row.
+    fn insert(&mut self, at: (usize, usize), text: &str);
+
+    /// Removes the text from `start` up to `end`, newlines and all.
+    fn remove(&mut self, start: (usize, usize), end: (usize, usize));
+
+    /// The text from `start` up to `end`.
+    fn slice(&self, start: (usize, usize), end: (usize, usize)) -> String;
+
+    /// The text in order, a piece at a time.
+    fn chunks(&self) -> Box<dyn Iterator<Item = &str> + '_>;
+
+    /// Byte `x` of row `y`, as an offset into the whole text.
+    #[cfg(feature = "tree-sitter")]
+    fn offset(&self, at: (usize, usize)) -> usize;
+
+    /// The piece of the text holding byte `at`, with its own offset.
+    #[cfg(feature = "tree-sitter")]
+    fn chunk_at(&self, at: usize) -> (&str, usize);
+
+    /// A copy of the text as it is now, for telling later what changed.
+    fn snapshot(&self) -> Box<dyn TextBuffer>;
+
+    fn contents(&self) -> String {
+        self.chunks().collect()
+    }
+
+    fn same_as(&self, other: &dyn TextBuffer) -> bool {
+        self.len_bytes() == other.len_bytes()
+            && (0..self.rows()).all(|at| self.row(at) == other.row(at))
+    }
+}
+
+/// An empty text, in whatever suits one expected to grow to `bytes`.
+pub fn for_size(bytes: usize) -> Box<dyn TextBuffer> {
+    if bytes > ROPE_THRESHOLD {
+        Box::new(Rope::new())
+    } else {
+        Box::new(Lines::default())
+    }
+}
+
+/// The lines of `text`, in whatever suits its size.
+pub fn from_lines(text: &str) -> Box<dyn TextBuffer> {
+    let mut buffer = for_size(text.len());
+    let rows: String = text.lines().flat_map(|line| [line, "\n"]).collect();
+    buffer.insert((0, 0), &rows);
+    buffer
+}
+
+/// A text kept as its rows, which is simple and quick while it is small.
+#[derive(Clone, Default)]
+pub struct Lines(Vec<String>);
+
+impl TextBuffer for Lines {
+    fn rows(&self) -> usize {
+        self.0.len()
+    }
+
+    fn row(&self, at: usize) -> Cow<'_, str> {
+        Cow::Borrowed(&self.0[at])
+    }
+
+    fn len_bytes(&self) -> usize {
+        self.0.iter().map(|row| row.len() + 1).sum()
+    }
+
+    fn len_chars(&self) -> usize {
+        self.0.iter().map(|row| row.chars().count() + 1).sum()
+    }
+
+    fn memory(&self) -> usize {
+        let rows = self.0.capacity() * size_of::<String>();
+        rows + self.0.iter().map(String::capacity).sum::<usize>()
+    }
+
+    fn insert(&mut self, (y, x): (usize, usize), text: &str) {
+        let rows = &mut self.0;
+        if y < rows.len() && !text.contains('\n') {
+            rows[y].insert_str(x, text);
+            return;
+        }
+        let row = rows.get(y).map_or("", String::as_str);
+        let joined = format!("{}{}{}", &row[..x], text, &row[x..]);
+        let mut lines: Vec<String> = joined.split('\n').map(String::from).collect();
+        if y < rows.len() {
+            rows.splice(y..y + 1, lines);
+        } else {
+            if lines.last().is_some_and(String::is_empty) {
+                lines.pop();
+            }
+            rows.extend(lines);
+        }
+    }
+
+    fn remove(&mut self, (start_y, start_x): (usize, usize), (end_y, end_x): (usize, usize)) {
+        let rows = &mut self.0;
+        if start_y == end_y {
+            if let Some(row) = rows.get_mut(start_y) {
+                row.replace_range(start_x..end_x, "");
+            }
+            return;
+        }
+        let tail = rows.get(end_y).map(|row| row[end_x..].to_string());
+        rows.drain(start_y + 1..rows.len().min(end_y + 1));
+        match tail {
+            Some(tail) => {
+                rows[start_y].truncate(start_x);
+                rows[start_y].push_str(&tail);
+            }
+            // Removing to the end takes the last newline too, leaving what
+            // is kept of the first row as a row of its own.
+            None if start_x == 0 => {
+                rows.remove(start_y);
+            }
+            None => rows[start_y].truncate(start_x),
+        }
+    }
+
+    fn slice(&self, (start_y, start_x): (usize, usize), (end_y, end_x): (usize, usize)) -> String {
+        let row = |at: usize| self.0.get(at).map_or("", String::as_str);
+        if start_y == end_y {
+            return row(start_y)[start_x..end_x].into();
+        }
+        let mut slice = String::from(&row(start_y)[start_x..]);
+        for at in start_y + 1..end_y {
+            slice.push('\n');
+            slice.push_str(row(at));
+        }
+        slice.push('\n');
+        slice.push_str(&row(end_y)[..end_x]);
+        slice
+    }
+
+    fn chunks(&self) -> Box<dyn Iterator<Item = &str> + '_> {
+        Box::new(self.0.iter().flat_map(|row| iter::once(row.as_str()).chain(iter::once("\n"))))
+    }
+
+    #[cfg(feature = "tree-sitter")]
+    fn offset(&self, (y, x): (usize, usize)) -> usize {
+        self.0[..y].iter().map(|row| row.len() + 1).sum::<usize>() + x
+    }
+
+    #[cfg(feature = "tree-sitter")]
+    fn chunk_at(&self, at: usize) -> (&str, usize) {
+        let mut start = 0;
+        for row in &self.0 {
+            if at < start + row.len() {
+                return (row, start);
+            }
+            if at == start + row.len() {
+                return ("\n", at);
+            }
+            start += row.len() + 1;
+        }
+        ("", start)
+    }
+
+    fn snapshot(&self) -> Box<dyn TextBuffer> {
+        Box::new(self.clone())
+    }
+}
+
+impl TextBuffer for Rope {
+    fn rows(&self) -> usize {
+        self.len_lines() - 1
+    }
+
+    fn row(&self, at: usize) -> Cow<'_, str> {
+        let start = self.line_to_byte(at);
+        let end = self.line_to_byte(at + 1) - 1;
+        let row = self.byte_slice(start..end);
+        match row.as_str() {
+            Some(row) => Cow::Borrowed(row),
+            None => Cow::Owned(row.to_string()),
+        }
+    }
+
+    fn len_bytes(&self) -> usize {
+        Rope::len_bytes(self)
+    }
+
+    fn len_chars(&self) -> usize {
+        Rope::len_chars(self)
+    }
+
+    fn memory(&self) -> usize {
+        self.capacity()
+    }
+
+    fn insert(&mut self, at: (usize, usize), text: &str) {
+        let index = char_index(self, at);
+        Rope::insert(self, index, text);
+    }
+
+    fn remove(&mut self, start: (usize, usize), end: (usize, usize)) {
+        let range = char_index(self, start)..char_index(self, end);
+        Rope::remove(self, range);
+    }
+
+    fn slice(&self, (start_y, start_x): (usize, usize), (end_y, end_x): (usize, usize)) -> String {
+        let start = self.line_to_byte(start_y) + start_x;
+        let end = self.line_to_byte(end_y) + end_x;
+        self.byte_slice(start..end).to_string()
+    }
+
+    fn chunks(&self) -> Box<dyn Iterator<Item = &str> + '_> {
+        Box::new(Rope::chunks(self))
+    }
+
+    #[cfg(feature = "tree-sitter")]
+    fn offset(&self, (y, x): (usize, usize)) -> usize {
+        self.line_to_byte(y) + x
+    }
+
+    #[cfg(feature = "tree-sitter")]
+    fn chunk_at(&self, at: usize) -> (&str, usize) {
+        let (chunk, start, _, _) = self.chunk_at_byte(at);
+        (chunk, start)
+    }
+
+    fn snapshot(&self) -> Box<dyn TextBuffer> {
+        // Ropes share what they have in common, so it costs little.
+        Box::new(self.clone())
+    }
+}
+
+/// The rope's char index of byte `x` of row `y`.
+fn char_index(rope: &Rope, (y, x): (usize, usize)) -> usize {
+    rope.byte_to_char(rope.line_to_byte(y) + x)
+}
@@ -0,0 +1,208 @@
+use crate::config::{self, parse_color};
+use crate::palette::Depth;
+use crossterm::style::{Attribute, Color, SetBackgroundColor, SetForegroundColor};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+
+/// Colours from a `[colors]` table, named as crossterm names them
+/// (`dark_blue`) or written `#rrggbb`.
+#[derive(Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ColorNames {
+    status_fg: Option<String>,
+    status_bg: Option<String>,
+    line_numbers: Option<String>,
+    selection_fg: Option<String>,
+    selection_bg: Option<String>,
+    search_fg: Option<String>,
+    search_bg: Option<String>,
+}
+
+impl ColorNames {
+    pub fn merge(self, over: Self) -> Self {
+        Self {
+            status_fg: over.status_fg.or(self.status_fg),
+            status_bg: over.status_bg.or(self.status_bg),
+            line_numbers: over.line_numbers.or(self.line_numbers),
+            selection_fg: over.selection_fg.or(self.selection_fg),
+            selection_bg: over.selection_bg.or(self.selection_bg),
+            search_fg: over.search_fg.or(self.search_fg),
+            search_bg: over.search_bg.or(self.search_bg),
+        }
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        [
+            &self.status_fg,
+            &self.status_bg,
+            &self.line_numbers,
+            &self.selection_fg,
+            &self.selection_bg,
+            &self.search_fg,
+            &self.search_bg,
+        ]
+        .into_iter()
+        .flatten()
+    }
+}
+
+/// A theme file, `<config dir>/themes/<name>.toml`.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ThemeFile {
+    /// The syntax highlighting theme it goes with.
+    syntax: Option<String>,
+    #[serde(default)]
+    colors: ColorNames,
+    /// Syntax colours by scope (`comment`, `entity.name.function`), over
+    /// those of the syntax theme.
+    #[serde(default)]
+    scopes: BTreeMap<String, String>,
+}
+
+/// The colours of one kind of text. With neither set it's drawn in
+/// reverse video.
+#[derive(Clone, Copy, Default)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+}
+
+impl Style {
+    fn new(fg: Option<&str>, bg: Option<&str>, depth: Depth) -> Self {
+        Self {
+            fg: fg.and_then(parse_color).map(|color| depth.fit(color)),
+            bg: bg.and_then(parse_color).map(|color| depth.fit(color)),
+        }
+    }
+
+    /// `text` in this style.
+    pub fn paint(&self, text: &str) -> String {
+        format!("{}{}{}", self.start(), text, self.end())
+    }
+
+    pub fn start(&self) -> String {
+        if self.fg.is_none() && self.bg.is_none() {
+            return Attribute::Reverse.to_string();
+        }
+        let mut codes = String::new();
+        if let Some(fg) = self.fg {
+            codes.push_str(&SetForegroundColor(fg).to_string());
+        }
+        if let Some(bg) = self.bg {
+            codes.push_str(&SetBackgroundColor(bg).to_string());
+        }
+        codes
+    }
+
+    pub fn end(&self) -> String {
+        if self.fg.is_none() && self.bg.is_none() {
+            return Attribute::NoReverse.to_string();
+        }
+        let mut codes = String::new();
+        if self.fg.is_some() {
+            codes.push_str(&SetForegroundColor(Color::Reset).to_string());
+        }
+        if self.bg.is_some() {
+            codes.push_str(&SetBackgroundColor(Color::Reset).to_string());
+        }
+        codes
+    }
+}
+
+/// How the editor is coloured: the status bar, line numbers, selections,
+/// the search match and, through the syntax theme and scope overrides,
+/// the text itself.
+pub struct Theme {
+    /// The syntax highlighting theme, or the default one.
+    pub syntax: Option<String>,
+    /// How many colours the terminal shows; every colour drawn is fitted
+    /// to it.
+    pub depth: Depth,
+    pub status: Style,
+    pub line_numbers: Color,
+    pub selection: Style,
+    pub search: Style,
+    /// Colours by scope, applied over the syntax theme.
+    pub scopes: Vec<(String, (u8, u8, u8))>,
+}
+
+impl Theme {
+    /// The theme called `name` with the config's `colors` over it, for a
+    /// terminal showing `depth` colours. A name with no theme file is the
+    /// name of a syntax theme, and gets the default colours otherwise.
+    pub fn load(
+        name: Option<&str>,
+        colors: &ColorNames,
+        depth: Depth,
+        errors: &mut Vec<String>,
+    ) -> Self {
+        let file = match name {
+            Some(name) => load_file(name, errors),
+            None => None,
+        };
+        let (syntax, file) = match file {
+            Some(file) => (file.syntax.clone(), file),
+            None => (name.map(String::from), ThemeFile::default()),
+        };
+        let mut scopes = Vec::new();
+        for (scope, color) in &file.scopes {
+            match parse_color(color) {
+                Some(Color::Rgb { r, g, b }) => scopes.push((scope.clone(), (r, g, b))),
+                _ => errors.push(format!("Scope colors must be #rrggbb: {}", color)),
+            }
+        }
+        let colors = file.colors.merge(colors.clone());
+        let style = |fg: &Option<String>, bg: &Option<String>| {
+            Style::new(fg.as_deref(), bg.as_deref(), depth)
+        };
+        Self {
+            syntax,
+            depth,
+            status: style(&colors.status_fg, &colors.status_bg),
+            line_numbers: colors
+                .line_numbers
+                .as_deref()
+                .and_then(parse_color)
+                .map_or(Color::DarkGrey, |color| depth.fit(color)),
+            selection: style(&colors.selection_fg, &colors.selection_bg),
+            search: style(&colors.search_fg, &colors.search_bg),
+            scopes,
+        }
+    }
+}
+
+/// The names of the theme files in `<config dir>/themes`.
+pub fn file_names() -> Vec<String> {
+    let Ok(entries) = fs::read_dir(config::config_dir().join("themes")) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "toml"))
+        .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+        .collect();
+    names.sort();
+    names
+}
+
+fn load_file(name: &str, errors: &mut Vec<String>) -> Option<ThemeFile> {
+    let path = config::config_dir().join("themes").join(format!("{}.toml", name));
+    let text = fs::read_to_string(&path).ok()?;
+    match toml::from_str::<ThemeFile>(&text) {
+        Ok(file) => {
+            for name in file.colors.names() {
+                if parse_color(name).is_none() {
+                    errors.push(format!("Unknown color: {}", name));
+                }
+            }
+            Some(file)
+        }
+        Err(err) => {
+            errors.push(format!("{}: {}", path.display(), err));
+            None
+        }
+    }
+}
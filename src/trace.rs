@@ -0,0 +1,92 @@
+use crate::action::Action;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+const HEADER: &str = "# rustext trace: line:column (in bytes) of the cursor, then the action";
+
+/// Writes the actions keys come to for `--record`, a line each with where
+/// the cursor was. None of the text goes in: typed characters only keep
+/// whether they were a letter, a digit or something else. Lines are
+/// written as they come, so a trace is whole up to a crash.
+pub struct Recorder {
+    file: File,
+}
+
+/// An action read back from a trace, with the cursor position `(x, y)` it
+/// was taken at.
+pub struct Recorded {
+    pub at: (usize, usize),
+    pub action: Action,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut file = File::create(path)?;
+        writeln!(file, "{}", HEADER)?;
+        Ok(Self { file })
+    }
+
+    pub fn record(&mut self, (x, y): (usize, usize), action: Action) {
+        let name = match action {
+            Action::InsertChar(ch) => format!("insert-char {}", anonymize(ch)),
+            action => action.name().into(),
+        };
+        let _ = writeln!(self.file, "{}:{} {}", y + 1, x + 1, name);
+    }
+}
+
+fn anonymize(ch: char) -> char {
+    if ch.is_uppercase() {
+        'A'
+    } else if ch.is_alphabetic() {
+        'a'
+    } else if ch.is_numeric() {
+        '0'
+    } else {
+        ch
+    }
+}
+
+/// Reads the trace `--replay` names.
+pub fn read(path: &Path) -> io::Result<Vec<Recorded>> {
+    let text = fs::read_to_string(path)?;
+    let invalid = |line: usize, what: &str| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", line, what))
+    };
+    let mut steps = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parsed = line.split_once(' ').and_then(|(at, name)| {
+            let (y, x) = at.split_once(':')?;
+            let x = x.parse::<usize>().ok()?.checked_sub(1)?;
+            let y = y.parse::<usize>().ok()?.checked_sub(1)?;
+            let action = match name.strip_prefix("insert-char ") {
+                Some(ch) => Action::InsertChar(ch.chars().next()?),
+                None => Action::from_name(name)?,
+            };
+            Some(Recorded { at: (x, y), action })
+        });
+        steps.push(parsed.ok_or_else(|| invalid(number + 1, line))?);
+    }
+    Ok(steps)
+}
+
+/// Whether replaying `action` makes sense: those that ask for something
+/// the trace doesn't hold, or that would write files, are left out.
+pub fn replayable(action: Action) -> bool {
+    !matches!(
+        action,
+        Action::Quit
+            | Action::Save
+            | Action::Open
+            | Action::CloseBuffer
+            | Action::FindFile
+            | Action::PickBuffer
+            | Action::Find
+            | Action::FindChar { .. }
+            | Action::CommandLine
+    )
+}
@@ -0,0 +1,75 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, IsTerminal, Stdout, Write};
+use std::sync::OnceLock;
+use std::{mem, ptr};
+
+/// The terminal itself, opened once stdout turns out to be piped.
+static TTY: OnceLock<File> = OnceLock::new();
+
+/// Where the screen is drawn: stdout, unless that is piped somewhere, as
+/// with `--stdout`.
+pub enum Screen {
+    Stdout(Stdout),
+    Tty(&'static File),
+}
+
+/// Draws on the terminal from now on if stdout isn't it.
+pub fn draw_on_tty() -> io::Result<()> {
+    if !io::stdout().is_terminal() && TTY.get().is_none() {
+        let tty = OpenOptions::new().write(true).open("/dev/tty")?;
+        let _ = TTY.set(tty);
+    }
+    Ok(())
+}
+
+/// Runs `f`, which runs a program on the terminal, with the editor shielded
+/// from the Ctrl-C and Ctrl-\ meant for that program. A handler that does
+/// nothing stands in for the usual ones meanwhile; `exec` puts caught
+/// signals back to their defaults, so the program still gets them.
+pub fn shielded<T>(f: impl FnOnce() -> T) -> T {
+    extern "C" fn ignore(_: libc::c_int) {}
+    let signals = [libc::SIGINT, libc::SIGQUIT];
+    // SAFETY: `sigaction` only reads `action` and writes `previous`, both
+    // plain structs of ours; all-zero is a valid value for either.
+    let mut previous: [libc::sigaction; 2] = unsafe { mem::zeroed() };
+    for (&signal, previous) in signals.iter().zip(&mut previous) {
+        unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = ignore as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, previous);
+        }
+    }
+    let result = f();
+    for (&signal, previous) in signals.iter().zip(&previous) {
+        // SAFETY: as above; this puts back what was there before.
+        unsafe {
+            libc::sigaction(signal, previous, ptr::null_mut());
+        }
+    }
+    result
+}
+
+pub fn screen() -> Screen {
+    match TTY.get() {
+        Some(tty) => Screen::Tty(tty),
+        None => Screen::Stdout(io::stdout()),
+    }
+}
+
+impl Write for Screen {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        match self {
+            Screen::Stdout(stdout) => stdout.write(bytes),
+            Screen::Tty(mut tty) => tty.write(bytes),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Screen::Stdout(stdout) => stdout.flush(),
+            Screen::Tty(mut tty) => tty.flush(),
+        }
+    }
+}
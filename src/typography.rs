@@ -0,0 +1,32 @@
+/// What typing `ch` after `before` (the line up to the cursor) becomes in
+/// prose mode: how many bytes before the cursor to replace, and the text
+/// to put there instead. `None` means `ch` is typed as it is.
+///
+/// Quotes curl according to what precedes them, `--` becomes an en dash
+/// and a third `-` an em dash, and `...` an ellipsis.
+pub fn substitute(before: &str, ch: char) -> Option<(usize, &'static str)> {
+    let opening = before
+        .chars()
+        .next_back()
+        .is_none_or(|previous| previous.is_whitespace() || "([{\u{2013}\u{2014}".contains(previous));
+    match ch {
+        '"' if opening => Some((0, "\u{201c}")),
+        '"' => Some((0, "\u{201d}")),
+        '\'' if opening => Some((0, "\u{2018}")),
+        '\'' => Some((0, "\u{2019}")),
+        '-' if before.ends_with('\u{2013}') => Some(('\u{2013}'.len_utf8(), "\u{2014}")),
+        '-' if before.ends_with('-') => Some((1, "\u{2013}")),
+        '.' if before.ends_with("..") => Some((2, "\u{2026}")),
+        _ => None,
+    }
+}
+
+/// Whether the cursor, after `before` on its line and below `above`,
+/// is in Markdown code (a backtick span or a fenced block), where text is
+/// left as typed.
+pub fn in_code(above: impl Iterator<Item = impl AsRef<str>>, before: &str) -> bool {
+    let fences = above
+        .filter(|line| line.as_ref().trim_start().starts_with("```"))
+        .count();
+    fences % 2 == 1 || before.matches('`').count() % 2 == 1
+}
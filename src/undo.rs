@@ -0,0 +1,174 @@
+use crate::journal::Edit;
+use std::collections::VecDeque;
+use std::mem;
+
+pub const MAX_ENTRIES: usize = 1000;
+pub const MAX_BYTES: usize = 64 * 1024 * 1024;
+
+/// Bookkeeping cost of an edit beyond the text it carries.
+const EDIT_OVERHEAD: usize = mem::size_of::<Edit>();
+
+/// The edits that undo one step, in the order the original edits were
+/// made, plus where the cursor was before the step.
+pub struct Step {
+    pub edits: Vec<Edit>,
+    pub cursor: (usize, usize),
+    bytes: usize,
+}
+
+impl Step {
+    fn new(cursor: (usize, usize)) -> Self {
+        Self {
+            edits: Vec::new(),
+            cursor,
+            bytes: 0,
+        }
+    }
+
+    /// Folds the `newer` step into this one so both are undone together.
+    fn absorb(&mut self, newer: Step) {
+        self.edits.extend(newer.edits);
+        self.bytes += newer.bytes;
+    }
+}
+
+/// Undo and redo stacks for one buffer. Edits are collected into steps
+/// between `checkpoint`s, normally one step per keypress.
+///
+/// Past `max_entries` the two oldest steps are merged into one coarser
+/// step, so very old history can still be undone but in bigger jumps.
+/// Past `max_bytes` the oldest steps are dropped.
+pub struct History {
+    undo: VecDeque<Step>,
+    redo: Vec<Step>,
+    open: Option<Step>,
+    cursor: (usize, usize),
+    undoing: bool,
+    bytes: usize,
+    max_entries: usize,
+    max_bytes: usize,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            open: None,
+            cursor: (0, 0),
+            undoing: false,
+            bytes: 0,
+            max_entries: MAX_ENTRIES,
+            max_bytes: MAX_BYTES,
+        }
+    }
+
+    pub fn set_limits(&mut self, max_entries: usize, max_bytes: usize) {
+        self.max_entries = max_entries;
+        self.max_bytes = max_bytes;
+        self.enforce_limits();
+    }
+
+    pub fn undo_depth(&self) -> usize {
+        self.undo.len() + usize::from(self.open.is_some() && !self.undoing)
+    }
+
+    pub fn redo_depth(&self) -> usize {
+        self.redo.len()
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.open = None;
+        self.bytes = 0;
+    }
+
+    /// Ends the current step; the next edit starts a new one, restoring the
+    /// cursor to `cursor` when undone.
+    pub fn checkpoint(&mut self, cursor: (usize, usize)) {
+        if let Some(step) = self.open.take() {
+            if self.undoing {
+                self.redo.push(step);
+            } else {
+                self.undo.push_back(step);
+                self.enforce_limits();
+            }
+        }
+        self.cursor = cursor;
+    }
+
+    /// Records the edit that reverses a change about to be made.
+    pub fn record(&mut self, inverse: Edit) {
+        if self.open.is_none() && !self.undoing {
+            self.redo.clear();
+        }
+        let size = EDIT_OVERHEAD + edit_text_len(&inverse);
+        let step = self.open.get_or_insert_with(|| Step::new(self.cursor));
+        step.edits.push(inverse);
+        step.bytes += size;
+        if !self.undoing {
+            self.bytes += size;
+        }
+    }
+
+    /// Takes the step to undo. Edits made while applying it are collected
+    /// as the matching redo step until `finish` is called.
+    pub fn take_undo(&mut self, cursor: (usize, usize)) -> Option<Step> {
+        self.checkpoint(cursor);
+        let step = self.undo.pop_back()?;
+        self.bytes -= step.bytes;
+        self.undoing = true;
+        Some(step)
+    }
+
+    /// Takes the step to redo; edits made while applying it form a new
+    /// undo step.
+    pub fn take_redo(&mut self, cursor: (usize, usize)) -> Option<Step> {
+        self.checkpoint(cursor);
+        let step = self.redo.pop()?;
+        self.undoing = false;
+        self.open = Some(Step::new(cursor));
+        Some(step)
+    }
+
+    pub fn finish(&mut self, cursor: (usize, usize)) {
+        if !self.undoing {
+            // Keep the redo stack: this step came from it.
+            if let Some(step) = self.open.take().filter(|step| !step.edits.is_empty()) {
+                self.undo.push_back(step);
+                self.enforce_limits();
+            }
+        }
+        self.checkpoint(cursor);
+        self.undoing = false;
+    }
+
+    fn enforce_limits(&mut self) {
+        while self.undo.len() > self.max_entries.max(1) {
+            let oldest = self.undo.pop_front().unwrap();
+            let next = self.undo.front_mut().unwrap();
+            let newer = mem::replace(next, oldest);
+            next.absorb(newer);
+        }
+        while self.bytes > self.max_bytes {
+            match self.undo.pop_front() {
+                Some(step) => self.bytes -= step.bytes,
+                None => break,
+            }
+        }
+    }
+}
+
+fn edit_text_len(edit: &Edit) -> usize {
+    match edit {
+        Edit::InsertRow { text, .. } | Edit::SetRow { text, .. } | Edit::Replace { text } => {
+            text.len()
+        }
+        _ => 0,
+    }
+}
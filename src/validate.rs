@@ -0,0 +1,69 @@
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+use std::fmt;
+
+/// Where a document stops parsing, and why. Lines and columns count from
+/// one.
+pub struct SyntaxError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at line {} column {}", self.message, self.line, self.column)
+    }
+}
+
+impl SyntaxError {
+    /// An error whose own text ends with where it happened, as serde_json
+    /// and serde_yaml write them, keeping just the message.
+    fn located(text: String, line: usize, column: usize) -> Self {
+        let suffix = format!(" at line {} column {}", line, column);
+        let message = text.strip_suffix(&suffix).unwrap_or(&text).to_string();
+        Self {
+            line,
+            column,
+            message,
+        }
+    }
+}
+
+/// Whether `filetype` is a data format `check` can parse.
+pub fn is_structured(filetype: &str) -> bool {
+    matches!(filetype, "json" | "yaml" | "toml")
+}
+
+/// Parses `text` as a whole document of the format `filetype` names,
+/// saying where it goes wrong if it isn't valid. Types `is_structured`
+/// doesn't cover always pass.
+pub fn check(filetype: &str, text: &str) -> Result<(), SyntaxError> {
+    match filetype {
+        "json" => serde_json::from_str::<IgnoredAny>(text)
+            .map(|_| ())
+            .map_err(|err| SyntaxError::located(err.to_string(), err.line(), err.column())),
+        // A YAML file may hold several documents, and an empty one is fine.
+        "yaml" => serde_yaml::Deserializer::from_str(text).try_for_each(|document| {
+            IgnoredAny::deserialize(document).map(|_| ()).map_err(|err| {
+                let (line, column) = err
+                    .location()
+                    .map_or((1, 1), |location| (location.line(), location.column()));
+                SyntaxError::located(err.to_string(), line, column)
+            })
+        }),
+        "toml" => text.parse::<toml::Table>().map(|_| ()).map_err(|err| {
+            let at = err.span().map_or(0, |span| span.start);
+            let before = text.get(..at).unwrap_or(text);
+            let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+            SyntaxError {
+                line: before.matches('\n').count() + 1,
+                column: before[line_start..].chars().count() + 1,
+                // toml puts a hint on a line of its own, as in "invalid
+                // array\nexpected `]`".
+                message: err.message().trim_end().replace('\n', ", "),
+            }
+        }),
+        _ => Ok(()),
+    }
+}
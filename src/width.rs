@@ -0,0 +1,40 @@
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// The columns `ch` takes on screen: two for wide CJK characters and most
+/// emoji, none for combining marks. Tabs depend on where they are, so
+/// callers deal with them.
+pub fn char_width(ch: char) -> usize {
+    ch.width().unwrap_or(1)
+}
+
+pub fn str_width(text: &str) -> usize {
+    text.width()
+}
+
+/// The byte offset into `render` (a row with tabs already expanded) where
+/// screen column `column` starts, and how many columns of a wide character
+/// straddling it were cut off.
+pub fn offset_at(render: &str, column: usize) -> (usize, usize) {
+    let mut at = 0;
+    for (index, ch) in render.char_indices() {
+        // Marks combining with the character before stay off screen with it.
+        if at >= column && char_width(ch) > 0 {
+            return (index, at - column);
+        }
+        at += char_width(ch);
+    }
+    (render.len(), at.saturating_sub(column))
+}
+
+/// The end of the longest part of `render` from byte `start` that fits in
+/// `columns` columns.
+pub fn fit(render: &str, start: usize, columns: usize) -> usize {
+    let mut used = 0;
+    for (index, ch) in render[start..].char_indices() {
+        used += char_width(ch);
+        if used > columns {
+            return start + index;
+        }
+    }
+    render.len()
+}